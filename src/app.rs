@@ -1,4 +1,21 @@
-use crate::backend::EmailSummary;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::backend::{
+    EmailSummary, OutgoingMessage,
+    address::{self, Address},
+    mime,
+};
+use crate::config::{self, Identity, Template};
+use crate::contacts::{Contact, ContactStore};
+use crate::date;
+use crate::imap::watch::Update;
+use crate::markdown;
+use crate::rules::Rules;
+use crate::spell::{self, Dictionary};
+use crate::template;
+use crate::tui::textarea;
+use crate::undo::UndoTree;
+use crate::vim::{self, Action, Command, Motion, Operator, Parsed, Target};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
@@ -6,22 +23,132 @@ pub enum Mode {
     Command,
     Help,
     Viewing,
+    LinkPicker,
     Compose,
+    /// Reviewing a composed message before it is sent.
+    SendConfirm,
     DeleteConfirm,
+    Contacts,
+}
+
+/// What to do with a link chosen in the link picker; carried out by the
+/// TUI loop since it needs to spawn a process or write to the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkAction {
+    Open(String),
+    Copy(String),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ComposeField {
     To,
+    Cc,
+    Bcc,
+    ReplyTo,
     Subject,
     Body,
 }
 
+impl ComposeField {
+    /// Cc, Bcc and Reply-To are hidden unless extra headers are shown.
+    pub fn is_extra(self) -> bool {
+        matches!(
+            self,
+            ComposeField::Cc | ComposeField::Bcc | ComposeField::ReplyTo
+        )
+    }
+
+    /// Fields holding address lists, which offer completion from contacts.
+    pub fn is_address(self) -> bool {
+        !matches!(self, ComposeField::Subject | ComposeField::Body)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ComposeMode {
     Normal,
     Insert,
     Visual,
+    /// `V`: the selection covers whole lines.
+    VisualLine,
+    /// `K`: the Markdown body rendered as the recipient will see it.
+    Preview,
+}
+
+/// A message that passed the pre-send checks, shown for confirmation.
+#[derive(Debug, Clone)]
+pub struct SendReview {
+    pub message: OutgoingMessage,
+    pub warnings: Vec<String>,
+}
+
+/// Spelling suggestions offered by `z=` for a word in the body.
+#[derive(Debug, Clone)]
+pub struct Suggestions {
+    pub range: std::ops::Range<usize>,
+    pub words: Vec<String>,
+    pub selected: usize,
+}
+
+/// Text held in a compose register.
+#[derive(Debug, Clone, Default)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines, which are put on lines of their own.
+    pub linewise: bool,
+}
+
+/// Compose fields and cursor as recorded in the undo history.
+#[derive(Debug, Clone)]
+pub struct ComposeSnapshot {
+    to: String,
+    cc: String,
+    bcc: String,
+    reply_to: String,
+    subject: String,
+    body: String,
+    field: ComposeField,
+    cursor: usize,
+}
+
+impl ComposeSnapshot {
+    fn same_text(&self, other: &ComposeSnapshot) -> bool {
+        self.to == other.to
+            && self.cc == other.cc
+            && self.bcc == other.bcc
+            && self.reply_to == other.reply_to
+            && self.subject == other.subject
+            && self.body == other.body
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactField {
+    Name,
+    Emails,
+    Notes,
+}
+
+/// A contact being added or edited on the contacts screen.
+#[derive(Debug, Clone)]
+pub struct ContactForm {
+    /// The contact being edited, or None when adding a new one.
+    pub index: Option<usize>,
+    pub field: ContactField,
+    pub name: String,
+    /// Comma-separated addresses.
+    pub emails: String,
+    pub notes: String,
+}
+
+impl ContactForm {
+    pub fn value_mut(&mut self) -> &mut String {
+        match self.field {
+            ContactField::Name => &mut self.name,
+            ContactField::Emails => &mut self.emails,
+            ContactField::Notes => &mut self.notes,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -33,15 +160,89 @@ pub struct App {
     pub command_buf: String,
     pub view_offset: usize,
     pub view_height: usize,
+    pub view_rows: usize,
+    pub view_expand_quotes: bool,
+    pub view_all_headers: bool,
+    pub view_raw: bool,
+    pub links: Vec<String>,
+    pub link_selected: usize,
+    pub link_action: Option<LinkAction>,
+    pub status: Option<String>,
     pub compose_to: String,
+    pub compose_cc: String,
+    pub compose_bcc: String,
+    pub compose_reply_to: String,
+    pub compose_show_extra: bool,
     pub compose_subject: String,
     pub compose_body: String,
     pub compose_field: ComposeField,
     pub compose_mode: ComposeMode,
     pub compose_cursor: usize,
-    pub compose_line: usize,
-    pub compose_col: usize,
+    /// First visible row of the wrapped body, kept following the cursor.
+    pub compose_scroll: usize,
+    /// Width the body wraps at, filled in by the UI for `gj` and `gk`.
+    pub compose_body_width: usize,
     pub compose_visual_start: Option<usize>,
+    pub compose_completions: Vec<Address>,
+    pub compose_completion_selected: usize,
+    pub compose_undo: UndoTree<ComposeSnapshot>,
+    pub compose_registers: HashMap<char, Register>,
+    /// Register chosen with `"x` for the next yank, delete or put.
+    pub compose_register: Option<char>,
+    /// Keys typed so far of an unfinished normal-mode command.
+    pub compose_pending: String,
+    /// Last change, for `.` to repeat, with the text typed if it started
+    /// an insert.
+    pub compose_last_change: Option<Command>,
+    pub compose_last_insert: String,
+    /// Text typed in the current insert session.
+    pub compose_insert_typed: String,
+    /// Pattern being typed after `/` or `?`.
+    pub compose_search_input: Option<String>,
+    pub compose_search_forward: bool,
+    /// Last search, for `n` and `N`.
+    pub compose_search_pattern: Option<String>,
+    /// Copy every yank and delete to the system clipboard as well.
+    pub compose_clipboard: bool,
+    /// Text the TUI loop should put on the system clipboard.
+    pub clipboard_copy: Option<String>,
+    pub contacts: ContactStore,
+    /// Set when the draft should be handed to the external editor.
+    pub compose_external: bool,
+    pub contact_selected: usize,
+    pub contact_form: Option<ContactForm>,
+    pub contact_delete_pending: bool,
+    pub identities: Vec<Identity>,
+    /// Identity new messages are sent from, chosen with `:identity`.
+    pub identity: usize,
+    /// Identity of the message being composed.
+    pub compose_identity: usize,
+    pub templates: BTreeMap<String, Template>,
+    /// Loaded spelling dictionaries, by configured name.
+    pub dictionaries: HashMap<String, Dictionary>,
+    /// Dictionary used when the identity does not name one.
+    pub dictionary: Option<String>,
+    /// `z=` suggestions being chosen from.
+    pub compose_suggestions: Option<Suggestions>,
+    /// Whether new messages start in Markdown.
+    pub markdown: bool,
+    /// Send the body as Markdown with a rendered HTML alternative.
+    pub compose_markdown: bool,
+    /// Whether new messages are signed and encrypted with OpenPGP.
+    pub pgp_sign: bool,
+    pub pgp_encrypt: bool,
+    pub compose_sign: bool,
+    pub compose_encrypt: bool,
+    /// Whether new messages are signed with S/MIME.
+    pub smime_sign: bool,
+    pub compose_smime: bool,
+    /// Filtering rules, run on mail fetched after sending.
+    pub rules: Rules,
+    /// Messages that arrived in background syncs since a key was last
+    /// pressed in the inbox.
+    pub new_mail: usize,
+    /// Message awaiting confirmation; sent by the TUI loop once confirmed.
+    pub send_review: Option<SendReview>,
     pub needs_refresh: bool,
     pub email_to_delete: Option<EmailSummary>,
 }
@@ -56,15 +257,73 @@ impl App {
             command_buf: String::new(),
             view_offset: 0,
             view_height: 0,
+            view_rows: 0,
+            view_expand_quotes: false,
+            view_all_headers: false,
+            view_raw: false,
+            links: Vec::new(),
+            link_selected: 0,
+            link_action: None,
+            status: None,
             compose_to: String::new(),
+            compose_cc: String::new(),
+            compose_bcc: String::new(),
+            compose_reply_to: String::new(),
+            compose_show_extra: false,
             compose_subject: String::new(),
             compose_body: String::new(),
             compose_field: ComposeField::To,
             compose_mode: ComposeMode::Normal,
             compose_cursor: 0,
-            compose_line: 0,
-            compose_col: 0,
+            compose_scroll: 0,
+            compose_body_width: 0,
             compose_visual_start: None,
+            compose_completions: Vec::new(),
+            compose_completion_selected: 0,
+            compose_undo: UndoTree::new(ComposeSnapshot {
+                to: String::new(),
+                cc: String::new(),
+                bcc: String::new(),
+                reply_to: String::new(),
+                subject: String::new(),
+                body: String::new(),
+                field: ComposeField::To,
+                cursor: 0,
+            }),
+            compose_registers: HashMap::new(),
+            compose_register: None,
+            compose_pending: String::new(),
+            compose_last_change: None,
+            compose_last_insert: String::new(),
+            compose_insert_typed: String::new(),
+            compose_search_input: None,
+            compose_search_forward: true,
+            compose_search_pattern: None,
+            compose_clipboard: false,
+            clipboard_copy: None,
+            contacts: ContactStore::default(),
+            compose_external: false,
+            contact_selected: 0,
+            contact_form: None,
+            contact_delete_pending: false,
+            identities: Vec::new(),
+            identity: 0,
+            compose_identity: 0,
+            templates: BTreeMap::new(),
+            dictionaries: HashMap::new(),
+            dictionary: None,
+            compose_suggestions: None,
+            markdown: false,
+            compose_markdown: false,
+            pgp_sign: false,
+            pgp_encrypt: false,
+            compose_sign: false,
+            compose_encrypt: false,
+            smime_sign: false,
+            rules: Rules::default(),
+            new_mail: 0,
+            compose_smime: false,
+            send_review: None,
             needs_refresh: false,
             email_to_delete: None,
         }
@@ -86,10 +345,109 @@ impl App {
         self.should_quit = true;
     }
 
+    /// Replaces the local messages in the inbox with a fresh fetch, keeping
+    /// the same message selected.
+    pub fn refresh_inbox(&mut self, inbox: Vec<EmailSummary>) {
+        self.change_inbox(|current| {
+            // Messages from watched IMAP folders come from their watchers
+            let remote: Vec<EmailSummary> = current
+                .drain(..)
+                .filter(|email| email.remote.is_some())
+                .collect();
+            *current = inbox;
+            current.extend(remote);
+        });
+    }
+
+    /// Applies a change a watched IMAP folder reported. Arrived messages
+    /// are shown as they are: rules act through the file backend, which
+    /// cannot move or delete messages on the server.
+    pub fn mailbox_update(&mut self, update: Update) {
+        let in_folder = |email: &EmailSummary, name: &str, uid: Option<u32>| {
+            email.remote.as_ref().is_some_and(|remote| {
+                remote.folder == name && uid.is_none_or(|uid| remote.uid == uid)
+            })
+        };
+        match update {
+            Update::Snapshot { folder, emails } => self.change_inbox(|inbox| {
+                inbox.retain(|email| !in_folder(email, &folder, None));
+                inbox.extend(emails);
+            }),
+            Update::Arrived { folder, emails } => {
+                self.new_mail += emails.len();
+                self.change_inbox(|inbox| {
+                    // A message fetched again replaces the copy already shown
+                    inbox.retain(|email| {
+                        !emails.iter().any(|new| {
+                            new.remote
+                                .as_ref()
+                                .is_some_and(|remote| in_folder(email, &folder, Some(remote.uid)))
+                        })
+                    });
+                    inbox.extend(emails);
+                });
+            }
+            Update::Expunged { folder, uid } => self.change_inbox(|inbox| {
+                inbox.retain(|email| !in_folder(email, &folder, Some(uid)));
+            }),
+            Update::Flags {
+                folder,
+                uid,
+                read,
+                flagged,
+            } => {
+                for email in &mut self.inbox {
+                    if in_folder(email, &folder, Some(uid)) {
+                        email.read = read;
+                        email.flagged = flagged;
+                    }
+                }
+            }
+            Update::Disconnected {
+                folder,
+                error,
+                retry,
+            } => {
+                self.status = Some(format!(
+                    "IMAP {}: {}; retrying in {}s",
+                    folder,
+                    error,
+                    retry.as_secs()
+                ));
+            }
+        }
+    }
+
+    /// Changes the inbox, keeping the same message selected. A message
+    /// being read that is gone closes the viewer.
+    fn change_inbox(&mut self, change: impl FnOnce(&mut Vec<EmailSummary>)) {
+        let selected = self
+            .inbox
+            .get(self.selected)
+            .map(|email| (email.file_path.clone(), email.remote.clone()));
+        change(&mut self.inbox);
+        match selected.and_then(|(path, remote)| {
+            self.inbox
+                .iter()
+                .position(|email| email.file_path == path && email.remote == remote)
+        }) {
+            Some(index) => self.selected = index,
+            None => {
+                self.selected = self.selected.min(self.inbox.len().saturating_sub(1));
+                if matches!(self.mode, Mode::Viewing | Mode::LinkPicker) {
+                    self.mode = Mode::Normal;
+                }
+            }
+        }
+    }
+
     pub fn open_selected(&mut self) {
         if self.selected < self.inbox.len() {
             self.mode = Mode::Viewing;
             self.view_offset = 0;
+            self.view_expand_quotes = false;
+            self.view_all_headers = false;
+            self.view_raw = false;
         }
     }
 
@@ -100,22 +458,118 @@ impl App {
         self.email_to_delete = None;
     }
 
+    // Viewer scrolling works in visual (wrapped) rows; `view_rows` and
+    // `view_height` are filled in by the UI each time the viewer is drawn.
+    fn view_max_offset(&self) -> usize {
+        self.view_rows.saturating_sub(self.view_height)
+    }
+
+    pub fn view_scroll_by(&mut self, delta: isize) {
+        let target = self.view_offset.saturating_add_signed(delta);
+        self.view_offset = target.min(self.view_max_offset());
+    }
+
     pub fn view_scroll_up(&mut self) {
-        if self.view_offset > 0 {
-            self.view_offset -= 1;
+        self.view_scroll_by(-1);
+    }
+
+    pub fn view_scroll_down(&mut self) {
+        self.view_scroll_by(1);
+    }
+
+    pub fn view_page_up(&mut self) {
+        self.view_scroll_by(-(self.view_height.max(1) as isize));
+    }
+
+    pub fn view_page_down(&mut self) {
+        self.view_scroll_by(self.view_height.max(1) as isize);
+    }
+
+    pub fn view_half_page_up(&mut self) {
+        self.view_scroll_by(-((self.view_height / 2).max(1) as isize));
+    }
+
+    pub fn view_half_page_down(&mut self) {
+        self.view_scroll_by((self.view_height / 2).max(1) as isize);
+    }
+
+    pub fn view_top(&mut self) {
+        self.view_offset = 0;
+    }
+
+    pub fn view_bottom(&mut self) {
+        self.view_offset = self.view_max_offset();
+    }
+
+    pub fn view_toggle_quotes(&mut self) {
+        self.view_expand_quotes = !self.view_expand_quotes;
+    }
+
+    pub fn view_toggle_headers(&mut self) {
+        self.view_all_headers = !self.view_all_headers;
+        self.view_raw = false;
+        self.view_offset = 0;
+    }
+
+    pub fn view_toggle_raw(&mut self) {
+        self.view_raw = !self.view_raw;
+        self.view_offset = 0;
+    }
+
+    /// Percentage of the body scrolled past, or `None` when it fits entirely.
+    pub fn view_scroll_percent(&self) -> Option<usize> {
+        let max_offset = self.view_max_offset();
+        if max_offset == 0 {
+            return None;
         }
+        Some(self.view_offset.min(max_offset) * 100 / max_offset)
     }
 
-    pub fn view_scroll_down(&mut self, total_lines: usize, visible_height: usize) {
-        if total_lines == 0 || visible_height == 0 {
+    pub fn open_link_picker(&mut self, links: Vec<String>) {
+        if links.is_empty() {
+            self.status = Some("No links in this message".to_string());
             return;
         }
-        // Calculate max offset: when total lines fit in view, don't scroll
-        // Otherwise, allow scrolling until last line is at bottom of view
-        let max_offset = total_lines.saturating_sub(visible_height);
-        if self.view_offset < max_offset {
-            self.view_offset += 1;
+        self.links = links;
+        self.link_selected = 0;
+        self.mode = Mode::LinkPicker;
+    }
+
+    pub fn close_link_picker(&mut self) {
+        self.mode = Mode::Viewing;
+    }
+
+    pub fn link_up(&mut self) {
+        if self.link_selected > 0 {
+            self.link_selected -= 1;
+        }
+    }
+
+    pub fn link_down(&mut self) {
+        if self.link_selected + 1 < self.links.len() {
+            self.link_selected += 1;
+        }
+    }
+
+    /// Selects a link by its 1-based number as shown in the picker.
+    pub fn link_select_number(&mut self, number: usize) {
+        if number >= 1 && number <= self.links.len() {
+            self.link_selected = number - 1;
+        }
+    }
+
+    pub fn link_open(&mut self) {
+        if let Some(url) = self.links.get(self.link_selected) {
+            self.link_action = Some(LinkAction::Open(url.clone()));
+        }
+        self.close_link_picker();
+    }
+
+    pub fn link_copy(&mut self) {
+        if let Some(url) = self.links.get(self.link_selected) {
+            self.link_action = Some(LinkAction::Copy(url.clone()));
         }
+        self.close_link_picker();
     }
 
     pub fn start_command(&mut self) {
@@ -137,6 +591,15 @@ impl App {
             "help" => {
                 self.mode = Mode::Help;
             }
+            "contacts" => self.open_contacts(),
+            _ if cmd == "template" || cmd.starts_with("template ") => {
+                let name = cmd["template".len()..].trim().to_string();
+                self.compose_from_template(&name);
+            }
+            _ if cmd == "identity" || cmd.starts_with("identity ") => {
+                let query = cmd["identity".len()..].trim().to_string();
+                self.select_identity(&query);
+            }
             _ => {
                 // Unknown command: just return to normal
                 self.mode = Mode::Normal;
@@ -145,6 +608,271 @@ impl App {
         self.command_buf.clear();
     }
 
+    /// Starts a message from a configured template, filling in its
+    /// placeholders. Without a name (or with an unknown one) the available
+    /// templates are listed instead.
+    pub fn compose_from_template(&mut self, name: &str) {
+        let Some(template) = self.templates.get(name).cloned() else {
+            self.mode = Mode::Normal;
+            let names: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+            self.status = Some(match (name.is_empty(), names.is_empty()) {
+                (_, true) => "No templates configured".to_string(),
+                (true, false) => format!("Templates: {}", names.join(", ")),
+                (false, false) => {
+                    format!("No template named {:?} (have: {})", name, names.join(", "))
+                }
+            });
+            return;
+        };
+
+        self.start_compose();
+        if let Some(query) = &template.identity {
+            match config::find_identity(&self.identities, query) {
+                Some(index) => self.compose_identity = index,
+                None => self.status = Some(format!("No identity matches {:?}", query)),
+            }
+        }
+        self.compose_to = template.to.unwrap_or_default();
+        self.compose_cc = template.cc.unwrap_or_default();
+        self.compose_show_extra = !self.compose_cc.is_empty();
+
+        // Expanded once, here: braces typed into the draft later are the
+        // user's own text
+        let vars = self.template_vars();
+        self.compose_subject = template::expand(&template.subject.unwrap_or_default(), &vars);
+        let body = template::expand(&template.body.unwrap_or_default(), &vars);
+        if !vars.iter().any(|(name, _)| *name == "recipient_name")
+            && [&self.compose_subject, &body]
+                .iter()
+                .any(|text| text.contains("{recipient_"))
+        {
+            self.status =
+                Some("Recipient placeholders need a To address in the template".to_string());
+        }
+        self.compose_body = format!("{}{}", body.trim_end(), self.compose_signature());
+
+        // Start on the first thing left to fill in
+        self.compose_field = if self.compose_to.is_empty() {
+            ComposeField::To
+        } else if self.compose_subject.is_empty() {
+            ComposeField::Subject
+        } else {
+            ComposeField::Body
+        };
+        self.compose_reset_undo();
+    }
+
+    /// Placeholder values for templates. The recipient ones are only known
+    /// once the To field holds an address.
+    fn template_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("date", date::today())];
+        if let Some(identity) = self.identities.get(self.compose_identity) {
+            vars.push(("sender_name", identity.name.clone().unwrap_or_default()));
+            vars.push(("sender_email", identity.email.clone()));
+        }
+        if let Some(recipient) = address::parse_address_list(&self.compose_to)
+            .ok()
+            .and_then(|list| list.into_iter().next())
+        {
+            let name = self
+                .contacts
+                .contacts
+                .iter()
+                .find(|c| c.has_email(&recipient.email))
+                .and_then(|c| c.name.clone())
+                .or_else(|| recipient.name.as_deref().map(mime::decode_header_value))
+                .unwrap_or_else(|| {
+                    let local = recipient.email.split('@').next().unwrap_or_default();
+                    local.to_string()
+                });
+            vars.push(("recipient_name", name));
+            vars.push(("recipient_email", recipient.email));
+        }
+        vars
+    }
+
+    /// The signature block of the identity being composed from.
+    fn compose_signature(&self) -> String {
+        self.identities
+            .get(self.compose_identity)
+            .and_then(|identity| identity.signature.as_deref())
+            .map(template::signature_block)
+            .unwrap_or_default()
+    }
+
+    /// Chooses the identity new messages are sent from; without a query
+    /// the configured identities are listed.
+    pub fn select_identity(&mut self, query: &str) {
+        self.mode = Mode::Normal;
+        if self.identities.is_empty() {
+            self.status = Some("No identities configured".to_string());
+            return;
+        }
+        if query.is_empty() {
+            let list: Vec<String> = self
+                .identities
+                .iter()
+                .enumerate()
+                .map(|(i, identity)| {
+                    let marker = if i == self.identity { "*" } else { "" };
+                    format!("{}{}", marker, identity.address())
+                })
+                .collect();
+            self.status = Some(format!("Identities: {}", list.join(", ")));
+            return;
+        }
+        self.status = Some(match config::find_identity(&self.identities, query) {
+            Some(index) => {
+                self.identity = index;
+                format!("Sending as {}", self.identities[index].address())
+            }
+            None => format!("No identity matches {:?}", query),
+        });
+    }
+
+    pub fn open_contacts(&mut self) {
+        self.mode = Mode::Contacts;
+        self.contact_form = None;
+        self.contact_delete_pending = false;
+        self.contact_selected = self
+            .contact_selected
+            .min(self.contacts.contacts.len().saturating_sub(1));
+    }
+
+    pub fn contact_up(&mut self) {
+        self.contact_selected = self.contact_selected.saturating_sub(1);
+    }
+
+    pub fn contact_down(&mut self) {
+        if self.contact_selected + 1 < self.contacts.contacts.len() {
+            self.contact_selected += 1;
+        }
+    }
+
+    pub fn contact_top(&mut self) {
+        self.contact_selected = 0;
+    }
+
+    pub fn contact_bottom(&mut self) {
+        self.contact_selected = self.contacts.contacts.len().saturating_sub(1);
+    }
+
+    pub fn contact_new(&mut self) {
+        self.contact_form = Some(ContactForm {
+            index: None,
+            field: ContactField::Name,
+            name: String::new(),
+            emails: String::new(),
+            notes: String::new(),
+        });
+    }
+
+    pub fn contact_edit(&mut self) {
+        if let Some(contact) = self.contacts.contacts.get(self.contact_selected) {
+            self.contact_form = Some(ContactForm {
+                index: Some(self.contact_selected),
+                field: ContactField::Name,
+                name: contact.name.clone().unwrap_or_default(),
+                emails: contact.emails.join(", "),
+                notes: contact.notes.clone(),
+            });
+        }
+    }
+
+    pub fn contact_form_next_field(&mut self) {
+        if let Some(form) = &mut self.contact_form {
+            form.field = match form.field {
+                ContactField::Name => ContactField::Emails,
+                ContactField::Emails => ContactField::Notes,
+                ContactField::Notes => ContactField::Name,
+            };
+        }
+    }
+
+    pub fn contact_form_prev_field(&mut self) {
+        if let Some(form) = &mut self.contact_form {
+            form.field = match form.field {
+                ContactField::Name => ContactField::Notes,
+                ContactField::Emails => ContactField::Name,
+                ContactField::Notes => ContactField::Emails,
+            };
+        }
+    }
+
+    pub fn contact_form_push_char(&mut self, ch: char) {
+        if let Some(form) = &mut self.contact_form {
+            form.value_mut().push(ch);
+        }
+    }
+
+    pub fn contact_form_pop_char(&mut self) {
+        if let Some(form) = &mut self.contact_form {
+            form.value_mut().pop();
+        }
+    }
+
+    pub fn contact_form_cancel(&mut self) {
+        self.contact_form = None;
+    }
+
+    /// Saves the form into the address book. The form stays open with a
+    /// status message when the addresses do not parse.
+    pub fn contact_form_submit(&mut self) {
+        let Some(form) = &self.contact_form else {
+            return;
+        };
+        let emails = match address::parse_address_list(&form.emails) {
+            Ok(addresses) if addresses.is_empty() => {
+                self.status = Some("A contact needs at least one address".to_string());
+                return;
+            }
+            Ok(addresses) => addresses.into_iter().map(|a| a.email).collect(),
+            Err(e) => {
+                self.status = Some(format!("Not saved: {}", e));
+                return;
+            }
+        };
+        let name = form.name.trim();
+        let contact = Contact {
+            name: (!name.is_empty()).then(|| name.to_string()),
+            emails,
+            notes: form.notes.trim_end().to_string(),
+        };
+        let index = form.index.unwrap_or(usize::MAX);
+        self.contact_selected = self.contacts.update(index, contact);
+        self.contact_form = None;
+    }
+
+    pub fn contact_request_delete(&mut self) {
+        self.contact_delete_pending = !self.contacts.contacts.is_empty();
+    }
+
+    pub fn contact_confirm_delete(&mut self, confirmed: bool) {
+        if confirmed {
+            self.contacts.remove(self.contact_selected);
+            self.contact_selected = self
+                .contact_selected
+                .min(self.contacts.contacts.len().saturating_sub(1));
+        }
+        self.contact_delete_pending = false;
+    }
+
+    /// Starts a new message to the selected contact.
+    pub fn contact_compose(&mut self) {
+        let Some(contact) = self.contacts.contacts.get(self.contact_selected) else {
+            return;
+        };
+        let to = contact
+            .emails
+            .first()
+            .map(|email| format!("{}, ", contact.address(email)))
+            .unwrap_or_default();
+        self.start_compose();
+        self.compose_to = to;
+        self.compose_field = ComposeField::Subject;
+        self.compose_reset_undo();
+    }
+
     pub fn show_delete_confirm(&mut self) {
         if self.selected < self.inbox.len() {
             self.email_to_delete = Some(self.inbox[self.selected].clone());
@@ -166,43 +894,131 @@ impl App {
     pub fn start_compose(&mut self) {
         self.mode = Mode::Compose;
         self.compose_to.clear();
+        self.compose_cc.clear();
+        self.compose_bcc.clear();
+        self.compose_reply_to.clear();
+        self.compose_show_extra = false;
         self.compose_subject.clear();
-        self.compose_body.clear();
         self.compose_field = ComposeField::To;
         self.compose_mode = ComposeMode::Normal;
         self.compose_cursor = 0;
-        self.compose_line = 0;
-        self.compose_col = 0;
+        self.compose_scroll = 0;
         self.compose_visual_start = None;
+        self.compose_completions.clear();
+        self.compose_pending.clear();
+        self.compose_register = None;
+        self.compose_search_input = None;
+        self.compose_suggestions = None;
+        self.compose_identity = self.identity;
+        self.compose_markdown = self.markdown;
+        self.compose_sign = self.pgp_sign;
+        self.compose_encrypt = self.pgp_encrypt;
+        // OpenPGP wins when both are on by default
+        self.compose_smime = self.smime_sign && !(self.pgp_sign || self.pgp_encrypt);
+        self.compose_body = self.compose_signature();
+        self.compose_reset_undo();
     }
 
     pub fn compose_next_field(&mut self) {
-        if matches!(self.compose_mode, ComposeMode::Normal | ComposeMode::Visual) {
-            self.compose_field = match self.compose_field {
-                ComposeField::To => ComposeField::Subject,
-                ComposeField::Subject => ComposeField::Body,
-                ComposeField::Body => ComposeField::To,
-            };
+        if self.compose_mode != ComposeMode::Insert {
+            loop {
+                self.compose_field = match self.compose_field {
+                    ComposeField::To => ComposeField::Cc,
+                    ComposeField::Cc => ComposeField::Bcc,
+                    ComposeField::Bcc => ComposeField::ReplyTo,
+                    ComposeField::ReplyTo => ComposeField::Subject,
+                    ComposeField::Subject => ComposeField::Body,
+                    ComposeField::Body => ComposeField::To,
+                };
+                if self.compose_show_extra || !self.compose_field.is_extra() {
+                    break;
+                }
+            }
             self.compose_cursor = 0;
             self.clamp_cursor();
         }
     }
 
     pub fn compose_prev_field(&mut self) {
-        if matches!(self.compose_mode, ComposeMode::Normal | ComposeMode::Visual) {
-            self.compose_field = match self.compose_field {
-                ComposeField::To => ComposeField::Body,
-                ComposeField::Subject => ComposeField::To,
-                ComposeField::Body => ComposeField::Subject,
-            };
+        if self.compose_mode != ComposeMode::Insert {
+            loop {
+                self.compose_field = match self.compose_field {
+                    ComposeField::To => ComposeField::Body,
+                    ComposeField::Cc => ComposeField::To,
+                    ComposeField::Bcc => ComposeField::Cc,
+                    ComposeField::ReplyTo => ComposeField::Bcc,
+                    ComposeField::Subject => ComposeField::ReplyTo,
+                    ComposeField::Body => ComposeField::Subject,
+                };
+                if self.compose_show_extra || !self.compose_field.is_extra() {
+                    break;
+                }
+            }
             self.compose_cursor = 0;
             self.clamp_cursor();
         }
     }
 
+    /// Shows or hides the Cc, Bcc and Reply-To fields. They stay visible
+    /// while any of them has content so nothing is sent unseen.
+    pub fn compose_toggle_extra(&mut self) {
+        let has_extra = !(self.compose_cc.is_empty()
+            && self.compose_bcc.is_empty()
+            && self.compose_reply_to.is_empty());
+        self.compose_show_extra = !self.compose_show_extra || has_extra;
+        if !self.compose_show_extra && self.compose_field.is_extra() {
+            self.compose_field = ComposeField::To;
+            self.compose_cursor = 0;
+        }
+    }
+
+    pub fn compose_toggle_markdown(&mut self) {
+        self.compose_markdown = !self.compose_markdown;
+        self.status = Some(if self.compose_markdown {
+            "Markdown on: sending plain text and HTML".to_string()
+        } else {
+            "Markdown off: sending plain text only".to_string()
+        });
+    }
+
+    /// How the message will be protected, for the compose box and review.
+    pub fn compose_security(&self) -> &'static str {
+        if self.compose_smime {
+            return "S/MIME: signed";
+        }
+        match (self.compose_sign, self.compose_encrypt) {
+            (false, false) => "not signed or encrypted",
+            (true, false) => "OpenPGP: signed",
+            (false, true) => "OpenPGP: encrypted",
+            (true, true) => "OpenPGP: signed and encrypted",
+        }
+    }
+
+    /// Whether the message will be signed or encrypted at all.
+    pub fn compose_protected(&self) -> bool {
+        self.compose_sign || self.compose_encrypt || self.compose_smime
+    }
+
+    /// Shows the rendered HTML part. The preview scrolls like the message
+    /// viewer and shares its scroll state.
+    pub fn compose_preview(&mut self) {
+        if !self.compose_markdown {
+            self.status = Some("Nothing to preview: Markdown is off (M to turn it on)".to_string());
+            return;
+        }
+        self.compose_mode = ComposeMode::Preview;
+        self.view_offset = 0;
+    }
+
+    pub fn compose_close_preview(&mut self) {
+        self.compose_mode = ComposeMode::Normal;
+        self.view_offset = 0;
+    }
+
     pub fn compose_enter_insert(&mut self) {
         self.compose_mode = ComposeMode::Insert;
         self.compose_visual_start = None;
+        self.compose_insert_typed.clear();
     }
 
     pub fn compose_enter_visual(&mut self) {
@@ -212,6 +1028,28 @@ impl App {
 
     pub fn compose_exit_insert(&mut self) {
         self.compose_mode = ComposeMode::Normal;
+        // Like vim, leaving insert mode steps back onto the last character
+        let text = self.get_current_field_text();
+        if self.compose_cursor > vim::line_start(text, self.compose_cursor.min(text.len())) {
+            self.compose_cursor = vim::prev_char(text, self.compose_cursor);
+            self.clamp_cursor();
+        }
+        self.compose_completions.clear();
+        self.compose_last_insert = std::mem::take(&mut self.compose_insert_typed);
+    }
+
+    pub fn compose_enter_visual_line(&mut self) {
+        self.compose_mode = ComposeMode::VisualLine;
+        self.compose_visual_start = Some(self.compose_cursor);
+    }
+
+    /// Switches between character-wise and line-wise selection, keeping
+    /// the anchor.
+    pub fn compose_toggle_visual_line(&mut self) {
+        self.compose_mode = match self.compose_mode {
+            ComposeMode::VisualLine => ComposeMode::Visual,
+            _ => ComposeMode::VisualLine,
+        };
     }
 
     pub fn compose_exit_visual(&mut self) {
@@ -222,6 +1060,9 @@ impl App {
     fn get_current_field_text(&self) -> &String {
         match self.compose_field {
             ComposeField::To => &self.compose_to,
+            ComposeField::Cc => &self.compose_cc,
+            ComposeField::Bcc => &self.compose_bcc,
+            ComposeField::ReplyTo => &self.compose_reply_to,
             ComposeField::Subject => &self.compose_subject,
             ComposeField::Body => &self.compose_body,
         }
@@ -230,82 +1071,28 @@ impl App {
     fn get_current_field_text_mut(&mut self) -> &mut String {
         match self.compose_field {
             ComposeField::To => &mut self.compose_to,
+            ComposeField::Cc => &mut self.compose_cc,
+            ComposeField::Bcc => &mut self.compose_bcc,
+            ComposeField::ReplyTo => &mut self.compose_reply_to,
             ComposeField::Subject => &mut self.compose_subject,
             ComposeField::Body => &mut self.compose_body,
         }
     }
 
+    /// Keeps the cursor inside the current field and on a UTF-8 boundary.
     fn clamp_cursor(&mut self) {
         let text = self.get_current_field_text();
-        let len = text.len();
-        if self.compose_cursor > len {
-            self.compose_cursor = len;
-        }
-        // Ensure cursor is at a valid UTF-8 boundary
-        let text_copy = self.get_current_field_text().clone();
-        while self.compose_cursor > 0 && !text_copy.is_char_boundary(self.compose_cursor) {
-            self.compose_cursor -= 1;
-        }
-
-        // Update line and col from cursor position for body field
-        if matches!(self.compose_field, ComposeField::Body) {
-            self.update_line_col_from_cursor();
-        }
-    }
-
-    fn update_line_col_from_cursor(&mut self) {
-        let text = &self.compose_body;
-        let mut pos = 0;
-        self.compose_line = 0;
-        self.compose_col = 0;
-
-        for line in text.lines() {
-            let line_len = line.len();
-            if pos + line_len >= self.compose_cursor {
-                self.compose_col = self.compose_cursor - pos;
-                break;
-            }
-            pos += line_len + 1; // +1 for newline
-            self.compose_line += 1;
-        }
-    }
-
-    fn update_cursor_from_line_col(&mut self) {
-        if !matches!(self.compose_field, ComposeField::Body) {
-            return;
-        }
-
-        let lines: Vec<&str> = self.compose_body.lines().collect();
-        if lines.is_empty() {
-            self.compose_cursor = 0;
-            return;
-        }
-
-        // Clamp line
-        if self.compose_line >= lines.len() {
-            self.compose_line = lines.len().saturating_sub(1);
-        }
-
-        // Calculate cursor position
-        let mut pos = 0;
-        for i in 0..self.compose_line {
-            pos += lines[i].len() + 1; // +1 for newline
-        }
-
-        // Clamp column
-        let line = lines[self.compose_line];
-        if self.compose_col > line.len() {
-            self.compose_col = line.len();
+        let mut cursor = self.compose_cursor.min(text.len());
+        while !text.is_char_boundary(cursor) {
+            cursor -= 1;
         }
-
-        self.compose_cursor = pos + self.compose_col;
+        self.compose_cursor = cursor;
     }
 
     pub fn compose_move_up(&mut self) {
         if matches!(self.compose_field, ComposeField::Body) {
-            if self.compose_line > 0 {
-                self.compose_line -= 1;
-                self.update_cursor_from_line_col();
+            if vim::line_start(&self.compose_body, self.compose_cursor) > 0 {
+                self.compose_cursor = vim::move_lines(&self.compose_body, self.compose_cursor, -1);
             } else {
                 // At top of body, move to previous field
                 self.compose_prev_field();
@@ -315,149 +1102,370 @@ impl App {
 
     pub fn compose_move_down(&mut self) {
         if matches!(self.compose_field, ComposeField::Body) {
-            let lines: Vec<&str> = self.compose_body.lines().collect();
-            if self.compose_line + 1 < lines.len() {
-                self.compose_line += 1;
-                self.update_cursor_from_line_col();
-            }
+            self.compose_cursor = vim::move_lines(&self.compose_body, self.compose_cursor, 1);
         }
     }
 
+    /// Moves one character left, onto the end of the previous line at a
+    /// line start.
     pub fn compose_move_left(&mut self) {
-        if matches!(self.compose_field, ComposeField::Body) {
-            if self.compose_col > 0 {
-                self.compose_col -= 1;
-                self.update_cursor_from_line_col();
-            } else if self.compose_line > 0 {
-                // Move to end of previous line
-                self.compose_line -= 1;
-                let lines: Vec<&str> = self.compose_body.lines().collect();
-                self.compose_col = lines.get(self.compose_line).map(|l| l.len()).unwrap_or(0);
-                self.update_cursor_from_line_col();
-            }
-        } else if self.compose_cursor > 0 {
-            self.compose_cursor -= 1;
-            self.clamp_cursor();
-        }
+        self.compose_cursor = vim::prev_char(self.get_current_field_text(), self.compose_cursor);
     }
 
     pub fn compose_move_right(&mut self) {
-        if matches!(self.compose_field, ComposeField::Body) {
-            let lines: Vec<&str> = self.compose_body.lines().collect();
-            if let Some(line) = lines.get(self.compose_line) {
-                if self.compose_col < line.len() {
-                    self.compose_col += 1;
-                    self.update_cursor_from_line_col();
-                } else if self.compose_line + 1 < lines.len() {
-                    // Move to start of next line
-                    self.compose_line += 1;
-                    self.compose_col = 0;
-                    self.update_cursor_from_line_col();
-                }
-            }
-        } else {
-            let text = self.get_current_field_text();
-            if self.compose_cursor < text.len() {
-                self.compose_cursor += 1;
-                self.clamp_cursor();
-            }
-        }
+        self.compose_cursor = vim::next_char(self.get_current_field_text(), self.compose_cursor);
     }
 
     pub fn compose_move_word_forward(&mut self) {
-        let text = self.get_current_field_text();
-        let chars: Vec<char> = text.chars().collect();
-        let mut char_pos = text[..self.compose_cursor].chars().count();
-        while char_pos < chars.len() && !chars[char_pos].is_whitespace() {
-            char_pos += 1;
-        }
-        while char_pos < chars.len() && chars[char_pos].is_whitespace() {
-            char_pos += 1;
-        }
-        // Convert char position back to byte position
-        self.compose_cursor = text
-            .char_indices()
-            .nth(char_pos)
-            .map(|(i, _)| i)
-            .unwrap_or(text.len());
+        self.compose_cursor = vim::word_forward(self.get_current_field_text(), self.compose_cursor);
+        self.clamp_cursor();
     }
 
     pub fn compose_move_word_backward(&mut self) {
-        if self.compose_cursor == 0 {
-            return;
-        }
-        let text = self.get_current_field_text();
-        let chars: Vec<char> = text.chars().collect();
-        let mut char_pos = text[..self.compose_cursor].chars().count();
-        if char_pos > 0 {
-            char_pos -= 1;
-        }
-        while char_pos > 0 && chars[char_pos].is_whitespace() {
-            char_pos -= 1;
-        }
-        while char_pos > 0 && !chars[char_pos - 1].is_whitespace() {
-            char_pos -= 1;
-        }
-        // Convert char position back to byte position
-        self.compose_cursor = text
-            .char_indices()
-            .nth(char_pos)
-            .map(|(i, _)| i)
-            .unwrap_or(0);
+        self.compose_cursor =
+            vim::word_backward(self.get_current_field_text(), self.compose_cursor);
+        self.clamp_cursor();
     }
 
     pub fn compose_move_line_start(&mut self) {
-        if matches!(self.compose_field, ComposeField::Body) {
-            self.compose_col = 0;
-            self.update_cursor_from_line_col();
-        } else {
-            self.compose_cursor = 0;
-        }
+        self.compose_cursor = vim::line_start(self.get_current_field_text(), self.compose_cursor);
     }
 
     pub fn compose_move_line_end(&mut self) {
-        if matches!(self.compose_field, ComposeField::Body) {
-            let lines: Vec<&str> = self.compose_body.lines().collect();
-            if let Some(line) = lines.get(self.compose_line) {
-                self.compose_col = line.len();
-                self.update_cursor_from_line_col();
-            }
-        } else {
-            let text = self.get_current_field_text();
-            self.compose_cursor = text.len();
-        }
+        self.compose_cursor = vim::line_end(self.get_current_field_text(), self.compose_cursor);
     }
 
-    pub fn compose_delete_char(&mut self) {
-        let cursor = self.compose_cursor;
-        let text = self.get_current_field_text_mut();
-        if cursor < text.len() {
-            text.remove(cursor);
+    /// Feeds a key to the normal-mode command parser, running the command
+    /// once it is complete.
+    pub fn compose_normal_key(&mut self, ch: char) {
+        self.compose_pending.push(ch);
+        match vim::parse(&self.compose_pending) {
+            Parsed::Pending => {}
+            Parsed::Invalid => self.compose_pending.clear(),
+            Parsed::Done(command) => {
+                self.compose_pending.clear();
+                self.compose_execute(command);
+            }
         }
     }
 
-    pub fn compose_delete_line(&mut self) {
-        let text = self.get_current_field_text_mut();
-        text.clear();
-        self.compose_cursor = 0;
-    }
-
-    pub fn compose_indent_right(&mut self) {
-        if matches!(self.compose_field, ComposeField::Body) {
-            let text = &mut self.compose_body;
-            text.insert_str(0, "  ");
-            self.compose_cursor += 2;
+    fn compose_execute(&mut self, command: Command) {
+        if let Some(name) = command.register {
+            self.compose_select_register(name);
+        }
+        if command.action.is_change() {
+            self.compose_last_change = Some(command);
+            self.compose_last_insert.clear();
+        }
+        let count = command.count();
+        match command.action {
+            Action::Move(motion) => self.compose_motion(motion, command.count),
+            Action::Operate(operator, target) => {
+                self.compose_operate(operator, target, command.count)
+            }
+            Action::DeleteChar => {
+                let text = self.get_current_field_text();
+                let start = self.compose_cursor.min(text.len());
+                let end = (0..count)
+                    .fold(start, |pos, _| vim::next_char(text, pos))
+                    .min(vim::line_end(text, start));
+                self.compose_delete_range(start..end, false);
+            }
+            Action::DeleteCharBefore => {
+                let text = self.get_current_field_text();
+                let end = self.compose_cursor.min(text.len());
+                let start = (0..count)
+                    .fold(end, |pos, _| vim::prev_char(text, pos))
+                    .max(vim::line_start(text, end));
+                self.compose_delete_range(start..end, false);
+            }
+            Action::Put { after } => {
+                let register = self.compose_register;
+                for _ in 0..count {
+                    self.compose_register = register;
+                    self.compose_put(after);
+                }
+            }
+            Action::Insert(kind) => match kind {
+                vim::Insert::Before => self.compose_enter_insert(),
+                vim::Insert::After => self.compose_append(),
+                vim::Insert::LineStart => self.compose_insert_start(),
+                vim::Insert::LineEnd => self.compose_append_end(),
+                vim::Insert::LineBelow => self.compose_open_below(),
+                vim::Insert::LineAbove => self.compose_open_above(),
+            },
+            Action::Indent { right } => self.compose_indent(right, count),
+            Action::Undo => {
+                for _ in 0..count {
+                    self.compose_undo();
+                }
+            }
+            Action::Chronological { later } => {
+                for _ in 0..count {
+                    self.compose_chronological(later);
+                }
+            }
+            Action::Repeat => self.compose_repeat(command.count),
+            Action::Visual => self.compose_enter_visual(),
+            Action::VisualLine => self.compose_enter_visual_line(),
+            Action::Search { forward } => self.compose_start_search(forward),
+            Action::SearchNext { reverse } => {
+                for _ in 0..count {
+                    self.compose_search_next(reverse);
+                }
+            }
+            Action::ToggleExtra => self.compose_toggle_extra(),
+            Action::ToggleMarkdown => self.compose_toggle_markdown(),
+            Action::Preview => self.compose_preview(),
+            // A message uses OpenPGP or S/MIME, so turning one on turns
+            // the other off
+            Action::ToggleSign => {
+                self.compose_sign = !self.compose_sign;
+                self.compose_smime = false;
+                self.status = Some(self.compose_security().to_string());
+            }
+            Action::ToggleEncrypt => {
+                self.compose_encrypt = !self.compose_encrypt;
+                self.compose_smime = false;
+                self.status = Some(self.compose_security().to_string());
+            }
+            Action::ToggleSmime => {
+                self.compose_smime = !self.compose_smime;
+                self.compose_sign = false;
+                self.compose_encrypt = false;
+                self.status = Some(self.compose_security().to_string());
+            }
+            Action::Editor => self.compose_open_editor(),
+            Action::Suggest => self.compose_suggest(command.count),
+            Action::Send => self.compose_review(),
+            Action::Quit => self.close_overlay(),
         }
     }
 
-    pub fn compose_indent_left(&mut self) {
-        if matches!(self.compose_field, ComposeField::Body) {
-            let text = &mut self.compose_body;
-            if text.starts_with("  ") {
-                text.drain(0..2);
-                self.compose_cursor = self.compose_cursor.saturating_sub(2);
+    /// `.`: runs the last change again, retyping any text it inserted. A
+    /// new count replaces the original one.
+    fn compose_repeat(&mut self, count: Option<usize>) {
+        let Some(mut command) = self.compose_last_change else {
+            return;
+        };
+        let inserted = self.compose_last_insert.clone();
+        if count.is_some() {
+            command.count = count;
+        }
+        self.compose_execute(command);
+        if self.compose_mode == ComposeMode::Insert {
+            for ch in inserted.chars() {
+                self.compose_push_char(ch);
+            }
+            self.compose_exit_insert();
+        }
+        self.compose_last_insert = inserted;
+    }
+
+    fn compose_motion(&mut self, motion: Motion, count: Option<usize>) {
+        let n = count.unwrap_or(1);
+        let in_body = matches!(self.compose_field, ComposeField::Body);
+        match motion {
+            // j/k move between header fields, and out of the body at its top
+            Motion::Down if !in_body => (0..n).for_each(|_| self.compose_next_field()),
+            Motion::Up if !in_body => (0..n).for_each(|_| self.compose_prev_field()),
+            Motion::NextMisspelled | Motion::PrevMisspelled => {
+                let forward = motion == Motion::NextMisspelled;
+                match self.misspelled_target(forward, n) {
+                    Some((pos, wrapped)) => {
+                        self.compose_cursor = pos;
+                        if wrapped {
+                            self.status = Some(if forward {
+                                "Search hit BOTTOM, continuing at TOP".to_string()
+                            } else {
+                                "Search hit TOP, continuing at BOTTOM".to_string()
+                            });
+                        }
+                    }
+                    None => self.status = Some("No misspelled words".to_string()),
+                }
+            }
+            Motion::DisplayDown if !in_body => (0..n).for_each(|_| self.compose_next_field()),
+            Motion::DisplayUp if !in_body => (0..n).for_each(|_| self.compose_prev_field()),
+            Motion::Up if vim::line_start(&self.compose_body, self.compose_cursor) == 0 => {
+                self.compose_prev_field()
+            }
+            Motion::DisplayUp
+                if textarea::move_rows(
+                    &self.compose_body,
+                    self.compose_body_width,
+                    self.compose_cursor,
+                    -1,
+                ) == self.compose_cursor =>
+            {
+                self.compose_prev_field()
+            }
+            _ => {
+                self.compose_cursor = self.compose_motion_target(motion, count);
+                self.clamp_cursor();
+            }
+        }
+    }
+
+    /// Where a motion repeated `count` times lands in the current field.
+    fn compose_motion_target(&self, motion: Motion, count: Option<usize>) -> usize {
+        let text = self.get_current_field_text();
+        let pos = self.compose_cursor.min(text.len());
+        let n = count.unwrap_or(1);
+        let repeat = |step: fn(&str, usize) -> usize| (0..n).fold(pos, |p, _| step(text, p));
+        match motion {
+            Motion::Left => repeat(vim::prev_char).max(vim::line_start(text, pos)),
+            Motion::Right => repeat(vim::next_char).min(vim::line_end(text, pos)),
+            Motion::Up => vim::move_lines(text, pos, -(n as isize)),
+            Motion::Down => vim::move_lines(text, pos, n as isize),
+            Motion::NextMisspelled => self.misspelled_target(true, n).map_or(pos, |(p, _)| p),
+            Motion::PrevMisspelled => self.misspelled_target(false, n).map_or(pos, |(p, _)| p),
+            Motion::DisplayUp => {
+                textarea::move_rows(text, self.compose_body_width, pos, -(n as isize))
+            }
+            Motion::DisplayDown => {
+                textarea::move_rows(text, self.compose_body_width, pos, n as isize)
+            }
+            Motion::WordForward => repeat(vim::word_forward),
+            Motion::WordBackward => repeat(vim::word_backward),
+            Motion::WordEnd => repeat(vim::word_end),
+            Motion::LineStart => vim::line_start(text, pos),
+            Motion::LineEnd => {
+                let line = vim::line_start(text, pos);
+                vim::line_end(text, vim::move_lines(text, line, n as isize - 1))
             }
+            Motion::FirstLine => vim::nth_line_start(text, count.unwrap_or(1) - 1),
+            Motion::LastLine => {
+                let last = vim::line_count(text);
+                vim::nth_line_start(text, count.unwrap_or(last) - 1)
+            }
+        }
+    }
+
+    /// Applies an operator to the text a motion, text object or line count
+    /// covers.
+    fn compose_operate(&mut self, operator: Operator, target: Target, count: Option<usize>) {
+        let text = self.get_current_field_text();
+        let cursor = self.compose_cursor.min(text.len());
+        let (range, linewise) = match target {
+            Target::Line => {
+                let start = vim::line_start(text, cursor);
+                let last = vim::move_lines(text, start, count.unwrap_or(1) as isize - 1);
+                (start..vim::line_end(text, last), true)
+            }
+            Target::Object(object) => match vim::text_object(text, cursor, object) {
+                Some(found) => found,
+                None => return,
+            },
+            Target::Motion(motion) => {
+                // `cw` on a word changes to its end, like `ce`
+                let on_word = text[cursor..].starts_with(|c: char| !c.is_whitespace());
+                let motion =
+                    if operator == Operator::Change && motion == Motion::WordForward && on_word {
+                        Motion::WordEnd
+                    } else {
+                        motion
+                    };
+                let dest = self.compose_motion_target(motion, count);
+                let (from, to) = (cursor.min(dest), cursor.max(dest));
+                if motion.is_linewise() {
+                    (vim::line_start(text, from)..vim::line_end(text, to), true)
+                } else if motion.is_inclusive() {
+                    (from..vim::next_char(text, to), false)
+                } else if motion == Motion::WordForward {
+                    // A word motion does not carry the operator onto the next line
+                    (from..to.min(vim::line_end(text, from)).max(from), false)
+                } else {
+                    (from..to, false)
+                }
+            }
+        };
+
+        match operator {
+            Operator::Yank => {
+                let yanked = text[range.clone()].to_string();
+                self.compose_store_register(yanked, linewise, true);
+                if !linewise {
+                    self.compose_cursor = range.start;
+                }
+            }
+            Operator::Delete => self.compose_delete_range(range, linewise),
+            Operator::Change => {
+                // Changing lines leaves an empty line to type on
+                let removed: String = self
+                    .get_current_field_text_mut()
+                    .drain(range.clone())
+                    .collect();
+                self.compose_store_register(removed, linewise, false);
+                self.compose_cursor = range.start;
+                self.compose_enter_insert();
+            }
+        }
+        self.clamp_cursor();
+    }
+
+    /// Deletes a range into the register. Line-wise deletes take a line
+    /// break with them so no empty line is left behind.
+    fn compose_delete_range(&mut self, range: std::ops::Range<usize>, linewise: bool) {
+        if range.is_empty() && !linewise {
+            return;
+        }
+        let text = self.get_current_field_text_mut();
+        let removed = text[range.clone()].to_string();
+        let mut span = range.clone();
+        if linewise {
+            if span.end < text.len() {
+                span.end += 1;
+            } else if span.start > 0 {
+                span.start -= 1;
+            }
+        }
+        text.drain(span.clone());
+        self.compose_cursor = if linewise {
+            vim::line_start(text, span.start.min(text.len()))
+        } else {
+            span.start
+        };
+        self.compose_store_register(removed, linewise, false);
+        self.clamp_cursor();
+    }
+
+    /// Indents (or unindents) `count` lines of the body starting at the
+    /// cursor's line, by two spaces.
+    pub fn compose_indent(&mut self, right: bool, count: usize) {
+        if !matches!(self.compose_field, ComposeField::Body) {
+            return;
         }
+        let start = vim::line_start(&self.compose_body, self.compose_cursor);
+        let last = vim::move_lines(&self.compose_body, start, count as isize - 1);
+        self.compose_indent_lines(start..vim::line_end(&self.compose_body, last), right);
+    }
+
+    /// Indents every body line touching `range`, leaving the cursor on the
+    /// first non-blank of the first line like vim.
+    fn compose_indent_lines(&mut self, range: std::ops::Range<usize>, right: bool) {
+        let start = vim::line_start(&self.compose_body, range.start);
+        let end = vim::line_end(&self.compose_body, range.end);
+        let indented: Vec<String> = self.compose_body[start..end]
+            .split('\n')
+            .map(|line| {
+                if right {
+                    if line.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  {}", line)
+                    }
+                } else {
+                    let spaces = line.len() - line.trim_start_matches(' ').len();
+                    line[spaces.min(2)..].to_string()
+                }
+            })
+            .collect();
+        self.compose_body
+            .replace_range(start..end, &indented.join("\n"));
+        let first = &self.compose_body[start..];
+        self.compose_cursor = start + (first.len() - first.trim_start_matches(' ').len());
+        self.clamp_cursor();
     }
 
     pub fn compose_append(&mut self) {
@@ -477,21 +1485,21 @@ impl App {
 
     pub fn compose_open_below(&mut self) {
         if matches!(self.compose_field, ComposeField::Body) {
-            self.compose_move_line_end();
-            self.compose_body.push('\n');
-            self.compose_cursor = self.compose_body.len();
-            self.compose_line += 1;
-            self.compose_col = 0;
+            let end = vim::line_end(&self.compose_body, self.compose_cursor);
+            self.compose_body.insert(end, '\n');
+            self.compose_cursor = end + 1;
+            self.clamp_cursor();
             self.compose_enter_insert();
         }
     }
 
     pub fn compose_open_above(&mut self) {
         if matches!(self.compose_field, ComposeField::Body) {
-            self.compose_move_line_start();
-            self.compose_body.insert_str(self.compose_cursor, "\n");
+            let start = vim::line_start(&self.compose_body, self.compose_cursor);
+            self.compose_body.insert(start, '\n');
             // Stay on the new line above
-            self.compose_col = 0;
+            self.compose_cursor = start;
+            self.clamp_cursor();
             self.compose_enter_insert();
         }
     }
@@ -502,19 +1510,189 @@ impl App {
         }
     }
 
+    /// Byte range covered by the visual selection.
+    fn compose_visual_range(&self) -> Option<(usize, usize)> {
+        let start = self.compose_visual_start?;
+        let cursor = self.compose_cursor;
+        let len = self.get_current_field_text().len();
+        let (begin, end) = if start < cursor {
+            (start, cursor)
+        } else {
+            (cursor, start)
+        };
+        Some((begin.min(len), end.min(len)))
+    }
+
     pub fn compose_delete_visual(&mut self) {
-        if let Some(start) = self.compose_visual_start {
+        if let Some((begin, end)) = self.compose_visual_range() {
+            let removed: String = self
+                .get_current_field_text_mut()
+                .drain(begin..end)
+                .collect();
+            self.compose_store_register(removed, false, false);
+            self.compose_cursor = begin;
+        }
+        self.compose_exit_visual();
+    }
+
+    pub fn compose_yank_visual(&mut self) {
+        if let Some((begin, end)) = self.compose_visual_range() {
+            let text = self.get_current_field_text()[begin..end].to_string();
+            self.compose_store_register(text, false, true);
+            self.compose_cursor = begin;
+        }
+        self.compose_exit_visual();
+    }
+
+    /// Byte range of the whole lines the line-wise selection covers,
+    /// without the final newline.
+    fn compose_visual_line_range(&self) -> Option<std::ops::Range<usize>> {
+        let (begin, end) = self.compose_visual_range()?;
+        let text = self.get_current_field_text();
+        Some(vim::line_start(text, begin)..vim::line_end(text, end))
+    }
+
+    /// Byte range of the visual selection in the current field, for
+    /// highlighting.
+    pub fn compose_selection(&self) -> Option<std::ops::Range<usize>> {
+        match self.compose_mode {
+            ComposeMode::Visual => self.compose_visual_range().map(|(begin, end)| begin..end),
+            ComposeMode::VisualLine => self.compose_visual_line_range(),
+            _ => None,
+        }
+    }
+
+    pub fn compose_visual_line_move(&mut self, delta: isize) {
+        let text = self.get_current_field_text();
+        self.compose_cursor = vim::move_lines(text, self.compose_cursor.min(text.len()), delta);
+        self.clamp_cursor();
+    }
+
+    pub fn compose_visual_line_delete(&mut self) {
+        if let Some(range) = self.compose_visual_line_range() {
+            self.compose_delete_range(range, true);
+        }
+        self.compose_exit_visual();
+    }
+
+    pub fn compose_visual_line_yank(&mut self) {
+        if let Some(range) = self.compose_visual_line_range() {
+            let text = self.get_current_field_text()[range.clone()].to_string();
+            self.compose_store_register(text, true, true);
+            self.compose_cursor = range.start;
+            self.clamp_cursor();
+        }
+        self.compose_exit_visual();
+    }
+
+    pub fn compose_visual_line_indent(&mut self, right: bool) {
+        if let Some(range) = self.compose_visual_line_range()
+            && matches!(self.compose_field, ComposeField::Body)
+        {
+            self.compose_indent_lines(range, right);
+        }
+        self.compose_exit_visual();
+    }
+
+    /// Byte range of the line the cursor is on, without its newline. Header
+    /// fields are a single line.
+    fn compose_line_range(&self) -> (usize, usize) {
+        let text = self.get_current_field_text();
+        let cursor = self.compose_cursor.min(text.len());
+        let start = text[..cursor].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let end = text[cursor..]
+            .find('\n')
+            .map(|i| cursor + i)
+            .unwrap_or(text.len());
+        (start, end)
+    }
+
+    /// Chooses the register for the next yank, delete or put (`"x`).
+    pub fn compose_select_register(&mut self, name: char) {
+        if name.is_ascii_alphanumeric() || "\"+*".contains(name) {
+            self.compose_register = Some(name);
+        } else {
+            self.status = Some(format!("Invalid register: {}", name));
+        }
+    }
+
+    /// Stores yanked or deleted text like vim: in the chosen register (an
+    /// uppercase name appends to it) and always in the unnamed one. Yanks
+    /// also go to register 0, and `+`/`*` copy to the system clipboard.
+    fn compose_store_register(&mut self, text: String, linewise: bool, yank: bool) {
+        let name = self.compose_register.take().unwrap_or('"');
+        let register = Register { text, linewise };
+        let stored = if name.is_ascii_uppercase() {
+            let entry = self
+                .compose_registers
+                .entry(name.to_ascii_lowercase())
+                .or_default();
+            if entry.linewise || linewise {
+                if !entry.text.is_empty() {
+                    entry.text.push('\n');
+                }
+                entry.linewise = true;
+            }
+            entry.text.push_str(&register.text);
+            entry.clone()
+        } else {
+            self.compose_registers.insert(name, register.clone());
+            register
+        };
+        if yank {
+            self.compose_registers.insert('0', stored.clone());
+        }
+        if "+*".contains(name) || self.compose_clipboard {
+            self.clipboard_copy = Some(stored.text.clone());
+        }
+        self.compose_registers.insert('"', stored);
+    }
+
+    /// Puts the chosen (or unnamed) register after or before the cursor;
+    /// line-wise text goes below or above the current line.
+    pub fn compose_put(&mut self, after: bool) {
+        let name = self.compose_register.take().unwrap_or('"');
+        let Some(register) = self
+            .compose_registers
+            .get(&name.to_ascii_lowercase())
+            .cloned()
+        else {
+            self.status = Some(format!("Register {} is empty", name));
+            return;
+        };
+
+        let is_body = matches!(self.compose_field, ComposeField::Body);
+        let mut text = register.text;
+        if !is_body {
+            // Header fields are a single line
+            text = text.replace('\n', " ");
+        }
+
+        if register.linewise && is_body {
+            let (start, end) = self.compose_line_range();
+            let body = &mut self.compose_body;
+            if after {
+                body.insert_str(end, &format!("\n{}", text));
+                self.compose_cursor = end + 1;
+            } else {
+                body.insert_str(start, &format!("{}\n", text));
+                self.compose_cursor = start;
+            }
+        } else {
             let cursor = self.compose_cursor;
-            let (begin, end) = if start < cursor {
-                (start, cursor)
+            let field = self.get_current_field_text_mut();
+            // The cursor may rest just past the end of a line
+            let at = if after && cursor < vim::line_end(field, cursor.min(field.len())) {
+                vim::next_char(field, cursor)
             } else {
-                (cursor, start)
+                cursor.min(field.len())
             };
-            let text = self.get_current_field_text_mut();
-            text.drain(begin..end.min(text.len()));
-            self.compose_cursor = begin;
+            field.insert_str(at, &text);
+            // The cursor ends on the last character put
+            let last = text.chars().next_back().map_or(0, char::len_utf8);
+            self.compose_cursor = at + text.len() - last;
         }
-        self.compose_exit_visual();
+        self.clamp_cursor();
     }
 
     pub fn compose_push_char(&mut self, ch: char) {
@@ -523,39 +1701,668 @@ impl App {
         if cursor <= text.len() && text.is_char_boundary(cursor) {
             text.insert(cursor, ch);
             self.compose_cursor += ch.len_utf8();
-            if matches!(self.compose_field, ComposeField::Body) {
-                if ch == '\n' {
-                    self.compose_line += 1;
-                    self.compose_col = 0;
-                } else {
-                    self.compose_col += 1;
-                }
+            if self.compose_mode == ComposeMode::Insert {
+                self.compose_insert_typed.push(ch);
             }
+            self.clamp_cursor();
         }
     }
 
     pub fn compose_pop_char(&mut self) {
         if self.compose_cursor > 0 {
-            self.compose_cursor -= 1;
             let cursor = self.compose_cursor;
             let text = self.get_current_field_text_mut();
-            let removed_char = text.remove(cursor);
-            if matches!(self.compose_field, ComposeField::Body) {
-                if removed_char == '\n' {
-                    if self.compose_line > 0 {
-                        self.compose_line -= 1;
-                        let lines: Vec<&str> = self.compose_body.lines().collect();
-                        self.compose_col =
-                            lines.get(self.compose_line).map(|l| l.len()).unwrap_or(0);
+            let start = vim::prev_char(text, cursor.min(text.len()));
+            text.drain(start..cursor.min(text.len()));
+            self.compose_cursor = start;
+            if self.compose_mode == ComposeMode::Insert {
+                self.compose_insert_typed.pop();
+            }
+            self.clamp_cursor();
+        }
+    }
+
+    /// Byte offset where the address being typed starts: just after the
+    /// last comma before the cursor that is not inside quotes or angles.
+    fn recipient_token_start(&self) -> usize {
+        let text = self.get_current_field_text();
+        let mut start = 0;
+        let mut in_quote = false;
+        let mut in_angle = false;
+        for (idx, ch) in text[..self.compose_cursor.min(text.len())].char_indices() {
+            match ch {
+                '"' => in_quote = !in_quote,
+                '<' if !in_quote => in_angle = true,
+                '>' if !in_quote => in_angle = false,
+                ',' if !in_quote && !in_angle => start = idx + 1,
+                _ => {}
+            }
+        }
+        start
+    }
+
+    fn recipient_candidates(&self) -> Vec<Address> {
+        let text = self.get_current_field_text();
+        let start = self.recipient_token_start();
+        let query = text[start..self.compose_cursor.min(text.len())].trim();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.contacts.complete(query)
+    }
+
+    /// Completes the address before the cursor from the address book. A
+    /// single match is inserted straight away; several open a popup, in
+    /// which further presses cycle through the candidates.
+    pub fn compose_complete(&mut self) {
+        if !self.compose_field.is_address() {
+            return;
+        }
+        if !self.compose_completions.is_empty() {
+            self.compose_completion_next();
+            return;
+        }
+        let candidates = self.recipient_candidates();
+        match candidates.len() {
+            0 => self.status = Some("No matching contacts".to_string()),
+            1 => {
+                self.compose_completions = candidates;
+                self.compose_accept_completion();
+            }
+            _ => {
+                self.compose_completions = candidates;
+                self.compose_completion_selected = 0;
+            }
+        }
+    }
+
+    /// Narrows an open completion popup after the typed text changed.
+    pub fn compose_refresh_completion(&mut self) {
+        if self.compose_completions.is_empty() {
+            return;
+        }
+        self.compose_completions = self.recipient_candidates();
+        self.compose_completion_selected = 0;
+    }
+
+    pub fn compose_completion_next(&mut self) {
+        if !self.compose_completions.is_empty() {
+            self.compose_completion_selected =
+                (self.compose_completion_selected + 1) % self.compose_completions.len();
+        }
+    }
+
+    pub fn compose_completion_prev(&mut self) {
+        let len = self.compose_completions.len();
+        if len > 0 {
+            self.compose_completion_selected = (self.compose_completion_selected + len - 1) % len;
+        }
+    }
+
+    /// Replaces the address being typed with the selected candidate.
+    pub fn compose_accept_completion(&mut self) {
+        let Some(address) = self
+            .compose_completions
+            .get(self.compose_completion_selected)
+            .cloned()
+        else {
+            return;
+        };
+        let start = self.recipient_token_start();
+        let cursor = self.compose_cursor;
+        let replacement = if start == 0 {
+            format!("{}, ", address)
+        } else {
+            format!(" {}, ", address)
+        };
+        let text = self.get_current_field_text_mut();
+        let end = cursor.min(text.len());
+        text.replace_range(start..end, &replacement);
+        self.compose_cursor = start + replacement.len();
+        self.compose_completions.clear();
+    }
+
+    pub fn compose_cancel_completion(&mut self) {
+        self.compose_completions.clear();
+    }
+
+    /// The spelling dictionary for the identity being composed from.
+    pub fn compose_dictionary(&self) -> Option<&Dictionary> {
+        let name = self
+            .identities
+            .get(self.compose_identity)
+            .and_then(|identity| identity.dictionary.as_ref())
+            .or(self.dictionary.as_ref())?;
+        self.dictionaries.get(name)
+    }
+
+    /// Byte ranges of the misspelled words in the body.
+    pub fn compose_misspellings(&self) -> Vec<std::ops::Range<usize>> {
+        self.compose_dictionary()
+            .map(|dictionary| dictionary.misspellings(&self.compose_body))
+            .unwrap_or_default()
+    }
+
+    /// Start of the `count`th misspelled word after (or before) the cursor
+    /// in the body, and whether the search wrapped around.
+    fn misspelled_target(&self, forward: bool, count: usize) -> Option<(usize, bool)> {
+        if !matches!(self.compose_field, ComposeField::Body) {
+            return None;
+        }
+        let starts: Vec<usize> = self
+            .compose_misspellings()
+            .into_iter()
+            .map(|range| range.start)
+            .collect();
+        if starts.is_empty() {
+            return None;
+        }
+        let mut pos = self.compose_cursor;
+        let mut wrapped = false;
+        for _ in 0..count {
+            let next = if forward {
+                starts.iter().find(|&&start| start > pos)
+            } else {
+                starts.iter().rev().find(|&&start| start < pos)
+            };
+            pos = match next {
+                Some(&start) => start,
+                None => {
+                    wrapped = true;
+                    if forward {
+                        starts[0]
+                    } else {
+                        starts[starts.len() - 1]
                     }
-                } else if self.compose_col > 0 {
-                    self.compose_col -= 1;
+                }
+            };
+        }
+        Some((pos, wrapped))
+    }
+
+    /// `z=`: offers suggestions for the word under the cursor, or with a
+    /// count replaces it with that suggestion straight away.
+    fn compose_suggest(&mut self, count: Option<usize>) {
+        let Some(dictionary) = self.compose_dictionary() else {
+            self.status = Some("No spelling dictionary configured".to_string());
+            return;
+        };
+        let word = matches!(self.compose_field, ComposeField::Body)
+            .then(|| spell::word_at(&self.compose_body, self.compose_cursor))
+            .flatten();
+        let Some(range) = word else {
+            self.status = Some("No word under the cursor".to_string());
+            return;
+        };
+        let words = dictionary.suggest(&self.compose_body[range.clone()]);
+        if words.is_empty() {
+            self.status = Some(format!(
+                "No suggestions for \"{}\"",
+                &self.compose_body[range]
+            ));
+            return;
+        }
+        self.compose_suggestions = Some(Suggestions {
+            range,
+            words,
+            selected: 0,
+        });
+        if let Some(n) = count {
+            self.compose_accept_suggestion(Some(n - 1));
+        }
+    }
+
+    pub fn compose_suggestion_next(&mut self) {
+        if let Some(suggestions) = &mut self.compose_suggestions {
+            suggestions.selected = (suggestions.selected + 1) % suggestions.words.len();
+        }
+    }
+
+    pub fn compose_suggestion_prev(&mut self) {
+        if let Some(suggestions) = &mut self.compose_suggestions {
+            let len = suggestions.words.len();
+            suggestions.selected = (suggestions.selected + len - 1) % len;
+        }
+    }
+
+    /// Replaces the word with a suggestion: the numbered one if given,
+    /// otherwise the highlighted one.
+    pub fn compose_accept_suggestion(&mut self, index: Option<usize>) {
+        let Some(suggestions) = self.compose_suggestions.take() else {
+            return;
+        };
+        let Some(word) = suggestions.words.get(index.unwrap_or(suggestions.selected)) else {
+            self.status = Some(format!("Only {} suggestions", suggestions.words.len()));
+            return;
+        };
+        self.compose_body
+            .replace_range(suggestions.range.clone(), word);
+        self.compose_cursor = suggestions.range.start;
+        self.clamp_cursor();
+    }
+
+    pub fn compose_cancel_suggestions(&mut self) {
+        self.compose_suggestions = None;
+    }
+
+    pub fn compose_open_editor(&mut self) {
+        self.compose_external = true;
+    }
+
+    /// The draft as text for the external editor: header lines, a blank
+    /// line, then the body.
+    pub fn compose_draft(&self) -> String {
+        format!(
+            "To: {}\nCc: {}\nBcc: {}\nReply-To: {}\nSubject: {}\n\n{}",
+            self.compose_to,
+            self.compose_cc,
+            self.compose_bcc,
+            self.compose_reply_to,
+            self.compose_subject,
+            self.compose_body
+        )
+    }
+
+    /// Reads a draft written by `compose_draft` back into the compose
+    /// fields. Folded header lines are joined and unknown headers ignored.
+    pub fn compose_load_draft(&mut self, text: &str) {
+        let (header_block, body) = match text.split_once("\n\n") {
+            Some((headers, body)) => (headers, body),
+            // Only headers, or the blank line was deleted along with the body
+            None => (text, ""),
+        };
+        for (name, value) in mime::parse_headers(header_block) {
+            let field = match name.to_ascii_lowercase().as_str() {
+                "to" => &mut self.compose_to,
+                "cc" => &mut self.compose_cc,
+                "bcc" => &mut self.compose_bcc,
+                "reply-to" => &mut self.compose_reply_to,
+                "subject" => &mut self.compose_subject,
+                _ => continue,
+            };
+            *field = value;
+        }
+        self.compose_body = body.trim_end_matches('\n').to_string();
+        if !(self.compose_cc.is_empty()
+            && self.compose_bcc.is_empty()
+            && self.compose_reply_to.is_empty())
+        {
+            self.compose_show_extra = true;
+        }
+        if self.compose_field.is_extra() && !self.compose_show_extra {
+            self.compose_field = ComposeField::To;
+        }
+        self.compose_mode = ComposeMode::Normal;
+        self.compose_visual_start = None;
+        self.compose_completions.clear();
+        self.clamp_cursor();
+        self.compose_checkpoint();
+    }
+
+    fn compose_snapshot(&self) -> ComposeSnapshot {
+        ComposeSnapshot {
+            to: self.compose_to.clone(),
+            cc: self.compose_cc.clone(),
+            bcc: self.compose_bcc.clone(),
+            reply_to: self.compose_reply_to.clone(),
+            subject: self.compose_subject.clone(),
+            body: self.compose_body.clone(),
+            field: self.compose_field,
+            cursor: self.compose_cursor,
+        }
+    }
+
+    fn compose_restore(&mut self, snapshot: ComposeSnapshot) {
+        self.compose_to = snapshot.to;
+        self.compose_cc = snapshot.cc;
+        self.compose_bcc = snapshot.bcc;
+        self.compose_reply_to = snapshot.reply_to;
+        self.compose_subject = snapshot.subject;
+        self.compose_body = snapshot.body;
+        self.compose_field = snapshot.field;
+        self.compose_cursor = snapshot.cursor;
+        if self.compose_field.is_extra() {
+            self.compose_show_extra = true;
+        }
+        self.clamp_cursor();
+    }
+
+    /// Starts a fresh undo history from the current draft.
+    pub fn compose_reset_undo(&mut self) {
+        self.compose_undo = UndoTree::new(self.compose_snapshot());
+    }
+
+    /// Records the draft in the undo history if it changed since the last
+    /// checkpoint. Called after every key outside insert mode, so a whole
+    /// insert session becomes a single undo step.
+    pub fn compose_checkpoint(&mut self) {
+        if self.compose_mode == ComposeMode::Insert {
+            return;
+        }
+        let snapshot = self.compose_snapshot();
+        if self.compose_undo.current().same_text(&snapshot) {
+            // Remember where the cursor is, so undoing the next change
+            // returns to it
+            *self.compose_undo.current_mut() = snapshot;
+        } else {
+            self.compose_undo.record(snapshot);
+        }
+    }
+
+    pub fn compose_undo(&mut self) {
+        match self.compose_undo.undo().cloned() {
+            Some(snapshot) => self.compose_restore(snapshot),
+            None => self.status = Some("Already at oldest change".to_string()),
+        }
+    }
+
+    pub fn compose_redo(&mut self) {
+        match self.compose_undo.redo().cloned() {
+            Some(snapshot) => self.compose_restore(snapshot),
+            None => self.status = Some("Already at newest change".to_string()),
+        }
+    }
+
+    /// `g-`/`g+`: moves to the draft as it was one change earlier or later,
+    /// whichever branch that change was made on.
+    pub fn compose_chronological(&mut self, later: bool) {
+        let snapshot = if later {
+            self.compose_undo.later()
+        } else {
+            self.compose_undo.earlier()
+        };
+        match snapshot.cloned() {
+            Some(snapshot) => self.compose_restore(snapshot),
+            None if later => self.status = Some("Already at newest change".to_string()),
+            None => self.status = Some("Already at oldest change".to_string()),
+        }
+    }
+
+    /// Opens the `/` or `?` prompt.
+    pub fn compose_start_search(&mut self, forward: bool) {
+        self.compose_search_input = Some(String::new());
+        self.compose_search_forward = forward;
+    }
+
+    pub fn compose_search_push_char(&mut self, ch: char) {
+        if let Some(input) = &mut self.compose_search_input {
+            input.push(ch);
+        }
+    }
+
+    /// Backspace in the prompt; on an empty prompt it cancels, as in vim.
+    pub fn compose_search_pop_char(&mut self) {
+        if let Some(input) = &mut self.compose_search_input
+            && input.pop().is_none()
+        {
+            self.compose_search_input = None;
+        }
+    }
+
+    pub fn compose_search_cancel(&mut self) {
+        self.compose_search_input = None;
+    }
+
+    /// Runs the typed search; an empty pattern repeats the last one.
+    pub fn compose_search_submit(&mut self) {
+        let Some(input) = self.compose_search_input.take() else {
+            return;
+        };
+        if !input.is_empty() {
+            self.compose_search_pattern = Some(input);
+        }
+        self.compose_search_next(false);
+    }
+
+    /// Jumps to the next match of the last pattern in the body, in the
+    /// search's direction (or the opposite one for `N`), wrapping around.
+    /// From a header field the search starts at the top of the body (or
+    /// the bottom, going backwards). The pattern is matched ignoring case
+    /// unless it has capitals.
+    pub fn compose_search_next(&mut self, reverse: bool) {
+        let Some(pattern) = self.compose_search_pattern.clone() else {
+            self.status = Some("No previous search pattern".to_string());
+            return;
+        };
+        let forward = self.compose_search_forward != reverse;
+        let smart_case = pattern.chars().any(char::is_uppercase);
+        let fold = |text: &str| {
+            if smart_case {
+                text.to_string()
+            } else {
+                // Lowercasing ASCII keeps byte offsets unchanged
+                text.to_ascii_lowercase()
+            }
+        };
+        let body = fold(&self.compose_body);
+        let needle = fold(&pattern);
+
+        // Only a search from within the body can wrap around
+        let cursor = matches!(self.compose_field, ComposeField::Body)
+            .then(|| self.compose_cursor.min(body.len()));
+        let found = if forward {
+            let after = cursor.map_or(0, |from| vim::next_char(&body, from));
+            match body[after..].find(&needle) {
+                Some(i) => Some((after + i, false)),
+                None if cursor.is_some() => body.find(&needle).map(|i| (i, true)),
+                None => None,
+            }
+        } else {
+            let before = cursor.unwrap_or(body.len());
+            match body[..before].rfind(&needle) {
+                Some(i) => Some((i, false)),
+                None if cursor.is_some() => body.rfind(&needle).map(|i| (i, true)),
+                None => None,
+            }
+        };
+
+        match found {
+            Some((pos, wrapped)) => {
+                self.compose_field = ComposeField::Body;
+                self.compose_cursor = pos;
+                self.clamp_cursor();
+                if wrapped {
+                    self.status = Some(if forward {
+                        "Search hit BOTTOM, continuing at TOP".to_string()
+                    } else {
+                        "Search hit TOP, continuing at BOTTOM".to_string()
+                    });
                 }
             }
+            None => self.status = Some(format!("Pattern not found: {}", pattern)),
         }
     }
 
-    pub fn get_compose_data(&self) -> (&str, &str, &str) {
-        (&self.compose_to, &self.compose_subject, &self.compose_body)
+    /// Checks the draft before sending. Recipient lists that do not parse,
+    /// or no recipients at all, keep the draft open with the cursor on the
+    /// field to fix; otherwise the message is shown for confirmation along
+    /// with any warnings.
+    pub fn compose_review(&mut self) {
+        let fields = [
+            (ComposeField::To, "To", &self.compose_to),
+            (ComposeField::Cc, "Cc", &self.compose_cc),
+            (ComposeField::Bcc, "Bcc", &self.compose_bcc),
+            (ComposeField::ReplyTo, "Reply-To", &self.compose_reply_to),
+        ];
+        let invalid = fields.into_iter().find_map(|(field, label, value)| {
+            address::parse_address_list(value)
+                .err()
+                .map(|e| (field, format!("{}: {}", label, e)))
+        });
+        let message = self.outgoing_message();
+        let (field, problem) = match (invalid, message) {
+            (Some(invalid), _) => invalid,
+            (None, Err(e)) => (self.compose_field, format!("{:#}", e)),
+            (None, Ok(message))
+                if message.to.is_empty() && message.cc.is_empty() && message.bcc.is_empty() =>
+            {
+                (
+                    ComposeField::To,
+                    "Add a recipient before sending".to_string(),
+                )
+            }
+            (None, Ok(message)) => {
+                self.send_review = Some(SendReview {
+                    warnings: message.warnings(),
+                    message,
+                });
+                self.mode = Mode::SendConfirm;
+                return;
+            }
+        };
+        self.status = Some(format!("Not sent: {}", problem));
+        if field.is_extra() {
+            self.compose_show_extra = true;
+        }
+        self.compose_field = field;
+        self.compose_cursor = 0;
+        self.clamp_cursor();
+    }
+
+    /// Sends the reviewed message; the TUI loop hands it to the backend.
+    pub fn confirm_send(&mut self) {
+        self.needs_refresh = true;
+        self.mode = Mode::Normal;
+        self.view_offset = 0;
+    }
+
+    /// Goes back to editing the reviewed message.
+    pub fn cancel_send(&mut self) {
+        self.send_review = None;
+        self.mode = Mode::Compose;
+    }
+
+    /// Builds the message to send from the compose fields, parsing the
+    /// recipient fields as RFC 5322 address lists.
+    pub fn outgoing_message(&self) -> anyhow::Result<OutgoingMessage> {
+        Ok(OutgoingMessage {
+            from: self
+                .identities
+                .get(self.compose_identity)
+                .map(Identity::address),
+            to: address::parse_address_list(&self.compose_to)?,
+            cc: address::parse_address_list(&self.compose_cc)?,
+            bcc: address::parse_address_list(&self.compose_bcc)?,
+            reply_to: address::parse_address_list(&self.compose_reply_to)?,
+            subject: self.compose_subject.clone(),
+            html: self
+                .compose_markdown
+                .then(|| markdown::to_html(&self.compose_body)),
+            body: self.compose_body.clone(),
+            sign: self.compose_sign,
+            encrypt: self.compose_encrypt,
+            smime_sign: self.compose_smime,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn searching(body: &str, pattern: &str, field: ComposeField) -> App {
+        let mut app = App::new(Vec::new());
+        app.compose_body = body.to_string();
+        app.compose_field = field;
+        app.compose_cursor = 0;
+        app.compose_search_pattern = Some(pattern.to_string());
+        app
+    }
+
+    #[test]
+    fn search_from_a_header_starts_at_the_top_without_wrapping() {
+        let mut app = searching("one Two two", "two", ComposeField::Subject);
+        app.compose_search_next(false);
+        assert_eq!(app.compose_field, ComposeField::Body);
+        assert_eq!(app.compose_cursor, 4);
+        assert_eq!(app.status, None);
+
+        let mut app = searching("one two two", "two", ComposeField::To);
+        app.compose_search_next(true);
+        assert_eq!(app.compose_cursor, 8);
+        assert_eq!(app.status, None);
+    }
+
+    #[test]
+    fn search_in_the_body_wraps_around() {
+        let mut app = searching("two one two", "two", ComposeField::Body);
+        app.compose_cursor = 8;
+        app.compose_search_next(false);
+        assert_eq!(app.compose_cursor, 0);
+        assert_eq!(
+            app.status.as_deref(),
+            Some("Search hit BOTTOM, continuing at TOP")
+        );
+    }
+
+    #[test]
+    fn search_failure_echoes_the_pattern_as_typed() {
+        let mut app = searching("body", "Missing", ComposeField::Body);
+        app.compose_search_next(false);
+        assert_eq!(app.status.as_deref(), Some("Pattern not found: Missing"));
+        // Smart case: a lowercase pattern matches any case
+        let mut app = searching("A MISSING word", "missing", ComposeField::Subject);
+        app.compose_search_next(false);
+        assert_eq!(app.compose_cursor, 2);
+    }
+
+    #[test]
+    fn undo_branches_are_reachable_in_time_order() {
+        let mut app = App::new(Vec::new());
+        app.compose_body = "abc".to_string();
+        app.compose_field = ComposeField::Body;
+        app.compose_cursor = 0;
+        app.compose_reset_undo();
+        let keys = |app: &mut App, keys: &str| {
+            for ch in keys.chars() {
+                app.compose_normal_key(ch);
+                app.compose_checkpoint();
+            }
+        };
+        keys(&mut app, "x");
+        keys(&mut app, "u");
+        keys(&mut app, "lx");
+        assert_eq!(app.compose_body, "ac");
+        keys(&mut app, "g-");
+        assert_eq!(app.compose_body, "bc");
+        keys(&mut app, "g-");
+        assert_eq!(app.compose_body, "abc");
+        keys(&mut app, "g-");
+        assert_eq!(app.status.as_deref(), Some("Already at oldest change"));
+        keys(&mut app, "2g+");
+        assert_eq!(app.compose_body, "ac");
+    }
+
+    #[test]
+    fn templates_are_expanded_once_when_applied() {
+        let mut app = App::new(Vec::new());
+        app.identities = vec![Identity {
+            name: Some("Jane Doe".to_string()),
+            email: "jane@example.com".to_string(),
+            ..Default::default()
+        }];
+        app.templates.insert(
+            "report".to_string(),
+            Template {
+                to: Some("Bob <bob@example.com>".to_string()),
+                subject: Some("Report {date}".to_string()),
+                body: Some("Hi {recipient_name},\n\n{sender_name}".to_string()),
+                ..Default::default()
+            },
+        );
+        app.compose_from_template("report");
+        assert_eq!(app.compose_subject, format!("Report {}", date::today()));
+        assert_eq!(app.compose_body, "Hi Bob,\n\nJane Doe");
+
+        // Placeholders typed afterwards are sent as they are
+        app.compose_body.push_str(" {date} {recipient_email}");
+        let message = app.outgoing_message().unwrap();
+        assert_eq!(message.body, "Hi Bob,\n\nJane Doe {date} {recipient_email}");
+
+        // Without a recipient the placeholders stay, and the user is told
+        app.templates.get_mut("report").unwrap().to = None;
+        app.compose_from_template("report");
+        assert_eq!(app.compose_body, "Hi {recipient_name},\n\nJane Doe");
+        assert!(app.status.is_some());
     }
 }
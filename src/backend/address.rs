@@ -0,0 +1,407 @@
+//! RFC 5322 address lists: `Name <user@example.com>, other@example.com`.
+
+use std::fmt;
+
+use anyhow::{Result, bail};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) if needs_quoting(name) => {
+                let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "\"{}\" <{}>", escaped, self.email)
+            }
+            Some(name) => write!(f, "{} <{}>", name, self.email),
+            None => write!(f, "{}", self.email),
+        }
+    }
+}
+
+fn needs_quoting(name: &str) -> bool {
+    name.chars().any(|c| "()<>[]:;@\\,.\"".contains(c))
+}
+
+/// Formats addresses as a comma-separated header value.
+pub fn format_list(addresses: &[Address]) -> String {
+    addresses
+        .iter()
+        .map(Address::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats addresses for a MIME header, encoding non-ASCII display names as
+/// RFC 2047 encoded-words.
+pub fn encode_list(addresses: &[Address]) -> String {
+    addresses
+        .iter()
+        .map(|address| match &address.name {
+            Some(name) if !name.is_ascii() => format!(
+                "{} <{}>",
+                super::mime::encode_header_value(name),
+                address.email
+            ),
+            _ => address.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses a comma-separated address list. Display names may be quoted and
+/// contain commas, comments are ignored (or used as the name for bare
+/// addresses), and group syntax (`team: a@x, b@x;`) is flattened.
+pub fn parse_address_list(input: &str) -> Result<Vec<Address>> {
+    split_mailboxes(input)?
+        .iter()
+        .map(|m| parse_mailbox(m))
+        .collect()
+}
+
+/// Splits an address list into individual mailbox strings at top-level
+/// commas, dropping group names and terminators.
+fn split_mailboxes(input: &str) -> Result<Vec<String>> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut in_quote = false;
+    let mut escape = false;
+    let mut in_angle = false;
+    let mut comment_depth = 0;
+
+    for ch in input.chars() {
+        if escape {
+            current.push(ch);
+            escape = false;
+            continue;
+        }
+        if in_quote {
+            current.push(ch);
+            match ch {
+                '\\' => escape = true,
+                '"' => in_quote = false,
+                _ => {}
+            }
+            continue;
+        }
+        if comment_depth > 0 {
+            current.push(ch);
+            match ch {
+                '\\' => escape = true,
+                '(' => comment_depth += 1,
+                ')' => comment_depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        match ch {
+            '"' => {
+                in_quote = true;
+                current.push(ch);
+            }
+            '(' => {
+                comment_depth = 1;
+                current.push(ch);
+            }
+            '<' => {
+                in_angle = true;
+                current.push(ch);
+            }
+            '>' => {
+                in_angle = false;
+                current.push(ch);
+            }
+            // Start of a group: what came before is the group's name
+            ':' if !in_angle => current.clear(),
+            ',' | ';' if !in_angle => items.push(std::mem::take(&mut current)),
+            _ => current.push(ch),
+        }
+    }
+
+    if in_quote {
+        bail!("unterminated quoted string in address list");
+    }
+    if in_angle {
+        bail!("missing '>' in address list");
+    }
+    items.push(current);
+    Ok(items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect())
+}
+
+fn parse_mailbox(input: &str) -> Result<Address> {
+    let (text, comment) = strip_comments(input);
+    let text = text.trim();
+
+    let (name, email) = match find_unquoted(text, '<') {
+        Some(open) => {
+            let Some(close) = text[open..].find('>') else {
+                bail!("missing '>' in address: {}", input);
+            };
+            let name = unquote(text[..open].trim());
+            let email = text[open + 1..open + close].trim().to_string();
+            ((!name.is_empty()).then_some(name), email)
+        }
+        None => (comment, text.to_string()),
+    };
+
+    validate_addr_spec(&email).map_err(|e| anyhow::anyhow!("{}: {}", e, input))?;
+    Ok(Address { name, email })
+}
+
+/// Removes `(comments)` outside quoted strings, returning the remaining text
+/// and the first comment's contents.
+fn strip_comments(input: &str) -> (String, Option<String>) {
+    let mut out = String::new();
+    let mut comment = String::new();
+    let mut first_comment: Option<String> = None;
+    let mut depth = 0;
+    let mut in_quote = false;
+    let mut escape = false;
+
+    for ch in input.chars() {
+        if escape {
+            if depth > 0 {
+                comment.push(ch);
+            } else {
+                out.push(ch);
+            }
+            escape = false;
+            continue;
+        }
+        if depth > 0 {
+            match ch {
+                '\\' => escape = true,
+                '(' => {
+                    depth += 1;
+                    comment.push(ch);
+                }
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        if first_comment.is_none() && !comment.trim().is_empty() {
+                            first_comment = Some(comment.trim().to_string());
+                        }
+                        comment.clear();
+                    } else {
+                        comment.push(ch);
+                    }
+                }
+                _ => comment.push(ch),
+            }
+            continue;
+        }
+        match ch {
+            '\\' if in_quote => {
+                escape = true;
+                out.push(ch);
+            }
+            '"' => {
+                in_quote = !in_quote;
+                out.push(ch);
+            }
+            '(' if !in_quote => depth = 1,
+            _ => out.push(ch),
+        }
+    }
+    (out, first_comment)
+}
+
+fn find_unquoted(text: &str, target: char) -> Option<usize> {
+    let mut in_quote = false;
+    let mut escape = false;
+    for (idx, ch) in text.char_indices() {
+        if escape {
+            escape = false;
+        } else if ch == '\\' && in_quote {
+            escape = true;
+        } else if ch == '"' {
+            in_quote = !in_quote;
+        } else if ch == target && !in_quote {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// Removes surrounding quotes from a display name and resolves escapes.
+fn unquote(name: &str) -> String {
+    let Some(inner) = name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) else {
+        return name.split_whitespace().collect::<Vec<_>>().join(" ");
+    };
+    let mut out = String::new();
+    let mut escape = false;
+    for ch in inner.chars() {
+        if escape {
+            out.push(ch);
+            escape = false;
+        } else if ch == '\\' {
+            escape = true;
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Checks that an address has the `local@domain` shape RFC 5322 requires.
+fn validate_addr_spec(email: &str) -> Result<()> {
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        bail!("missing '@' in address");
+    };
+    if local.is_empty() {
+        bail!("empty local part in address");
+    }
+    let quoted_local = local.len() >= 2 && local.starts_with('"') && local.ends_with('"');
+    if !quoted_local {
+        let atext = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c);
+        if local.starts_with('.')
+            || local.ends_with('.')
+            || local.contains("..")
+            || !local.chars().all(|c| atext(c) || c == '.' || !c.is_ascii())
+        {
+            bail!("invalid local part in address");
+        }
+    }
+
+    if domain.starts_with('[') && domain.ends_with(']') {
+        return Ok(());
+    }
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || !c.is_ascii())
+    };
+    if domain.is_empty() || !domain.split('.').all(valid_label) {
+        bail!("invalid domain in address");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(name: Option<&str>, email: &str) -> Address {
+        Address {
+            name: name.map(str::to_string),
+            email: email.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_bare_and_named_addresses() {
+        assert_eq!(
+            parse_address_list("a@example.com, Bob Smith <bob@example.com>").unwrap(),
+            vec![
+                address(None, "a@example.com"),
+                address(Some("Bob Smith"), "bob@example.com"),
+            ]
+        );
+        assert!(parse_address_list("").unwrap().is_empty());
+        assert!(parse_address_list(" , ,").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quoted_names_may_contain_commas_and_escapes() {
+        assert_eq!(
+            parse_address_list(r#""Smith, Bob" <bob@example.com>, "Say \"hi\"" <hi@example.com>"#)
+                .unwrap(),
+            vec![
+                address(Some("Smith, Bob"), "bob@example.com"),
+                address(Some(r#"Say "hi""#), "hi@example.com"),
+            ]
+        );
+        // Commas inside angle brackets do not split either
+        assert_eq!(
+            parse_address_list(r#"<"odd,local"@example.com>"#).unwrap(),
+            vec![address(None, r#""odd,local"@example.com"#)]
+        );
+    }
+
+    #[test]
+    fn comments_name_bare_addresses() {
+        assert_eq!(
+            parse_address_list(
+                "bob@example.com (Bob (the builder)), Ann <ann@example.com> (ignored)"
+            )
+            .unwrap(),
+            vec![
+                address(Some("Bob (the builder)"), "bob@example.com"),
+                address(Some("Ann"), "ann@example.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn flattens_groups() {
+        assert_eq!(
+            parse_address_list("team: a@example.com, b@example.com;, c@example.com").unwrap(),
+            vec![
+                address(None, "a@example.com"),
+                address(None, "b@example.com"),
+                address(None, "c@example.com"),
+            ]
+        );
+        assert!(
+            parse_address_list("undisclosed-recipients:;")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for input in [
+            "no-at-sign",
+            "@example.com",
+            "a..b@example.com",
+            ".a@example.com",
+            "a@-example.com",
+            "a@example..com",
+            "a@",
+            "Bob <bob@example.com",
+            "\"unterminated <a@example.com>",
+        ] {
+            assert!(parse_address_list(input).is_err(), "{} was accepted", input);
+        }
+        assert!(parse_address_list("a@[192.0.2.1]").is_ok());
+        assert!(parse_address_list("\"quoted local\"@example.com").is_ok());
+    }
+
+    #[test]
+    fn formatting_quotes_names_when_needed() {
+        let list = vec![
+            address(Some("Smith, Bob"), "bob@example.com"),
+            address(Some("Ann Lee"), "ann@example.com"),
+            address(None, "c@example.com"),
+        ];
+        let formatted = format_list(&list);
+        assert_eq!(
+            formatted,
+            r#""Smith, Bob" <bob@example.com>, Ann Lee <ann@example.com>, c@example.com"#
+        );
+        assert_eq!(parse_address_list(&formatted).unwrap(), list);
+    }
+
+    #[test]
+    fn encodes_non_ascii_names() {
+        let encoded = encode_list(&[address(Some("Zoë"), "zoe@example.com")]);
+        assert_eq!(encoded, "=?UTF-8?B?Wm/Dqw==?= <zoe@example.com>");
+        assert_eq!(
+            crate::backend::mime::decode_header_value(&encoded),
+            "Zoë <zoe@example.com>"
+        );
+    }
+}
@@ -0,0 +1,612 @@
+//! Minimal MIME (RFC 2045/2046/2047) parsing and writing for `.eml`
+//! message files.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A parsed MIME entity: the message itself or one of its body parts.
+#[derive(Debug, Clone, Default)]
+pub struct Part {
+    pub headers: Vec<(String, String)>,
+    /// Body exactly as it appears in the source, before transfer decoding.
+    pub body: Vec<u8>,
+    pub parts: Vec<Part>,
+}
+
+impl Part {
+    pub fn parse(raw: &[u8]) -> Part {
+        let (header_block, body) = split_header_body(raw);
+        let headers = parse_headers(&String::from_utf8_lossy(header_block));
+        let mut part = Part {
+            headers,
+            body: body.to_vec(),
+            parts: Vec::new(),
+        };
+
+        if part.mime_type().starts_with("multipart/")
+            && let Some(boundary) = part.content_type_param("boundary")
+        {
+            part.parts = split_multipart(body, &boundary)
+                .into_iter()
+                .map(Part::parse)
+                .collect();
+        }
+        part
+    }
+
+    /// Looks up a header by name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The lowercased media type, defaulting to `text/plain` as RFC 2045 says.
+    pub fn mime_type(&self) -> String {
+        self.header("Content-Type")
+            .and_then(|ct| ct.split(';').next())
+            .map(|t| t.trim().to_ascii_lowercase())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "text/plain".to_string())
+    }
+
+    pub fn content_type_param(&self, name: &str) -> Option<String> {
+        header_param(self.header("Content-Type")?, name)
+    }
+
+    pub fn is_attachment(&self) -> bool {
+        self.header("Content-Disposition")
+            .map(|d| {
+                d.trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with("attachment")
+            })
+            .unwrap_or(false)
+    }
+
+    /// Body with the Content-Transfer-Encoding removed.
+    pub fn decoded_body(&self) -> Vec<u8> {
+        let encoding = self
+            .header("Content-Transfer-Encoding")
+            .map(|e| e.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        match encoding.as_str() {
+            "base64" => decode_base64(&self.body),
+            "quoted-printable" => decode_quoted_printable(&self.body),
+            _ => self.body.clone(),
+        }
+    }
+
+    /// Decoded body converted to a string using the part's charset.
+    pub fn text(&self) -> String {
+        let charset = self.content_type_param("charset").unwrap_or_default();
+        let text = decode_charset(&self.decoded_body(), &charset);
+        text.replace("\r\n", "\n")
+    }
+
+    /// A UTF-8 `text/<subtype>` part. Line endings become CRLF, and the
+    /// text is quoted-printable encoded unless it is ASCII with short lines
+    /// and no trailing whitespace, which could be lost in transit.
+    pub fn new_text(subtype: &str, text: &str) -> Part {
+        let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+        let plain = text.is_ascii()
+            && text
+                .split("\r\n")
+                .all(|line| line.len() <= 998 && !line.ends_with([' ', '\t']));
+        let (encoding, body) = if plain {
+            ("7bit", text.into_bytes())
+        } else {
+            (
+                "quoted-printable",
+                encode_quoted_printable(text.as_bytes()).into_bytes(),
+            )
+        };
+        Part {
+            headers: vec![
+                (
+                    "Content-Type".to_string(),
+                    format!("text/{}; charset=utf-8", subtype),
+                ),
+                (
+                    "Content-Transfer-Encoding".to_string(),
+                    encoding.to_string(),
+                ),
+            ],
+            body,
+            parts: Vec::new(),
+        }
+    }
+
+    /// A `multipart/<subtype>` container with a fresh boundary.
+    pub fn new_multipart(subtype: &str, parts: Vec<Part>) -> Part {
+        Part {
+            headers: vec![(
+                "Content-Type".to_string(),
+                format!("multipart/{}; boundary=\"{}\"", subtype, new_boundary()),
+            )],
+            body: Vec::new(),
+            parts,
+        }
+    }
+
+    /// Serialises the entity with CRLF line endings. Multipart bodies are
+    /// rebuilt from their parts.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, value) in &self.headers {
+            out.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        match self.content_type_param("boundary") {
+            Some(boundary) if !self.parts.is_empty() => {
+                for part in &self.parts {
+                    out.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+                    out.extend_from_slice(&part.to_bytes());
+                    out.extend_from_slice(b"\r\n");
+                }
+                out.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+            }
+            _ => out.extend_from_slice(&self.body),
+        }
+        out
+    }
+
+    /// Source bytes of each body part of a multipart entity, as a signature
+    /// over one of them must be checked against.
+    pub fn raw_parts(&self) -> Vec<&[u8]> {
+        match self.content_type_param("boundary") {
+            Some(boundary) => split_multipart(&self.body, &boundary),
+            None => Vec::new(),
+        }
+    }
+
+    /// Finds the first inline, non-attachment leaf part of the given type.
+    pub fn find_inline(&self, mime_type: &str) -> Option<&Part> {
+        if self.parts.is_empty() {
+            return (self.mime_type() == mime_type && !self.is_attachment()).then_some(self);
+        }
+        self.parts.iter().find_map(|p| p.find_inline(mime_type))
+    }
+}
+
+/// Splits a raw message at the first empty line.
+fn split_header_body(raw: &[u8]) -> (&[u8], &[u8]) {
+    for i in 0..raw.len() {
+        if raw[i..].starts_with(b"\r\n\r\n") {
+            return (&raw[..i], &raw[i + 4..]);
+        }
+        if raw[i..].starts_with(b"\n\n") {
+            return (&raw[..i], &raw[i + 2..]);
+        }
+    }
+    // A part may start with the blank line directly (no headers)
+    if let Some(rest) = raw
+        .strip_prefix(b"\r\n")
+        .or_else(|| raw.strip_prefix(b"\n"))
+    {
+        return (&[], rest);
+    }
+    (raw, &[])
+}
+
+/// Parses a header block, unfolding continuation lines.
+pub fn parse_headers(block: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in block.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    headers
+}
+
+/// Extracts a `name=value` parameter from a structured header value.
+pub fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (k, v) = param.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut pos = 0;
+
+    while pos < body.len() {
+        let line_end = body[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|i| pos + i + 1)
+            .unwrap_or(body.len());
+        let line = trim_line_ending(&body[pos..line_end]);
+
+        if line.starts_with(delimiter.as_bytes()) {
+            if let Some(s) = start {
+                // The line break before the delimiter belongs to the delimiter
+                parts.push(trim_line_ending(&body[s..pos]));
+            }
+            if line[delimiter.len()..].starts_with(b"--") {
+                return parts;
+            }
+            start = Some(line_end);
+        }
+        pos = line_end;
+    }
+
+    if let Some(s) = start {
+        parts.push(&body[s..]);
+    }
+    parts
+}
+
+/// A boundary unlikely to occur in any body: `=_` never appears in
+/// quoted-printable or base64 output.
+fn new_boundary() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!(
+        "=_hermes_{:x}_{:x}",
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+pub fn encode_base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn decode_base64(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for &c in input {
+        let val = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        buf = (buf << 6) | val as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+        }
+    }
+    out
+}
+
+pub fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'=' {
+            // Soft line break
+            if input[i + 1..].starts_with(b"\r\n") {
+                i += 3;
+                continue;
+            }
+            if input[i + 1..].starts_with(b"\n") {
+                i += 2;
+                continue;
+            }
+            if i + 2 < input.len()
+                && let (Some(hi), Some(lo)) = (hex_val(input[i + 1]), hex_val(input[i + 2]))
+            {
+                out.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(input[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Quoted-printable encodes CRLF-separated text, keeping lines under 76
+/// characters with soft line breaks.
+pub fn encode_quoted_printable(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len() * 3 / 2);
+    let lines: Vec<&[u8]> = input.split(|&b| b == b'\n').collect();
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut width = 0;
+        for (i, &b) in line.iter().enumerate() {
+            // Whitespace at the end of a line would be lost in transit
+            let at_end = i + 1 == line.len();
+            let literal =
+                matches!(b, b'!'..=b'<' | b'>'..=b'~') || (matches!(b, b' ' | b'\t') && !at_end);
+            let token = if literal {
+                (b as char).to_string()
+            } else {
+                format!("={:02X}", b)
+            };
+            // Leave room for the `=` of a soft break, except on the last token
+            let limit = if at_end { 76 } else { 75 };
+            if width + token.len() > limit {
+                out.push_str("=\r\n");
+                width = 0;
+            }
+            out.push_str(&token);
+            width += token.len();
+        }
+        if index + 1 < lines.len() {
+            out.push_str("\r\n");
+        }
+    }
+    out
+}
+
+fn hex_val(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Converts bytes in the given charset to a string. Only UTF-8 and the
+/// Latin-1 family are understood; anything else is decoded as lossy UTF-8.
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" | "iso-8859-15" | "windows-1252" | "cp1252" => {
+            bytes.iter().map(|&b| b as char).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Encodes a header value with RFC 2047 encoded-words when it is not plain
+/// ASCII. Long values are split into several words on separate lines.
+pub fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    // 45 bytes of input make a 60 character base64 word, within the limit
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for ch in value.chars() {
+        if chunk.len() + ch.len_utf8() > 45 {
+            words.push(std::mem::take(&mut chunk));
+        }
+        chunk.push(ch);
+    }
+    words.push(chunk);
+    words
+        .iter()
+        .map(|word| format!("=?UTF-8?B?{}?=", encode_base64(word.as_bytes())))
+        .collect::<Vec<_>>()
+        .join("\r\n ")
+}
+
+/// Decodes RFC 2047 encoded-words (`=?charset?B|Q?text?=`) in a header value.
+pub fn decode_header_value(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut last_was_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let Some((word, consumed)) = decode_encoded_word(&rest[start..]) else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            last_was_word = false;
+            continue;
+        };
+        let between = &rest[..start];
+        // Whitespace between adjacent encoded-words is dropped
+        if !(last_was_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        out.push_str(&word);
+        rest = &rest[start + consumed..];
+        last_was_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_encoded_word(s: &str) -> Option<(String, usize)> {
+    let inner = s.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    let bytes = match encoding.to_ascii_lowercase().as_str() {
+        "b" => decode_base64(text.as_bytes()),
+        "q" => decode_quoted_printable(text.replace('_', " ").as_bytes()),
+        _ => return None,
+    };
+    let consumed = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+    Some((decode_charset(&bytes, charset), consumed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trips_with_padding() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        for input in [&b"hello, world"[..], b"\x00\xff\xfe binary", b"ab"] {
+            assert_eq!(decode_base64(encode_base64(input).as_bytes()), input);
+        }
+        // Line breaks inside the encoded text are skipped
+        assert_eq!(decode_base64(b"Zm9v\r\nYmFy"), b"foobar");
+        // So is the URL-safe alphabet
+        assert_eq!(decode_base64(b"-_-_"), decode_base64(b"+/+/"));
+    }
+
+    #[test]
+    fn quoted_printable_decodes_escapes_and_soft_breaks() {
+        assert_eq!(decode_quoted_printable(b"caf=C3=A9"), "café".as_bytes());
+        assert_eq!(decode_quoted_printable(b"soft=\r\nbreak"), b"softbreak");
+        assert_eq!(decode_quoted_printable(b"soft=\nbreak"), b"softbreak");
+        // A stray `=` that is not an escape is kept
+        assert_eq!(decode_quoted_printable(b"a=zz b="), b"a=zz b=");
+    }
+
+    #[test]
+    fn quoted_printable_encoding_keeps_lines_short() {
+        let long = "x".repeat(200);
+        let encoded = encode_quoted_printable(long.as_bytes());
+        assert!(encoded.split("\r\n").all(|line| line.len() <= 76));
+        assert_eq!(decode_quoted_printable(encoded.as_bytes()), long.as_bytes());
+    }
+
+    #[test]
+    fn quoted_printable_encoding_protects_trailing_whitespace() {
+        assert_eq!(encode_quoted_printable(b"end \r\nnext"), "end=20\r\nnext");
+        assert_eq!(encode_quoted_printable(b"a=b"), "a=3Db");
+        let text = "naïve tab\t \r\nline two ";
+        let encoded = encode_quoted_printable(text.as_bytes());
+        assert_eq!(decode_quoted_printable(encoded.as_bytes()), text.as_bytes());
+    }
+
+    #[test]
+    fn header_values_round_trip_through_encoded_words() {
+        assert_eq!(encode_header_value("plain ascii"), "plain ascii");
+        let value = "Grüße aus Köln — ".repeat(5);
+        let encoded = encode_header_value(&value);
+        assert!(encoded.starts_with("=?UTF-8?B?"));
+        assert!(encoded.lines().count() > 1);
+        // Folding whitespace between the words disappears again
+        assert_eq!(decode_header_value(&encoded), value);
+    }
+
+    #[test]
+    fn decodes_q_encoded_words_and_keeps_surrounding_text() {
+        assert_eq!(
+            decode_header_value("Re: =?iso-8859-1?Q?caf=E9_cr=E8me?= (1)"),
+            "Re: café crème (1)"
+        );
+        assert_eq!(decode_header_value("=?utf-8?q?a?= =?utf-8?q?b?="), "ab");
+        // Not an encoded-word
+        assert_eq!(decode_header_value("x =? y"), "x =? y");
+    }
+
+    #[test]
+    fn unfolds_headers_and_reads_parameters() {
+        let headers = parse_headers(
+            "Subject: one\r\n  two\r\nContent-Type: text/plain;\r\n\tcharset=\"utf-8\"",
+        );
+        assert_eq!(headers[0], ("Subject".to_string(), "one two".to_string()));
+        assert_eq!(
+            header_param(&headers[1].1, "CHARSET").as_deref(),
+            Some("utf-8")
+        );
+        assert_eq!(header_param(&headers[1].1, "format"), None);
+    }
+
+    #[test]
+    fn parses_nested_multiparts() {
+        let raw = b"Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=iso-8859-1\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+caf=E9\r\n\
+--inner\r\n\
+Content-Type: text/html\r\n\r\n\
+<p>hi</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: text/plain\r\n\
+Content-Disposition: attachment; filename=notes.txt\r\n\
+Content-Transfer-Encoding: base64\r\n\r\n\
+bm90ZXM=\r\n\
+--outer--\r\n\
+epilogue\r\n";
+        let message = Part::parse(raw);
+        assert_eq!(message.mime_type(), "multipart/mixed");
+        assert_eq!(message.parts.len(), 2);
+        assert_eq!(message.parts[0].parts.len(), 2);
+        assert_eq!(message.find_inline("text/plain").unwrap().text(), "café");
+        assert_eq!(
+            message.find_inline("text/html").unwrap().text(),
+            "<p>hi</p>"
+        );
+        let attachment = &message.parts[1];
+        assert!(attachment.is_attachment());
+        assert_eq!(attachment.decoded_body(), b"notes");
+        assert_eq!(message.raw_parts().len(), 2);
+    }
+
+    #[test]
+    fn defaults_to_text_plain() {
+        let part = Part::parse(b"Subject: x\n\nbody");
+        assert_eq!(part.mime_type(), "text/plain");
+        assert_eq!(part.text(), "body");
+        // A part may start with the blank line directly
+        let part = Part::parse(b"\r\nno headers");
+        assert!(part.headers.is_empty());
+        assert_eq!(part.body, b"no headers");
+    }
+
+    #[test]
+    fn written_messages_parse_back() {
+        let alternative = Part::new_multipart(
+            "alternative",
+            vec![
+                Part::new_text("plain", "hello\nworld"),
+                Part::new_text("html", "<p>héllo</p>"),
+            ],
+        );
+        let bytes = alternative.to_bytes();
+        let parsed = Part::parse(&bytes);
+        assert_eq!(parsed.parts.len(), 2);
+        assert_eq!(
+            parsed.parts[0].header("Content-Transfer-Encoding"),
+            Some("7bit")
+        );
+        assert_eq!(parsed.parts[0].text(), "hello\nworld");
+        assert_eq!(
+            parsed.parts[1].header("Content-Transfer-Encoding"),
+            Some("quoted-printable")
+        );
+        assert_eq!(parsed.parts[1].text(), "<p>héllo</p>");
+    }
+
+    #[test]
+    fn boundaries_are_unique() {
+        assert_ne!(new_boundary(), new_boundary());
+    }
+}
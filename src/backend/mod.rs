@@ -1,29 +1,189 @@
 use std::{
     fs::{self, OpenOptions},
     io::{BufRead, BufReader, Write},
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
+    sync::{Mutex, PoisonError},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    crypto::{self, auth::Authentication, pgp::Gpg, smime::Smime},
+    date, spell,
+};
+
+pub mod address;
+pub mod mime;
+pub mod state;
+
+use address::Address;
+use state::{MessageState, StateFile};
 
 #[derive(Debug, Clone)]
 pub struct EmailSummary {
     pub subject: String,
     pub from: String,
+    /// The text/plain body; empty when the message has no plain-text part.
     pub body: String,
+    /// The text/html body, if the message has one.
+    pub html: Option<String>,
+    /// Content-Type of the part `body` was taken from.
+    pub content_type: Option<String>,
+    pub headers: Vec<(String, String)>,
+    /// The message source as stored by the backend.
+    pub raw: String,
     pub file_path: Option<PathBuf>,
+    /// Signature and encryption status of a signed or encrypted message.
+    /// Only known once the message has been opened.
+    pub security: Option<crypto::Security>,
+    /// DKIM, SPF and DMARC results, as reported by the receiving server and
+    /// from local DKIM checks. Only known once the message has been opened.
+    pub authentication: Vec<crypto::auth::AuthResult>,
+    /// Decrypted, verified and checked by `EmailBackend::open_email`.
+    pub opened: bool,
+    pub read: bool,
+    pub flagged: bool,
+    pub tags: Vec<String>,
+    /// Fetched for the first time, so filtering rules have not seen it.
+    pub recent: bool,
+    /// Where the message is on an IMAP server, for messages in a watched
+    /// folder.
+    pub remote: Option<RemoteMessage>,
+}
+
+/// A message on an IMAP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteMessage {
+    pub folder: String,
+    pub uid: u32,
+}
+
+impl EmailSummary {
+    /// Looks up a header by name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn state(&self) -> MessageState {
+        MessageState {
+            read: self.read,
+            flagged: self.flagged,
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+/// A message ready to be handed to a backend for delivery.
+#[derive(Debug, Clone, Default)]
+pub struct OutgoingMessage {
+    /// Sending identity; backends fall back to their own address.
+    pub from: Option<Address>,
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+    pub bcc: Vec<Address>,
+    pub reply_to: Vec<Address>,
+    pub subject: String,
+    pub body: String,
+    /// HTML rendering of the body. When set the message is sent as
+    /// multipart/alternative with `body` as the text/plain part.
+    pub html: Option<String>,
+    /// Sign with the sender's OpenPGP key (PGP/MIME).
+    pub sign: bool,
+    /// Encrypt to the OpenPGP keys of every recipient and the sender.
+    pub encrypt: bool,
+    /// Sign with the configured S/MIME identity instead.
+    pub smime_sign: bool,
+}
+
+impl OutgoingMessage {
+    /// Things the sender probably did not mean, shown before sending. They
+    /// do not stop the message from going out.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.to.is_empty() && !(self.cc.is_empty() && self.bcc.is_empty()) {
+            warnings.push("No To recipients; only Cc or Bcc".to_string());
+        }
+        if self.subject.trim().is_empty() {
+            warnings.push("The subject is empty".to_string());
+        }
+        // Outgoing messages cannot carry attachments yet, so any mention of
+        // one in the sender's own text is worth a warning
+        let mentions_attachment = spell::words(&self.body)
+            .into_iter()
+            .any(|word| self.body[word].to_lowercase().starts_with("attach"));
+        if mentions_attachment {
+            warnings
+                .push("The message mentions an attachment, but nothing is attached".to_string());
+        }
+        warnings
+    }
 }
 
 pub trait EmailBackend {
     fn fetch_inbox(&self) -> Result<Vec<EmailSummary>>;
-    fn send_email(&self, to: &str, subject: &str, body: &str) -> Result<()>;
+    fn send_email(&self, message: &OutgoingMessage) -> Result<()>;
     fn delete_email(&self, email: &EmailSummary) -> Result<()>;
+    /// Saves whether an email is read or flagged, and its tags.
+    fn update_email(&self, email: &EmailSummary) -> Result<()>;
+    /// Moves an email out of the inbox into a folder.
+    fn move_email(&self, email: &EmailSummary, folder: &str) -> Result<()>;
+    /// Decrypts and verifies an email and checks its authentication. This
+    /// runs gpg, openssl and DNS lookups, so it is left until the email is
+    /// shown rather than done for every fetched message.
+    fn open_email(&self, email: &mut EmailSummary) -> Result<()>;
+}
+
+/// A backend shared between the interface and the background sync. Each
+/// call holds the lock, so only one of them fetches a new message first
+/// and runs the rules on it.
+impl<B: EmailBackend> EmailBackend for Mutex<B> {
+    fn fetch_inbox(&self) -> Result<Vec<EmailSummary>> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fetch_inbox()
+    }
+
+    fn send_email(&self, message: &OutgoingMessage) -> Result<()> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .send_email(message)
+    }
+
+    fn delete_email(&self, email: &EmailSummary) -> Result<()> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .delete_email(email)
+    }
+
+    fn update_email(&self, email: &EmailSummary) -> Result<()> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .update_email(email)
+    }
+
+    fn move_email(&self, email: &EmailSummary, folder: &str) -> Result<()> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .move_email(email, folder)
+    }
+
+    fn open_email(&self, email: &mut EmailSummary) -> Result<()> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .open_email(email)
+    }
 }
 
 /// Simple file-backed backend
 pub struct FileBackend {
     path: PathBuf,
     user_email: String,
+    gpg: Gpg,
+    smime: Smime,
+    authentication: Authentication,
 }
 
 impl FileBackend {
@@ -31,41 +191,92 @@ impl FileBackend {
         Self {
             path: path.into(),
             user_email: user_email.into(),
+            gpg: Gpg::default(),
+            smime: Smime::default(),
+            authentication: Authentication::default(),
         }
     }
+
+    /// Uses this gpg and keyring for signing, encryption and decryption.
+    pub fn with_gpg(mut self, gpg: Gpg) -> Self {
+        self.gpg = gpg;
+        self
+    }
+
+    /// Uses this openssl, CA bundle and identity for S/MIME.
+    pub fn with_smime(mut self, smime: Smime) -> Self {
+        self.smime = smime;
+        self
+    }
+
+    /// Trusts these servers' authentication results, and checks DKIM
+    /// signatures locally if set up to.
+    pub fn with_authentication(mut self, authentication: Authentication) -> Self {
+        self.authentication = authentication;
+        self
+    }
+
+    /// Reads one message file, in either format.
+    pub fn read_message(&self, path: &Path) -> Result<EmailSummary> {
+        let mut email = match path.extension().and_then(|e| e.to_str()) {
+            Some("eml") => parse_eml_file(path)?,
+            _ => parse_single_email_file(path)?,
+        };
+        email.file_path = Some(path.to_path_buf());
+        Ok(email)
+    }
+
+    /// Reads a message that arrived some other way, such as over IMAP.
+    pub fn parse_message(&self, raw: &[u8]) -> EmailSummary {
+        parse_eml(raw)
+    }
+}
+
+/// The directory and file name of a message in a directory backend.
+fn message_file(email: &EmailSummary) -> Option<(&Path, String)> {
+    let path = email.file_path.as_deref()?;
+    Some((path.parent()?, path.file_name()?.to_str()?.to_string()))
 }
 
 impl EmailBackend for FileBackend {
-    fn send_email(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+    fn send_email(&self, message: &OutgoingMessage) -> Result<()> {
         // If path is a directory, create a new file
         if self.path.is_dir() {
             let timestamp = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)?
                 .as_secs();
-            let filename = format!("email_{}.txt", timestamp);
+            // The text format has room for one plain body only
+            let mime =
+                message.html.is_some() || message.sign || message.encrypt || message.smime_sign;
+            let extension = if mime { "eml" } else { "txt" };
+            let filename = format!("email_{}.{}", timestamp, extension);
             let file_path = self.path.join(filename);
             let mut file = OpenOptions::new()
                 .create(true)
+                .truncate(true)
                 .write(true)
                 .open(&file_path)?;
 
-            writeln!(file, "FROM: {}", self.user_email)?;
-            writeln!(file, "TO: {}", to)?;
-            writeln!(file, "SUBJECT: {}", subject)?;
-            writeln!(file, "BODY:")?;
-            writeln!(file, "{body}")?;
+            if mime {
+                let email = mime_email(&self.user_email, message, &self.gpg, &self.smime)?;
+                file.write_all(&email.to_bytes())?;
+            } else {
+                write_text_email(&mut file, &self.user_email, message)?;
+            }
         } else {
             // Legacy: append to file
+            if message.sign || message.encrypt || message.smime_sign {
+                bail!("signed or encrypted mail needs a directory of message files");
+            }
+            if message.html.is_some() {
+                bail!("Markdown mail with an HTML part needs a directory of message files");
+            }
             let mut file = OpenOptions::new()
                 .create(true)
                 .append(true)
                 .open(&self.path)?;
 
-            writeln!(file, "FROM: {}", self.user_email)?;
-            writeln!(file, "TO: {}", to)?;
-            writeln!(file, "SUBJECT: {}", subject)?;
-            writeln!(file, "BODY:")?;
-            writeln!(file, "{body}")?;
+            write_text_email(&mut file, &self.user_email, message)?;
             writeln!(file, "---")?;
         }
 
@@ -76,6 +287,81 @@ impl EmailBackend for FileBackend {
         if let Some(file_path) = &email.file_path {
             fs::remove_file(file_path)?;
         }
+        if let Some((dir, name)) = message_file(email) {
+            let mut state = StateFile::load(dir)?;
+            state.remove(&name);
+            state.save()?;
+        }
+        Ok(())
+    }
+
+    fn update_email(&self, email: &EmailSummary) -> Result<()> {
+        // The single-file format has nowhere to keep state
+        let Some((dir, name)) = message_file(email) else {
+            return Ok(());
+        };
+        let mut state = StateFile::load(dir)?;
+        state.set(&name, email.state());
+        state.save()
+    }
+
+    fn move_email(&self, email: &EmailSummary, folder: &str) -> Result<()> {
+        let Some((dir, name)) = message_file(email) else {
+            bail!("folders need a directory of message files");
+        };
+        // Folders stay inside the mail directory
+        let relative = Path::new(folder);
+        if folder.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            bail!("invalid folder name: {}", folder);
+        }
+        let target = dir.join(relative);
+        fs::create_dir_all(&target).with_context(|| format!("creating {}", target.display()))?;
+        let from = dir.join(&name);
+        fs::rename(&from, target.join(&name))
+            .with_context(|| format!("moving {} to {}", from.display(), target.display()))?;
+
+        // One state file is locked at a time, so moves in opposite
+        // directions cannot wait on each other
+        {
+            let mut moved = StateFile::load(&target)?;
+            moved.set(&name, email.state());
+            moved.save()?;
+        }
+        let mut state = StateFile::load(dir)?;
+        state.remove(&name);
+        state.save()
+    }
+
+    fn open_email(&self, email: &mut EmailSummary) -> Result<()> {
+        if email.opened {
+            return Ok(());
+        }
+        let raw = match &email.file_path {
+            Some(path) if path.extension().is_some_and(|e| e == "eml") => {
+                fs::read(path).with_context(|| format!("reading {}", path.display()))?
+            }
+            // The text format has no MIME structure to open
+            Some(_) => Vec::new(),
+            None if email.remote.is_some() => email.raw.as_bytes().to_vec(),
+            None => Vec::new(),
+        };
+        if !raw.is_empty() {
+            let opened = open_eml(&raw, &self.gpg, &self.smime, &self.authentication);
+            *email = EmailSummary {
+                file_path: email.file_path.take(),
+                read: email.read,
+                flagged: email.flagged,
+                tags: std::mem::take(&mut email.tags),
+                recent: email.recent,
+                remote: email.remote.take(),
+                ..opened
+            };
+        }
+        email.opened = true;
         Ok(())
     }
 
@@ -86,17 +372,36 @@ impl EmailBackend for FileBackend {
 
         // Support either a single file (legacy) or a directory of email files
         if self.path.is_dir() {
+            let mut state = StateFile::load(&self.path)?;
             let mut inbox = Vec::new();
             for entry in fs::read_dir(&self.path)? {
                 let entry = entry?;
                 let p = entry.path();
-                if p.is_file() && p.extension().map(|e| e == "txt").unwrap_or(false) {
-                    if let Ok(mut email) = parse_single_email_file(&p) {
-                        email.file_path = Some(p);
-                        inbox.push(email);
+                if !p.is_file()
+                    || !matches!(p.extension().and_then(|e| e.to_str()), Some("txt" | "eml"))
+                {
+                    continue;
+                }
+                let Ok(mut email) = self.read_message(&p) else {
+                    continue;
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                match state.get(&name) {
+                    Some(saved) => {
+                        email.read = saved.read;
+                        email.flagged = saved.flagged;
+                        email.tags = saved.tags.clone();
+                    }
+                    None => {
+                        // First time this message is seen
+                        email.recent = true;
+                        state.set(&name, email.state());
                     }
                 }
+                inbox.push(email);
             }
+            state.retain(|name| self.path.join(name).is_file());
+            state.save()?;
             return Ok(inbox);
         }
 
@@ -108,29 +413,42 @@ impl EmailBackend for FileBackend {
         let mut from = String::new();
         let mut subject = String::new();
         let mut body_lines: Vec<String> = Vec::new();
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut raw_lines: Vec<String> = Vec::new();
         let mut in_body = false;
 
         for line in reader.lines() {
             let line = line?;
+            if line != "---" {
+                raw_lines.push(line.clone());
+            }
 
             if let Some(rest) = line.strip_prefix("FROM: ") {
                 from = rest.to_string();
                 in_body = false;
                 body_lines.clear();
+                headers.clear();
+                raw_lines = vec![line.clone()];
+                headers.push(("From".to_string(), from.clone()));
             } else if let Some(rest) = line.strip_prefix("SUBJECT: ") {
                 subject = rest.to_string();
                 in_body = false;
+                headers.push(("Subject".to_string(), subject.clone()));
             } else if line == "BODY:" {
                 in_body = true;
                 body_lines.clear();
             } else if line == "---" {
                 // finalize one email entry
-                inbox.push(EmailSummary {
-                    from: from.clone(),
-                    subject: subject.clone(),
-                    body: body_lines.join("\n"),
-                    file_path: None,
-                });
+                let mut email = text_email(
+                    from.clone(),
+                    subject.clone(),
+                    body_lines.join("\n"),
+                    std::mem::take(&mut headers),
+                );
+                email.raw = std::mem::take(&mut raw_lines).join("\n");
+                // No state is kept for the single file, so nothing is unread
+                email.read = true;
+                inbox.push(email);
 
                 from.clear();
                 subject.clear();
@@ -138,41 +456,160 @@ impl EmailBackend for FileBackend {
                 in_body = false;
             } else if in_body {
                 body_lines.push(line.to_string());
+            } else if let Some(header) = parse_header_line(&line) {
+                headers.push(header);
             }
         }
 
         // If file ended without trailing --- but we have content, push last email
         if !(from.is_empty() && subject.is_empty() && body_lines.is_empty()) {
-            inbox.push(EmailSummary {
-                from,
-                subject,
-                body: body_lines.join("\n"),
-                file_path: None,
-            });
+            let mut email = text_email(from, subject, body_lines.join("\n"), headers);
+            email.raw = raw_lines.join("\n");
+            email.read = true;
+            inbox.push(email);
         }
 
         Ok(inbox)
     }
 }
 
-fn parse_single_email_file(path: &PathBuf) -> Result<EmailSummary> {
-    let file = fs::File::open(path)?;
-    let reader = BufReader::new(file);
+/// Writes a message in the FROM:/SUBJECT:/BODY: text format. Bcc is kept
+/// since the file is the sender's own copy of the message.
+fn write_text_email(out: &mut impl Write, from: &str, message: &OutgoingMessage) -> Result<()> {
+    match &message.from {
+        Some(address) => writeln!(out, "FROM: {}", address)?,
+        None => writeln!(out, "FROM: {}", from)?,
+    }
+    writeln!(out, "TO: {}", address::format_list(&message.to))?;
+    if !message.cc.is_empty() {
+        writeln!(out, "CC: {}", address::format_list(&message.cc))?;
+    }
+    if !message.bcc.is_empty() {
+        writeln!(out, "BCC: {}", address::format_list(&message.bcc))?;
+    }
+    if !message.reply_to.is_empty() {
+        writeln!(out, "REPLY-TO: {}", address::format_list(&message.reply_to))?;
+    }
+    writeln!(out, "SUBJECT: {}", message.subject)?;
+    writeln!(out, "BODY:")?;
+    writeln!(out, "{}", message.body)?;
+    Ok(())
+}
+
+/// Builds an RFC 5322 message. The body is multipart/alternative when the
+/// message has an HTML part, and plain text otherwise; it is then signed
+/// and/or encrypted as PGP/MIME, or signed with S/MIME, if asked.
+fn mime_email(
+    from: &str,
+    message: &OutgoingMessage,
+    gpg: &Gpg,
+    smime: &Smime,
+) -> Result<mime::Part> {
+    if message.smime_sign && (message.sign || message.encrypt) {
+        bail!("choose either OpenPGP or S/MIME for a message, not both");
+    }
+    let from = match &message.from {
+        Some(address) => address.clone(),
+        None => Address {
+            name: None,
+            email: from.to_string(),
+        },
+    };
+    let mut part = match &message.html {
+        Some(html) => mime::Part::new_multipart(
+            "alternative",
+            vec![
+                mime::Part::new_text("plain", &message.body),
+                mime::Part::new_text("html", html),
+            ],
+        ),
+        None => mime::Part::new_text("plain", &message.body),
+    };
+    if message.encrypt {
+        // The sender is a recipient too, to be able to read the sent copy
+        let recipients: Vec<&str> = message
+            .to
+            .iter()
+            .chain(&message.cc)
+            .map(|a| a.email.as_str())
+            .chain([from.email.as_str()])
+            .collect();
+        let hidden: Vec<&str> = message.bcc.iter().map(|a| a.email.as_str()).collect();
+        part = crypto::pgp::encrypt_part(
+            gpg,
+            part,
+            &recipients,
+            &hidden,
+            Some(&from.email),
+            message.sign,
+        )?;
+    } else if message.sign {
+        part = crypto::pgp::sign_part(gpg, part, Some(&from.email))?;
+    } else if message.smime_sign {
+        part = crypto::smime::sign_part(smime, part)?;
+    }
+
+    let mut headers = vec![
+        ("From", address::encode_list(std::slice::from_ref(&from))),
+        ("To", address::encode_list(&message.to)),
+    ];
+    for (name, list) in [
+        ("Cc", &message.cc),
+        ("Bcc", &message.bcc),
+        ("Reply-To", &message.reply_to),
+    ] {
+        if !list.is_empty() {
+            headers.push((name, address::encode_list(list)));
+        }
+    }
+    headers.extend([
+        ("Subject", mime::encode_header_value(&message.subject)),
+        ("Date", date::rfc5322(date::now())),
+        ("Message-ID", message_id(&from.email)),
+        ("MIME-Version", "1.0".to_string()),
+    ]);
+    let mut all: Vec<(String, String)> = headers
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    all.append(&mut part.headers);
+    part.headers = all;
+    Ok(part)
+}
+
+/// A unique Message-ID in the sender's domain.
+fn message_id(from: &str) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let domain = from
+        .rsplit_once('@')
+        .map_or("localhost", |(_, domain)| domain);
+    format!("<{:x}.{}@{}>", nanos, std::process::id(), domain)
+}
+
+fn parse_single_email_file(path: &Path) -> Result<EmailSummary> {
+    let raw = fs::read_to_string(path)?;
     let mut from = String::new();
     let mut subject = String::new();
     let mut body_lines: Vec<String> = Vec::new();
+    let mut headers: Vec<(String, String)> = Vec::new();
     let mut in_body = false;
 
-    for line in reader.lines() {
-        let line = line?;
+    for line in raw.lines() {
         if let Some(rest) = line.strip_prefix("FROM: ") {
             from = rest.to_string();
+            headers.push(("From".to_string(), from.clone()));
         } else if let Some(rest) = line.strip_prefix("SUBJECT: ") {
             subject = rest.to_string();
+            headers.push(("Subject".to_string(), subject.clone()));
         } else if line == "BODY:" {
             in_body = true;
         } else if in_body {
             body_lines.push(line.to_string());
+        } else if let Some(header) = parse_header_line(line) {
+            headers.push(header);
         }
     }
 
@@ -194,12 +631,289 @@ fn parse_single_email_file(path: &PathBuf) -> Result<EmailSummary> {
         body_lines.pop();
     }
 
-    Ok(EmailSummary {
+    let mut email = text_email(from, subject, body_lines.join("\n"), headers);
+    email.raw = raw;
+    Ok(email)
+}
+
+/// Builds an email from the FROM:/SUBJECT:/BODY: text format. A
+/// `CONTENT-TYPE: text/html` header marks the body as HTML.
+fn text_email(
+    from: String,
+    subject: String,
+    body: String,
+    headers: Vec<(String, String)>,
+) -> EmailSummary {
+    let mut email = EmailSummary {
         from,
         subject,
-        body: body_lines.join("\n"),
+        body,
+        html: None,
+        content_type: None,
+        headers,
+        raw: String::new(),
         file_path: None,
-    })
+        security: None,
+        authentication: Vec::new(),
+        opened: false,
+        read: false,
+        flagged: false,
+        tags: Vec::new(),
+        recent: false,
+        remote: None,
+    };
+    email.content_type = email.header("Content-Type").map(str::to_string);
+
+    let is_html = email
+        .content_type
+        .as_deref()
+        .map(|ct| {
+            ct.trim_start()
+                .to_ascii_lowercase()
+                .starts_with("text/html")
+        })
+        .unwrap_or(false);
+    if is_html {
+        email.html = Some(std::mem::take(&mut email.body));
+    }
+    email
+}
+
+/// Parses an RFC 5322 message file, picking the text/plain and text/html
+/// bodies out of its MIME structure.
+fn parse_eml_file(path: &Path) -> Result<EmailSummary> {
+    let raw = fs::read(path)?;
+    Ok(parse_eml(&raw))
+}
+
+/// Parses a message as it is, without decrypting or verifying it.
+fn parse_eml(raw: &[u8]) -> EmailSummary {
+    summarize(&mime::Part::parse(raw), raw, None)
+}
+
+/// Parses a message, decrypting and verifying PGP/MIME and S/MIME messages
+/// and reading what they contain, and checks its authentication.
+fn open_eml(raw: &[u8], gpg: &Gpg, smime: &Smime, authentication: &Authentication) -> EmailSummary {
+    let message = mime::Part::parse(raw);
+    let opened = crypto::pgp::open(gpg, &message).or_else(|| crypto::smime::open(smime, &message));
+    let mut email = match opened {
+        Some((content, security)) => summarize(&content, raw, Some(security)),
+        None => summarize(&message, raw, None),
+    };
+    email.authentication = authentication.check(&email.headers, raw);
+    email.opened = true;
+    email
+}
+
+fn summarize(message: &mime::Part, raw: &[u8], security: Option<crypto::Security>) -> EmailSummary {
+    let headers: Vec<(String, String)> = message
+        .headers
+        .iter()
+        .map(|(k, v)| (k.clone(), mime::decode_header_value(v)))
+        .collect();
+    let plain = message.find_inline("text/plain");
+
+    let mut email = EmailSummary {
+        from: String::new(),
+        subject: String::new(),
+        body: plain
+            .map(|p| p.text().trim_end().to_string())
+            .unwrap_or_default(),
+        html: message.find_inline("text/html").map(|p| p.text()),
+        content_type: plain.and_then(|p| p.header("Content-Type").map(str::to_string)),
+        headers,
+        raw: String::from_utf8_lossy(raw).into_owned(),
+        file_path: None,
+        security,
+        authentication: Vec::new(),
+        opened: false,
+        read: false,
+        flagged: false,
+        tags: Vec::new(),
+        recent: false,
+        remote: None,
+    };
+    email.from = email.header("From").unwrap_or_default().to_string();
+    email.subject = email.header("Subject").unwrap_or_default().to_string();
+    email
+}
+
+/// Parses a `NAME: value` line from the header section of an email file.
+fn parse_header_line(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.split_once(':')?;
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((name.to_string(), value.trim().to_string()))
 }
 
 // Removed preview summarization; full body stored in EmailSummary.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "hermes-backend-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn message(html: Option<&str>) -> OutgoingMessage {
+        OutgoingMessage {
+            to: address::parse_address_list("bob@example.com").unwrap(),
+            subject: "Hi".to_string(),
+            body: "*hello*".to_string(),
+            html: html.map(str::to_string),
+            ..OutgoingMessage::default()
+        }
+    }
+
+    #[test]
+    fn legacy_single_file_refuses_html_mail() {
+        let dir = TempDir::new("legacy");
+        let path = dir.0.join("mail.txt");
+        let backend = FileBackend::new(&path, "me@example.com");
+        backend.send_email(&message(None)).unwrap();
+        let error = backend
+            .send_email(&message(Some("<p><em>hello</em></p>")))
+            .unwrap_err();
+        assert!(error.to_string().contains("HTML"));
+        // Only the plain message was written
+        assert_eq!(backend.fetch_inbox().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn html_mail_is_written_as_multipart_alternative() {
+        let dir = TempDir::new("html");
+        let backend = FileBackend::new(&dir.0, "me@example.com");
+        backend
+            .send_email(&message(Some("<p><em>hello</em></p>")))
+            .unwrap();
+        let inbox = backend.fetch_inbox().unwrap();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].body, "*hello*");
+        assert_eq!(inbox[0].html.as_deref(), Some("<p><em>hello</em></p>"));
+        assert!(
+            inbox[0]
+                .header("Content-Type")
+                .unwrap()
+                .starts_with("multipart/alternative")
+        );
+    }
+
+    #[test]
+    fn authentication_is_checked_when_a_message_is_opened() {
+        let dir = TempDir::new("open");
+        fs::write(
+            dir.0.join("a.eml"),
+            "Authentication-Results: mx.example.com; spf=pass\r\n\
+From: alice@example.com\r\nSubject: Hi\r\n\r\nHello\r\n",
+        )
+        .unwrap();
+        let backend =
+            FileBackend::new(&dir.0, "me@example.com").with_authentication(Authentication {
+                authserv_ids: vec!["mx.example.com".to_string()],
+                dkim: None,
+            });
+        let mut email = backend.fetch_inbox().unwrap().remove(0);
+        assert!(!email.opened);
+        assert!(email.authentication.is_empty());
+
+        email.read = true;
+        email.tags = vec!["work".to_string()];
+        backend.open_email(&mut email).unwrap();
+        assert!(email.opened);
+        assert_eq!(email.authentication.len(), 1);
+        assert_eq!(email.authentication[0].result, "pass");
+        // What the user changed is kept
+        assert!(email.read);
+        assert_eq!(email.tags, vec!["work"]);
+        assert_eq!(email.file_path, Some(dir.0.join("a.eml")));
+        assert_eq!(email.body, "Hello");
+    }
+
+    /// A directory of `count` plain-text messages, fetched once.
+    fn mailbox(name: &str, count: usize) -> (TempDir, Vec<EmailSummary>) {
+        let dir = TempDir::new(name);
+        for i in 0..count {
+            fs::write(
+                dir.0.join(format!("{:02}.txt", i)),
+                format!("FROM:a@example.com\nSUBJECT:{}\nBODY:\nHi\n", i),
+            )
+            .unwrap();
+        }
+        let inbox = FileBackend::new(&dir.0, "me@example.com")
+            .fetch_inbox()
+            .unwrap();
+        (dir, inbox)
+    }
+
+    #[test]
+    fn concurrent_updates_keep_each_others_flags() {
+        let (dir, inbox) = mailbox("concurrent-updates", 40);
+        // Separate backends stand in for separate hermes processes
+        std::thread::scope(|scope| {
+            for half in inbox.chunks(20) {
+                let backend = FileBackend::new(&dir.0, "me@example.com");
+                scope.spawn(move || {
+                    for email in half {
+                        let mut email = email.clone();
+                        email.read = true;
+                        backend.update_email(&email).unwrap();
+                    }
+                });
+            }
+        });
+        let inbox = FileBackend::new(&dir.0, "me@example.com")
+            .fetch_inbox()
+            .unwrap();
+        assert_eq!(inbox.iter().filter(|email| email.read).count(), 40);
+    }
+
+    #[test]
+    fn new_mail_is_recent_for_one_fetch_only() {
+        let (dir, _) = mailbox("concurrent-fetches", 0);
+        let backend = Mutex::new(FileBackend::new(&dir.0, "me@example.com"));
+        let recent = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..30 {
+                    fs::write(
+                        dir.0.join(format!("{:02}.txt", i)),
+                        "FROM:a@example.com\nSUBJECT:x\nBODY:\nHi\n",
+                    )
+                    .unwrap();
+                }
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        let inbox = backend.fetch_inbox().unwrap();
+                        let new = inbox.iter().filter(|email| email.recent).count();
+                        recent.fetch_add(new, std::sync::atomic::Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        let inbox = backend.fetch_inbox().unwrap();
+        let new = inbox.iter().filter(|email| email.recent).count();
+        assert_eq!(recent.into_inner() + new, 30);
+    }
+}
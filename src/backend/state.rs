@@ -0,0 +1,191 @@
+//! Per-message state for the file backend: whether a message has been read
+//! or flagged, and its tags. It is kept in a `.hermes-state` file in each
+//! mail directory, one line per message file with tab-separated fields:
+//! the file name, its flags and its comma-separated tags.
+//!
+//! `S` marks a read (seen) message and `F` a flagged one, as in Maildir; a
+//! message with neither has `-`. A message file that is not listed has not
+//! been fetched before.
+//!
+//! A `.hermes-state.lock` file next to it is locked from when the state is
+//! loaded until the `StateFile` is dropped, so another thread or hermes
+//! process changing the same directory waits instead of overwriting what
+//! this one saves. Changes are written to a temporary file that then
+//! replaces the state file, so a crash never leaves half of one behind.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+const FILE_NAME: &str = ".hermes-state";
+const LOCK_NAME: &str = ".hermes-state.lock";
+const TEMP_NAME: &str = ".hermes-state.tmp";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageState {
+    pub read: bool,
+    pub flagged: bool,
+    pub tags: Vec<String>,
+}
+
+/// The state file of one directory.
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    /// The lock file, locked for as long as the state is held.
+    _lock: File,
+    entries: BTreeMap<String, MessageState>,
+    changed: bool,
+}
+
+impl StateFile {
+    /// Locks and reads the state file in `dir`; without one the state is
+    /// empty.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(FILE_NAME);
+        let lock_path = dir.join(LOCK_NAME);
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .with_context(|| format!("opening {}", lock_path.display()))?;
+        lock.lock()
+            .with_context(|| format!("locking {}", lock_path.display()))?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading {}", path.display()));
+            }
+        };
+        let entries = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let name = fields.next().filter(|name| !name.is_empty())?;
+                let flags = fields.next().unwrap_or_default();
+                let tags = fields
+                    .next()
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                let state = MessageState {
+                    read: flags.contains('S'),
+                    flagged: flags.contains('F'),
+                    tags,
+                };
+                Some((name.to_string(), state))
+            })
+            .collect();
+        Ok(Self {
+            path,
+            _lock: lock,
+            entries,
+            changed: false,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MessageState> {
+        self.entries.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, state: MessageState) {
+        if self.entries.get(name) != Some(&state) {
+            self.entries.insert(name.to_string(), state);
+            self.changed = true;
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if self.entries.remove(name).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// Forgets messages whose files are gone.
+    pub fn retain(&mut self, present: impl Fn(&str) -> bool) {
+        let before = self.entries.len();
+        self.entries.retain(|name, _| present(name));
+        self.changed |= self.entries.len() != before;
+    }
+
+    /// Writes the file if anything changed since it was loaded.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut text = String::new();
+        for (name, state) in &self.entries {
+            let mut flags = String::new();
+            if state.read {
+                flags.push('S');
+            }
+            if state.flagged {
+                flags.push('F');
+            }
+            if flags.is_empty() {
+                flags.push('-');
+            }
+            text.push_str(&format!("{}\t{}\t{}\n", name, flags, state.tags.join(",")));
+        }
+        // Replaced while the lock is still held
+        let temp = self.path.with_file_name(TEMP_NAME);
+        File::create(&temp)
+            .and_then(|mut file| {
+                file.write_all(text.as_bytes())?;
+                file.sync_all()
+            })
+            .with_context(|| format!("writing {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("replacing {}", self.path.display()))?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_by_replacing_the_file() {
+        let dir = std::env::temp_dir().join(format!("hermes-state-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut state = StateFile::load(&dir).unwrap();
+        assert_eq!(state.get("a.eml"), None);
+        let read = MessageState {
+            read: true,
+            flagged: false,
+            tags: vec!["list".to_string(), "news".to_string()],
+        };
+        state.set("a.eml", read.clone());
+        state.set("b.eml", MessageState::default());
+        state.save().unwrap();
+        drop(state);
+        assert_eq!(
+            fs::read_to_string(dir.join(FILE_NAME)).unwrap(),
+            "a.eml\tS\tlist,news\nb.eml\t-\t\n"
+        );
+        assert!(!dir.join(TEMP_NAME).exists());
+
+        let mut state = StateFile::load(&dir).unwrap();
+        assert_eq!(state.get("a.eml"), Some(&read));
+        state.retain(|name| name == "a.eml");
+        state.save().unwrap();
+        drop(state);
+        let state = StateFile::load(&dir).unwrap();
+        assert_eq!(state.get("b.eml"), None);
+        drop(state);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
@@ -12,6 +14,7 @@ pub struct Cli {
 pub enum Commands {
     /// Send an email (non-interactive)
     Send {
+        /// Comma-separated recipients
         #[arg(short, long)]
         to: String,
 
@@ -20,6 +23,39 @@ pub enum Commands {
 
         #[arg(short, long)]
         body: String,
+
+        /// Comma-separated Cc recipients
+        #[arg(long)]
+        cc: Option<String>,
+
+        /// Comma-separated Bcc recipients
+        #[arg(long)]
+        bcc: Option<String>,
+
+        /// Reply-To address(es)
+        #[arg(long)]
+        reply_to: Option<String>,
+
+        /// Identity to send from (email or name); defaults to the first
+        /// configured one
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Treat the body as Markdown and add a rendered HTML part
+        #[arg(long)]
+        markdown: bool,
+
+        /// Sign with the sender's OpenPGP key
+        #[arg(long)]
+        sign: bool,
+
+        /// Encrypt to the recipients' OpenPGP keys
+        #[arg(long)]
+        encrypt: bool,
+
+        /// Sign with the configured S/MIME identity
+        #[arg(long, conflicts_with_all = ["sign", "encrypt"])]
+        smime: bool,
     },
 
     /// Delete an email by subject
@@ -30,4 +66,67 @@ pub enum Commands {
 
     /// Sync emails with the server
     Sync,
+
+    /// Manage the address book
+    Contacts {
+        #[command(subcommand)]
+        command: ContactsCommand,
+    },
+
+    /// Work with the filtering rules in the config file
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+
+    /// Check the Sieve script and manage it on the server
+    Sieve {
+        #[command(subcommand)]
+        command: SieveCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ContactsCommand {
+    /// Merge the contacts in a vCard file into the address book
+    Import { file: PathBuf },
+
+    /// Write the address book as vCard, to a file or standard output
+    Export {
+        file: Option<PathBuf>,
+
+        /// vCard version to write
+        #[arg(long = "vcard-version", value_parser = ["3.0", "4.0"], default_value = "4.0")]
+        version: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RulesCommand {
+    /// Show which rules would fire for a message file, without applying them
+    Test { message: PathBuf },
+}
+
+#[derive(Subcommand)]
+pub enum SieveCommand {
+    /// Check a script, by default the configured one
+    Check {
+        file: Option<PathBuf>,
+
+        /// Have the ManageSieve server check it too
+        #[arg(long)]
+        server: bool,
+    },
+
+    /// Upload a script, by default the configured one, to the ManageSieve server
+    Upload {
+        file: Option<PathBuf>,
+
+        /// Make it the script the server runs
+        #[arg(long)]
+        activate: bool,
+    },
+
+    /// List the scripts on the ManageSieve server
+    List,
 }
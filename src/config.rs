@@ -0,0 +1,547 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, process::Command};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::backend::address::Address;
+use crate::crypto::{
+    auth::Authentication,
+    dkim::{CommandResolver, StaticResolver, TxtResolver, Verifier},
+    pgp::Gpg,
+    smime::Smime,
+};
+use crate::net::{Server, Tls};
+
+/// User configuration, read from `$XDG_CONFIG_HOME/hermes/config.toml`
+/// (or `~/.config/hermes/config.toml`). Every setting has a default, so a
+/// missing file or section is not an error.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub viewer: ViewerConfig,
+    pub contacts: ContactsConfig,
+    pub compose: ComposeConfig,
+    pub pgp: PgpConfig,
+    pub smime: SmimeConfig,
+    pub authentication: AuthenticationConfig,
+    /// Addresses to send from, as `[[identity]]` tables; the first is the
+    /// default.
+    #[serde(rename = "identity")]
+    pub identities: Vec<Identity>,
+    /// Named message templates, as `[templates."name"]` tables.
+    pub templates: BTreeMap<String, Template>,
+    /// Filtering rules for new mail, as `[[rule]]` tables, tried in order.
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+    pub sieve: SieveConfig,
+    pub sync: SyncConfig,
+    pub imap: ImapConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ViewerConfig {
+    /// Command used to open links. `{}` is replaced with the URL; without
+    /// it the URL is appended as the last argument.
+    pub open_command: String,
+}
+
+impl Default for ViewerConfig {
+    fn default() -> Self {
+        Self {
+            open_command: if cfg!(target_os = "macos") {
+                "open".to_string()
+            } else {
+                "xdg-open".to_string()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ContactsConfig {
+    /// Address book file; defaults to `$XDG_DATA_HOME/hermes/contacts.vcf`.
+    pub path: Option<String>,
+    /// vCard files merged into the address book on startup.
+    pub import: Vec<String>,
+}
+
+/// OpenPGP through gpg and a local keyring.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PgpConfig {
+    /// gpg program (default: `gpg`).
+    pub program: Option<String>,
+    /// Keyring directory; gpg's default (`~/.gnupg`) when unset.
+    pub homedir: Option<String>,
+    /// Sign new messages by default.
+    pub sign: bool,
+    /// Encrypt new messages by default.
+    pub encrypt: bool,
+}
+
+impl PgpConfig {
+    pub fn gpg(&self) -> Gpg {
+        let mut gpg = Gpg::default();
+        if let Some(program) = &self.program {
+            gpg.program = program.clone();
+        }
+        gpg.homedir = self.homedir.as_deref().map(expand_path);
+        gpg
+    }
+}
+
+/// S/MIME through openssl, trusting a local CA bundle.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SmimeConfig {
+    /// openssl program, OpenSSL 3 or newer (default: `openssl`).
+    pub program: Option<String>,
+    /// PEM file of the CA certificates to trust; OpenSSL's default store
+    /// when unset.
+    pub ca_file: Option<String>,
+    /// PKCS#12 file with the key and certificate to sign with.
+    pub pkcs12: Option<String>,
+    /// Command printing the PKCS#12 password, such as `pass show smime`.
+    pub password_command: Option<String>,
+    /// Sign new messages by default.
+    pub sign: bool,
+}
+
+impl SmimeConfig {
+    pub fn smime(&self) -> Smime {
+        let mut smime = Smime::default();
+        if let Some(program) = &self.program {
+            smime.program = program.clone();
+        }
+        smime.ca_file = self.ca_file.as_deref().map(expand_path);
+        smime.pkcs12 = self.pkcs12.as_deref().map(expand_path);
+        smime.password_command = self.password_command.clone();
+        smime
+    }
+}
+
+/// Which DKIM, SPF and DMARC results to show, and local DKIM checks.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuthenticationConfig {
+    /// `authserv-id`s of your own mail servers; only their
+    /// `Authentication-Results` headers are believed. When empty, none
+    /// are.
+    pub authserv_ids: Vec<String>,
+    /// Verify DKIM signatures locally.
+    pub verify_dkim: bool,
+    /// Command printing the TXT records for `{}`; empty to use only
+    /// `dkim_records`.
+    pub dns_command: String,
+    /// DKIM key records by name (`selector._domainkey.domain`), consulted
+    /// before DNS.
+    pub dkim_records: BTreeMap<String, String>,
+}
+
+impl Default for AuthenticationConfig {
+    fn default() -> Self {
+        Self {
+            authserv_ids: Vec::new(),
+            verify_dkim: false,
+            dns_command: "dig +short TXT {}".to_string(),
+            dkim_records: BTreeMap::new(),
+        }
+    }
+}
+
+impl AuthenticationConfig {
+    /// `openssl` is the program DKIM signatures are checked with.
+    pub fn authentication(&self, openssl: &str) -> Authentication {
+        let mut resolvers: Vec<Box<dyn TxtResolver>> = Vec::new();
+        if !self.dkim_records.is_empty() {
+            resolvers.push(Box::new(StaticResolver {
+                records: self.dkim_records.clone(),
+            }));
+        }
+        if !self.dns_command.trim().is_empty() {
+            resolvers.push(Box::new(CommandResolver {
+                command: self.dns_command.clone(),
+            }));
+        }
+        Authentication {
+            authserv_ids: self.authserv_ids.clone(),
+            dkim: self.verify_dkim.then(|| Verifier {
+                program: openssl.to_string(),
+                resolver: Box::new(resolvers),
+            }),
+        }
+    }
+}
+
+/// Fetching mail in the background while the interface is open.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Seconds between syncs; 0 turns background sync off.
+    pub interval: u64,
+    /// Command run when new mail arrives. `{}` is replaced with a summary
+    /// such as `Jane Doe: Lunch?` or `3 new messages`; without a
+    /// placeholder the summary is appended.
+    pub notify_command: Option<String>,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            interval: 300,
+            notify_command: None,
+        }
+    }
+}
+
+/// An IMAP account whose folders are watched with IDLE while the
+/// interface is open, their messages shown in the inbox as they change.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImapConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    pub user: String,
+    /// Command printing the password, such as `pass show mail`.
+    pub password_command: Option<String>,
+    /// `tls` (the default), `starttls`, or `none` for a server on the same
+    /// machine.
+    pub tls: Tls,
+    /// PEM file of the CA certificates to trust instead of the usual roots.
+    pub ca_file: Option<String>,
+    /// Folders to watch, such as `INBOX`. Their mail is not run through
+    /// the filtering rules or the Sieve script.
+    pub watch: Vec<String>,
+    /// Seconds before IDLE is restarted; servers may end it after 30
+    /// minutes.
+    pub idle_timeout: u64,
+}
+
+impl Default for ImapConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 993,
+            user: String::new(),
+            password_command: None,
+            tls: Tls::Implicit,
+            ca_file: None,
+            watch: Vec::new(),
+            idle_timeout: 29 * 60,
+        }
+    }
+}
+
+impl ImapConfig {
+    pub fn server(&self) -> Result<Server> {
+        let Some(host) = &self.host else {
+            bail!("no IMAP server configured (imap.host)");
+        };
+        Ok(Server {
+            host: host.clone(),
+            port: self.port,
+            user: self.user.clone(),
+            password_command: self.password_command.clone(),
+            tls: self.tls,
+            ca_file: self.ca_file.as_deref().map(expand_path),
+        })
+    }
+}
+
+/// A Sieve script run on new mail after the `[[rule]]` tables, and the
+/// ManageSieve server it is uploaded to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SieveConfig {
+    /// Script file; none is run when unset.
+    pub script: Option<String>,
+    /// ManageSieve server; needed only to upload the script.
+    pub host: Option<String>,
+    pub port: u16,
+    pub user: String,
+    /// Command printing the password, such as `pass show mail`.
+    pub password_command: Option<String>,
+    /// Name the script is stored under on the server.
+    pub name: String,
+    /// `starttls` (the default), `tls`, or `none` for a server on the
+    /// same machine.
+    pub tls: Tls,
+    /// PEM file of the CA certificates to trust instead of the usual roots.
+    pub ca_file: Option<String>,
+}
+
+impl Default for SieveConfig {
+    fn default() -> Self {
+        Self {
+            script: None,
+            host: None,
+            port: 4190,
+            user: String::new(),
+            password_command: None,
+            name: "hermes".to_string(),
+            tls: Tls::default(),
+            ca_file: None,
+        }
+    }
+}
+
+impl SieveConfig {
+    pub fn script_path(&self) -> Option<PathBuf> {
+        self.script.as_deref().map(expand_path)
+    }
+
+    pub fn server(&self) -> Result<Server> {
+        let Some(host) = &self.host else {
+            bail!("no ManageSieve server configured (sieve.host)");
+        };
+        Ok(Server {
+            host: host.clone(),
+            port: self.port,
+            user: self.user.clone(),
+            password_command: self.password_command.clone(),
+            tls: self.tls,
+            ca_file: self.ca_file.as_deref().map(expand_path),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ComposeConfig {
+    /// Editor for `E` in compose; defaults to `$VISUAL`, then `$EDITOR`,
+    /// then `vi`. `{}` is replaced with the draft's path.
+    pub editor: Option<String>,
+    /// Open new messages straight in the external editor.
+    pub use_editor: bool,
+    /// Copy every yank and delete in compose to the system clipboard
+    /// (OSC 52), not just those into the `+` and `*` registers.
+    pub clipboard: bool,
+    /// Hunspell dictionary for spell checking the body, by name (`en_US`)
+    /// or path; identities may choose their own.
+    pub dictionary: Option<String>,
+    /// Start new messages in Markdown, sent with a rendered HTML part.
+    pub markdown: bool,
+}
+
+impl ComposeConfig {
+    /// The editor command line to run.
+    pub fn editor_command(&self) -> String {
+        self.editor
+            .clone()
+            .or_else(|| std::env::var("VISUAL").ok())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .filter(|e| !e.trim().is_empty())
+            .unwrap_or_else(|| "vi".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Identity {
+    /// Display name for the From header.
+    pub name: Option<String>,
+    pub email: String,
+    /// Signature appended to new messages below a `-- ` line.
+    pub signature: Option<String>,
+    /// File to read the signature from instead.
+    pub signature_file: Option<String>,
+    /// Spell checking dictionary, overriding `compose.dictionary`.
+    pub dictionary: Option<String>,
+}
+
+impl Identity {
+    pub fn address(&self) -> Address {
+        Address {
+            name: self.name.clone(),
+            email: self.email.clone(),
+        }
+    }
+}
+
+/// Finds an identity by exact email, or else by a case-insensitive match
+/// anywhere in its name or email.
+pub fn find_identity(identities: &[Identity], query: &str) -> Option<usize> {
+    let query = query.trim().to_lowercase();
+    identities
+        .iter()
+        .position(|i| i.email.to_lowercase() == query)
+        .or_else(|| {
+            identities.iter().position(|i| {
+                i.email.to_lowercase().contains(&query)
+                    || i.name
+                        .as_deref()
+                        .is_some_and(|n| n.to_lowercase().contains(&query))
+            })
+        })
+}
+
+/// A message skeleton chosen with `:template <name>`. Text fields may use
+/// the placeholders listed in `template::expand`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Template {
+    /// Identity to send from, matched as with `:identity`.
+    pub identity: Option<String>,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+/// A filtering rule; see `rules::Rules` for how it is applied.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    /// Fire when any condition matches, rather than all of them.
+    pub any: bool,
+    /// `[[rule.condition]]` tables.
+    #[serde(rename = "condition")]
+    pub conditions: Vec<Condition>,
+    /// Folder to move matching mail to.
+    #[serde(rename = "move")]
+    pub move_to: Option<String>,
+    pub tag: Vec<String>,
+    pub mark_read: bool,
+    pub flag: bool,
+    pub delete: bool,
+    /// Skip the rules after this one when it fires.
+    pub stop: bool,
+}
+
+/// One test of a rule: a header or the body against `contains`, `is` or
+/// `regex`, or the message size against `larger_than`/`smaller_than`
+/// (bytes, or with a `K`, `M` or `G` suffix).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Condition {
+    pub header: Option<String>,
+    pub body: bool,
+    pub contains: Option<String>,
+    pub is: Option<String>,
+    pub regex: Option<String>,
+    pub larger_than: Option<String>,
+    pub smaller_than: Option<String>,
+    /// Invert the test.
+    pub not: bool,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        let mut config: Config = toml::from_str(&text)
+            .with_context(|| format!("parsing config file {}", path.display()))?;
+
+        // Read signature files up front so composing never touches the disk
+        for identity in &mut config.identities {
+            if let Some(file) = &identity.signature_file {
+                let path = expand_path(file);
+                let text = fs::read_to_string(&path)
+                    .with_context(|| format!("reading signature file {}", path.display()))?;
+                identity.signature = Some(text);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Every dictionary named in the configuration, without duplicates.
+    pub fn dictionaries(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        let configured = self.compose.dictionary.iter();
+        for name in configured.chain(self.identities.iter().filter_map(|i| i.dictionary.as_ref())) {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The identity named by `query`, or the default one.
+    pub fn identity(&self, query: Option<&str>) -> Result<Option<&Identity>> {
+        match query {
+            Some(query) => match find_identity(&self.identities, query) {
+                Some(index) => Ok(Some(&self.identities[index])),
+                None => anyhow::bail!("no identity matches {:?}", query),
+            },
+            None => Ok(self.identities.first()),
+        }
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("HERMES_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("hermes").join("config.toml"))
+}
+
+/// Directory for data Hermes maintains itself, such as the address book:
+/// `$XDG_DATA_HOME/hermes` or `~/.local/share/hermes`.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })?;
+    Some(base.join("hermes"))
+}
+
+/// Expands a leading `~/` in a configured path to the home directory.
+pub fn expand_path(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/")
+        && let Some(home) = std::env::var_os("HOME")
+    {
+        return PathBuf::from(home).join(rest);
+    }
+    PathBuf::from(path)
+}
+
+/// Splits a configured command line into program and arguments, substituting
+/// `{}` with `arg` (or appending it when there is no placeholder).
+pub fn command_line(template: &str, arg: &str) -> Option<(String, Vec<String>)> {
+    let mut words = template.split_whitespace().map(str::to_string);
+    let program = words.next()?;
+    let mut args: Vec<String> = words.collect();
+    let mut substituted = false;
+    for a in args.iter_mut() {
+        if a.contains("{}") {
+            *a = a.replace("{}", arg);
+            substituted = true;
+        }
+    }
+    if !substituted {
+        args.push(arg.to_string());
+    }
+    Some((program, args))
+}
+
+/// Runs a configured command and returns the first line it prints.
+pub fn password(command: &str) -> Result<String> {
+    let Some((program, args)) = command_line(command, "") else {
+        bail!("empty password command");
+    };
+    let output = Command::new(&program)
+        .args(args.iter().filter(|a| !a.is_empty()))
+        .output()
+        .with_context(|| format!("running {}", program))?;
+    if !output.status.success() {
+        bail!("password command failed: {}", command);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
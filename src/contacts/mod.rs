@@ -0,0 +1,417 @@
+//! The address book: contacts harvested from message headers or imported
+//! from vCard files, stored as a vCard file in the data directory.
+
+pub mod vcard;
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::backend::{
+    EmailSummary, OutgoingMessage,
+    address::{self, Address},
+    mime,
+};
+use crate::config::{self, ContactsConfig};
+
+/// Most completion candidates offered at once.
+const MAX_COMPLETIONS: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contact {
+    pub name: Option<String>,
+    pub emails: Vec<String>,
+    pub notes: String,
+}
+
+impl Contact {
+    /// The contact's name, or its first address when it has none.
+    pub fn display_name(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.emails.first().map(String::as_str))
+            .unwrap_or_default()
+    }
+
+    pub fn has_email(&self, email: &str) -> bool {
+        self.emails.iter().any(|e| e.eq_ignore_ascii_case(email))
+    }
+
+    pub fn address(&self, email: &str) -> Address {
+        Address {
+            name: self.name.clone(),
+            email: email.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ContactStore {
+    pub contacts: Vec<Contact>,
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+
+impl ContactStore {
+    /// Loads the address book and merges in the vCard files listed under
+    /// `[contacts] import`. A missing address book is simply empty.
+    pub fn load(config: &ContactsConfig) -> Result<Self> {
+        let path = match &config.path {
+            Some(path) => Some(config::expand_path(path)),
+            None => config::data_dir().map(|dir| dir.join("contacts.vcf")),
+        };
+        let mut store = ContactStore {
+            path,
+            ..Default::default()
+        };
+        if let Some(path) = &store.path
+            && path.exists()
+        {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("reading address book {}", path.display()))?;
+            store.contacts = vcard::parse(&text);
+        }
+        for file in &config.import {
+            let path = config::expand_path(file);
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("reading vCard file {}", path.display()))?;
+            store.import(vcard::parse(&text));
+        }
+        store.sort();
+        Ok(store)
+    }
+
+    /// Writes the address book back if anything was added since loading.
+    pub fn save(&mut self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("creating directory {}", dir.display()))?;
+        }
+        fs::write(path, vcard::write(&self.contacts, vcard::Version::V4))
+            .with_context(|| format!("writing address book {}", path.display()))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Records an address, filling in the name of a known contact that had
+    /// none. Returns true when the store changed.
+    pub fn add(&mut self, address: &Address) -> bool {
+        let name = address
+            .name
+            .as_deref()
+            .map(mime::decode_header_value)
+            .filter(|n| !n.trim().is_empty() && !n.eq_ignore_ascii_case(&address.email));
+        if let Some(contact) = self
+            .contacts
+            .iter_mut()
+            .find(|c| c.has_email(&address.email))
+        {
+            if contact.name.is_none() && name.is_some() {
+                contact.name = name;
+                self.dirty = true;
+                return true;
+            }
+            return false;
+        }
+        self.contacts.push(Contact {
+            name,
+            emails: vec![address.email.clone()],
+            notes: String::new(),
+        });
+        self.dirty = true;
+        true
+    }
+
+    /// Merges imported contacts, adding addresses and names to existing
+    /// entries with a matching address. Returns how many contacts changed.
+    pub fn import(&mut self, contacts: Vec<Contact>) -> usize {
+        let mut changed = 0;
+        for incoming in contacts {
+            let existing = self
+                .contacts
+                .iter_mut()
+                .find(|c| incoming.emails.iter().any(|e| c.has_email(e)));
+            match existing {
+                Some(contact) => {
+                    let mut updated = false;
+                    if contact.name.is_none() && incoming.name.is_some() {
+                        contact.name = incoming.name;
+                        updated = true;
+                    }
+                    for email in incoming.emails {
+                        if !contact.has_email(&email) {
+                            contact.emails.push(email);
+                            updated = true;
+                        }
+                    }
+                    if contact.notes.is_empty() && !incoming.notes.is_empty() {
+                        contact.notes = incoming.notes;
+                        updated = true;
+                    }
+                    changed += updated as usize;
+                }
+                None => {
+                    self.contacts.push(incoming);
+                    changed += 1;
+                }
+            }
+        }
+        self.dirty |= changed > 0;
+        changed
+    }
+
+    /// Replaces the contact at `index`, returning its position after the
+    /// list is re-sorted.
+    pub fn update(&mut self, index: usize, contact: Contact) -> usize {
+        let emails = contact.emails.clone();
+        match self.contacts.get_mut(index) {
+            Some(existing) => *existing = contact,
+            None => self.contacts.push(contact),
+        }
+        self.dirty = true;
+        self.sort();
+        self.contacts
+            .iter()
+            .position(|c| c.emails == emails)
+            .unwrap_or(0)
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.contacts.len() {
+            self.contacts.remove(index);
+            self.dirty = true;
+        }
+    }
+
+    /// Orders contacts by name, ignoring case.
+    pub fn sort(&mut self) {
+        self.contacts
+            .sort_by_key(|c| c.display_name().to_lowercase());
+    }
+
+    /// Collects the senders and recipients of the given messages, skipping
+    /// the user's own address.
+    pub fn harvest(&mut self, emails: &[EmailSummary], own_email: &str) {
+        for email in emails {
+            let mut lists = vec![email.from.as_str()];
+            for name in ["To", "Cc", "Reply-To"] {
+                if let Some(value) = email.header(name) {
+                    lists.push(value);
+                }
+            }
+            for list in lists {
+                // Malformed headers in received mail are not worth failing over
+                let Ok(addresses) = address::parse_address_list(list) else {
+                    continue;
+                };
+                for address in addresses {
+                    if !address.email.eq_ignore_ascii_case(own_email) {
+                        self.add(&address);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Collects the recipients of a message the user sent.
+    pub fn harvest_sent(&mut self, message: &OutgoingMessage) {
+        for address in message.to.iter().chain(&message.cc).chain(&message.bcc) {
+            self.add(address);
+        }
+    }
+
+    /// Addresses fuzzily matching `query`, best first.
+    pub fn complete(&self, query: &str) -> Vec<Address> {
+        let mut scored: Vec<(i64, Address)> = self
+            .contacts
+            .iter()
+            .flat_map(|contact| contact.emails.iter().map(move |e| contact.address(e)))
+            .filter_map(|address| Some((fuzzy_score(query, &address.to_string())?, address)))
+            .collect();
+        scored.sort_by(|(a, x), (b, y)| b.cmp(a).then_with(|| x.to_string().cmp(&y.to_string())));
+        scored
+            .into_iter()
+            .take(MAX_COMPLETIONS)
+            .map(|(_, address)| address)
+            .collect()
+    }
+}
+
+/// Scores `candidate` against `query` when every query character appears in
+/// it in order (ignoring case). Consecutive matches and matches at the
+/// start of a word score higher, so "jd" prefers "John Doe" over "jared".
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut previous: Option<usize> = None;
+
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let found = pos + candidate[pos..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        // Skipped characters cost a little, so tighter matches win
+        score -= (found - pos).min(10) as i64 / 2;
+        previous = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FileBackend;
+
+    fn address(name: Option<&str>, email: &str) -> Address {
+        Address {
+            name: name.map(str::to_string),
+            email: email.to_string(),
+        }
+    }
+
+    fn store(addresses: &[(Option<&str>, &str)]) -> ContactStore {
+        let mut store = ContactStore::default();
+        for (name, email) in addresses {
+            store.add(&address(*name, email));
+        }
+        store.sort();
+        store
+    }
+
+    #[test]
+    fn fuzzy_scores_prefer_word_starts_and_runs() {
+        assert_eq!(fuzzy_score("xyz", "John Doe"), None);
+        assert_eq!(fuzzy_score("eoj", "John Doe"), None);
+        assert!(fuzzy_score("", "anything").is_some());
+        // Word starts beat scattered letters
+        assert!(fuzzy_score("jd", "John Doe") > fuzzy_score("jd", "jared"));
+        // A run of letters beats the same letters spread out
+        assert!(fuzzy_score("ann", "Anna") > fuzzy_score("ann", "Alan North"));
+        // Case and spaces in the query do not matter
+        assert_eq!(
+            fuzzy_score("J D", "john doe"),
+            fuzzy_score("jd", "John Doe")
+        );
+    }
+
+    #[test]
+    fn completes_best_matches_first() {
+        let store = store(&[
+            (Some("Jared Smith"), "jared@example.com"),
+            (Some("John Doe"), "john@example.com"),
+            (None, "support@example.org"),
+        ]);
+        let completions = store.complete("jd");
+        assert_eq!(completions[0].email, "john@example.com");
+        assert_eq!(completions.len(), 2);
+        assert_eq!(
+            store.complete("support").first(),
+            Some(&address(None, "support@example.org"))
+        );
+        assert!(store.complete("nobody").is_empty());
+    }
+
+    #[test]
+    fn adds_names_to_known_addresses() {
+        let mut store = ContactStore::default();
+        assert!(store.add(&address(None, "ann@example.com")));
+        assert!(!store.add(&address(None, "ANN@example.com")));
+        assert!(store.add(&address(Some("Ann Lee"), "ann@example.com")));
+        // Names that are only the address, and names already known, are kept
+        assert!(!store.add(&address(Some("Ann B"), "ann@example.com")));
+        assert!(store.add(&address(Some("bob@example.com"), "bob@example.com")));
+        assert_eq!(store.contacts[0].name.as_deref(), Some("Ann Lee"));
+        assert_eq!(store.contacts[1].name, None);
+        // Encoded names are decoded
+        store.add(&address(Some("=?UTF-8?Q?Jos=C3=A9?="), "jose@example.com"));
+        assert_eq!(store.contacts[2].name.as_deref(), Some("José"));
+    }
+
+    #[test]
+    fn harvests_senders_and_recipients() {
+        let backend = FileBackend::new("unused", "me@example.com");
+        let emails = [
+            backend.parse_message(
+                b"From: Ann Lee <ann@example.com>\r\nTo: me@example.com, Bob <bob@example.com>\r\n\
+Cc: undisclosed-recipients:;\r\nReply-To: <list@example.org>\r\nSubject: Hi\r\n\r\nHello\r\n",
+            ),
+            backend.parse_message(b"From: <<broken\r\nTo: Carol <carol@example.com>\r\n\r\nHi\r\n"),
+        ];
+        let mut store = ContactStore::default();
+        store.harvest(&emails, "ME@example.com");
+        let mut found: Vec<String> = store
+            .contacts
+            .iter()
+            .flat_map(|c| c.emails.clone())
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                "ann@example.com",
+                "bob@example.com",
+                "carol@example.com",
+                "list@example.org"
+            ]
+        );
+
+        let mut sent = ContactStore::default();
+        sent.harvest_sent(&OutgoingMessage {
+            to: vec![address(Some("Dan"), "dan@example.com")],
+            bcc: vec![address(None, "eve@example.com")],
+            ..Default::default()
+        });
+        assert_eq!(sent.contacts.len(), 2);
+    }
+
+    #[test]
+    fn saves_only_when_changed() {
+        let dir = std::env::temp_dir().join(format!("hermes-contacts-{}", std::process::id()));
+        let path = dir.join("book").join("contacts.vcf");
+        let config = ContactsConfig {
+            path: Some(path.to_string_lossy().into_owned()),
+            import: Vec::new(),
+        };
+
+        // A missing address book is empty and not written until it changes
+        let mut store = ContactStore::load(&config).unwrap();
+        assert!(store.contacts.is_empty());
+        store.save().unwrap();
+        assert!(!path.exists());
+
+        store.add(&address(Some("Ann Lee"), "ann@example.com"));
+        store.save().unwrap();
+        let reloaded = ContactStore::load(&config).unwrap();
+        assert_eq!(reloaded.contacts, store.contacts);
+
+        // Unchanged stores leave the file alone
+        fs::write(&path, "edited elsewhere").unwrap();
+        store.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+
+        // Unreadable files fail to load rather than loading empty
+        let broken = ContactsConfig {
+            path: Some(dir.to_string_lossy().into_owned()),
+            import: Vec::new(),
+        };
+        let loaded = ContactStore::load(&broken);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(text, "edited elsewhere");
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Reading and writing vCard (RFC 2426 / RFC 6350) address books.
+
+use super::Contact;
+
+/// Parses every `BEGIN:VCARD` … `END:VCARD` block in a vCard file. Cards
+/// without an email address are skipped since they cannot be used as
+/// recipients.
+pub fn parse(text: &str) -> Vec<Contact> {
+    let mut contacts = Vec::new();
+    let mut current: Option<Contact> = None;
+    let mut structured_name: Option<String> = None;
+
+    for line in unfold(text) {
+        let Some((property, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = property.split(';');
+        // Drop a group prefix such as "item1.EMAIL"
+        let name = params
+            .next()
+            .unwrap_or_default()
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+
+        match name.as_str() {
+            "BEGIN" if value.trim().eq_ignore_ascii_case("VCARD") => {
+                current = Some(Contact::default());
+                structured_name = None;
+            }
+            "END" if value.trim().eq_ignore_ascii_case("VCARD") => {
+                if let Some(mut contact) = current.take() {
+                    if contact.name.is_none() {
+                        contact.name = structured_name.take();
+                    }
+                    if !contact.emails.is_empty() {
+                        contacts.push(contact);
+                    }
+                }
+            }
+            _ => {
+                let Some(contact) = current.as_mut() else {
+                    continue;
+                };
+                match name.as_str() {
+                    "FN" => {
+                        let value = unescape(value);
+                        if !value.trim().is_empty() {
+                            contact.name = Some(value.trim().to_string());
+                        }
+                    }
+                    "N" => {
+                        // Family;Given;Additional;Prefix;Suffix
+                        let parts: Vec<String> = split_unescaped(value, ';');
+                        let given = parts.get(1).map(String::as_str).unwrap_or_default();
+                        let family = parts.first().map(String::as_str).unwrap_or_default();
+                        let full = format!("{} {}", given, family).trim().to_string();
+                        if !full.is_empty() {
+                            structured_name = Some(full);
+                        }
+                    }
+                    "EMAIL" => {
+                        let email = value.trim().trim_start_matches("mailto:").to_string();
+                        if !email.is_empty() && !contact.has_email(&email) {
+                            contact.emails.push(email);
+                        }
+                    }
+                    "NOTE" => {
+                        if !contact.notes.is_empty() {
+                            contact.notes.push('\n');
+                        }
+                        contact.notes.push_str(&unescape(value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    contacts
+}
+
+/// The vCard versions that can be written. Both are read the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// RFC 2426, still the most widely supported.
+    V3,
+    /// RFC 6350.
+    V4,
+}
+
+impl Version {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "3" | "3.0" => Some(Version::V3),
+            "4" | "4.0" => Some(Version::V4),
+            _ => None,
+        }
+    }
+}
+
+/// Serialises contacts in the given vCard version.
+pub fn write(contacts: &[Contact], version: Version) -> String {
+    let mut out = String::new();
+    for contact in contacts {
+        let name = contact.display_name();
+        push_line(&mut out, "BEGIN:VCARD");
+        push_line(
+            &mut out,
+            match version {
+                Version::V3 => "VERSION:3.0",
+                Version::V4 => "VERSION:4.0",
+            },
+        );
+        push_line(&mut out, &format!("FN:{}", escape(name)));
+        // N is mandatory in 3.0; split off the last word as the family name
+        let (given, family) = match &contact.name {
+            Some(name) => name.rsplit_once(' ').unwrap_or(("", name)),
+            None => ("", ""),
+        };
+        push_line(
+            &mut out,
+            &format!("N:{};{};;;", escape(family), escape(given)),
+        );
+        for email in &contact.emails {
+            let line = match version {
+                Version::V3 => format!("EMAIL;TYPE=INTERNET:{}", email),
+                Version::V4 => format!("EMAIL:{}", email),
+            };
+            push_line(&mut out, &line);
+        }
+        if !contact.notes.is_empty() {
+            push_line(&mut out, &format!("NOTE:{}", escape(&contact.notes)));
+        }
+        push_line(&mut out, "END:VCARD");
+    }
+    out
+}
+
+/// Appends a content line, folded at 75 octets as the RFCs require.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for ch in line.chars() {
+        if width + ch.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(ch);
+        width += ch.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Joins folded continuation lines (those starting with a space or tab).
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(rest) = line.strip_prefix([' ', '\t'])
+            && let Some(last) = lines.last_mut()
+        {
+            last.push_str(rest);
+        } else if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => {}
+            }
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Splits a structured value on unescaped separators.
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for ch in value.chars() {
+        if escaped {
+            // Keep the escape for `unescape` to resolve
+            parts.last_mut().unwrap().push('\\');
+            parts.last_mut().unwrap().push(ch);
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == separator {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(ch);
+        }
+    }
+    parts.into_iter().map(|p| unescape(&p)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(name: Option<&str>, emails: &[&str], notes: &str) -> Contact {
+        Contact {
+            name: name.map(str::to_string),
+            emails: emails.iter().map(|e| e.to_string()).collect(),
+            notes: notes.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_version_3_cards() {
+        let text = "BEGIN:VCARD\r\n\
+VERSION:3.0\r\n\
+N:Smith;Bob;;;\r\n\
+item1.EMAIL;TYPE=INTERNET:bob@example.com\r\n\
+EMAIL;TYPE=WORK:mailto:bob@work.example\r\n\
+EMAIL:BOB@example.com\r\n\
+NOTE:line one\\nline two\\, with a comma\r\n\
+END:VCARD\r\n\
+BEGIN:VCARD\r\n\
+VERSION:3.0\r\n\
+FN:No Address\r\n\
+END:VCARD\r\n";
+        assert_eq!(
+            parse(text),
+            vec![contact(
+                Some("Bob Smith"),
+                &["bob@example.com", "bob@work.example"],
+                "line one\nline two, with a comma"
+            )]
+        );
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        // Version 3 folds with a space, version 4 clients also use tabs
+        let text = "BEGIN:VCARD\nVERSION:4.0\nFN:Ann\n  Lee\nEMAIL:ann@exa\n\tmple.com\nNOTE:a\n b\nEND:VCARD\n";
+        assert_eq!(
+            parse(text),
+            vec![contact(Some("Ann Lee"), &["ann@example.com"], "ab")]
+        );
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let long = contact(Some(&"é".repeat(60)), &["a@example.com"], &"x".repeat(200));
+        for version in [Version::V3, Version::V4] {
+            let text = write(std::slice::from_ref(&long), version);
+            assert!(text.split("\r\n").all(|line| line.len() <= 75));
+            assert!(text.contains("\r\n "));
+            assert_eq!(parse(&text), vec![long.clone()]);
+        }
+    }
+
+    #[test]
+    fn round_trips_both_versions() {
+        let contacts = vec![
+            contact(
+                Some("Smith; Bob, Jr"),
+                &["bob@example.com"],
+                "back\\slash\nnew line",
+            ),
+            contact(None, &["anon@example.com", "other@example.com"], ""),
+        ];
+        for version in [Version::V3, Version::V4] {
+            let text = write(&contacts, version);
+            let mut expected = contacts.clone();
+            // A nameless contact is written with its address as FN
+            expected[1].name = Some("anon@example.com".to_string());
+            assert_eq!(parse(&text), expected);
+        }
+        assert!(write(&contacts, Version::V3).contains("VERSION:3.0\r\n"));
+        assert!(write(&contacts, Version::V4).contains("VERSION:4.0\r\n"));
+    }
+
+    #[test]
+    fn parses_version_numbers() {
+        assert_eq!(Version::parse("3"), Some(Version::V3));
+        assert_eq!(Version::parse("4.0"), Some(Version::V4));
+        assert_eq!(Version::parse("2.1"), None);
+    }
+
+    #[test]
+    fn splits_structured_values_on_unescaped_separators() {
+        assert_eq!(
+            split_unescaped(r"Family\;Name;Given;;;", ';'),
+            vec!["Family;Name", "Given", "", "", ""]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Sender authentication: the DKIM, SPF and DMARC results a receiving
+//! server recorded in `Authentication-Results` (RFC 8601), plus DKIM
+//! signatures checked locally.
+
+use super::{Level, dkim};
+
+/// The outcome of one authentication method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthResult {
+    /// `dkim`, `spf`, `dmarc`, ...
+    pub method: String,
+    /// `pass`, `fail`, `softfail`, `neutral`, `none`, `temperror`, ...
+    pub result: String,
+    /// What was checked, such as the signing domain for DKIM.
+    pub subject: Option<String>,
+    /// Why it failed, when known.
+    pub reason: Option<String>,
+    /// Checked by hermes rather than reported by a server.
+    pub local: bool,
+}
+
+impl AuthResult {
+    /// Short form for the viewer, such as `DKIM pass (example.com)`.
+    pub fn label(&self) -> String {
+        let mut label = self.method.to_ascii_uppercase();
+        if self.local {
+            label.push_str(" (local)");
+        }
+        label.push(' ');
+        label.push_str(&self.result);
+        match (&self.subject, &self.reason) {
+            (Some(subject), Some(reason)) => label.push_str(&format!(" ({}: {})", subject, reason)),
+            (Some(detail), None) | (None, Some(detail)) => {
+                label.push_str(&format!(" ({})", detail))
+            }
+            (None, None) => {}
+        }
+        label
+    }
+
+    pub fn level(&self) -> Level {
+        match self.result.as_str() {
+            "pass" => Level::Good,
+            "fail" | "permerror" => Level::Bad,
+            _ => Level::Warning,
+        }
+    }
+}
+
+/// Which results to believe and how to check DKIM locally.
+#[derive(Default)]
+pub struct Authentication {
+    /// `authserv-id`s of the servers whose results are trusted. Senders
+    /// can add `Authentication-Results` headers of their own (RFC 8601,
+    /// section 7.1), so when this is empty none are read.
+    pub authserv_ids: Vec<String>,
+    pub dkim: Option<dkim::Verifier>,
+}
+
+impl Authentication {
+    /// Results for a message; `raw` is its source as received.
+    pub fn check(&self, headers: &[(String, String)], raw: &[u8]) -> Vec<AuthResult> {
+        let mut results = reported(headers, &self.authserv_ids);
+        if let Some(verifier) = &self.dkim {
+            results.extend(verifier.verify(raw));
+        }
+        results
+    }
+}
+
+/// Results from the `Authentication-Results` headers of trusted servers.
+pub fn reported(headers: &[(String, String)], authserv_ids: &[String]) -> Vec<AuthResult> {
+    let fields = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Authentication-Results"))
+        .map(|(_, value)| parse(value));
+    fields
+        .filter(|(id, _)| authserv_ids.iter().any(|t| t.eq_ignore_ascii_case(id)))
+        .flat_map(|(_, results)| results)
+        .collect()
+}
+
+/// Parses an `Authentication-Results` value into its authserv-id and
+/// results.
+pub fn parse(value: &str) -> (String, Vec<AuthResult>) {
+    let text = strip_comments(value);
+    let mut statements = split_unquoted(&text, ';').into_iter();
+    let authserv_id = statements
+        .next()
+        .and_then(|s| s.split_whitespace().next().map(str::to_string))
+        .unwrap_or_default();
+
+    let mut results = Vec::new();
+    for statement in statements {
+        let mut tokens = split_unquoted(&statement, ' ')
+            .into_iter()
+            .filter(|t| !t.is_empty());
+        let Some((method, result)) = tokens.next().and_then(|t| {
+            let (method, result) = t.split_once('=')?;
+            Some((
+                method.split('/').next()?.to_ascii_lowercase(),
+                result.to_ascii_lowercase(),
+            ))
+        }) else {
+            // `none`: nothing was checked
+            continue;
+        };
+        let mut properties = Vec::new();
+        let mut reason = None;
+        for token in tokens {
+            if let Some((key, value)) = token.split_once('=') {
+                let value = value.trim_matches('"').to_string();
+                if key.eq_ignore_ascii_case("reason") {
+                    reason = Some(value);
+                } else {
+                    properties.push((key.to_ascii_lowercase(), value));
+                }
+            }
+        }
+        // The property that says whose identity was checked
+        let wanted: &[&str] = match method.as_str() {
+            "dkim" => &["header.d", "header.i"],
+            "spf" => &["smtp.mailfrom", "smtp.helo"],
+            "dmarc" => &["header.from"],
+            _ => &[],
+        };
+        let subject = wanted.iter().find_map(|w| {
+            properties
+                .iter()
+                .find(|(key, _)| key == w)
+                .map(|(_, value)| value.clone())
+        });
+        results.push(AuthResult {
+            method,
+            result,
+            subject,
+            reason,
+            local: false,
+        });
+    }
+    (authserv_id, results)
+}
+
+/// Removes `(comments)`, which may nest, outside quoted strings.
+fn strip_comments(value: &str) -> String {
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let next = chars.next();
+                if depth == 0 {
+                    out.push(c);
+                    out.extend(next);
+                }
+            }
+            '"' if depth == 0 => {
+                quoted = !quoted;
+                out.push(c);
+            }
+            '(' if !quoted => depth += 1,
+            ')' if !quoted && depth > 0 => {
+                depth -= 1;
+                out.push(' ');
+            }
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Splits on `separator` outside quoted strings; whitespace separates
+/// runs of any whitespace.
+fn split_unquoted(text: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    for c in text.chars() {
+        let splits = if separator == ' ' {
+            c.is_whitespace()
+        } else {
+            c == separator
+        };
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if splits && !quoted {
+            parts.push(String::new());
+        } else if let Some(last) = parts.last_mut() {
+            last.push(c);
+        }
+    }
+    parts.into_iter().map(|p| p.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(values: &[&str]) -> Vec<(String, String)> {
+        values
+            .iter()
+            .map(|v| ("Authentication-Results".to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_results_with_comments_and_quotes() {
+        let (id, results) = parse(
+            "mx.example.com (version 1); dkim=pass (2048-bit key) header.d=example.org \
+             header.i=@example.org; spf=softfail reason=\"not; listed\" smtp.mailfrom=a@b.example; \
+             dmarc=FAIL header.from=example.org; arc=none",
+        );
+        assert_eq!(id, "mx.example.com");
+        let labels: Vec<String> = results.iter().map(AuthResult::label).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "DKIM pass (example.org)",
+                "SPF softfail (a@b.example: not; listed)",
+                "DMARC fail (example.org)",
+                "ARC none",
+            ]
+        );
+        assert_eq!(results[0].level(), Level::Good);
+        assert_eq!(results[1].level(), Level::Warning);
+        assert_eq!(results[2].level(), Level::Bad);
+    }
+
+    #[test]
+    fn nothing_checked_gives_no_results() {
+        assert_eq!(
+            parse("mx.example.com; none"),
+            ("mx.example.com".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn only_configured_servers_are_believed() {
+        let headers = headers(&[
+            "evil.example; dkim=pass header.d=bank.example",
+            "MX.example.com; dkim=fail header.d=bank.example",
+        ]);
+        // A header the sender wrote is never shown on its own
+        assert!(reported(&headers, &[]).is_empty());
+        let results = reported(&headers, &["mx.example.com".to_string()]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].result, "fail");
+    }
+}
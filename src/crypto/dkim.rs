@@ -0,0 +1,937 @@
+//! DKIM signature verification (RFC 6376, RFC 8463) through the `openssl`
+//! program. Public keys come from DNS TXT records, looked up through a
+//! [`TxtResolver`] so they can also be supplied from configuration.
+
+use std::{collections::BTreeMap, process::Command};
+
+use anyhow::{Result, bail};
+
+use super::{Scratch, auth::AuthResult, crlf};
+use crate::backend::mime;
+use crate::{config, date};
+
+/// Looks up DNS TXT records.
+pub trait TxtResolver: Send + Sync {
+    /// Every TXT record for `name`, with multi-string records joined.
+    fn txt(&self, name: &str) -> Result<Vec<String>>;
+}
+
+/// Fixed records, keyed by domain name.
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    pub records: BTreeMap<String, String>,
+}
+
+impl TxtResolver for StaticResolver {
+    fn txt(&self, name: &str) -> Result<Vec<String>> {
+        let name = name.trim_end_matches('.');
+        Ok(self
+            .records
+            .iter()
+            .filter(|(key, _)| key.trim_end_matches('.').eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .collect())
+    }
+}
+
+/// Runs a command such as `dig +short TXT {}` and reads one record per
+/// line of its output. Only DNS names are looked up, so nothing from a
+/// message reaches the command as an option.
+#[derive(Debug, Clone)]
+pub struct CommandResolver {
+    pub command: String,
+}
+
+impl TxtResolver for CommandResolver {
+    fn txt(&self, name: &str) -> Result<Vec<String>> {
+        if !is_dns_name(name) {
+            bail!("{:?} is not a DNS name", name);
+        }
+        let Some((program, args)) = config::command_line(&self.command, name) else {
+            bail!("empty resolver command");
+        };
+        let output = Command::new(&program).args(&args).output()?;
+        if !output.status.success() {
+            bail!("{} failed for {}", program, name);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(unquote_record)
+            .collect())
+    }
+}
+
+/// Tries each resolver in turn until one has records.
+impl TxtResolver for Vec<Box<dyn TxtResolver>> {
+    fn txt(&self, name: &str) -> Result<Vec<String>> {
+        let mut error = None;
+        for resolver in self {
+            match resolver.txt(name) {
+                Ok(records) if !records.is_empty() => return Ok(records),
+                Ok(_) => {}
+                Err(e) => error = Some(e),
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Whether `name` is made of DNS labels: letters, digits, `-` and `_`
+/// (as in `_domainkey`), with no label empty or starting or ending with
+/// `-`.
+fn is_dns_name(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+/// Joins the quoted strings of a record as printed by `dig`, like
+/// `"v=DKIM1; k=rsa; " "p=MIIB..."`.
+fn unquote_record(line: &str) -> String {
+    let line = line.trim();
+    if !line.starts_with('"') {
+        return line.to_string();
+    }
+    let mut out = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => out.extend(chars.next()),
+            _ if quoted => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Checks DKIM signatures with keys from a resolver.
+pub struct Verifier {
+    /// The `openssl` program.
+    pub program: String,
+    pub resolver: Box<dyn TxtResolver>,
+}
+
+/// Why a signature did not verify; `result` is the RFC 8601 result.
+struct Failure {
+    result: &'static str,
+    reason: String,
+}
+
+fn fail(result: &'static str, reason: impl Into<String>) -> Failure {
+    Failure {
+        result,
+        reason: reason.into(),
+    }
+}
+
+impl Verifier {
+    /// One result per `DKIM-Signature` header of the message. A signature
+    /// whose `l=` tag leaves part of the body unsigned is `neutral`, since
+    /// anything could have been appended.
+    pub fn verify(&self, raw: &[u8]) -> Vec<AuthResult> {
+        let message = crlf(raw);
+        let (header, body) = match find(&message, b"\r\n\r\n") {
+            Some(i) => (&message[..i + 2], &message[i + 4..]),
+            None => (&message[..], &b""[..]),
+        };
+        let fields = header_fields(header);
+        fields
+            .iter()
+            .filter(|field| field_name(field).eq_ignore_ascii_case("DKIM-Signature"))
+            .map(|signature| {
+                let tags = tags(&String::from_utf8_lossy(field_value(signature)));
+                let domain = tags.get("d").cloned();
+                let (result, reason) = match self.check(&tags, signature, &fields, body) {
+                    Ok(()) => ("pass", None),
+                    Err(failure) => (failure.result, Some(failure.reason)),
+                };
+                AuthResult {
+                    method: "dkim".to_string(),
+                    result: result.to_string(),
+                    subject: domain,
+                    reason,
+                    local: true,
+                }
+            })
+            .collect()
+    }
+
+    fn check(
+        &self,
+        tags: &BTreeMap<String, String>,
+        signature: &[u8],
+        fields: &[&[u8]],
+        body: &[u8],
+    ) -> Result<(), Failure> {
+        let tag = |name: &str| {
+            tags.get(name)
+                .map(String::as_str)
+                .ok_or_else(|| fail("permerror", format!("missing {}= tag", name)))
+        };
+        if tag("v")? != "1" {
+            return Err(fail("permerror", "unknown DKIM version"));
+        }
+        let (key_type, hash) = match tag("a")?.to_ascii_lowercase().as_str() {
+            "rsa-sha256" => ("rsa", "sha256"),
+            "rsa-sha1" => ("rsa", "sha1"),
+            "ed25519-sha256" => ("ed25519", "sha256"),
+            other => {
+                return Err(fail(
+                    "permerror",
+                    format!("unsupported algorithm {}", other),
+                ));
+            }
+        };
+        let (domain, selector) = (tag("d")?, tag("s")?);
+        for (name, value) in [("d", domain), ("s", selector)] {
+            if !is_dns_name(value) || value.contains('_') {
+                return Err(fail("permerror", format!("invalid {}= tag", name)));
+            }
+        }
+        let signed: Vec<&str> = tag("h")?.split(':').map(str::trim).collect();
+        if !signed.iter().any(|h| h.eq_ignore_ascii_case("from")) {
+            return Err(fail("permerror", "From is not signed"));
+        }
+        if let Some(expires) = tags.get("x").and_then(|x| x.parse::<u64>().ok())
+            && expires < date::now()
+        {
+            return Err(fail("fail", "signature expired"));
+        }
+        let canonicalization = tags.get("c").map(String::as_str).unwrap_or("simple");
+        let (header_c, body_c) = canonicalization
+            .split_once('/')
+            .unwrap_or((canonicalization, "simple"));
+        let relaxed_header = header_c.eq_ignore_ascii_case("relaxed");
+        let relaxed_body = body_c.eq_ignore_ascii_case("relaxed");
+
+        let key = self.key(domain, selector, key_type)?;
+
+        let mut body = if relaxed_body {
+            relaxed_body_text(body)
+        } else {
+            simple_body_text(body)
+        };
+        let mut unsigned = 0;
+        if let Some(length) = tags.get("l") {
+            let length = length
+                .parse::<usize>()
+                .map_err(|_| fail("permerror", "invalid l= tag"))?;
+            if length > body.len() {
+                return Err(fail("fail", "body shorter than l= tag"));
+            }
+            unsigned = body.len() - length;
+            body.truncate(length);
+        }
+        let scratch = Scratch::new().map_err(|e| fail("temperror", format!("{:#}", e)))?;
+        let body_hash = self.digest(&scratch, hash, &body)?;
+        if mime::encode_base64(&body_hash) != tag("bh")? {
+            return Err(fail("fail", "body hash mismatch"));
+        }
+
+        // Signed headers, each taken from the bottom up; then the signature
+        // header itself with an empty b= value, without the final CRLF
+        let mut data = Vec::new();
+        let mut used = vec![false; fields.len()];
+        for name in &signed {
+            let found = (0..fields.len())
+                .rev()
+                .find(|&i| !used[i] && field_name(fields[i]).eq_ignore_ascii_case(name));
+            if let Some(i) = found {
+                used[i] = true;
+                data.extend(canonical_header(fields[i], relaxed_header));
+                data.extend(b"\r\n");
+            }
+        }
+        data.extend(canonical_header(&without_b(signature), relaxed_header));
+
+        let signature = mime::decode_base64(tag("b")?.as_bytes());
+        let file = |name: &str, contents: &[u8]| {
+            scratch
+                .file(name, contents)
+                .map(|p| p.to_string_lossy().into_owned())
+                .map_err(|e| fail("temperror", format!("{:#}", e)))
+        };
+        let key = file("key.pem", key.as_bytes())?;
+        let sig = file("signature", &signature)?;
+        let verified = if key_type == "ed25519" {
+            // Ed25519 signs the hash of the data (RFC 8463, section 3)
+            let hashed = file("hash", &self.digest(&scratch, hash, &data)?)?;
+            self.run(&[
+                "pkeyutl", "-verify", "-pubin", "-inkey", &key, "-rawin", "-in", &hashed,
+                "-sigfile", &sig,
+            ])
+        } else {
+            let data = file("data", &data)?;
+            self.run(&[
+                "dgst",
+                &format!("-{}", hash),
+                "-verify",
+                &key,
+                "-signature",
+                &sig,
+                &data,
+            ])
+        };
+        match verified {
+            Ok(_) if unsigned > 0 => Err(fail(
+                "neutral",
+                format!(
+                    "only the first {} body bytes are signed, {} are not",
+                    body.len(),
+                    unsigned
+                ),
+            )),
+            Ok(_) => Ok(()),
+            Err(_) => Err(fail("fail", "signature mismatch")),
+        }
+    }
+
+    /// The public key for a selector, as PEM.
+    fn key(&self, domain: &str, selector: &str, key_type: &str) -> Result<String, Failure> {
+        let name = format!("{}._domainkey.{}", selector, domain);
+        let records = self
+            .resolver
+            .txt(&name)
+            .map_err(|e| fail("temperror", format!("looking up {}: {:#}", name, e)))?;
+        let record = records
+            .iter()
+            .map(|r| tags(r))
+            .find(|t| t.get("v").is_none_or(|v| v == "DKIM1"))
+            .ok_or_else(|| fail("permerror", format!("no key at {}", name)))?;
+        if record.get("k").map(String::as_str).unwrap_or("rsa") != key_type {
+            return Err(fail("permerror", "key type does not match"));
+        }
+        let key = match record.get("p").map(String::as_str) {
+            None => return Err(fail("permerror", "malformed key record")),
+            Some("") => return Err(fail("permerror", "key revoked")),
+            Some(p) => mime::decode_base64(p.as_bytes()),
+        };
+        // Ed25519 records hold the bare key; wrap it as SubjectPublicKeyInfo
+        let der = if key_type == "ed25519" {
+            let mut der = vec![
+                0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+            ];
+            der.extend(key);
+            der
+        } else {
+            key
+        };
+        let base64 = mime::encode_base64(&der);
+        let lines: Vec<&str> = base64
+            .as_bytes()
+            .chunks(64)
+            .map(|line| std::str::from_utf8(line).unwrap_or_default())
+            .collect();
+        Ok(format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            lines.join("\n")
+        ))
+    }
+
+    fn digest(&self, scratch: &Scratch, hash: &str, data: &[u8]) -> Result<Vec<u8>, Failure> {
+        let input = scratch
+            .file("digest-input", data)
+            .map_err(|e| fail("temperror", format!("{:#}", e)))?;
+        self.run(&[
+            "dgst",
+            &format!("-{}", hash),
+            "-binary",
+            &input.to_string_lossy(),
+        ])
+        .map_err(|e| fail("temperror", e))
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Vec<u8>, String> {
+        let output = Command::new(&self.program)
+            .args(args)
+            .output()
+            .map_err(|e| format!("running {}: {}", self.program, e))?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
+
+/// Parses `tag=value; ...` lists, with folding whitespace removed from the
+/// base64 values.
+fn tags(text: &str) -> BTreeMap<String, String> {
+    text.split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim().to_string();
+            let value = if name == "b" || name == "bh" || name == "p" {
+                value.split_whitespace().collect()
+            } else {
+                value.trim().to_string()
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// Splits a header block into fields, each with its folded lines but
+/// without the final CRLF.
+fn header_fields(header: &[u8]) -> Vec<&[u8]> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while let Some(offset) = find(&header[i..], b"\r\n") {
+        let end = i + offset;
+        let next = end + 2;
+        // A field ends where the next line does not start with whitespace
+        if !matches!(header.get(next), Some(b' ' | b'\t')) {
+            if end > start {
+                fields.push(&header[start..end]);
+            }
+            start = next;
+        }
+        i = next;
+    }
+    fields
+}
+
+fn field_name(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == b':').unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).trim().to_string()
+}
+
+fn field_value(field: &[u8]) -> &[u8] {
+    match field.iter().position(|&b| b == b':') {
+        Some(i) => &field[i + 1..],
+        None => b"",
+    }
+}
+
+/// The signature header with the value of its b= tag removed.
+fn without_b(field: &[u8]) -> Vec<u8> {
+    let colon = field.iter().position(|&b| b == b':').unwrap_or(field.len());
+    let mut out = field[..colon].to_vec();
+    let value = field_value(field);
+    let mut segments = value.split(|&b| b == b';').peekable();
+    if colon < field.len() {
+        out.push(b':');
+    }
+    while let Some(segment) = segments.next() {
+        let eq = segment.iter().position(|&b| b == b'=');
+        let is_b = eq.is_some_and(|eq| {
+            segment[..eq]
+                .iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .eq(b"b".iter())
+        });
+        match eq {
+            Some(eq) if is_b => out.extend(&segment[..=eq]),
+            _ => out.extend(segment),
+        }
+        if segments.peek().is_some() {
+            out.push(b';');
+        }
+    }
+    out
+}
+
+/// Header canonicalization (RFC 6376, section 3.4.1 and 3.4.2).
+fn canonical_header(field: &[u8], relaxed: bool) -> Vec<u8> {
+    if !relaxed {
+        return field.to_vec();
+    }
+    let mut out = field_name(field).to_ascii_lowercase().into_bytes();
+    out.push(b':');
+    let start = out.len();
+    let mut space = false;
+    for &b in field_value(field) {
+        match b {
+            b'\r' | b'\n' => {}
+            b' ' | b'\t' => space = true,
+            _ => {
+                // Whitespace after the colon is dropped
+                if space && out.len() > start {
+                    out.push(b' ');
+                }
+                space = false;
+                out.push(b);
+            }
+        }
+    }
+    out
+}
+
+/// Simple body canonicalization: trailing empty lines removed, and an
+/// empty body becomes a single CRLF.
+fn simple_body_text(body: &[u8]) -> Vec<u8> {
+    let mut body = body.to_vec();
+    if !body.ends_with(b"\r\n") && !body.is_empty() {
+        body.extend(b"\r\n");
+    }
+    while body.ends_with(b"\r\n\r\n") {
+        body.truncate(body.len() - 2);
+    }
+    if body.is_empty() {
+        body.extend(b"\r\n");
+    }
+    body
+}
+
+/// Relaxed body canonicalization: runs of whitespace become one space,
+/// whitespace at line ends and trailing empty lines are removed.
+fn relaxed_body_text(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut lines: Vec<&[u8]> = split(body, b"\r\n");
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    for line in lines {
+        let mut space = false;
+        for &b in line {
+            if b == b' ' || b == b'\t' {
+                space = true;
+            } else {
+                if space {
+                    out.push(b' ');
+                }
+                space = false;
+                out.push(b);
+            }
+        }
+        out.extend(b"\r\n");
+    }
+    while out.ends_with(b"\r\n\r\n") {
+        out.truncate(out.len() - 2);
+    }
+    if out == b"\r\n" {
+        out.clear();
+    }
+    out
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn split<'a>(data: &'a [u8], separator: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    let mut rest = data;
+    while let Some(i) = find(rest, separator) {
+        parts.push(&rest[..i]);
+        rest = &rest[i + separator.len()..];
+    }
+    parts.push(rest);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOMAIN: &str = "example.com";
+    const SELECTOR: &str = "test";
+
+    fn openssl(args: &[&str]) -> Vec<u8> {
+        let output = Command::new("openssl").args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output.stdout
+    }
+
+    /// A fresh signing key and the DNS record publishing it.
+    struct Signer {
+        scratch: Scratch,
+        key_type: &'static str,
+        record: String,
+    }
+
+    impl Signer {
+        fn new(key_type: &'static str) -> Signer {
+            let scratch = Scratch::new().unwrap();
+            let key = scratch.0.join("private.pem");
+            let key = key.to_str().unwrap();
+            let algorithm = if key_type == "rsa" { "RSA" } else { "ED25519" };
+            openssl(&["genpkey", "-algorithm", algorithm, "-out", key]);
+            let der = openssl(&["pkey", "-in", key, "-pubout", "-outform", "DER"]);
+            // Ed25519 records hold the bare key after the 12-byte prefix
+            let public = if key_type == "rsa" {
+                &der[..]
+            } else {
+                &der[12..]
+            };
+            let record = format!("v=DKIM1; k={}; p={}", key_type, mime::encode_base64(public));
+            Signer {
+                scratch,
+                key_type,
+                record,
+            }
+        }
+
+        fn verifier(&self) -> Verifier {
+            let mut records = BTreeMap::new();
+            records.insert(
+                format!("{}._domainkey.{}", SELECTOR, DOMAIN),
+                self.record.clone(),
+            );
+            Verifier {
+                program: "openssl".to_string(),
+                resolver: Box::new(StaticResolver { records }),
+            }
+        }
+
+        fn sha256(&self, data: &[u8]) -> Vec<u8> {
+            let input = self.scratch.file("input", data).unwrap();
+            openssl(&["dgst", "-sha256", "-binary", input.to_str().unwrap()])
+        }
+
+        /// Signs a message whose header lines end in CRLF, covering From
+        /// and Subject.
+        fn sign(&self, header: &str, body: &str, canonicalization: &str) -> Vec<u8> {
+            self.sign_length(header, body, canonicalization, None)
+        }
+
+        /// Signs, with an `l=` tag when `length` is given.
+        fn sign_length(
+            &self,
+            header: &str,
+            body: &str,
+            canonicalization: &str,
+            length: Option<usize>,
+        ) -> Vec<u8> {
+            let relaxed = canonicalization.starts_with("relaxed");
+            let mut body_bytes = if canonicalization.ends_with("relaxed") {
+                relaxed_body_text(body.as_bytes())
+            } else {
+                simple_body_text(body.as_bytes())
+            };
+            let mut length_tag = String::new();
+            if let Some(length) = length {
+                body_bytes.truncate(length);
+                length_tag = format!(" l={};", length);
+            }
+            let signature_header = format!(
+                "DKIM-Signature: v=1; a={}-sha256; c={}; d={}; s={};{}\r\n\th=from:subject; bh={}; b=",
+                self.key_type,
+                canonicalization,
+                DOMAIN,
+                SELECTOR,
+                length_tag,
+                mime::encode_base64(&self.sha256(&body_bytes))
+            );
+            let header_bytes = header.as_bytes();
+            let fields = header_fields(header_bytes);
+            let mut data = Vec::new();
+            for name in ["from", "subject"] {
+                let field = fields
+                    .iter()
+                    .rev()
+                    .find(|f| field_name(f).eq_ignore_ascii_case(name))
+                    .unwrap();
+                data.extend(canonical_header(field, relaxed));
+                data.extend(b"\r\n");
+            }
+            data.extend(canonical_header(signature_header.as_bytes(), relaxed));
+
+            let key = self.scratch.0.join("private.pem");
+            let key = key.to_str().unwrap();
+            let signature = if self.key_type == "rsa" {
+                let input = self.scratch.file("data", &data).unwrap();
+                openssl(&["dgst", "-sha256", "-sign", key, input.to_str().unwrap()])
+            } else {
+                let hashed = self.scratch.file("hash", &self.sha256(&data)).unwrap();
+                openssl(&[
+                    "pkeyutl",
+                    "-sign",
+                    "-inkey",
+                    key,
+                    "-rawin",
+                    "-in",
+                    hashed.to_str().unwrap(),
+                ])
+            };
+            format!(
+                "{}{}\r\n{}\r\n{}",
+                signature_header,
+                mime::encode_base64(&signature),
+                header,
+                body
+            )
+            .into_bytes()
+        }
+    }
+
+    const HEADER: &str =
+        "From: Alice <alice@example.com>\r\nTo: bob@example.org\r\nSubject:  Hello\r\n  there\r\n";
+    const BODY: &str = "Hi Bob,\r\n\r\nSee you  soon.\r\n\r\n\r\n";
+
+    fn results(verifier: &Verifier, message: &[u8]) -> Vec<(String, Option<String>)> {
+        verifier
+            .verify(message)
+            .into_iter()
+            .map(|r| (r.result, r.reason))
+            .collect()
+    }
+
+    fn pass() -> Vec<(String, Option<String>)> {
+        vec![("pass".to_string(), None)]
+    }
+
+    #[test]
+    fn relaxed_header_canonicalization() {
+        // RFC 6376, section 3.4.5
+        let header = b"A: X\r\nB : Y\t\r\n\tZ  \r\n";
+        let fields = header_fields(header);
+        assert_eq!(canonical_header(fields[0], true), b"a:X");
+        assert_eq!(canonical_header(fields[1], true), b"b:Y Z");
+        assert_eq!(canonical_header(fields[1], false), b"B : Y\t\r\n\tZ  ");
+    }
+
+    #[test]
+    fn body_canonicalization() {
+        // RFC 6376, section 3.4.5
+        let body = b" C \r\nD \t E\r\n\r\n\r\n";
+        assert_eq!(relaxed_body_text(body), b" C\r\nD E\r\n");
+        assert_eq!(simple_body_text(body), b" C \r\nD \t E\r\n");
+        // Empty bodies (RFC 6376 errata 3192)
+        assert_eq!(simple_body_text(b""), b"\r\n");
+        assert_eq!(relaxed_body_text(b""), b"");
+        assert_eq!(relaxed_body_text(b"\r\n\r\n"), b"");
+        // A missing final line break is added
+        assert_eq!(simple_body_text(b"end"), b"end\r\n");
+        assert_eq!(relaxed_body_text(b"end  "), b"end\r\n");
+    }
+
+    #[test]
+    fn signature_header_loses_only_its_b_value() {
+        assert_eq!(
+            without_b(b"DKIM-Signature: v=1; bh=abc=; b=sig\r\n\tnature; d=x"),
+            b"DKIM-Signature: v=1; bh=abc=; b=; d=x"
+        );
+    }
+
+    #[test]
+    fn reads_dig_output() {
+        assert_eq!(
+            unquote_record(r#""v=DKIM1; k=rsa; " "p=AB\"C""#),
+            r#"v=DKIM1; k=rsa; p=AB"C"#
+        );
+        assert_eq!(unquote_record("v=DKIM1; p=x"), "v=DKIM1; p=x");
+    }
+
+    #[test]
+    fn static_resolver_ignores_case_and_trailing_dots() {
+        let mut records = BTreeMap::new();
+        records.insert(
+            "Sel._domainkey.Example.com.".to_string(),
+            "v=DKIM1".to_string(),
+        );
+        let resolver = StaticResolver { records };
+        assert_eq!(
+            resolver.txt("sel._domainkey.example.com").unwrap(),
+            vec!["v=DKIM1"]
+        );
+        assert!(resolver.txt("other.example.com").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rsa_signatures_verify() {
+        let signer = Signer::new("rsa");
+        let verifier = signer.verifier();
+        for c in [
+            "relaxed/relaxed",
+            "simple/simple",
+            "relaxed/simple",
+            "simple/relaxed",
+        ] {
+            let message = signer.sign(HEADER, BODY, c);
+            assert_eq!(results(&verifier, &message), pass(), "{}", c);
+        }
+    }
+
+    #[test]
+    fn ed25519_signatures_verify() {
+        let signer = Signer::new("ed25519");
+        let message = signer.sign(HEADER, BODY, "relaxed/relaxed");
+        assert_eq!(results(&signer.verifier(), &message), pass());
+    }
+
+    #[test]
+    fn relaxed_signatures_survive_whitespace_changes() {
+        let signer = Signer::new("rsa");
+        let message = String::from_utf8(signer.sign(HEADER, BODY, "relaxed/relaxed")).unwrap();
+        let rewrapped = message
+            .replace("Subject:  Hello\r\n  there", "subject: Hello there")
+            .replace("See you  soon.", "See you \t soon.  ");
+        assert_eq!(results(&signer.verifier(), rewrapped.as_bytes()), pass());
+    }
+
+    #[test]
+    fn changed_bodies_fail_the_body_hash() {
+        let signer = Signer::new("rsa");
+        let message = String::from_utf8(signer.sign(HEADER, BODY, "simple/simple")).unwrap();
+        let changed = message.replace("soon", "later");
+        assert_eq!(
+            results(&signer.verifier(), changed.as_bytes()),
+            vec![("fail".to_string(), Some("body hash mismatch".to_string()))]
+        );
+        // Simple canonicalization notices whitespace changes too
+        let spaced = message.replace("See you  soon.", "See you soon.");
+        assert_eq!(results(&signer.verifier(), spaced.as_bytes())[0].0, "fail");
+    }
+
+    #[test]
+    fn changed_headers_fail_the_signature() {
+        let signer = Signer::new("rsa");
+        let message = String::from_utf8(signer.sign(HEADER, BODY, "relaxed/relaxed")).unwrap();
+        let changed = message.replace("Hello", "Goodbye");
+        assert_eq!(
+            results(&signer.verifier(), changed.as_bytes()),
+            vec![("fail".to_string(), Some("signature mismatch".to_string()))]
+        );
+    }
+
+    #[test]
+    fn signatures_by_other_keys_fail() {
+        let signer = Signer::new("rsa");
+        let message = signer.sign(HEADER, BODY, "relaxed/relaxed");
+        let other = Signer::new("rsa");
+        assert_eq!(
+            results(&other.verifier(), &message),
+            vec![("fail".to_string(), Some("signature mismatch".to_string()))]
+        );
+    }
+
+    #[test]
+    fn missing_and_revoked_keys_are_permanent_errors() {
+        let signer = Signer::new("rsa");
+        let message = signer.sign(HEADER, BODY, "relaxed/relaxed");
+        let verifier = Verifier {
+            program: "openssl".to_string(),
+            resolver: Box::new(StaticResolver::default()),
+        };
+        assert_eq!(
+            results(&verifier, &message),
+            vec![(
+                "permerror".to_string(),
+                Some("no key at test._domainkey.example.com".to_string())
+            )]
+        );
+        let mut records = BTreeMap::new();
+        records.insert(
+            "test._domainkey.example.com".to_string(),
+            "v=DKIM1; k=rsa; p=".to_string(),
+        );
+        let verifier = Verifier {
+            program: "openssl".to_string(),
+            resolver: Box::new(StaticResolver { records }),
+        };
+        assert_eq!(
+            results(&verifier, &message),
+            vec![("permerror".to_string(), Some("key revoked".to_string()))]
+        );
+    }
+
+    #[test]
+    fn unsigned_messages_have_no_results() {
+        let message = format!("{}\r\n{}", HEADER, BODY);
+        assert!(
+            Signer::new("rsa")
+                .verifier()
+                .verify(message.as_bytes())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn partly_signed_bodies_are_neutral() {
+        let signer = Signer::new("rsa");
+        let verifier = signer.verifier();
+        let whole = relaxed_body_text(BODY.as_bytes()).len();
+        let message = signer.sign_length(HEADER, BODY, "relaxed/relaxed", Some(whole));
+        assert_eq!(results(&verifier, &message), pass());
+
+        let message = signer.sign_length(HEADER, BODY, "relaxed/relaxed", Some(8));
+        assert_eq!(
+            results(&verifier, &message),
+            vec![(
+                "neutral".to_string(),
+                Some(format!(
+                    "only the first 8 body bytes are signed, {} are not",
+                    whole - 8
+                ))
+            )]
+        );
+        // Text appended below the signed part is what l= lets through
+        let appended = String::from_utf8(message).unwrap() + "Click here\r\n";
+        assert_eq!(results(&verifier, appended.as_bytes())[0].0, "neutral");
+
+        let message = signer.sign_length(HEADER, BODY, "relaxed/relaxed", Some(whole + 1));
+        assert_eq!(
+            results(&verifier, &message),
+            vec![(
+                "fail".to_string(),
+                Some("body shorter than l= tag".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn selectors_and_domains_must_be_dns_names() {
+        let verifier = Signer::new("rsa").verifier();
+        for (tags, error) in [
+            ("d=-oProxy.example; s=test", "invalid d= tag"),
+            ("d=example.com; s=a b", "invalid s= tag"),
+            ("d=example.com; s=$(id)", "invalid s= tag"),
+            ("d=example..com; s=test", "invalid d= tag"),
+            ("d=example.com; s=_x", "invalid s= tag"),
+        ] {
+            let message = format!(
+                "DKIM-Signature: v=1; a=rsa-sha256; {}; h=from; bh=; b=\r\n{}\r\n{}",
+                tags, HEADER, BODY
+            );
+            assert_eq!(
+                results(&verifier, message.as_bytes()),
+                vec![("permerror".to_string(), Some(error.to_string()))],
+                "{}",
+                tags
+            );
+        }
+    }
+
+    #[test]
+    fn command_resolver_looks_up_only_dns_names() {
+        let resolver = CommandResolver {
+            command: "echo {}".to_string(),
+        };
+        assert_eq!(
+            resolver.txt("sel._domainkey.example.com.").unwrap(),
+            vec!["sel._domainkey.example.com."]
+        );
+        for name in [
+            "-n",
+            "a b.example",
+            "a;b.example",
+            "",
+            "x.-y.example",
+            "a..b",
+        ] {
+            assert!(resolver.txt(name).is_err(), "{:?}", name);
+        }
+    }
+}
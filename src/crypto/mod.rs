@@ -0,0 +1,215 @@
+//! Signed and encrypted mail: what was found when a message was opened,
+//! and how the viewer sums it up.
+
+pub mod auth;
+pub mod dkim;
+pub mod pgp;
+pub mod smime;
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::backend::mime::Part;
+
+/// How a message was protected and whether its signature checked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Security {
+    /// `OpenPGP` or `S/MIME`, for the badge.
+    pub scheme: &'static str,
+    pub encrypted: bool,
+    /// Why an encrypted message could not be read.
+    pub error: Option<String>,
+    pub signature: Option<Signature>,
+}
+
+/// Outcome of checking a signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signature {
+    /// The signature is valid. `trusted` says whether the key is certified
+    /// as belonging to the signer, rather than just present in the keyring;
+    /// for S/MIME, whether the certificate chains to a trusted CA and names
+    /// the sender's address.
+    Good {
+        signer: String,
+        trusted: bool,
+    },
+    /// The message was changed after it was signed.
+    Bad {
+        signer: String,
+    },
+    Expired {
+        signer: String,
+    },
+    Revoked {
+        signer: String,
+    },
+    /// The signing key is not in the keyring.
+    UnknownKey {
+        key: String,
+    },
+    Error(String),
+}
+
+/// How reassuring a badge is, for its colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Good,
+    Warning,
+    Bad,
+}
+
+impl Security {
+    /// One-line summary for the viewer, such as
+    /// `OpenPGP: encrypted, good signature from Jane <jane@example.com>`.
+    pub fn badge(&self) -> (String, Level) {
+        let mut parts = Vec::new();
+        let mut level = Level::Good;
+        if let Some(error) = &self.error {
+            parts.push(format!("could not decrypt ({})", error));
+            level = Level::Bad;
+        } else if self.encrypted {
+            parts.push("encrypted".to_string());
+        }
+        match &self.signature {
+            Some(Signature::Good {
+                signer,
+                trusted: true,
+            }) => parts.push(format!("good signature from {}", signer)),
+            Some(Signature::Good {
+                signer,
+                trusted: false,
+            }) => {
+                let reason = if self.scheme == "S/MIME" {
+                    "certificate not trusted"
+                } else {
+                    "key not certified"
+                };
+                parts.push(format!("good signature from {} ({})", signer, reason));
+                level = level.max(Level::Warning);
+            }
+            Some(Signature::Bad { signer }) => {
+                parts.push(format!("BAD signature from {}", signer));
+                level = Level::Bad;
+            }
+            Some(Signature::Expired { signer }) => {
+                parts.push(format!("signature from {} with an expired key", signer));
+                level = level.max(Level::Warning);
+            }
+            Some(Signature::Revoked { signer }) => {
+                parts.push(format!("signature from {} with a REVOKED key", signer));
+                level = Level::Bad;
+            }
+            Some(Signature::UnknownKey { key }) => {
+                parts.push(format!("signed by unknown key {}", key));
+                level = level.max(Level::Warning);
+            }
+            Some(Signature::Error(error)) => {
+                parts.push(format!("signature not checked ({})", error));
+                level = level.max(Level::Warning);
+            }
+            None if self.encrypted && self.error.is_none() => parts.push("not signed".to_string()),
+            None => {}
+        }
+        (format!("{}: {}", self.scheme, parts.join(", ")), level)
+    }
+}
+
+/// The message headers with the content headers of its decrypted or
+/// verified content.
+fn with_headers(message: &Part, content: Part) -> Part {
+    let mut headers: Vec<(String, String)> = message
+        .headers
+        .iter()
+        .filter(|(name, _)| !name.to_ascii_lowercase().starts_with("content-"))
+        .cloned()
+        .collect();
+    headers.extend(content.headers);
+    Part {
+        headers,
+        body: content.body,
+        parts: content.parts,
+    }
+}
+
+fn add_params(part: &mut Part, params: &str) {
+    if let Some((_, value)) = part
+        .headers
+        .iter_mut()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+    {
+        value.push_str("; ");
+        value.push_str(params);
+    }
+}
+
+/// Converts bare LF line endings to CRLF, the canonical form signatures
+/// are made over.
+fn crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 32);
+    for (i, &b) in data.iter().enumerate() {
+        if b == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+    out
+}
+
+/// A private directory for the files gpg and openssl work on (and drafts
+/// handed to an external editor), removed when dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "hermes-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        let mut builder = fs::DirBuilder::new();
+        // Key material and drafts are written here, so keep other users out
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder
+            .create(&path)
+            .with_context(|| format!("creating {}", path.display()))?;
+        Ok(Self(path))
+    }
+
+    pub fn file(&self, name: &str, contents: &[u8]) -> Result<PathBuf> {
+        let path = self.0.join(name);
+        fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratch_directories_are_private_and_removed() {
+        let scratch = Scratch::new().unwrap();
+        let dir = scratch.0.clone();
+        let file = scratch.file("draft.eml", b"Subject: Hi\n").unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"Subject: Hi\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        drop(scratch);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,599 @@
+//! PGP/MIME (RFC 3156) through the `gpg` program and its local keyring.
+//!
+//! Everything works offline: keys are looked up in the keyring only and
+//! never fetched from a keyserver. gpg runs in batch mode, so keys that
+//! need a passphrase must be unlocked by gpg-agent beforehand.
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+
+use super::{Scratch, Security, Signature, add_params, crlf, with_headers};
+use crate::backend::mime::Part;
+
+/// The `gpg` program and the keyring it uses.
+#[derive(Debug, Clone)]
+pub struct Gpg {
+    pub program: String,
+    /// Keyring directory (`--homedir`); gpg's own default when unset.
+    pub homedir: Option<PathBuf>,
+}
+
+impl Default for Gpg {
+    fn default() -> Self {
+        Self {
+            program: "gpg".to_string(),
+            homedir: None,
+        }
+    }
+}
+
+/// What a gpg run produced: its output, the machine-readable status lines
+/// (without the `[GNUPG:] ` prefix) and its human-readable messages.
+struct Output {
+    stdout: Vec<u8>,
+    status: Vec<String>,
+    messages: String,
+    success: bool,
+}
+
+impl Output {
+    fn status(&self, keyword: &str) -> Option<Vec<&str>> {
+        self.status.iter().find_map(|line| {
+            let mut fields = line.split(' ');
+            (fields.next() == Some(keyword)).then(|| fields.collect())
+        })
+    }
+
+    /// Fails when the signing key could not be used.
+    fn check_signer(&self) -> Result<()> {
+        match self.status("INV_SGNR") {
+            Some(fields) => bail!(
+                "no usable OpenPGP secret key for {}",
+                fields.get(1).copied().unwrap_or("the sender")
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// The last thing gpg complained about, for error messages.
+    fn error(&self) -> String {
+        self.messages
+            .lines()
+            .rev()
+            .map(|line| line.trim_start_matches("gpg: ").trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or("gpg failed")
+            .to_string()
+    }
+}
+
+impl Gpg {
+    fn run(&self, args: &[&str], input: &[u8]) -> Result<Output> {
+        let mut command = Command::new(&self.program);
+        command.args(["--batch", "--no-tty", "--status-fd", "2"]);
+        if let Some(homedir) = &self.homedir {
+            command.arg("--homedir").arg(homedir);
+        }
+        let mut child = command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("running {}", self.program))?;
+
+        // Feed stdin from another thread so a full stdout pipe cannot
+        // deadlock the two processes
+        let mut stdin = child.stdin.take().context("gpg stdin")?;
+        let input = input.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // gpg may stop reading early when it fails; its messages say why
+        let _ = writer.join();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut status = Vec::new();
+        let mut messages = String::new();
+        for line in stderr.lines() {
+            match line.strip_prefix("[GNUPG:] ") {
+                Some(line) => status.push(line.to_string()),
+                None => {
+                    messages.push_str(line);
+                    messages.push('\n');
+                }
+            }
+        }
+        Ok(Output {
+            stdout: output.stdout,
+            status,
+            messages,
+            success: output.status.success(),
+        })
+    }
+
+    /// Makes an armored detached signature, returning it with the `micalg`
+    /// parameter naming the hash it used.
+    pub fn sign(&self, data: &[u8], signer: Option<&str>) -> Result<(Vec<u8>, String)> {
+        let mut args = vec!["--armor", "--detach-sign", "--digest-algo", "SHA256"];
+        if let Some(signer) = signer {
+            args.extend(["--local-user", signer]);
+        }
+        let output = self.run(&args, data)?;
+        output.check_signer()?;
+        if !output.success {
+            bail!("signing failed: {}", output.error());
+        }
+        let hash = output
+            .status("SIG_CREATED")
+            .and_then(|fields| fields.get(2).map(|algo| hash_name(algo)))
+            .unwrap_or("sha256");
+        Ok((output.stdout, format!("pgp-{}", hash)))
+    }
+
+    /// Encrypts to every recipient, and signs too when `sign` is set. Bcc
+    /// recipients go in `hidden` so their key IDs are left out.
+    pub fn encrypt(
+        &self,
+        data: &[u8],
+        recipients: &[&str],
+        hidden: &[&str],
+        signer: Option<&str>,
+        sign: bool,
+    ) -> Result<Vec<u8>> {
+        let mut args = vec!["--armor", "--encrypt"];
+        for recipient in recipients {
+            args.extend(["--recipient", recipient]);
+        }
+        for recipient in hidden {
+            args.extend(["--hidden-recipient", recipient]);
+        }
+        if sign {
+            args.push("--sign");
+            if let Some(signer) = signer {
+                args.extend(["--local-user", signer]);
+            }
+        }
+        let output = self.run(&args, data)?;
+        output.check_signer()?;
+        if let Some(fields) = output.status("INV_RECP") {
+            bail!(
+                "no usable OpenPGP key for {}",
+                fields.get(1).copied().unwrap_or("a recipient")
+            );
+        }
+        if !output.success {
+            bail!("encryption failed: {}", output.error());
+        }
+        Ok(output.stdout)
+    }
+
+    /// Decrypts a message, also checking the signature of one that was
+    /// signed and encrypted in one go.
+    pub fn decrypt(&self, data: &[u8]) -> Result<(Vec<u8>, Option<Signature>)> {
+        let output = self.run(&["--decrypt"], data)?;
+        // gpg exits non-zero for a bad signature even when decryption worked
+        if output.status("DECRYPTION_OKAY").is_none() {
+            if output.status("NO_SECKEY").is_some() {
+                bail!("no secret key for this message");
+            }
+            bail!("{}", output.error());
+        }
+        let signature = signature(&output);
+        Ok((output.stdout, signature))
+    }
+
+    /// Checks a detached signature over `data`.
+    pub fn verify(&self, data: &[u8], signature_data: &[u8]) -> Signature {
+        let result = Scratch::new().and_then(|scratch| {
+            let path = scratch.file("signature.asc", signature_data)?;
+            self.run(&["--verify", &path.to_string_lossy(), "-"], data)
+        });
+        match result {
+            Ok(output) => signature(&output).unwrap_or_else(|| Signature::Error(output.error())),
+            Err(e) => Signature::Error(format!("{:#}", e)),
+        }
+    }
+}
+
+/// Reads a signature result from gpg's status lines, if there was one.
+fn signature(output: &Output) -> Option<Signature> {
+    let signer = |fields: Vec<&str>| unescape(&fields[1..].join(" "));
+    if let Some(fields) = output.status("GOODSIG") {
+        let trusted =
+            output.status("TRUST_FULLY").is_some() || output.status("TRUST_ULTIMATE").is_some();
+        return Some(Signature::Good {
+            signer: signer(fields),
+            trusted,
+        });
+    }
+    if let Some(fields) = output.status("BADSIG") {
+        return Some(Signature::Bad {
+            signer: signer(fields),
+        });
+    }
+    if let Some(fields) = output
+        .status("EXPKEYSIG")
+        .or_else(|| output.status("EXPSIG"))
+    {
+        return Some(Signature::Expired {
+            signer: signer(fields),
+        });
+    }
+    if let Some(fields) = output.status("REVKEYSIG") {
+        return Some(Signature::Revoked {
+            signer: signer(fields),
+        });
+    }
+    // ERRSIG <keyid> <pkalgo> <hashalgo> <class> <time> <rc> [<fpr>]
+    if let Some(fields) = output.status("ERRSIG") {
+        let key = fields.get(6).or(fields.first()).unwrap_or(&"").to_string();
+        return Some(match fields.get(5) {
+            Some(&"9") => Signature::UnknownKey { key },
+            _ => Signature::Error(output.error()),
+        });
+    }
+    None
+}
+
+/// Decodes the `%XX` escapes gpg uses in status line user IDs.
+fn unescape(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// RFC 4880 hash algorithm IDs, as named in `micalg` (RFC 3156, section 5).
+fn hash_name(id: &str) -> &'static str {
+    match id {
+        "1" => "md5",
+        "2" => "sha1",
+        "3" => "ripemd160",
+        "8" => "sha256",
+        "9" => "sha384",
+        "10" => "sha512",
+        "11" => "sha224",
+        _ => "sha256",
+    }
+}
+
+/// Wraps an entity in multipart/signed with a detached signature
+/// (RFC 3156, section 5).
+pub fn sign_part(gpg: &Gpg, entity: Part, signer: Option<&str>) -> Result<Part> {
+    let (signature, micalg) = gpg.sign(&entity.to_bytes(), signer)?;
+    let signature = Part {
+        headers: vec![
+            (
+                "Content-Type".to_string(),
+                "application/pgp-signature; name=\"signature.asc\"".to_string(),
+            ),
+            (
+                "Content-Description".to_string(),
+                "OpenPGP digital signature".to_string(),
+            ),
+            (
+                "Content-Disposition".to_string(),
+                "attachment; filename=\"signature.asc\"".to_string(),
+            ),
+        ],
+        body: crlf(&signature),
+        parts: Vec::new(),
+    };
+    let mut signed = Part::new_multipart("signed", vec![entity, signature]);
+    add_params(
+        &mut signed,
+        &format!("protocol=\"application/pgp-signature\"; micalg={}", micalg),
+    );
+    Ok(signed)
+}
+
+/// Encrypts an entity into multipart/encrypted (RFC 3156, section 4),
+/// signing it first when `sign` is set.
+pub fn encrypt_part(
+    gpg: &Gpg,
+    entity: Part,
+    recipients: &[&str],
+    hidden: &[&str],
+    signer: Option<&str>,
+    sign: bool,
+) -> Result<Part> {
+    let encrypted = gpg.encrypt(&entity.to_bytes(), recipients, hidden, signer, sign)?;
+    let control = Part {
+        headers: vec![
+            (
+                "Content-Type".to_string(),
+                "application/pgp-encrypted".to_string(),
+            ),
+            (
+                "Content-Description".to_string(),
+                "PGP/MIME version identification".to_string(),
+            ),
+        ],
+        body: b"Version: 1\r\n".to_vec(),
+        parts: Vec::new(),
+    };
+    let payload = Part {
+        headers: vec![
+            (
+                "Content-Type".to_string(),
+                "application/octet-stream; name=\"encrypted.asc\"".to_string(),
+            ),
+            (
+                "Content-Description".to_string(),
+                "OpenPGP encrypted message".to_string(),
+            ),
+            (
+                "Content-Disposition".to_string(),
+                "inline; filename=\"encrypted.asc\"".to_string(),
+            ),
+        ],
+        body: crlf(&encrypted),
+        parts: Vec::new(),
+    };
+    let mut message = Part::new_multipart("encrypted", vec![control, payload]);
+    add_params(&mut message, "protocol=\"application/pgp-encrypted\"");
+    Ok(message)
+}
+
+/// Decrypts or verifies a PGP/MIME message. Returns the readable content,
+/// under the original message headers, with what was found; `None` when
+/// the message does not use PGP/MIME.
+pub fn open(gpg: &Gpg, message: &Part) -> Option<(Part, Security)> {
+    let security = |encrypted, error, signature| Security {
+        scheme: "OpenPGP",
+        encrypted,
+        error,
+        signature,
+    };
+    match message.mime_type().as_str() {
+        "multipart/encrypted" if has_protocol(message, "application/pgp-encrypted") => {
+            let payload = message.parts.get(1)?;
+            match gpg.decrypt(&payload.decoded_body()) {
+                Ok((plain, signature)) => {
+                    let inner = Part::parse(&plain);
+                    // Signed, then encrypted (RFC 3156, section 6.1)
+                    let (content, signature) = match open_signed(gpg, &inner) {
+                        Some((content, inner_signature)) => (content, Some(inner_signature)),
+                        None => (inner, signature),
+                    };
+                    Some((
+                        with_headers(message, content),
+                        security(true, None, signature),
+                    ))
+                }
+                Err(e) => Some((
+                    message.clone(),
+                    security(true, Some(format!("{:#}", e)), None),
+                )),
+            }
+        }
+        "multipart/signed" => {
+            let (content, signature) = open_signed(gpg, message)?;
+            Some((
+                with_headers(message, content),
+                security(false, None, Some(signature)),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Verifies a multipart/signed entity, returning its signed content.
+fn open_signed(gpg: &Gpg, part: &Part) -> Option<(Part, Signature)> {
+    if part.mime_type() != "multipart/signed"
+        || !has_protocol(part, "application/pgp-signature")
+        || part.parts.len() < 2
+    {
+        return None;
+    }
+    let signed = crlf(part.raw_parts().first()?);
+    let signature = gpg.verify(&signed, &part.parts[1].decoded_body());
+    Some((part.parts[0].clone(), signature))
+}
+
+fn has_protocol(part: &Part, protocol: &str) -> bool {
+    part.content_type_param("protocol")
+        .is_some_and(|p| p.eq_ignore_ascii_case(protocol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "alice@example.com";
+
+    /// A throwaway keyring holding a fresh key for Alice.
+    struct Keyring {
+        gpg: Gpg,
+        // Removed after the agent below is stopped
+        _home: Scratch,
+    }
+
+    impl Keyring {
+        /// `None` when gpg is not installed.
+        fn new() -> Option<Keyring> {
+            let home = Scratch::new().unwrap();
+            let gpg = Gpg {
+                homedir: Some(home.0.clone()),
+                ..Gpg::default()
+            };
+            let uid = format!("Alice <{}>", ALICE);
+            let output = gpg
+                .run(
+                    &[
+                        "--passphrase",
+                        "",
+                        "--pinentry-mode",
+                        "loopback",
+                        "--quick-gen-key",
+                        &uid,
+                        "future-default",
+                        "default",
+                        "never",
+                    ],
+                    b"",
+                )
+                .ok()?;
+            assert!(output.success, "{}", output.error());
+            Some(Keyring { gpg, _home: home })
+        }
+    }
+
+    impl Drop for Keyring {
+        fn drop(&mut self) {
+            if let Some(home) = &self.gpg.homedir {
+                let _ = Command::new("gpgconf")
+                    .arg("--homedir")
+                    .arg(home)
+                    .args(["--kill", "gpg-agent"])
+                    .status();
+            }
+        }
+    }
+
+    fn content() -> Part {
+        Part::new_text("plain", "hello\nworld\n")
+    }
+
+    /// Writes a message out and reads it back, as it would be sent.
+    fn transmit(part: &Part) -> Part {
+        Part::parse(&part.to_bytes())
+    }
+
+    #[test]
+    fn signed_messages_verify() {
+        let Some(keyring) = Keyring::new() else {
+            return;
+        };
+        let signed = sign_part(&keyring.gpg, content(), Some(ALICE)).unwrap();
+        assert!(
+            signed
+                .header("Content-Type")
+                .unwrap()
+                .contains("micalg=pgp-sha")
+        );
+        let (opened, security) = open(&keyring.gpg, &transmit(&signed)).unwrap();
+        assert_eq!(opened.text(), "hello\nworld\n");
+        assert!(!security.encrypted);
+        assert!(matches!(
+            security.signature,
+            Some(Signature::Good { ref signer, trusted: true }) if signer.contains(ALICE)
+        ));
+    }
+
+    #[test]
+    fn tampered_messages_fail_to_verify() {
+        let Some(keyring) = Keyring::new() else {
+            return;
+        };
+        let signed = sign_part(&keyring.gpg, content(), Some(ALICE)).unwrap();
+        let bytes = String::from_utf8(signed.to_bytes())
+            .unwrap()
+            .replace("hello", "jello");
+        let (opened, security) = open(&keyring.gpg, &Part::parse(bytes.as_bytes())).unwrap();
+        assert_eq!(opened.text(), "jello\nworld\n");
+        assert!(matches!(security.signature, Some(Signature::Bad { .. })));
+    }
+
+    #[test]
+    fn signatures_from_unknown_keys_are_reported() {
+        let (Some(keyring), Some(other)) = (Keyring::new(), Keyring::new()) else {
+            return;
+        };
+        let signed = sign_part(&keyring.gpg, content(), Some(ALICE)).unwrap();
+        let (_, security) = open(&other.gpg, &transmit(&signed)).unwrap();
+        // `other` has its own Alice, but not the key that signed
+        assert!(matches!(
+            security.signature,
+            Some(Signature::UnknownKey { .. })
+        ));
+    }
+
+    #[test]
+    fn encrypted_messages_decrypt() {
+        let Some(keyring) = Keyring::new() else {
+            return;
+        };
+        let encrypted =
+            encrypt_part(&keyring.gpg, content(), &[ALICE], &[], Some(ALICE), false).unwrap();
+        let sent = transmit(&encrypted);
+        assert_eq!(sent.mime_type(), "multipart/encrypted");
+        assert!(!String::from_utf8_lossy(&sent.to_bytes()).contains("hello"));
+        let (opened, security) = open(&keyring.gpg, &sent).unwrap();
+        assert_eq!(opened.text(), "hello\nworld\n");
+        assert!(security.encrypted);
+        assert_eq!(security.error, None);
+        assert_eq!(security.signature, None);
+    }
+
+    #[test]
+    fn signed_and_encrypted_messages_decrypt_and_verify() {
+        let Some(keyring) = Keyring::new() else {
+            return;
+        };
+        let encrypted =
+            encrypt_part(&keyring.gpg, content(), &[ALICE], &[], Some(ALICE), true).unwrap();
+        let (opened, security) = open(&keyring.gpg, &transmit(&encrypted)).unwrap();
+        assert_eq!(opened.text(), "hello\nworld\n");
+        assert!(security.encrypted);
+        assert!(matches!(security.signature, Some(Signature::Good { .. })));
+    }
+
+    #[test]
+    fn messages_for_other_keys_report_an_error() {
+        let (Some(keyring), Some(other)) = (Keyring::new(), Keyring::new()) else {
+            return;
+        };
+        let encrypted = encrypt_part(&keyring.gpg, content(), &[ALICE], &[], None, false).unwrap();
+        let (_, security) = open(&other.gpg, &transmit(&encrypted)).unwrap();
+        assert!(security.encrypted);
+        assert_eq!(
+            security.error.as_deref(),
+            Some("no secret key for this message")
+        );
+    }
+
+    #[test]
+    fn unknown_recipients_are_refused() {
+        let Some(keyring) = Keyring::new() else {
+            return;
+        };
+        let error = encrypt_part(
+            &keyring.gpg,
+            content(),
+            &["nobody@example.com"],
+            &[],
+            None,
+            false,
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("nobody@example.com"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn plain_messages_are_left_alone() {
+        assert!(open(&Gpg::default(), &content()).is_none());
+    }
+}
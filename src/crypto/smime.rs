@@ -0,0 +1,645 @@
+//! S/MIME detached signatures (RFC 8551) through the `openssl` program.
+//!
+//! Signatures are checked against a local CA bundle, and outgoing mail can
+//! be signed with a PKCS#12 identity. Certificates are never fetched or
+//! checked for revocation online. Needs OpenSSL 3, which reads keys from
+//! PKCS#12 files directly and can be kept off its default trust store.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result, bail};
+
+use super::{Scratch, Security, Signature, add_params, crlf, with_headers};
+use crate::backend::mime::{self, Part};
+use crate::config;
+
+/// The `openssl` program, trusted CAs and signing identity.
+#[derive(Debug, Clone)]
+pub struct Smime {
+    pub program: String,
+    /// PEM bundle of trusted CA certificates. When set, only these are
+    /// trusted; otherwise OpenSSL's default store is used.
+    pub ca_file: Option<PathBuf>,
+    /// PKCS#12 (`.p12`/`.pfx`) file holding the signing key and certificate.
+    pub pkcs12: Option<PathBuf>,
+    /// Command printing the PKCS#12 password; the file is assumed to have
+    /// an empty password without one.
+    pub password_command: Option<String>,
+}
+
+impl Default for Smime {
+    fn default() -> Self {
+        Self {
+            program: "openssl".to_string(),
+            ca_file: None,
+            pkcs12: None,
+            password_command: None,
+        }
+    }
+}
+
+/// Where output that is not needed goes.
+const NULL: &str = if cfg!(windows) { "NUL" } else { "/dev/null" };
+
+impl Smime {
+    /// Runs openssl, returning its stdout or everything it wrote to stderr.
+    fn run(&self, args: &[&str], env: &[(&str, &str)]) -> Result<Vec<u8>, String> {
+        let output = Command::new(&self.program)
+            .args(args)
+            .envs(env.iter().copied())
+            .output()
+            .map_err(|e| format!("running {}: {}", self.program, e))?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).into_owned())
+        }
+    }
+
+    /// Fails unless the program is OpenSSL 3 or newer; older versions and
+    /// LibreSSL lack options used here.
+    fn check_version(&self) -> Result<()> {
+        let output = self
+            .run(&["version"], &[])
+            .map_err(|e| anyhow::anyhow!("{}", summary(&e)))?;
+        let version = String::from_utf8_lossy(&output);
+        let mut words = version.split_whitespace();
+        let major = match (words.next(), words.next()) {
+            (Some("OpenSSL"), Some(number)) => {
+                number.split('.').next().and_then(|n| n.parse().ok())
+            }
+            _ => None,
+        };
+        if major.is_none_or(|major: u32| major < 3) {
+            bail!(
+                "S/MIME needs OpenSSL 3 or newer, but {} is {}",
+                self.program,
+                version.trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks a DER-encoded detached signature over `data`, which must be
+    /// in canonical (CRLF) form. `from` is the address the message claims
+    /// to be from; a certificate issued for another address, or a message
+    /// without one, is not trusted.
+    pub fn verify(&self, data: &[u8], signature: &[u8], from: Option<&str>) -> Signature {
+        match self.try_verify(data, signature, from) {
+            Ok(signature) => signature,
+            Err(e) => Signature::Error(format!("{:#}", e)),
+        }
+    }
+
+    fn try_verify(&self, data: &[u8], signature: &[u8], from: Option<&str>) -> Result<Signature> {
+        self.check_version()?;
+        let scratch = Scratch::new()?;
+        let content = scratch.file("content", data)?;
+        let sig = scratch.file("smime.p7s", signature)?;
+        let signer = scratch.0.join("signer.pem");
+        let (content, sig, signer) = (path_str(&content), path_str(&sig), path_str(&signer));
+        let mut args = vec![
+            "cms", "-verify", "-binary", "-inform", "DER", "-in", &sig, "-content", &content,
+            "-signer", &signer, "-out", NULL,
+        ];
+
+        let ca_file = self.ca_file.as_deref().map(path_str);
+        let mut trusted_args = args.clone();
+        if let Some(ca_file) = &ca_file {
+            trusted_args.extend(["-CAfile", ca_file, "-no-CApath", "-no-CAstore"]);
+        }
+        let trusted = self.run(&trusted_args, &[]).is_ok();
+        if !trusted {
+            // Tell a valid signature from an untrusted certificate apart
+            // from one over different content
+            args.push("-noverify");
+            if let Err(error) = self.run(&args, &[]) {
+                let certs = path_str(&scratch.0.join("certs.pem"));
+                let description = self.describe_certs(&sig, &certs).unwrap_or_default();
+                let tampered = ["content verify error", "digest failure", "verify failure"]
+                    .iter()
+                    .any(|e| error.to_ascii_lowercase().contains(e));
+                return Ok(if tampered {
+                    Signature::Bad {
+                        signer: description.label(),
+                    }
+                } else {
+                    Signature::Error(summary(&error).to_string())
+                });
+            }
+        }
+
+        let certificate = self.describe(&signer).unwrap_or_default();
+        // The certificate must be for the address the message is from
+        let matches_from = from.is_some_and(|from| {
+            certificate
+                .emails
+                .iter()
+                .any(|email| email.eq_ignore_ascii_case(from))
+        });
+        Ok(Signature::Good {
+            signer: certificate.label(),
+            trusted: trusted && matches_from,
+        })
+    }
+
+    /// Subject and addresses of a PEM certificate.
+    fn describe(&self, pem: &str) -> Result<Certificate, String> {
+        let output = self.run(
+            &[
+                "x509", "-in", pem, "-noout", "-subject", "-email", "-nameopt", "RFC2253",
+            ],
+            &[],
+        )?;
+        Ok(Certificate::parse(&String::from_utf8_lossy(&output)))
+    }
+
+    /// The first certificate carried in a signature, when the signer could
+    /// not be determined by verifying it.
+    fn describe_certs(&self, sig: &str, certs: &str) -> Result<Certificate, String> {
+        self.run(
+            &[
+                "cms",
+                "-cmsout",
+                "-inform",
+                "DER",
+                "-in",
+                sig,
+                "-certsout",
+                certs,
+                "-out",
+                NULL,
+            ],
+            &[],
+        )?;
+        self.describe(certs)
+    }
+
+    /// Makes a DER-encoded detached signature over `data` with the PKCS#12
+    /// identity.
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(pkcs12) = &self.pkcs12 else {
+            bail!("no S/MIME identity configured (smime.pkcs12)");
+        };
+        self.check_version()?;
+        let password = match &self.password_command {
+            Some(command) => config::password(command)?,
+            None => String::new(),
+        };
+        let scratch = Scratch::new()?;
+        let pkcs12 = path_str(pkcs12);
+        let certificate = path_str(&scratch.0.join("certificate.pem"));
+        let content = path_str(&scratch.file("content", data)?);
+        let sig = path_str(&scratch.0.join("smime.p7s"));
+
+        // Only the certificate is taken out of the PKCS#12 file; openssl
+        // reads the key from it while signing, so it is never written out.
+        // The password goes through the environment, not the command line.
+        let env = [("HERMES_PKCS12_PASSWORD", password.as_str())];
+        let passin = ["-passin", "env:HERMES_PKCS12_PASSWORD"];
+        self.run(
+            &[
+                &["pkcs12", "-in", &pkcs12, "-nokeys", "-out", &certificate][..],
+                &passin,
+            ]
+            .concat(),
+            &env,
+        )
+        .map_err(|e| anyhow::anyhow!("reading {}: {}", pkcs12, summary(&e)))?;
+        self.run(
+            &[
+                &[
+                    "cms",
+                    "-sign",
+                    "-binary",
+                    "-md",
+                    "sha256",
+                    "-signer",
+                    &certificate,
+                    "-inkey",
+                    &pkcs12,
+                    "-in",
+                    &content,
+                    "-outform",
+                    "DER",
+                    "-out",
+                    &sig,
+                ][..],
+                &passin,
+            ]
+            .concat(),
+            &env,
+        )
+        .map_err(|e| anyhow::anyhow!("S/MIME signing failed: {}", summary(&e)))?;
+        fs::read(&sig).with_context(|| format!("reading {}", sig))
+    }
+}
+
+/// What a certificate says about its holder.
+#[derive(Debug, Default)]
+struct Certificate {
+    name: Option<String>,
+    emails: Vec<String>,
+}
+
+impl Certificate {
+    /// Reads `subject=...` and address lines printed by `openssl x509`.
+    fn parse(text: &str) -> Certificate {
+        let mut certificate = Certificate::default();
+        for line in text.lines().map(str::trim) {
+            if let Some(subject) = line.strip_prefix("subject=") {
+                for (key, value) in rdns(subject) {
+                    match key.as_str() {
+                        "CN" => certificate.name = Some(value),
+                        "emailAddress" => certificate.emails.push(value),
+                        _ => {}
+                    }
+                }
+            } else if line.contains('@') && !line.contains('=') {
+                certificate.emails.push(line.to_string());
+            }
+        }
+        certificate.emails.dedup();
+        certificate
+    }
+
+    fn label(&self) -> String {
+        match (&self.name, self.emails.first()) {
+            (Some(name), Some(email)) => format!("{} <{}>", name, email),
+            (Some(name), None) => name.clone(),
+            (None, Some(email)) => email.clone(),
+            (None, None) => "an unknown signer".to_string(),
+        }
+    }
+}
+
+/// Splits an RFC 2253 distinguished name into `key=value` pairs.
+fn rdns(dn: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut current = String::new();
+    let mut chars = dn.chars();
+    let mut push = |current: &mut String| {
+        if let Some((key, value)) = current.split_once('=') {
+            pairs.push((key.trim().to_string(), value.trim().to_string()));
+        }
+        current.clear();
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            ',' | '+' => push(&mut current),
+            _ => current.push(c),
+        }
+    }
+    push(&mut current);
+    pairs
+}
+
+/// The first line of openssl's error output, which says what went wrong;
+/// the rest is a trace of library errors.
+fn summary(stderr: &str) -> &str {
+    stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("openssl failed")
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Wraps an entity in multipart/signed with an S/MIME signature
+/// (RFC 8551, section 3.5.3).
+pub fn sign_part(smime: &Smime, entity: Part) -> Result<Part> {
+    let signature = smime.sign(&entity.to_bytes())?;
+    let mut body = mime::encode_base64(&signature);
+    // Base64 in 76 character lines
+    body = body
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\r\n");
+    let signature = Part {
+        headers: vec![
+            (
+                "Content-Type".to_string(),
+                "application/pkcs7-signature; name=\"smime.p7s\"".to_string(),
+            ),
+            (
+                "Content-Transfer-Encoding".to_string(),
+                "base64".to_string(),
+            ),
+            (
+                "Content-Disposition".to_string(),
+                "attachment; filename=\"smime.p7s\"".to_string(),
+            ),
+            (
+                "Content-Description".to_string(),
+                "S/MIME Cryptographic Signature".to_string(),
+            ),
+        ],
+        body: body.into_bytes(),
+        parts: Vec::new(),
+    };
+    let mut signed = Part::new_multipart("signed", vec![entity, signature]);
+    add_params(
+        &mut signed,
+        "protocol=\"application/pkcs7-signature\"; micalg=sha-256",
+    );
+    Ok(signed)
+}
+
+/// Verifies an S/MIME signed message, returning its content under the
+/// original headers; `None` when it is not S/MIME signed.
+pub fn open(smime: &Smime, message: &Part) -> Option<(Part, Security)> {
+    let protocol = message.content_type_param("protocol")?.to_ascii_lowercase();
+    if message.mime_type() != "multipart/signed"
+        || !matches!(
+            protocol.as_str(),
+            "application/pkcs7-signature" | "application/x-pkcs7-signature"
+        )
+        || message.parts.len() < 2
+    {
+        return None;
+    }
+    let signed = crlf(message.raw_parts().first()?);
+    let from = message
+        .header("From")
+        .map(mime::decode_header_value)
+        .and_then(|from| crate::backend::address::parse_address_list(&from).ok())
+        .and_then(|list| list.into_iter().next())
+        .map(|address| address.email);
+    let signature = smime.verify(&signed, &message.parts[1].decoded_body(), from.as_deref());
+    Some((
+        with_headers(message, message.parts[0].clone()),
+        Security {
+            scheme: "S/MIME",
+            encrypted: false,
+            error: None,
+            signature: Some(signature),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "alice@example.com";
+    const PASSWORD: &str = "secret";
+
+    /// A throwaway CA and a PKCS#12 identity for Alice issued by it.
+    struct Pki {
+        dir: Scratch,
+    }
+
+    impl Pki {
+        /// `None` when openssl is not installed.
+        fn new() -> Option<Pki> {
+            let pki = Pki {
+                dir: Scratch::new().unwrap(),
+            };
+            pki.openssl(&["version"])?;
+            for name in ["ca", "other-ca"] {
+                let subject = format!("/CN={}", name);
+                pki.openssl(&[
+                    "req",
+                    "-x509",
+                    "-newkey",
+                    "ec",
+                    "-pkeyopt",
+                    "ec_paramgen_curve:P-256",
+                    "-nodes",
+                    "-keyout",
+                    &pki.path(&format!("{}.key", name)),
+                    "-out",
+                    &pki.path(&format!("{}.pem", name)),
+                    "-subj",
+                    &subject,
+                    "-days",
+                    "2",
+                ])
+                .unwrap();
+            }
+            pki.dir
+                .file(
+                    "alice.ext",
+                    format!(
+                        "subjectAltName=email:{}\nkeyUsage=digitalSignature\n\
+extendedKeyUsage=emailProtection\n",
+                        ALICE
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+            let subject = format!("/CN=Alice/emailAddress={}", ALICE);
+            for args in [
+                &[
+                    "req",
+                    "-newkey",
+                    "ec",
+                    "-pkeyopt",
+                    "ec_paramgen_curve:P-256",
+                    "-nodes",
+                    "-keyout",
+                    &pki.path("alice.key"),
+                    "-out",
+                    &pki.path("alice.csr"),
+                    "-subj",
+                    &subject,
+                ][..],
+                &[
+                    "x509",
+                    "-req",
+                    "-in",
+                    &pki.path("alice.csr"),
+                    "-CA",
+                    &pki.path("ca.pem"),
+                    "-CAkey",
+                    &pki.path("ca.key"),
+                    "-CAcreateserial",
+                    "-out",
+                    &pki.path("alice.pem"),
+                    "-days",
+                    "2",
+                    "-extfile",
+                    &pki.path("alice.ext"),
+                ],
+                &[
+                    "pkcs12",
+                    "-export",
+                    "-in",
+                    &pki.path("alice.pem"),
+                    "-inkey",
+                    &pki.path("alice.key"),
+                    "-passout",
+                    &format!("pass:{}", PASSWORD),
+                    "-out",
+                    &pki.path("alice.p12"),
+                ],
+            ] {
+                pki.openssl(args).unwrap();
+            }
+            Some(pki)
+        }
+
+        fn path(&self, name: &str) -> String {
+            path_str(&self.dir.0.join(name))
+        }
+
+        fn openssl(&self, args: &[&str]) -> Option<()> {
+            let output = Command::new("openssl").args(args).output().ok()?;
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            Some(())
+        }
+
+        /// Trusts `ca` and signs as Alice.
+        fn smime(&self, ca: &str) -> Smime {
+            Smime {
+                ca_file: Some(self.dir.0.join(format!("{}.pem", ca))),
+                pkcs12: Some(self.dir.0.join("alice.p12")),
+                password_command: Some(format!("echo {}", PASSWORD)),
+                ..Smime::default()
+            }
+        }
+    }
+
+    /// A message signed by Alice, written out and read back as it would
+    /// be sent, claiming to be from `from`.
+    fn signed(smime: &Smime, from: Option<&str>) -> Part {
+        let mut signed = sign_part(smime, Part::new_text("plain", "hello\nworld\n")).unwrap();
+        if let Some(from) = from {
+            signed.headers.push(("From".to_string(), from.to_string()));
+        }
+        Part::parse(&signed.to_bytes())
+    }
+
+    fn signature(smime: &Smime, message: &Part) -> Signature {
+        open(smime, message).unwrap().1.signature.unwrap()
+    }
+
+    #[test]
+    fn signed_messages_verify() {
+        let Some(pki) = Pki::new() else {
+            return;
+        };
+        let smime = pki.smime("ca");
+        let message = signed(&smime, Some("Alice <ALICE@example.com>"));
+        assert!(
+            message
+                .header("Content-Type")
+                .unwrap()
+                .contains("application/pkcs7-signature")
+        );
+        let (opened, security) = open(&smime, &message).unwrap();
+        assert_eq!(opened.text(), "hello\nworld\n");
+        assert_eq!(security.scheme, "S/MIME");
+        assert!(matches!(
+            security.signature,
+            Some(Signature::Good { ref signer, trusted: true })
+                if signer == "Alice <alice@example.com>"
+        ));
+    }
+
+    #[test]
+    fn signers_from_other_cas_are_not_trusted() {
+        let Some(pki) = Pki::new() else {
+            return;
+        };
+        let message = signed(&pki.smime("ca"), Some(ALICE));
+        assert!(matches!(
+            signature(&pki.smime("other-ca"), &message),
+            Signature::Good { trusted: false, .. }
+        ));
+    }
+
+    #[test]
+    fn certificates_must_name_the_sender() {
+        let Some(pki) = Pki::new() else {
+            return;
+        };
+        let smime = pki.smime("ca");
+        for from in [Some("Mallory <mallory@example.com>"), None] {
+            assert!(
+                matches!(
+                    signature(&smime, &signed(&smime, from)),
+                    Signature::Good { trusted: false, .. }
+                ),
+                "{:?}",
+                from
+            );
+        }
+    }
+
+    #[test]
+    fn tampered_messages_fail_to_verify() {
+        let Some(pki) = Pki::new() else {
+            return;
+        };
+        let smime = pki.smime("ca");
+        let bytes = String::from_utf8(signed(&smime, Some(ALICE)).to_bytes())
+            .unwrap()
+            .replace("hello", "jello");
+        let message = Part::parse(bytes.as_bytes());
+        assert!(matches!(
+            signature(&smime, &message),
+            Signature::Bad { ref signer } if signer.contains(ALICE)
+        ));
+    }
+
+    #[test]
+    fn signing_needs_the_right_password() {
+        let Some(pki) = Pki::new() else {
+            return;
+        };
+        let smime = Smime {
+            password_command: Some("echo wrong".to_string()),
+            ..pki.smime("ca")
+        };
+        assert!(smime.sign(b"hello\r\n").is_err());
+        assert!(Smime::default().sign(b"hello\r\n").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn older_openssl_versions_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = Scratch::new().unwrap();
+        let program = dir
+            .file("openssl", b"#!/bin/sh\necho 'LibreSSL 3.8.2'\n")
+            .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o700)).unwrap();
+        let smime = Smime {
+            program: path_str(&program),
+            pkcs12: Some(dir.0.join("unused.p12")),
+            ..Smime::default()
+        };
+        let error = smime.sign(b"hello\r\n").unwrap_err().to_string();
+        assert!(error.contains("needs OpenSSL 3"), "{}", error);
+        assert!(matches!(
+            smime.verify(b"hello\r\n", b"", None),
+            Signature::Error(ref e) if e.contains("LibreSSL 3.8.2")
+        ));
+    }
+
+    #[test]
+    fn parses_certificate_subjects() {
+        let certificate = Certificate::parse(
+            "subject=emailAddress=a@example.com,CN=Doe\\, Jane,O=Example\nb@example.com\n",
+        );
+        assert_eq!(certificate.name.as_deref(), Some("Doe, Jane"));
+        assert_eq!(certificate.emails, vec!["a@example.com", "b@example.com"]);
+        assert_eq!(certificate.label(), "Doe, Jane <a@example.com>");
+        assert_eq!(Certificate::default().label(), "an unknown signer");
+    }
+}
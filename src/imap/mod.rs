@@ -0,0 +1,703 @@
+//! A small IMAP4rev1 client (RFC 3501): enough to open a folder, fetch its
+//! messages and wait for changes with IDLE (RFC 2177).
+
+pub mod watch;
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config;
+use crate::net::{Server, Stream, Tls};
+
+/// How long a command may take before the server is given up on.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One item of a response: an atom, a quoted or literal string, `NIL`, or
+/// a parenthesized list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Atom(String),
+    String(Vec<u8>),
+    List(Vec<Value>),
+    Nil,
+}
+
+impl Value {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Value::Atom(atom) => Some(atom),
+            _ => None,
+        }
+    }
+}
+
+/// A message as fetched.
+#[derive(Debug, Clone, Default)]
+pub struct Fetched {
+    pub uid: u32,
+    /// `\Seen` and `\Flagged`; `None` when the response had no FLAGS,
+    /// as when the server reports some other change.
+    pub seen: Option<bool>,
+    pub flagged: Option<bool>,
+    /// The full source, when it was asked for.
+    pub body: Option<Vec<u8>>,
+}
+
+/// What the server reported about the selected folder during IDLE.
+#[derive(Debug, Clone)]
+pub enum Change {
+    Arrived(Vec<Fetched>),
+    Expunged(u32),
+    Flags { uid: u32, seen: bool, flagged: bool },
+}
+
+#[derive(Debug)]
+enum Untagged {
+    Exists(u32),
+    Expunge(u32),
+    Fetch(u32, Fetched),
+    Capability(Vec<String>),
+    UidValidity(u32),
+    Bye(String),
+    Other,
+}
+
+#[derive(Debug)]
+enum Response {
+    /// `+`: go on, or IDLE has started.
+    Continue,
+    Untagged(Untagged),
+    /// The tagged status that completes a command.
+    Done {
+        tag: String,
+        ok: bool,
+        text: String,
+    },
+}
+
+/// A logged-in connection.
+pub struct Session {
+    stream: Stream,
+    buffer: Vec<u8>,
+    tag: u32,
+    capabilities: Vec<String>,
+    /// UIDs of the selected folder's messages, in sequence number order.
+    uids: Vec<u32>,
+    /// Messages the server says the folder holds.
+    exists: u32,
+    uid_validity: u32,
+    /// Changes reported during commands, passed on by the next `idle`.
+    changes: Vec<Change>,
+}
+
+impl Session {
+    /// Connects, negotiates TLS as configured and logs in.
+    pub fn connect(server: &Server) -> Result<Session> {
+        let stream = match server.tls {
+            Tls::Implicit => {
+                Stream::connect_tls(&server.host, server.port, server.ca_file.as_deref())?
+            }
+            Tls::Starttls | Tls::None => Stream::connect(&server.host, server.port)?,
+        };
+        let mut session = Session {
+            stream,
+            buffer: Vec::new(),
+            tag: 0,
+            capabilities: Vec::new(),
+            uids: Vec::new(),
+            exists: 0,
+            uid_validity: 0,
+            changes: Vec::new(),
+        };
+        match session.read_response()? {
+            Response::Untagged(Untagged::Bye(text)) => bail!("server replied BYE {}", text),
+            Response::Untagged(_) => {}
+            _ => bail!("unexpected greeting from {}", server.host),
+        }
+
+        if server.tls == Tls::Starttls {
+            session.command("CAPABILITY")?;
+            if !session.has_capability("STARTTLS") {
+                bail!("{} does not offer STARTTLS", server.host);
+            }
+            session.command("STARTTLS")?;
+            let Session { stream, tag, .. } = session;
+            session = Session {
+                stream: stream.start_tls(&server.host, server.ca_file.as_deref())?,
+                buffer: Vec::new(),
+                tag,
+                capabilities: Vec::new(),
+                uids: Vec::new(),
+                exists: 0,
+                uid_validity: 0,
+                changes: Vec::new(),
+            };
+        }
+
+        session.command("CAPABILITY")?;
+        if session.has_capability("LOGINDISABLED") {
+            bail!("{} does not accept logins without TLS", server.host);
+        }
+        let password = match &server.password_command {
+            Some(command) => config::password(command)?,
+            None => String::new(),
+        };
+        session
+            .command(&format!(
+                "LOGIN {} {}",
+                quote(&server.user),
+                quote(&password)
+            ))
+            .with_context(|| format!("logging in to {} as {}", server.host, server.user))?;
+        // Capabilities may change once logged in
+        session.command("CAPABILITY")?;
+        Ok(session)
+    }
+
+    pub fn has_capability(&self, name: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(name))
+    }
+
+    /// Opens a folder and fetches the UID and flags of every message in
+    /// it, but not their source; see `fetch_uids`.
+    pub fn select(&mut self, folder: &str) -> Result<Vec<Fetched>> {
+        self.uids.clear();
+        self.exists = 0;
+        self.uid_validity = 0;
+        self.changes.clear();
+        self.command(&format!("SELECT {}", quote(folder)))
+            .with_context(|| format!("opening {}", folder))?;
+        if self.exists == 0 {
+            return Ok(Vec::new());
+        }
+        self.fetch(&format!("FETCH 1:{} (UID FLAGS)", self.exists), false)
+    }
+
+    /// The UIDVALIDITY of the selected folder: while it stays the same, a
+    /// UID keeps naming the same message. 0 if the server did not say.
+    pub fn uid_validity(&self) -> u32 {
+        self.uid_validity
+    }
+
+    /// Fetches the messages with these UIDs in full.
+    pub fn fetch_uids(&mut self, uids: &[u32]) -> Result<Vec<Fetched>> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.fetch(
+            &format!("UID FETCH {} (UID FLAGS BODY.PEEK[])", uid_set(uids)),
+            true,
+        )
+    }
+
+    /// Waits in IDLE until the folder changes or `timeout` passes, then
+    /// ends IDLE and returns the changes, with new messages fetched. An
+    /// empty list means nothing changed in time, and IDLE can be issued
+    /// again. Changes reported during earlier commands are returned at
+    /// once.
+    pub fn idle(&mut self, timeout: Duration) -> Result<Vec<Change>> {
+        if !self.changes.is_empty() {
+            return Ok(std::mem::take(&mut self.changes));
+        }
+        let tag = self.next_tag();
+        self.send(&format!("{} IDLE", tag))?;
+        loop {
+            match self.read_response()? {
+                Response::Continue => break,
+                Response::Untagged(untagged) => self.apply(untagged)?,
+                Response::Done { text, .. } => bail!("IDLE refused: {}", text),
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        while self.changes.is_empty() && self.exists as usize <= self.uids.len() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            self.stream.set_read_timeout(Some(left))?;
+            let response = self.read_response();
+            self.stream.set_read_timeout(Some(COMMAND_TIMEOUT))?;
+            match response {
+                Ok(Response::Untagged(untagged)) => self.apply(untagged)?,
+                Ok(_) => bail!("unexpected response during IDLE"),
+                Err(e) if timed_out(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.send("DONE")?;
+        self.finish(&tag)?;
+        let known = self.uids.len() as u32;
+        if self.exists > known {
+            let command = format!(
+                "FETCH {}:{} (UID FLAGS BODY.PEEK[])",
+                known + 1,
+                self.exists
+            );
+            let arrived = self.fetch(&command, true)?;
+            self.changes.push(Change::Arrived(arrived));
+        }
+        Ok(std::mem::take(&mut self.changes))
+    }
+
+    /// Logs out. The server says BYE before confirming, so the reply is
+    /// not checked.
+    pub fn logout(mut self) {
+        let _ = self.command("LOGOUT");
+    }
+
+    /// Runs a FETCH or UID FETCH command that asks for UIDs, and for the
+    /// source when `body` is set, recording the UIDs. Other FETCH
+    /// responses the server sends meanwhile are changes.
+    fn fetch(&mut self, command: &str, body: bool) -> Result<Vec<Fetched>> {
+        let tag = self.next_tag();
+        self.send(&format!("{} {}", tag, command))?;
+        let mut fetched: Vec<Fetched> = Vec::new();
+        loop {
+            match self.read_response()? {
+                Response::Untagged(Untagged::Fetch(seq, message))
+                    if message.uid != 0 && (message.body.is_some() || !body) =>
+                {
+                    let index = seq as usize - 1;
+                    if index >= self.uids.len() {
+                        self.uids.resize(index + 1, 0);
+                    }
+                    self.uids[index] = message.uid;
+                    match fetched.iter_mut().find(|m| m.uid == message.uid) {
+                        Some(earlier) => *earlier = message,
+                        None => fetched.push(message),
+                    }
+                }
+                Response::Untagged(untagged) => self.apply(untagged)?,
+                Response::Done {
+                    tag: done,
+                    ok,
+                    text,
+                } if done == tag => {
+                    if !ok {
+                        bail!("fetching messages failed: {}", text);
+                    }
+                    return Ok(fetched);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Notes an untagged response in the folder state, keeping what changed
+    /// for `idle` to return.
+    fn apply(&mut self, untagged: Untagged) -> Result<()> {
+        match untagged {
+            Untagged::Exists(count) => self.exists = count,
+            Untagged::Expunge(seq) => {
+                let index = seq as usize - 1;
+                if index < self.uids.len() {
+                    let uid = self.uids.remove(index);
+                    // 0 stands for a message not fetched yet
+                    if uid != 0 {
+                        self.changes.push(Change::Expunged(uid));
+                    }
+                }
+                self.exists = self.exists.saturating_sub(1);
+            }
+            Untagged::Fetch(seq, message) => {
+                if let Some(&uid) = self.uids.get(seq as usize - 1)
+                    && uid != 0
+                    && let (Some(seen), Some(flagged)) = (message.seen, message.flagged)
+                {
+                    self.changes.push(Change::Flags { uid, seen, flagged });
+                }
+            }
+            Untagged::Capability(capabilities) => self.capabilities = capabilities,
+            Untagged::UidValidity(validity) => self.uid_validity = validity,
+            Untagged::Bye(text) => bail!("server closed the connection: {}", text),
+            Untagged::Other => {}
+        }
+        Ok(())
+    }
+
+    /// Sends a command and waits for it to complete.
+    fn command(&mut self, command: &str) -> Result<()> {
+        let tag = self.next_tag();
+        self.send(&format!("{} {}", tag, command))?;
+        self.finish(&tag)
+    }
+
+    /// Reads responses up to the tagged one for `tag`.
+    fn finish(&mut self, tag: &str) -> Result<()> {
+        loop {
+            match self.read_response()? {
+                Response::Untagged(untagged) => self.apply(untagged)?,
+                Response::Done {
+                    tag: done,
+                    ok,
+                    text,
+                } if done == tag => {
+                    if !ok {
+                        bail!("server replied {}", text);
+                    }
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn next_tag(&mut self) -> String {
+        self.tag += 1;
+        format!("h{}", self.tag)
+    }
+
+    fn send(&mut self, line: &str) -> Result<()> {
+        // One write, so the line break does not wait for an ACK
+        self.stream.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn read_response(&mut self) -> Result<Response> {
+        loop {
+            if let Some(length) = complete_response(&self.buffer) {
+                let raw: Vec<u8> = self.buffer.drain(..length).collect();
+                return Ok(parse_response(&raw));
+            }
+            let mut chunk = [0; 8192];
+            let n = self
+                .stream
+                .read(&mut chunk)
+                .context("reading from the IMAP server")?;
+            if n == 0 {
+                bail!("the IMAP server closed the connection");
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// Whether a read gave up waiting rather than failing.
+fn timed_out(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<io::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    })
+}
+
+/// A quoted string.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A sequence set naming `uids`, with runs written as ranges.
+fn uid_set(uids: &[u32]) -> String {
+    let mut sorted = uids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for uid in sorted {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == uid => *last = uid,
+            _ => runs.push((uid, uid)),
+        }
+    }
+    let parts: Vec<String> = runs
+        .into_iter()
+        .map(|(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}:{}", first, last)
+            }
+        })
+        .collect();
+    parts.join(",")
+}
+
+/// The length of the first whole response in `buffer`, including any
+/// literals and the final line break.
+fn complete_response(buffer: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    loop {
+        let end = pos + buffer[pos..].iter().position(|&c| c == b'\n')?;
+        let line = buffer[pos..end]
+            .strip_suffix(b"\r")
+            .unwrap_or(&buffer[pos..end]);
+        match literal_length(line) {
+            Some(length) => {
+                pos = end + 1 + length;
+                if pos > buffer.len() {
+                    return None;
+                }
+            }
+            None => return Some(end + 1),
+        }
+    }
+}
+
+/// The length of the `{N}` literal a line ends with.
+fn literal_length(line: &[u8]) -> Option<usize> {
+    let inner = line.strip_suffix(b"}")?;
+    let start = inner.iter().rposition(|&c| c == b'{')?;
+    let digits = inner[start + 1..]
+        .strip_suffix(b"+")
+        .unwrap_or(&inner[start + 1..]);
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn parse_response(raw: &[u8]) -> Response {
+    let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+    let raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+    if raw.starts_with(b"+") {
+        return Response::Continue;
+    }
+    let (tag, rest) = split_word(raw);
+    let (word, after) = split_word(rest);
+    let text = || String::from_utf8_lossy(after).into_owned();
+    if tag != b"*" {
+        return Response::Done {
+            tag: String::from_utf8_lossy(tag).into_owned(),
+            ok: word.eq_ignore_ascii_case(b"OK"),
+            text: format!("{} {}", String::from_utf8_lossy(word), text()),
+        };
+    }
+    if let Some(number) = std::str::from_utf8(word).ok().and_then(|w| w.parse().ok()) {
+        let (kind, after) = split_word(after);
+        return Response::Untagged(match kind.to_ascii_uppercase().as_slice() {
+            b"EXISTS" => Untagged::Exists(number),
+            b"EXPUNGE" if number > 0 => Untagged::Expunge(number),
+            b"FETCH" if number > 0 => Untagged::Fetch(number, fetched(&values(after, &mut 0))),
+            _ => Untagged::Other,
+        });
+    }
+    Response::Untagged(match word.to_ascii_uppercase().as_slice() {
+        b"CAPABILITY" => {
+            Untagged::Capability(text().split_whitespace().map(str::to_string).collect())
+        }
+        b"OK" => match text()
+            .strip_prefix("[UIDVALIDITY ")
+            .and_then(|rest| rest.split_once(']'))
+            .and_then(|(number, _)| number.parse().ok())
+        {
+            Some(validity) => Untagged::UidValidity(validity),
+            None => Untagged::Other,
+        },
+        b"BYE" => Untagged::Bye(text()),
+        _ => Untagged::Other,
+    })
+}
+
+fn split_word(input: &[u8]) -> (&[u8], &[u8]) {
+    match input.iter().position(|&c| c == b' ') {
+        Some(space) => (&input[..space], &input[space + 1..]),
+        None => (input, &[]),
+    }
+}
+
+/// The attributes of a FETCH response.
+fn fetched(values: &[Value]) -> Fetched {
+    let mut message = Fetched::default();
+    let Some(Value::List(items)) = values.first() else {
+        return message;
+    };
+    for pair in items.chunks(2) {
+        let [Value::Atom(name), value] = pair else {
+            continue;
+        };
+        match (name.to_ascii_uppercase().as_str(), value) {
+            ("UID", Value::Atom(uid)) => message.uid = uid.parse().unwrap_or_default(),
+            ("FLAGS", Value::List(flags)) => {
+                let has = |flag: &str| {
+                    flags
+                        .iter()
+                        .filter_map(Value::atom)
+                        .any(|f| f.eq_ignore_ascii_case(flag))
+                };
+                message.seen = Some(has("\\Seen"));
+                message.flagged = Some(has("\\Flagged"));
+            }
+            ("BODY[]", Value::String(body)) => message.body = Some(body.clone()),
+            ("BODY[]", Value::Nil) => message.body = Some(Vec::new()),
+            _ => {}
+        }
+    }
+    message
+}
+
+/// Reads values up to the end of the input or of the list being read.
+fn values(input: &[u8], pos: &mut usize) -> Vec<Value> {
+    let mut out = Vec::new();
+    while let Some(&c) = input.get(*pos) {
+        match c {
+            b' ' | b'\r' | b'\n' => *pos += 1,
+            b'(' => {
+                *pos += 1;
+                out.push(Value::List(values(input, pos)));
+            }
+            b')' => {
+                *pos += 1;
+                return out;
+            }
+            b'"' => {
+                let mut text = Vec::new();
+                *pos += 1;
+                while let Some(&c) = input.get(*pos) {
+                    *pos += 1;
+                    match c {
+                        b'"' => break,
+                        b'\\' => {
+                            text.extend(input.get(*pos));
+                            *pos += 1;
+                        }
+                        _ => text.push(c),
+                    }
+                }
+                out.push(Value::String(text));
+            }
+            b'{' => {
+                let close = input[*pos..].iter().position(|&c| c == b'}');
+                let length = close.and_then(|close| literal_length(&input[*pos..=*pos + close]));
+                let (Some(close), Some(length)) = (close, length) else {
+                    *pos += 1;
+                    continue;
+                };
+                let mut start = *pos + close + 1;
+                if input[start..].starts_with(b"\r\n") {
+                    start += 2;
+                } else if input[start..].starts_with(b"\n") {
+                    start += 1;
+                }
+                let end = (start + length).min(input.len());
+                out.push(Value::String(input[start..end].to_vec()));
+                *pos = end;
+            }
+            _ => {
+                // Atoms such as `BODY[]` may hold a bracketed section
+                let start = *pos;
+                let mut depth = 0;
+                while let Some(&c) = input.get(*pos) {
+                    match c {
+                        b'[' => depth += 1,
+                        b']' => depth -= 1,
+                        b' ' | b'(' | b')' | b'\r' | b'\n' if depth <= 0 => break,
+                        _ => {}
+                    }
+                    *pos += 1;
+                }
+                let atom = String::from_utf8_lossy(&input[start..*pos]).into_owned();
+                out.push(if atom.eq_ignore_ascii_case("NIL") {
+                    Value::Nil
+                } else {
+                    Value::Atom(atom)
+                });
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(response: &str) -> (u32, Fetched) {
+        match parse_response(response.as_bytes()) {
+            Response::Untagged(Untagged::Fetch(seq, message)) => (seq, message),
+            other => panic!("not a FETCH: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn waits_for_whole_literals() {
+        let response = b"* 1 FETCH (BODY[] {5}\r\nab\r\nc UID 3)\r\n* 2 EXISTS\r\n";
+        assert_eq!(complete_response(&response[..20]), None);
+        assert_eq!(complete_response(&response[..30]), None);
+        let first = response.len() - b"* 2 EXISTS\r\n".len();
+        assert_eq!(complete_response(response), Some(first));
+        assert_eq!(complete_response(b"* OK"), None);
+    }
+
+    #[test]
+    fn reads_fetched_messages() {
+        let (seq, message) =
+            fetch("* 4 FETCH (UID 17 FLAGS (\\Seen $Junk) BODY[] {5}\r\nab\r\nc)\r\n");
+        assert_eq!(seq, 4);
+        assert_eq!(message.uid, 17);
+        assert_eq!(message.seen, Some(true));
+        assert_eq!(message.flagged, Some(false));
+        assert_eq!(message.body.as_deref(), Some(&b"ab\r\nc"[..]));
+
+        let (_, message) = fetch("* 1 FETCH (FLAGS (\\flagged) BODY[] \"a \\\"b\\\"\")");
+        assert_eq!(message.flagged, Some(true));
+        assert_eq!(message.body.as_deref(), Some(&b"a \"b\""[..]));
+        assert_eq!(fetch("* 1 FETCH (BODY[] NIL)").1.body, Some(Vec::new()));
+    }
+
+    #[test]
+    fn fetches_without_flags_leave_them_unknown() {
+        let (_, message) = fetch("* 2 FETCH (UID 9 MODSEQ (12345))");
+        assert_eq!(message.uid, 9);
+        assert_eq!((message.seen, message.flagged), (None, None));
+        assert!(message.body.is_none());
+    }
+
+    #[test]
+    fn reads_status_responses() {
+        assert!(matches!(
+            parse_response(b"+ idling\r\n"),
+            Response::Continue
+        ));
+        assert!(matches!(
+            parse_response(b"* 3 EXISTS\r\n"),
+            Response::Untagged(Untagged::Exists(3))
+        ));
+        assert!(matches!(
+            parse_response(b"* 2 EXPUNGE\r\n"),
+            Response::Untagged(Untagged::Expunge(2))
+        ));
+        assert!(matches!(
+            parse_response(b"* 0 EXPUNGE\r\n"),
+            Response::Untagged(Untagged::Other)
+        ));
+        assert!(matches!(
+            parse_response(b"* OK [UIDVALIDITY 3857529045] UIDs valid\r\n"),
+            Response::Untagged(Untagged::UidValidity(3857529045))
+        ));
+        assert!(matches!(
+            parse_response(b"* OK [UIDNEXT 4] next\r\n"),
+            Response::Untagged(Untagged::Other)
+        ));
+        match parse_response(b"* CAPABILITY IMAP4rev1 IDLE\r\n") {
+            Response::Untagged(Untagged::Capability(capabilities)) => {
+                assert_eq!(capabilities, vec!["IMAP4rev1", "IDLE"])
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_response(b"h3 NO [ALERT] mailbox is full\r\n") {
+            Response::Done { tag, ok, text } => {
+                assert_eq!(tag, "h3");
+                assert!(!ok);
+                assert_eq!(text, "NO [ALERT] mailbox is full");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn writes_uid_sets() {
+        assert_eq!(uid_set(&[7]), "7");
+        assert_eq!(uid_set(&[12, 10, 11, 15, 17, 16, 11]), "10:12,15:17");
+    }
+
+    #[test]
+    fn quotes_strings() {
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}
@@ -0,0 +1,480 @@
+//! Watching an IMAP folder with IDLE: the folder is fetched on connecting,
+//! then every change the server pushes is passed on. A dropped connection
+//! is retried with a growing delay, and IDLE is restarted before servers
+//! time it out. Messages already fetched are kept, so reconnecting only
+//! downloads the ones that arrived meanwhile.
+
+use std::{
+    collections::{HashMap, HashSet},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+
+use super::{Change, Fetched, Session};
+use crate::backend::{EmailSummary, RemoteMessage};
+use crate::net::Server;
+
+/// Delay before the first reconnection attempt, doubled after each failure.
+const RETRY_FIRST: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// A change to a watched folder.
+#[derive(Debug, Clone)]
+pub enum Update {
+    /// Every message in the folder, fetched on (re)connecting.
+    Snapshot {
+        folder: String,
+        emails: Vec<EmailSummary>,
+    },
+    Arrived {
+        folder: String,
+        emails: Vec<EmailSummary>,
+    },
+    Expunged {
+        folder: String,
+        uid: u32,
+    },
+    Flags {
+        folder: String,
+        uid: u32,
+        read: bool,
+        flagged: bool,
+    },
+    /// The connection failed; it is tried again after `retry`.
+    Disconnected {
+        folder: String,
+        error: String,
+        retry: Duration,
+    },
+}
+
+/// The messages fetched so far, by UID.
+#[derive(Default)]
+struct Known {
+    uid_validity: u32,
+    emails: HashMap<u32, EmailSummary>,
+}
+
+/// Watches `folder` until `send` returns false. `idle_timeout` is how
+/// long IDLE runs before it is restarted; `parse` reads fetched messages.
+pub fn watch(
+    server: &Server,
+    folder: &str,
+    idle_timeout: Duration,
+    parse: impl Fn(&[u8]) -> EmailSummary,
+    send: impl FnMut(Update) -> bool,
+) {
+    watch_retrying(server, folder, idle_timeout, RETRY_FIRST, parse, send)
+}
+
+/// `watch`, first reconnecting after `retry_first`.
+fn watch_retrying(
+    server: &Server,
+    folder: &str,
+    idle_timeout: Duration,
+    retry_first: Duration,
+    parse: impl Fn(&[u8]) -> EmailSummary,
+    mut send: impl FnMut(Update) -> bool,
+) {
+    let mut known = Known::default();
+    let mut retry = retry_first;
+    loop {
+        let mut connected = false;
+        let result = session(
+            server,
+            folder,
+            idle_timeout,
+            &parse,
+            &mut send,
+            &mut known,
+            &mut connected,
+        );
+        let error = match result {
+            Ok(()) => return,
+            Err(e) => format!("{:#}", e),
+        };
+        if connected {
+            retry = retry_first;
+        }
+        let disconnected = Update::Disconnected {
+            folder: folder.to_string(),
+            error,
+            retry,
+        };
+        if !send(disconnected) {
+            return;
+        }
+        thread::sleep(retry);
+        retry = (retry * 2).min(RETRY_MAX);
+    }
+}
+
+/// One connection, until it fails or `send` asks to stop. `connected` is
+/// set once the folder has been fetched.
+fn session(
+    server: &Server,
+    folder: &str,
+    idle_timeout: Duration,
+    parse: &impl Fn(&[u8]) -> EmailSummary,
+    send: &mut impl FnMut(Update) -> bool,
+    known: &mut Known,
+    connected: &mut bool,
+) -> Result<()> {
+    let mut session = Session::connect(server)?;
+    if !session.has_capability("IDLE") {
+        bail!("{} does not support IDLE", server.host);
+    }
+    let listed = session.select(folder)?;
+    if session.uid_validity() != known.uid_validity {
+        // The UIDs name other messages now
+        known.emails.clear();
+        known.uid_validity = session.uid_validity();
+    }
+    let present: HashSet<u32> = listed.iter().map(|message| message.uid).collect();
+    known.emails.retain(|uid, _| present.contains(uid));
+    let missing: Vec<u32> = listed
+        .iter()
+        .map(|message| message.uid)
+        .filter(|uid| !known.emails.contains_key(uid))
+        .collect();
+    for message in session.fetch_uids(&missing)? {
+        known
+            .emails
+            .insert(message.uid, email(folder, message, parse));
+    }
+    let emails = listed
+        .iter()
+        .filter_map(|message| {
+            let mut email = known.emails.get(&message.uid)?.clone();
+            email.read = message.seen.unwrap_or_default();
+            email.flagged = message.flagged.unwrap_or_default();
+            Some(email)
+        })
+        .collect();
+    *connected = true;
+    let mut open = send(Update::Snapshot {
+        folder: folder.to_string(),
+        emails,
+    });
+    while open {
+        for change in session.idle(idle_timeout)? {
+            let folder = folder.to_string();
+            let update = match change {
+                Change::Arrived(messages) => {
+                    let emails: Vec<EmailSummary> = messages
+                        .into_iter()
+                        .map(|message| email(&folder, message, parse))
+                        .collect();
+                    for email in &emails {
+                        if let Some(remote) = &email.remote {
+                            known.emails.insert(remote.uid, email.clone());
+                        }
+                    }
+                    Update::Arrived { folder, emails }
+                }
+                Change::Expunged(uid) => {
+                    known.emails.remove(&uid);
+                    Update::Expunged { folder, uid }
+                }
+                Change::Flags { uid, seen, flagged } => Update::Flags {
+                    folder,
+                    uid,
+                    read: seen,
+                    flagged,
+                },
+            };
+            open &= send(update);
+        }
+    }
+    session.logout();
+    Ok(())
+}
+
+fn email(folder: &str, message: Fetched, parse: &impl Fn(&[u8]) -> EmailSummary) -> EmailSummary {
+    let mut email = parse(&message.body.unwrap_or_default());
+    email.read = message.seen.unwrap_or_default();
+    email.flagged = message.flagged.unwrap_or_default();
+    email.remote = Some(RemoteMessage {
+        folder: folder.to_string(),
+        uid: message.uid,
+    });
+    email
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+
+    use super::*;
+    use crate::backend::FileBackend;
+    use crate::net::Tls;
+
+    /// One client connection to the scripted server.
+    struct Client {
+        reader: BufReader<TcpStream>,
+        stream: TcpStream,
+        /// Every command received, without its tag.
+        log: Vec<String>,
+    }
+
+    impl Client {
+        fn accept(listener: &TcpListener) -> Client {
+            let (stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            // Replies are written a line at a time
+            stream.set_nodelay(true).unwrap();
+            let mut client = Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                stream,
+                log: Vec::new(),
+            };
+            client.send("* OK scripted server ready");
+            client
+        }
+
+        fn send(&mut self, text: &str) {
+            for line in text.lines() {
+                self.stream.write_all(line.as_bytes()).unwrap();
+                self.stream.write_all(b"\r\n").unwrap();
+            }
+        }
+
+        /// Reads a command, checks it starts as expected and returns its tag.
+        fn expect(&mut self, command: &str) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            // DONE is the one line sent without a tag
+            let (tag, rest) = match line.split_once(' ') {
+                Some((tag, rest)) if line != "DONE" => (tag, rest),
+                _ => ("", line),
+            };
+            assert!(
+                rest.starts_with(command),
+                "expected {}, got {:?}",
+                command,
+                line
+            );
+            self.log.push(rest.to_string());
+            tag.to_string()
+        }
+
+        /// Answers a command with `untagged` lines and OK.
+        fn reply(&mut self, command: &str, untagged: &str) {
+            let tag = self.expect(command);
+            self.send(untagged);
+            self.send(&format!("{} OK done", tag));
+        }
+
+        fn log_in(&mut self) {
+            let capabilities = "* CAPABILITY IMAP4rev1 IDLE";
+            self.reply("CAPABILITY", capabilities);
+            self.reply("LOGIN \"alice\" \"\"", "");
+            self.reply("CAPABILITY", capabilities);
+        }
+
+        /// Starts IDLE, returning its tag.
+        fn idle(&mut self) -> String {
+            let tag = self.expect("IDLE");
+            self.send("+ idling");
+            tag
+        }
+
+        fn done(&mut self, tag: &str) {
+            self.expect("DONE");
+            self.send(&format!("{} OK IDLE terminated", tag));
+        }
+    }
+
+    fn message(uid: u32, flags: &str) -> String {
+        let body = format!(
+            "From: a@example.com\r\nSubject: Message {}\r\n\r\nHi\r\n",
+            uid
+        );
+        format!(
+            "UID {} FLAGS ({}) BODY[] {{{}}}\n{})",
+            uid,
+            flags,
+            body.len(),
+            body
+        )
+    }
+
+    /// Plays the server side: a session that sees new mail, flag changes
+    /// and expunges and is then dropped, and a second one after the
+    /// reconnect that fetches only the message that is new.
+    fn serve(listener: TcpListener) -> Vec<String> {
+        let mut first = Client::accept(&listener);
+        first.log_in();
+        first.reply(
+            "SELECT \"INBOX\"",
+            "* 2 EXISTS\n* OK [UIDVALIDITY 1] UIDs valid",
+        );
+        first.reply(
+            "FETCH 1:2 (UID FLAGS)",
+            "* 1 FETCH (UID 10 FLAGS (\\Seen))\n* 2 FETCH (UID 11 FLAGS ())",
+        );
+        first.reply(
+            "UID FETCH 10:11 (UID FLAGS BODY.PEEK[])",
+            &format!(
+                "* 1 FETCH ({})\n* 2 FETCH ({})",
+                message(10, "\\Seen"),
+                message(11, "")
+            ),
+        );
+        // Nothing happens, so IDLE times out and is started again
+        let tag = first.idle();
+        first.done(&tag);
+        let tag = first.idle();
+        // A FETCH without FLAGS changes nothing that is shown
+        first.send("* 2 FETCH (MODSEQ (7))");
+        first.send("* 1 FETCH (FLAGS (\\Seen \\Flagged))");
+        first.done(&tag);
+        let tag = first.idle();
+        first.send("* 3 EXISTS");
+        first.done(&tag);
+        // The first message goes while the new one is fetched
+        first.reply(
+            "FETCH 3:3 (UID FLAGS BODY.PEEK[])",
+            &format!("* 1 EXPUNGE\n* 2 FETCH ({})", message(12, "")),
+        );
+        let tag = first.idle();
+        first.send("* 1 EXPUNGE");
+        first.done(&tag);
+        first.idle();
+        first.stream.shutdown(Shutdown::Both).unwrap();
+
+        let mut second = Client::accept(&listener);
+        second.log_in();
+        second.reply(
+            "SELECT \"INBOX\"",
+            "* 2 EXISTS\n* OK [UIDVALIDITY 1] UIDs valid",
+        );
+        second.reply(
+            "FETCH 1:2 (UID FLAGS)",
+            "* 1 FETCH (UID 12 FLAGS (\\Flagged))\n* 2 FETCH (UID 13 FLAGS ())",
+        );
+        second.reply(
+            "UID FETCH 13 (UID FLAGS BODY.PEEK[])",
+            &format!("* 2 FETCH ({})", message(13, "")),
+        );
+        second.reply("LOGOUT", "* BYE logging out");
+        first.log.into_iter().chain(second.log).collect()
+    }
+
+    fn describe(update: &Update) -> String {
+        let emails = |emails: &[EmailSummary]| {
+            let parts: Vec<String> = emails
+                .iter()
+                .map(|email| {
+                    let remote = email.remote.as_ref().unwrap();
+                    assert_eq!(remote.folder, "INBOX");
+                    format!(
+                        "{}{}{} {}",
+                        remote.uid,
+                        if email.read { " read" } else { "" },
+                        if email.flagged { " flagged" } else { "" },
+                        email.subject
+                    )
+                })
+                .collect();
+            parts.join(", ")
+        };
+        match update {
+            Update::Snapshot { emails: list, .. } => format!("snapshot: {}", emails(list)),
+            Update::Arrived { emails: list, .. } => format!("arrived: {}", emails(list)),
+            Update::Expunged { uid, .. } => format!("expunged {}", uid),
+            Update::Flags {
+                uid, read, flagged, ..
+            } => format!("flags {} read={} flagged={}", uid, read, flagged),
+            Update::Disconnected { error, retry, .. } => {
+                format!("disconnected: {}; retry in {:?}", error, retry)
+            }
+        }
+    }
+
+    #[test]
+    fn watches_a_folder_through_idle_and_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Server {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            user: "alice".to_string(),
+            password_command: None,
+            tls: Tls::None,
+            ca_file: None,
+        };
+        let script = thread::spawn(move || serve(listener));
+
+        let parser = FileBackend::new("unused", "me@example.com");
+        let mut updates = Vec::new();
+        let mut snapshots = 0;
+        let mut disconnects = 0;
+        watch_retrying(
+            &server,
+            "INBOX",
+            Duration::from_millis(200),
+            Duration::from_millis(10),
+            |raw| parser.parse_message(raw),
+            |update| {
+                snapshots += matches!(update, Update::Snapshot { .. }) as usize;
+                disconnects += matches!(update, Update::Disconnected { .. }) as usize;
+                updates.push(describe(&update));
+                // Stop after the reconnect, or when the script went wrong
+                snapshots < 2 && disconnects < 2
+            },
+        );
+
+        assert_eq!(
+            updates,
+            vec![
+                "snapshot: 10 read Message 10, 11 Message 11",
+                "flags 10 read=true flagged=true",
+                "expunged 10",
+                "arrived: 12 Message 12",
+                "expunged 11",
+                "disconnected: the IMAP server closed the connection; retry in 10ms",
+                "snapshot: 12 flagged Message 12, 13 Message 13",
+            ]
+        );
+        let commands: Vec<String> = script
+            .join()
+            .unwrap()
+            .into_iter()
+            .map(|command| command.split(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                "CAPABILITY",
+                "LOGIN",
+                "CAPABILITY",
+                "SELECT",
+                "FETCH",
+                "UID",
+                "IDLE",
+                "DONE",
+                "IDLE",
+                "DONE",
+                "IDLE",
+                "DONE",
+                "FETCH",
+                "IDLE",
+                "DONE",
+                "IDLE",
+                "CAPABILITY",
+                "LOGIN",
+                "CAPABILITY",
+                "SELECT",
+                "FETCH",
+                "UID",
+                "LOGOUT",
+            ]
+        );
+    }
+}
@@ -1,21 +1,102 @@
 mod app;
 mod backend;
 mod cli;
+mod config;
+mod contacts;
+mod crypto;
+mod date;
+mod imap;
+mod markdown;
+mod net;
+mod rules;
+mod sieve;
+mod spell;
+mod sync;
+mod template;
 mod tui;
+mod undo;
+mod vim;
 
-use backend::{EmailBackend, FileBackend};
+use anyhow::Context;
+use backend::{EmailBackend, FileBackend, OutgoingMessage, address};
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, ContactsCommand, RulesCommand, SieveCommand};
+use config::Config;
+use contacts::{ContactStore, vcard};
+use sieve::managesieve::ManageSieve;
+use std::sync::{Arc, Mutex};
+
+/// Address the file backend sends from; excluded from the address book.
+const USER_EMAIL: &str = "me@hermes.local";
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let backend = FileBackend::new("sample", "me@hermes.local");
+    let config = Config::load()?;
+    let mut rules = rules::Rules::compile(&config.rules)?;
+    if let Some(path) = config.sieve.script_path() {
+        rules.sieve = Some(sieve::Script::load(&path)?);
+    }
+    let backend = file_backend(&config);
 
     match cli.command {
-        Some(Commands::Send { to, subject, body }) => {
-            backend.send_email(&to, &subject, &body)?;
+        Some(Commands::Send {
+            to,
+            subject,
+            body,
+            cc,
+            bcc,
+            reply_to,
+            from,
+            markdown,
+            sign,
+            encrypt,
+            smime,
+        }) => {
+            let identity = config.identity(from.as_deref())?;
+            let signature = identity
+                .and_then(|identity| identity.signature.as_deref())
+                .map(template::signature_block)
+                .unwrap_or_default();
+            let parse = |list: Option<String>| {
+                address::parse_address_list(list.as_deref().unwrap_or_default())
+            };
+            let body = format!("{}{}", body, signature);
+            let message = OutgoingMessage {
+                from: identity.map(config::Identity::address),
+                to: address::parse_address_list(&to)?,
+                cc: parse(cc)?,
+                bcc: parse(bcc)?,
+                reply_to: parse(reply_to)?,
+                subject,
+                html: (markdown || config.compose.markdown).then(|| markdown::to_html(&body)),
+                body,
+                sign: sign || (config.pgp.sign && !smime),
+                encrypt: encrypt || (config.pgp.encrypt && !smime),
+                // OpenPGP wins when both are on by default
+                smime_sign: smime
+                    || (config.smime.sign
+                        && !(sign || encrypt || config.pgp.sign || config.pgp.encrypt)),
+            };
+            if message.to.is_empty() && message.cc.is_empty() && message.bcc.is_empty() {
+                anyhow::bail!("no recipients");
+            }
+            for warning in message.warnings() {
+                eprintln!("Warning: {}", warning);
+            }
+            backend.send_email(&message)?;
             println!("Email sent successfully");
+
+            // The message is out; a broken address book is only worth a warning
+            match ContactStore::load(&config.contacts) {
+                Ok(mut contacts) => {
+                    contacts.harvest_sent(&message);
+                    if let Err(e) = contacts.save() {
+                        eprintln!("Warning: {:#}", e);
+                    }
+                }
+                Err(e) => eprintln!("Warning: {:#}", e),
+            }
         }
         Some(Commands::Delete { subject }) => {
             let inbox = backend.fetch_inbox()?;
@@ -30,13 +111,185 @@ fn main() -> anyhow::Result<()> {
         Some(Commands::Sync) => {
             let inbox = backend.fetch_inbox()?;
             println!("Fetched {} emails", inbox.len());
+            let applied = rules.run(&backend, inbox);
+            if let Some(summary) = applied.summary() {
+                println!("{}", summary);
+            }
+            for error in &applied.errors {
+                eprintln!("Rule failed for {}", error);
+            }
+        }
+        Some(Commands::Rules { command }) => match command {
+            RulesCommand::Test { message } => {
+                let email = backend
+                    .read_message(&message)
+                    .with_context(|| format!("reading {}", message.display()))?;
+                print!("{}", rules.explain(&email));
+            }
+        },
+        Some(Commands::Sieve { command }) => {
+            let read = |file: Option<std::path::PathBuf>| -> anyhow::Result<_> {
+                let Some(path) = file.or_else(|| config.sieve.script_path()) else {
+                    anyhow::bail!("no Sieve script given or configured (sieve.script)");
+                };
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                sieve::Script::parse(&text)
+                    .with_context(|| format!("in Sieve script {}", path.display()))?;
+                Ok((path, text))
+            };
+            match command {
+                SieveCommand::Check { file, server } => {
+                    let (path, text) = read(file)?;
+                    println!("{}: OK", path.display());
+                    if server {
+                        let mut session = ManageSieve::connect(&config.sieve.server()?)?;
+                        session.check_script(&text)?;
+                        session.logout()?;
+                        println!("{}: accepted by the server", path.display());
+                    }
+                }
+                SieveCommand::Upload { file, activate } => {
+                    let (path, text) = read(file)?;
+                    let name = &config.sieve.name;
+                    let mut session = ManageSieve::connect(&config.sieve.server()?)?;
+                    session.put_script(name, &text)?;
+                    if activate {
+                        session.set_active(name)?;
+                    }
+                    session.logout()?;
+                    println!(
+                        "Uploaded {} as \"{}\"{}",
+                        path.display(),
+                        name,
+                        if activate { " and made it active" } else { "" }
+                    );
+                }
+                SieveCommand::List => {
+                    let mut session = ManageSieve::connect(&config.sieve.server()?)?;
+                    for (name, active) in session.list_scripts()? {
+                        println!("{}{}", name, if active { " (active)" } else { "" });
+                    }
+                    session.logout()?;
+                }
+            }
+        }
+        Some(Commands::Contacts { command }) => {
+            let mut contacts = ContactStore::load(&config.contacts)?;
+            match command {
+                ContactsCommand::Import { file } => {
+                    let text = std::fs::read_to_string(&file)
+                        .with_context(|| format!("reading {}", file.display()))?;
+                    let imported = vcard::parse(&text);
+                    let total = imported.len();
+                    let changed = contacts.import(imported);
+                    contacts.sort();
+                    contacts.save()?;
+                    println!(
+                        "Read {} contacts from {} ({} new or updated)",
+                        total,
+                        file.display(),
+                        changed
+                    );
+                }
+                ContactsCommand::Export { file, version } => {
+                    let version = vcard::Version::parse(&version).unwrap_or(vcard::Version::V4);
+                    let text = vcard::write(&contacts.contacts, version);
+                    match file {
+                        Some(file) => {
+                            std::fs::write(&file, text)
+                                .with_context(|| format!("writing {}", file.display()))?;
+                            println!(
+                                "Exported {} contacts to {}",
+                                contacts.contacts.len(),
+                                file.display()
+                            );
+                        }
+                        None => print!("{}", text),
+                    }
+                }
+            }
         }
         None => {
-            let inbox = backend.fetch_inbox()?;
+            // Shared with the background sync, which takes turns with the
+            // interface in changing mail and its state
+            let backend = Arc::new(Mutex::new(backend));
+            let applied = rules.run(&*backend, backend.fetch_inbox()?);
+            let status = applied.summary();
+            let inbox = applied.inbox;
+            // An unreadable address book leaves completion empty; the store
+            // is not saved, so the file is left as it was
+            let mut contacts_error = None;
+            let mut contacts = ContactStore::load(&config.contacts).unwrap_or_else(|e| {
+                contacts_error = Some(format!("Contacts: {:#}", e));
+                ContactStore::default()
+            });
+            contacts.harvest(&inbox, USER_EMAIL);
+            if let Err(e) = contacts.save() {
+                contacts_error = Some(format!("Contacts: {:#}", e));
+            }
+
             let mut app = app::App::new(inbox);
-            tui::run(&mut app, &backend)?;
+            app.contacts = contacts;
+            app.compose_clipboard = config.compose.clipboard;
+            app.identities = config.identities.clone();
+            app.templates = config.templates.clone();
+            app.dictionary = config.compose.dictionary.clone();
+            app.markdown = config.compose.markdown;
+            app.pgp_sign = config.pgp.sign;
+            app.pgp_encrypt = config.pgp.encrypt;
+            app.smime_sign = config.smime.sign;
+            app.rules = rules.clone();
+            app.status = contacts_error.or(status);
+            // A missing dictionary only turns spell checking off
+            for name in config.dictionaries() {
+                match spell::Dictionary::find(name) {
+                    Ok(dictionary) => {
+                        app.dictionaries.insert(name.to_string(), dictionary);
+                    }
+                    Err(e) => app.status = Some(format!("Spell checking: {:#}", e)),
+                }
+            }
+
+            let mut sync = None;
+            if config.sync.interval > 0 || !config.imap.watch.is_empty() {
+                let background = sync::BackgroundSync::new()?;
+                if config.sync.interval > 0 {
+                    let shared = Arc::clone(&backend);
+                    background.poll(&config.sync, move || {
+                        Ok(rules.run(&*shared, shared.fetch_inbox()?))
+                    });
+                }
+                if !config.imap.watch.is_empty() {
+                    let server = config.imap.server()?;
+                    let idle_timeout =
+                        std::time::Duration::from_secs(config.imap.idle_timeout.max(1));
+                    for folder in &config.imap.watch {
+                        let parser = file_backend(&config);
+                        background.watch(
+                            server.clone(),
+                            folder.clone(),
+                            idle_timeout,
+                            move |raw| parser.parse_message(raw),
+                        );
+                    }
+                }
+                sync = Some(background);
+            }
+            tui::run(&mut app, &*backend, &config, sync.as_mut())?;
         }
     }
 
     Ok(())
 }
+
+fn file_backend(config: &Config) -> FileBackend {
+    FileBackend::new("sample", USER_EMAIL)
+        .with_gpg(config.pgp.gpg())
+        .with_smime(config.smime.smime())
+        .with_authentication(
+            config
+                .authentication
+                .authentication(config.smime.program.as_deref().unwrap_or("openssl")),
+        )
+}
@@ -0,0 +1,729 @@
+//! Renders a Markdown message body to HTML for the text/html alternative.
+//!
+//! Covers what people write in mail: paragraphs, ATX and setext headings,
+//! emphasis, code spans and fenced code, links, block quotes, lists and
+//! rules. Raw HTML is escaped rather than passed through, and the
+//! signature below a `-- ` line is kept line for line.
+
+use crate::template::SIGNATURE_SEPARATOR;
+
+/// Converts a Markdown body into a complete HTML document.
+pub fn to_html(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let lines: Vec<&str> = text.lines().collect();
+    let (body, signature) = match lines.iter().position(|l| *l == SIGNATURE_SEPARATOR) {
+        Some(sep) => (&lines[..sep], Some(&lines[sep + 1..])),
+        None => (&lines[..], None),
+    };
+
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n</head>\n<body>\n",
+    );
+    render_blocks(body, false, &mut out);
+    if let Some(signature) = signature {
+        out.push_str("<p>");
+        out.push_str(&escape(SIGNATURE_SEPARATOR));
+        for line in signature {
+            out.push_str("<br>\n");
+            out.push_str(&escape(line));
+        }
+        out.push_str("</p>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders a run of block-level lines. In a tight list item paragraphs are
+/// written without `<p>` tags.
+fn render_blocks(lines: &[&str], tight: bool, out: &mut String) {
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.trim().is_empty() {
+            i += 1;
+        } else if let Some(fence) = fence(line) {
+            let indent = line.len() - line.trim_start().len();
+            out.push_str("<pre><code>");
+            i += 1;
+            while i < lines.len() && !closes_fence(lines[i], &fence) {
+                out.push_str(&escape(strip_indent(lines[i], indent)));
+                out.push('\n');
+                i += 1;
+            }
+            out.push_str("</code></pre>\n");
+            // Skip the closing fence
+            i += 1;
+        } else if let Some((level, heading)) = atx_heading(line) {
+            out.push_str(&format!("<h{0}>{1}</h{0}>\n", level, inline(heading)));
+            i += 1;
+        } else if is_rule(line) {
+            out.push_str("<hr>\n");
+            i += 1;
+        } else if is_quote(line) {
+            let start = i;
+            // Lazy continuation: unmarked lines carry on a quoted paragraph
+            while i < lines.len()
+                && (is_quote(lines[i]) || (!lines[i].trim().is_empty() && !starts_block(lines[i])))
+            {
+                i += 1;
+            }
+            let inner: Vec<&str> = lines[start..i]
+                .iter()
+                .map(|l| {
+                    let l = l.trim_start();
+                    let l = l.strip_prefix('>').unwrap_or(l);
+                    l.strip_prefix(' ').unwrap_or(l)
+                })
+                .collect();
+            out.push_str("<blockquote>\n");
+            render_blocks(&inner, false, out);
+            out.push_str("</blockquote>\n");
+        } else if let Some(marker) = list_marker(line) {
+            i = render_list(lines, i, marker, out);
+        } else {
+            let start = i;
+            i += 1;
+            while i < lines.len()
+                && !lines[i].trim().is_empty()
+                && !interrupts(lines[i])
+                && setext_level(lines[i]).is_none()
+            {
+                i += 1;
+            }
+            let text = lines[start..i]
+                .iter()
+                .map(|l| l.trim_start())
+                .collect::<Vec<_>>()
+                .join("\n");
+            match lines.get(i).and_then(|l| setext_level(l)) {
+                Some(level) => {
+                    out.push_str(&format!(
+                        "<h{0}>{1}</h{0}>\n",
+                        level,
+                        inline(text.trim_end())
+                    ));
+                    i += 1;
+                }
+                None if tight => {
+                    out.push_str(&inline(text.trim_end()));
+                    out.push('\n');
+                }
+                None => {
+                    out.push_str("<p>");
+                    out.push_str(&inline(text.trim_end()));
+                    out.push_str("</p>\n");
+                }
+            }
+        }
+    }
+}
+
+/// A list item marker: the item number for ordered lists and the width of
+/// the indent, marker and following space.
+#[derive(Debug, Clone, Copy)]
+struct Marker {
+    number: Option<usize>,
+    bullet: char,
+    width: usize,
+}
+
+fn list_marker(line: &str) -> Option<Marker> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 || is_rule(line) {
+        return None;
+    }
+    let rest = &line[indent..];
+    let (number, bullet, marker_len) = match rest.chars().next()? {
+        c @ ('-' | '*' | '+') => (None, c, 1),
+        _ => {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            let delimiter = rest[digits..].chars().next()?;
+            if digits == 0 || digits > 9 || !matches!(delimiter, '.' | ')') {
+                return None;
+            }
+            (Some(rest[..digits].parse().ok()?), delimiter, digits + 1)
+        }
+    };
+    let after = &rest[marker_len..];
+    if after.is_empty() {
+        return Some(Marker {
+            number,
+            bullet,
+            width: indent + marker_len + 1,
+        });
+    }
+    let spaces = after.len() - after.trim_start_matches(' ').len();
+    if spaces == 0 {
+        return None;
+    }
+    // More than four spaces start indented code; count only one
+    let spaces = if spaces > 4 || spaces == after.len() {
+        1
+    } else {
+        spaces
+    };
+    Some(Marker {
+        number,
+        bullet,
+        width: indent + marker_len + spaces,
+    })
+}
+
+/// Renders the list starting at line `start` and returns the index of the
+/// first line after it.
+fn render_list(lines: &[&str], start: usize, first: Marker, out: &mut String) -> usize {
+    let same_list =
+        |m: &Marker| m.bullet == first.bullet && m.number.is_some() == first.number.is_some();
+    let mut items: Vec<Vec<&str>> = Vec::new();
+    let mut loose = false;
+    let mut width = first.width;
+    let mut i = start;
+    while i < lines.len() {
+        let line = lines[i];
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if let Some(marker) = list_marker(line).filter(|m| same_list(m) && indent < width) {
+            width = marker.width;
+            items.push(vec![line.get(marker.width..).unwrap_or_default()]);
+            i += 1;
+        } else if line.trim().is_empty() {
+            let next = lines[i..].iter().position(|l| !l.trim().is_empty());
+            let Some(next) = next.map(|n| i + n) else {
+                break;
+            };
+            let next_indent = lines[next].len() - lines[next].trim_start_matches(' ').len();
+            let continues = next_indent >= width
+                || list_marker(lines[next]).is_some_and(|m| same_list(&m) && next_indent < width);
+            if !continues {
+                break;
+            }
+            loose = true;
+            if next_indent >= width
+                && let Some(item) = items.last_mut()
+            {
+                item.extend(std::iter::repeat_n("", next - i));
+            }
+            i = next;
+        } else if indent >= width {
+            if let Some(item) = items.last_mut() {
+                item.push(&line[width..]);
+            }
+            i += 1;
+        } else if items
+            .last()
+            .and_then(|item| item.last())
+            .is_some_and(|l| !l.trim().is_empty())
+            && !starts_block(line)
+        {
+            // Lazy continuation of the item's paragraph
+            if let Some(item) = items.last_mut() {
+                item.push(line.trim_start());
+            }
+            i += 1;
+        } else {
+            break;
+        }
+    }
+
+    match first.number {
+        Some(1) => out.push_str("<ol>\n"),
+        Some(n) => out.push_str(&format!("<ol start=\"{}\">\n", n)),
+        None => out.push_str("<ul>\n"),
+    }
+    for item in &items {
+        out.push_str("<li>");
+        render_blocks(item, !loose, out);
+        out.push_str("</li>\n");
+    }
+    out.push_str(if first.number.is_some() {
+        "</ol>\n"
+    } else {
+        "</ul>\n"
+    });
+    i
+}
+
+/// The fence character and length of a code fence opening line.
+fn fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let c = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = trimmed.chars().take_while(|&ch| ch == c).count();
+    // Backtick fences cannot have backticks in their info string
+    (len >= 3 && !(c == '`' && trimmed[len..].contains('`'))).then_some((c, len))
+}
+
+fn closes_fence(line: &str, (c, len): &(char, usize)) -> bool {
+    let trimmed = line.trim();
+    trimmed.len() >= *len && trimmed.chars().all(|ch| ch == *c)
+}
+
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
+
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    // A closing run of `#`s is not part of the heading
+    let text = rest.trim();
+    let closed = text.trim_end_matches('#');
+    let text = if closed.is_empty() || closed.ends_with(' ') {
+        closed.trim_end()
+    } else {
+        text
+    };
+    Some((level, text))
+}
+
+/// The heading level a paragraph underlined with `===` or `---` gets.
+fn setext_level(line: &str) -> Option<usize> {
+    let underline = line.trim();
+    if !underline.is_empty() && underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if underline.len() >= 2 && underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// A thematic break: three or more `-`, `*` or `_`, optionally spaced.
+fn is_rule(line: &str) -> bool {
+    let mut chars = line.chars().filter(|c| !c.is_whitespace());
+    let Some(first) = chars.next().filter(|c| matches!(c, '-' | '*' | '_')) else {
+        return false;
+    };
+    let rest: Vec<char> = chars.collect();
+    rest.len() >= 2 && rest.iter().all(|&c| c == first) && line.len() - line.trim_start().len() <= 3
+}
+
+fn is_quote(line: &str) -> bool {
+    line.trim_start().starts_with('>') && line.len() - line.trim_start().len() <= 3
+}
+
+/// Whether a line starts a block that ends a lazy continuation.
+fn starts_block(line: &str) -> bool {
+    fence(line).is_some()
+        || atx_heading(line).is_some()
+        || is_rule(line)
+        || is_quote(line)
+        || list_marker(line).is_some()
+}
+
+/// Whether a line ends a paragraph. Ordered lists can only interrupt one
+/// when they start at 1, so numbers that happen to open a line do not.
+fn interrupts(line: &str) -> bool {
+    fence(line).is_some()
+        || atx_heading(line).is_some()
+        || is_rule(line)
+        || is_quote(line)
+        || list_marker(line).is_some_and(|m| {
+            matches!(m.number, None | Some(1)) && !line[m.width.min(line.len())..].trim().is_empty()
+        })
+}
+
+/// Renders inline Markdown: code spans, emphasis, links, images,
+/// autolinks, backslash escapes and hard line breaks.
+fn inline(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                push_escaped(&mut out, chars[i + 1]);
+                i += 2;
+            }
+            '\\' if chars.get(i + 1) == Some(&'\n') => {
+                out.push_str("<br>\n");
+                i += 2;
+            }
+            '\n' => {
+                // Two trailing spaces make a hard break
+                let spaces = out.len() - out.trim_end_matches(' ').len();
+                out.truncate(out.len() - spaces);
+                out.push_str(if spaces >= 2 { "<br>\n" } else { "\n" });
+                i += 1;
+            }
+            '`' => {
+                let run = run_length(&chars, i);
+                match find_code_close(&chars, i + run, run) {
+                    Some(close) => {
+                        let code: String = chars[i + run..close].iter().collect();
+                        let code = code.replace('\n', " ");
+                        let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                            Some(inner) if !inner.trim().is_empty() => inner.to_string(),
+                            _ => code,
+                        };
+                        out.push_str("<code>");
+                        out.push_str(&escape(&code));
+                        out.push_str("</code>");
+                        i = close + run;
+                    }
+                    None => {
+                        out.extend(std::iter::repeat_n('`', run));
+                        i += run;
+                    }
+                }
+            }
+            '!' if chars.get(i + 1) == Some(&'[') => match link(&chars, i + 1) {
+                Some((alt, url, end)) => {
+                    out.push_str(&format!(
+                        "<img src=\"{}\" alt=\"{}\">",
+                        escape(&url),
+                        escape(&alt)
+                    ));
+                    i = end;
+                }
+                None => {
+                    out.push('!');
+                    i += 1;
+                }
+            },
+            '[' => match link(&chars, i) {
+                Some((label, url, end)) => {
+                    out.push_str(&format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape(&url),
+                        inline(&label)
+                    ));
+                    i = end;
+                }
+                None => {
+                    out.push('[');
+                    i += 1;
+                }
+            },
+            '<' => match autolink(&chars, i) {
+                Some((target, end)) => {
+                    let href = if target.contains("://") {
+                        target.clone()
+                    } else {
+                        format!("mailto:{}", target)
+                    };
+                    out.push_str(&format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape(&href),
+                        escape(&target)
+                    ));
+                    i = end;
+                }
+                None => {
+                    out.push_str("&lt;");
+                    i += 1;
+                }
+            },
+            '*' | '_' => {
+                let run = run_length(&chars, i);
+                let n = run.min(2);
+                match find_emphasis_close(&chars, i, n) {
+                    Some(close) => {
+                        let inner: String = chars[i + n..close].iter().collect();
+                        let tag = if n == 2 { "strong" } else { "em" };
+                        out.push_str(&format!("<{0}>{1}</{0}>", tag, inline(&inner)));
+                        i = close + n;
+                    }
+                    None => {
+                        out.extend(std::iter::repeat_n(c, run));
+                        i += run;
+                    }
+                }
+            }
+            _ => {
+                push_escaped(&mut out, c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn run_length(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .take_while(|&&c| c == chars[start])
+        .count()
+}
+
+/// Finds a backtick run of exactly `len` closing a code span.
+fn find_code_close(chars: &[char], from: usize, len: usize) -> Option<usize> {
+    let mut j = from;
+    while j < chars.len() {
+        if chars[j] == '`' {
+            let run = run_length(chars, j);
+            if run == len {
+                return Some(j);
+            }
+            j += run;
+        } else {
+            j += 1;
+        }
+    }
+    None
+}
+
+/// Finds the delimiter run closing emphasis opened by `n` of `chars[open]`.
+/// Underscores only emphasise whole words, so `snake_case` stays as it is.
+fn find_emphasis_close(chars: &[char], open: usize, n: usize) -> Option<usize> {
+    let c = chars[open];
+    let is_word = |i: Option<&char>| i.is_some_and(|ch| ch.is_alphanumeric());
+    if chars.get(open + n).is_none_or(|ch| ch.is_whitespace())
+        || (c == '_' && open > 0 && is_word(chars.get(open - 1)))
+    {
+        return None;
+    }
+    let mut j = open + n;
+    while j < chars.len() {
+        if chars[j] == '`' {
+            // Delimiters inside code spans do not count
+            let run = run_length(chars, j);
+            j = find_code_close(chars, j + run, run).map_or(j + run, |close| close + run);
+            continue;
+        }
+        if chars[j] != c {
+            j += 1;
+            continue;
+        }
+        let run = run_length(chars, j);
+        let closes = (run == n || (run > n && n == 2))
+            && j > open + n
+            && !chars[j - 1].is_whitespace()
+            && !(c == '_' && is_word(chars.get(j + run)));
+        if closes {
+            return Some(j + run - n);
+        }
+        j += run;
+    }
+    None
+}
+
+/// Parses `[label](url "title")` starting at the `[`, returning the label,
+/// the URL and the index after the closing parenthesis.
+fn link(chars: &[char], open: usize) -> Option<(String, String, usize)> {
+    let mut depth = 0;
+    let mut close = None;
+    let mut j = open;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 1,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(j);
+                    break;
+                }
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    let close = close?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = chars[close + 2..].iter().position(|&c| c == ')')? + close + 2;
+    let target: String = chars[close + 2..end].iter().collect();
+    let target = target.trim();
+    // `<…>` lets the URL contain spaces
+    let url = match target.strip_prefix('<') {
+        Some(rest) => rest.split('>').next().unwrap_or_default(),
+        None => target.split_whitespace().next().unwrap_or_default(),
+    };
+    let label = chars[open + 1..close].iter().collect();
+    Some((label, url.to_string(), end + 1))
+}
+
+/// Parses `<https://…>` or `<user@example.com>` starting at the `<`.
+fn autolink(chars: &[char], open: usize) -> Option<(String, usize)> {
+    let len = chars[open + 1..].iter().position(|&c| c == '>')?;
+    let target: String = chars[open + 1..open + 1 + len].iter().collect();
+    let is_url = target.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+    });
+    let is_email = target
+        .split_once('@')
+        .is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'));
+    if target.is_empty() || target.contains(char::is_whitespace) || !(is_url || is_email) {
+        return None;
+    }
+    Some((target, open + len + 2))
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        _ => out.push(c),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_escaped(&mut out, c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rendered body, without the document around it.
+    fn body(text: &str) -> String {
+        let html = to_html(text);
+        let start = html.find("<body>\n").unwrap() + "<body>\n".len();
+        let end = html.find("</body>").unwrap();
+        html[start..end].to_string()
+    }
+
+    #[test]
+    fn renders_a_complete_document() {
+        let html = to_html("Hi");
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"));
+        assert!(html.ends_with("<p>Hi</p>\n</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn paragraphs_headings_and_rules() {
+        assert_eq!(
+            body("one\ntwo\n\nthree  \nfour\r\n"),
+            "<p>one\ntwo</p>\n<p>three<br>\nfour</p>\n"
+        );
+        assert_eq!(
+            body("# Title #\n###### Six\n####### Seven\n#hashtag"),
+            "<h1>Title</h1>\n<h6>Six</h6>\n<p>####### Seven\n#hashtag</p>\n"
+        );
+        assert_eq!(
+            body("Big\n===\nSmall\n---\n\n***"),
+            "<h1>Big</h1>\n<h2>Small</h2>\n<hr>\n"
+        );
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(
+            body("*em* _em_ **strong** __strong__ ***both***"),
+            "<p><em>em</em> <em>em</em> <strong>strong</strong> <strong>strong</strong> \
+<strong><em>both</em></strong></p>\n"
+        );
+        // Delimiters that cannot open or close stay as they are
+        assert_eq!(
+            body("snake_case_name, 2 * 3 * 4, *unclosed, ** spaced **"),
+            "<p>snake_case_name, 2 * 3 * 4, *unclosed, ** spaced **</p>\n"
+        );
+        assert_eq!(
+            body("*outer `*not*` outer*"),
+            "<p><em>outer <code>*not*</code> outer</em></p>\n"
+        );
+    }
+
+    #[test]
+    fn code_spans_and_blocks() {
+        assert_eq!(
+            body("`a < b` and `` x ` y `` and `unclosed"),
+            "<p><code>a &lt; b</code> and <code>x ` y</code> and `unclosed</p>\n"
+        );
+        assert_eq!(
+            body("```rust\nfn main() {\n    *x = &y;\n}\n```\nafter"),
+            "<pre><code>fn main() {\n    *x = &amp;y;\n}\n</code></pre>\n<p>after</p>\n"
+        );
+        // An unclosed fence runs to the end
+        assert_eq!(
+            body("~~~\n# not a heading"),
+            "<pre><code># not a heading\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            body("- one\n- two\n  more\n\n1. first\n2. second"),
+            "<ul>\n<li>one\n</li>\n<li>two\nmore\n</li>\n</ul>\n\
+<ol>\n<li>first\n</li>\n<li>second\n</li>\n</ol>\n"
+        );
+        assert_eq!(
+            body("3) three\n4) four"),
+            "<ol start=\"3\">\n<li>three\n</li>\n<li>four\n</li>\n</ol>\n"
+        );
+        // Blank lines between items make a loose list with paragraphs
+        assert_eq!(
+            body("* a\n\n* b"),
+            "<ul>\n<li><p>a</p>\n</li>\n<li><p>b</p>\n</li>\n</ul>\n"
+        );
+        assert_eq!(
+            body("- outer\n  - inner"),
+            "<ul>\n<li>outer\n<ul>\n<li>inner\n</li>\n</ul>\n</li>\n</ul>\n"
+        );
+        // A number inside a paragraph does not start a list
+        assert_eq!(
+            body("The year was\n1999. It rained."),
+            "<p>The year was\n1999. It rained.</p>\n"
+        );
+    }
+
+    #[test]
+    fn links_images_and_autolinks() {
+        assert_eq!(
+            body("[the *site*](https://example.com \"Title\") ![logo](<a b.png>)"),
+            "<p><a href=\"https://example.com\">the <em>site</em></a> \
+<img src=\"a b.png\" alt=\"logo\"></p>\n"
+        );
+        assert_eq!(
+            body("<https://example.com/?a=1&b=2> <jane@example.com> <not a link>"),
+            "<p><a href=\"https://example.com/?a=1&amp;b=2\">https://example.com/?a=1&amp;b=2</a> \
+<a href=\"mailto:jane@example.com\">jane@example.com</a> &lt;not a link&gt;</p>\n"
+        );
+        assert_eq!(
+            body("[no target] and [x](\"quoted\")"),
+            "<p>[no target] and <a href=\"&quot;quoted&quot;\">x</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_raw_html() {
+        assert_eq!(
+            body("<script>alert(\"x\")</script> & <b>bold</b> \\*literal\\*"),
+            "<p>&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; \
+&lt;b&gt;bold&lt;/b&gt; *literal*</p>\n"
+        );
+        assert_eq!(
+            body("[x](javascript:\"<b>\")"),
+            "<p><a href=\"javascript:&quot;&lt;b&gt;&quot;\">x</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn block_quotes_with_lazy_continuation() {
+        assert_eq!(
+            body("> quoted\nlazy\n> > nested\n\nafter"),
+            "<blockquote>\n<p>quoted\nlazy</p>\n<blockquote>\n<p>nested</p>\n\
+</blockquote>\n</blockquote>\n<p>after</p>\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_signature_line_for_line() {
+        assert_eq!(
+            body("Hi *there*\n\n-- \nJane <jane@example.com>\n* not a list"),
+            "<p>Hi <em>there</em></p>\n<p>-- <br>\nJane &lt;jane@example.com&gt;<br>\n* not a list</p>\n"
+        );
+    }
+}
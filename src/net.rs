@@ -0,0 +1,147 @@
+//! TCP connections for the mail protocols, optionally over TLS.
+//!
+//! Servers are checked against the Mozilla root certificates, or against
+//! the certificates of a local CA file when one is configured.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use rustls::{
+    ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+    pki_types::{CertificateDer, ServerName, pem::PemObject},
+};
+use serde::Deserialize;
+
+/// How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where and as whom to connect.
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    /// Command printing the password.
+    pub password_command: Option<String>,
+    pub tls: Tls,
+    /// PEM file of the CA certificates to trust instead of the usual roots.
+    pub ca_file: Option<PathBuf>,
+}
+
+/// How a connection is protected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tls {
+    /// TLS from the start, on a port of its own.
+    #[serde(rename = "tls")]
+    Implicit,
+    /// A plain connection upgraded with STARTTLS before logging in.
+    #[default]
+    Starttls,
+    /// No encryption, for servers on the same machine.
+    None,
+}
+
+/// A connection to a server.
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Stream {
+    pub fn connect(host: &str, port: u16) -> Result<Stream> {
+        let tcp = TcpStream::connect((host, port))
+            .with_context(|| format!("connecting to {}:{}", host, port))?;
+        tcp.set_read_timeout(Some(TIMEOUT))?;
+        tcp.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Stream::Plain(tcp))
+    }
+
+    /// Connects with TLS from the start, as on the IMAPS port.
+    pub fn connect_tls(host: &str, port: u16, ca_file: Option<&Path>) -> Result<Stream> {
+        Stream::connect(host, port)?.start_tls(host, ca_file)
+    }
+
+    /// Negotiates TLS on a plain connection, after STARTTLS.
+    pub fn start_tls(self, host: &str, ca_file: Option<&Path>) -> Result<Stream> {
+        let Stream::Plain(tcp) = self else {
+            return Ok(self);
+        };
+        let name = ServerName::try_from(host.to_string())
+            .with_context(|| format!("invalid server name {}", host))?;
+        let connection = ClientConnection::new(client_config(ca_file)?, name)?;
+        let mut stream = StreamOwned::new(connection, tcp);
+        // Handshake now, so certificate errors show up here
+        while stream.conn.is_handshaking() {
+            stream
+                .conn
+                .complete_io(&mut stream.sock)
+                .with_context(|| format!("TLS handshake with {}", host))?;
+        }
+        Ok(Stream::Tls(Box::new(stream)))
+    }
+
+    /// Changes how long reads wait, as IDLE waits much longer than a
+    /// command does.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        let tcp = match self {
+            Stream::Plain(tcp) => tcp,
+            Stream::Tls(stream) => &stream.sock,
+        };
+        Ok(tcp.set_read_timeout(timeout)?)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(tcp) => tcp.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+fn client_config(ca_file: Option<&Path>) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(path) => {
+            let certs = CertificateDer::pem_file_iter(path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| anyhow::anyhow!("{}", e))
+                .with_context(|| format!("reading {}", path.display()))?;
+            roots.add_parsable_certificates(certs);
+            if roots.is_empty() {
+                bail!("no certificates in {}", path.display());
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
@@ -0,0 +1,777 @@
+//! Filtering rules for new mail: conditions on headers, body and size, and
+//! actions that move, tag, mark read, flag or delete the messages they
+//! match.
+
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+use crate::backend::{EmailBackend, EmailSummary};
+use crate::config;
+use crate::sieve::Script;
+
+/// How a header or the body is compared. `contains` and `is` ignore case;
+/// a regex is used as written (add `(?i)` to ignore case).
+#[derive(Debug, Clone)]
+pub enum Test {
+    Contains(String),
+    Is(String),
+    Regex(Regex),
+}
+
+impl Test {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Test::Contains(needle) => text.to_lowercase().contains(&needle.to_lowercase()),
+            Test::Is(value) => text.trim().eq_ignore_ascii_case(value.trim()),
+            Test::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Test::Contains(needle) => write!(f, "contains {:?}", needle),
+            Test::Is(value) => write!(f, "is {:?}", value),
+            Test::Regex(regex) => write!(f, "matches /{}/", regex.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// Any instance of the header passes the test.
+    Header {
+        name: String,
+        test: Test,
+    },
+    Body(Test),
+    /// The message source is larger than this many bytes.
+    LargerThan(u64),
+    SmallerThan(u64),
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn matches(&self, email: &EmailSummary) -> bool {
+        match self {
+            Condition::Header { name, test } => email
+                .headers
+                .iter()
+                .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+                .any(|(_, value)| test.matches(value)),
+            Condition::Body(test) => test.matches(body(email)),
+            Condition::LargerThan(size) => email.raw.len() as u64 > *size,
+            Condition::SmallerThan(size) => (email.raw.len() as u64) < *size,
+            Condition::Not(condition) => !condition.matches(email),
+            Condition::All(conditions) => conditions.iter().all(|c| c.matches(email)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.matches(email)),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter, conditions: &[Condition], join: &str| {
+            let parts: Vec<String> = conditions.iter().map(|c| c.to_string()).collect();
+            write!(f, "({})", parts.join(join))
+        };
+        match self {
+            Condition::Header { name, test } => write!(f, "{} {}", name, test),
+            Condition::Body(test) => write!(f, "body {}", test),
+            Condition::LargerThan(size) => write!(f, "size over {} bytes", size),
+            Condition::SmallerThan(size) => write!(f, "size under {} bytes", size),
+            Condition::Not(condition) => write!(f, "not {}", condition),
+            Condition::All(conditions) => list(f, conditions, " and "),
+            Condition::Any(conditions) => list(f, conditions, " or "),
+        }
+    }
+}
+
+/// The text/plain body, or the HTML source when there is none.
+fn body(email: &EmailSummary) -> &str {
+    match &email.html {
+        Some(html) if email.body.is_empty() => html,
+        _ => &email.body,
+    }
+}
+
+/// What a rule, or all the rules that fired, do to a message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Actions {
+    pub move_to: Option<String>,
+    pub tags: Vec<String>,
+    pub mark_read: bool,
+    pub flag: bool,
+    pub delete: bool,
+}
+
+impl Actions {
+    fn is_empty(&self) -> bool {
+        *self == Actions::default()
+    }
+
+    /// Adds another rule's actions; the first folder chosen wins.
+    fn merge(&mut self, other: &Actions) {
+        if self.move_to.is_none() {
+            self.move_to = other.move_to.clone();
+        }
+        for tag in &other.tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        self.mark_read |= other.mark_read;
+        self.flag |= other.flag;
+        self.delete |= other.delete;
+    }
+}
+
+impl fmt::Display for Actions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.tags.is_empty() {
+            parts.push(format!("tag {}", self.tags.join(", ")));
+        }
+        if self.mark_read {
+            parts.push("mark read".to_string());
+        }
+        if self.flag {
+            parts.push("flag".to_string());
+        }
+        // Deleting makes a move pointless
+        if self.delete {
+            parts.push("delete".to_string());
+        } else if let Some(folder) = &self.move_to {
+            parts.push(format!("move to {}", folder));
+        }
+        if parts.is_empty() {
+            parts.push("nothing".to_string());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub condition: Condition,
+    pub actions: Actions,
+    /// Later rules are skipped when this one fires.
+    pub stop: bool,
+}
+
+/// What running the rules over new mail did.
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// The inbox without the messages moved or deleted.
+    pub inbox: Vec<EmailSummary>,
+    pub moved: usize,
+    pub deleted: usize,
+    pub errors: Vec<String>,
+}
+
+impl Applied {
+    /// Summary for the status line, if anything was moved or deleted.
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.moved > 0 {
+            parts.push(format!("{} moved", self.moved));
+        }
+        if self.deleted > 0 {
+            parts.push(format!("{} deleted", self.deleted));
+        }
+        if let Some(error) = self.errors.first() {
+            parts.push(format!("{} failed: {}", self.errors.len(), error));
+        }
+        (!parts.is_empty()).then(|| format!("Rules: {}", parts.join(", ")))
+    }
+}
+
+/// Rules in the order they are tried.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    pub rules: Vec<Rule>,
+    /// A Sieve script run after the rules; its actions add to theirs.
+    pub sieve: Option<Script>,
+}
+
+impl Rules {
+    /// Checks and compiles the `[[rule]]` tables of the config file.
+    pub fn compile(rules: &[config::Rule]) -> Result<Rules> {
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let name = if rule.name.is_empty() {
+                    format!("rule {}", i + 1)
+                } else {
+                    rule.name.clone()
+                };
+                let compiled = compile_rule(rule, name.clone());
+                compiled.with_context(|| format!("in filtering rule \"{}\"", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Rules { rules, sieve: None })
+    }
+
+    /// The rules that fire for a message, up to the first that stops.
+    pub fn matching(&self, email: &EmailSummary) -> Vec<&Rule> {
+        let mut fired = Vec::new();
+        for rule in &self.rules {
+            if rule.condition.matches(email) {
+                fired.push(rule);
+                if rule.stop {
+                    break;
+                }
+            }
+        }
+        fired
+    }
+
+    /// The combined actions of every rule that fires.
+    pub fn actions(&self, email: &EmailSummary) -> Actions {
+        let mut actions = Actions::default();
+        for rule in self.matching(email) {
+            actions.merge(&rule.actions);
+        }
+        if let Some(script) = &self.sieve {
+            actions.merge(&script.run(email).actions());
+        }
+        actions
+    }
+
+    /// Applies the rules to mail fetched for the first time. Mail already
+    /// seen is left alone, so moving a message back is not undone, and so
+    /// is mail in watched IMAP folders, which the backend cannot change.
+    pub fn run(&self, backend: &impl EmailBackend, inbox: Vec<EmailSummary>) -> Applied {
+        let mut applied = Applied::default();
+        for mut email in inbox {
+            let actions = if email.recent && email.remote.is_none() {
+                self.actions(&email)
+            } else {
+                Actions::default()
+            };
+            if actions.is_empty() {
+                applied.inbox.push(email);
+                continue;
+            }
+            match apply(backend, &mut email, &actions) {
+                Ok(Outcome::Kept) => applied.inbox.push(email),
+                Ok(Outcome::Moved) => applied.moved += 1,
+                Ok(Outcome::Deleted) => applied.deleted += 1,
+                Err(e) => {
+                    applied.errors.push(format!("{}: {:#}", email.subject, e));
+                    applied.inbox.push(email);
+                }
+            }
+        }
+        applied
+    }
+
+    /// A dry run for one message: each rule, whether it fires and why, and
+    /// what would be done.
+    pub fn explain(&self, email: &EmailSummary) -> String {
+        let fired = self.matching(email);
+        let mut out = String::new();
+        let mut stopped = false;
+        for rule in &self.rules {
+            let fires = fired.iter().any(|r| std::ptr::eq(*r, rule));
+            let verdict = if stopped {
+                "not reached"
+            } else if fires {
+                "fires"
+            } else {
+                "does not fire"
+            };
+            out.push_str(&format!("{}: {}\n", rule.name, verdict));
+            explain_condition(&mut out, &rule.condition, email, 1);
+            if fires {
+                out.push_str(&format!("  => {}\n", rule.actions));
+                stopped |= rule.stop;
+            }
+        }
+        if let Some(script) = &self.sieve {
+            let outcome = script.run(email);
+            out.push_str(&format!("Sieve script: {}\n", outcome));
+            out.push_str(&format!("  => {}\n", outcome.actions()));
+        }
+        if self.rules.is_empty() && self.sieve.is_none() {
+            out.push_str("No rules are configured\n");
+        } else {
+            out.push_str(&format!("\nResult: {}\n", self.actions(email)));
+        }
+        out
+    }
+}
+
+fn explain_condition(out: &mut String, condition: &Condition, email: &EmailSummary, depth: usize) {
+    let mark = if condition.matches(email) {
+        "[x]"
+    } else {
+        "[ ]"
+    };
+    let indent = "  ".repeat(depth);
+    match condition {
+        Condition::All(conditions) | Condition::Any(conditions) => {
+            let kind = if matches!(condition, Condition::All(_)) {
+                "all of"
+            } else {
+                "any of"
+            };
+            out.push_str(&format!("{}{} {}\n", indent, mark, kind));
+            for condition in conditions {
+                explain_condition(out, condition, email, depth + 1);
+            }
+        }
+        _ => out.push_str(&format!("{}{} {}\n", indent, mark, condition)),
+    }
+}
+
+enum Outcome {
+    Kept,
+    Moved,
+    Deleted,
+}
+
+fn apply(
+    backend: &impl EmailBackend,
+    email: &mut EmailSummary,
+    actions: &Actions,
+) -> Result<Outcome> {
+    if actions.delete {
+        backend.delete_email(email)?;
+        return Ok(Outcome::Deleted);
+    }
+    email.read |= actions.mark_read;
+    email.flagged |= actions.flag;
+    for tag in &actions.tags {
+        if !email.tags.contains(tag) {
+            email.tags.push(tag.clone());
+        }
+    }
+    backend.update_email(email)?;
+    match &actions.move_to {
+        Some(folder) => {
+            backend.move_email(email, folder)?;
+            Ok(Outcome::Moved)
+        }
+        None => Ok(Outcome::Kept),
+    }
+}
+
+fn compile_rule(rule: &config::Rule, name: String) -> Result<Rule> {
+    let conditions = rule
+        .conditions
+        .iter()
+        .enumerate()
+        .map(|(i, condition)| {
+            compile_condition(condition).with_context(|| format!("condition {}", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if conditions.is_empty() {
+        bail!("no conditions; add a [[rule.condition]] table");
+    }
+    let actions = Actions {
+        move_to: rule.move_to.clone().filter(|folder| !folder.is_empty()),
+        tags: rule.tag.clone(),
+        mark_read: rule.mark_read,
+        flag: rule.flag,
+        delete: rule.delete,
+    };
+    if actions.is_empty() && !rule.stop {
+        bail!("no actions");
+    }
+    // Tags are stored comma-separated on one line of the state file
+    if let Some(tag) = actions
+        .tags
+        .iter()
+        .find(|tag| tag.is_empty() || tag.contains(|c: char| c == ',' || c.is_control()))
+    {
+        bail!(
+            "tag {:?} must not be empty or contain commas, tabs or line breaks",
+            tag
+        );
+    }
+    Ok(Rule {
+        name,
+        condition: if rule.any {
+            Condition::Any(conditions)
+        } else {
+            Condition::All(conditions)
+        },
+        actions,
+        stop: rule.stop,
+    })
+}
+
+fn compile_condition(condition: &config::Condition) -> Result<Condition> {
+    let tests: Vec<Test> = [
+        condition.contains.clone().map(Test::Contains),
+        condition.is.clone().map(Test::Is),
+        condition
+            .regex
+            .as_deref()
+            .map(|pattern| Regex::new(pattern).map(Test::Regex))
+            .transpose()?,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let sizes = [&condition.larger_than, &condition.smaller_than]
+        .iter()
+        .filter(|size| size.is_some())
+        .count();
+
+    let compiled = match (&condition.header, condition.body) {
+        (Some(_), true) => bail!("use either header or body, not both"),
+        (Some(_), false) | (None, true) if sizes > 0 => {
+            bail!("sizes apply to the whole message, not a header or the body")
+        }
+        (Some(name), false) => Condition::Header {
+            name: name.clone(),
+            test: single(tests)?,
+        },
+        (None, true) => Condition::Body(single(tests)?),
+        (None, false) => {
+            if !tests.is_empty() {
+                bail!("say what to test: header = \"Name\" or body = true");
+            }
+            match (&condition.larger_than, &condition.smaller_than) {
+                (Some(size), None) => Condition::LargerThan(parse_size(size)?),
+                (None, Some(size)) => Condition::SmallerThan(parse_size(size)?),
+                (Some(over), Some(under)) => Condition::All(vec![
+                    Condition::LargerThan(parse_size(over)?),
+                    Condition::SmallerThan(parse_size(under)?),
+                ]),
+                (None, None) => bail!("empty condition"),
+            }
+        }
+    };
+    Ok(if condition.not {
+        Condition::Not(Box::new(compiled))
+    } else {
+        compiled
+    })
+}
+
+fn single(mut tests: Vec<Test>) -> Result<Test> {
+    match tests.len() {
+        0 => bail!("missing contains, is or regex"),
+        1 => Ok(tests.remove(0)),
+        _ => bail!("use only one of contains, is and regex"),
+    }
+}
+
+/// Parses `1500`, `100K`, `2M` or `1G` into bytes.
+pub fn parse_size(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let (digits, multiplier) = match text.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&text[..text.len() - 1], 1 << 10),
+        Some('M') => (&text[..text.len() - 1], 1 << 20),
+        Some('G') => (&text[..text.len() - 1], 1 << 30),
+        _ => (text, 1),
+    };
+    let number: u64 = digits
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid size {:?}", text))?;
+    number
+        .checked_mul(multiplier)
+        .with_context(|| format!("size {:?} is too large", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::backend::{FileBackend, OutgoingMessage, RemoteMessage};
+
+    fn email(raw: &str) -> EmailSummary {
+        let mut email = FileBackend::new("unused", "me@example.com").parse_message(raw.as_bytes());
+        email.recent = true;
+        email
+    }
+
+    fn newsletter() -> EmailSummary {
+        email(
+            "From: News <news@lists.example.com>\r\n\
+List-Id: <news.example.com>\r\n\
+Subject: Weekly digest\r\n\r\nUnsubscribe at any time.\r\n",
+        )
+    }
+
+    fn compile(text: &str) -> Result<Rules> {
+        #[derive(serde::Deserialize)]
+        struct File {
+            rule: Vec<config::Rule>,
+        }
+        let file: File = toml::from_str(text).unwrap();
+        Rules::compile(&file.rule)
+    }
+
+    /// Records what the rules asked of it.
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl EmailBackend for Recorder {
+        fn fetch_inbox(&self) -> Result<Vec<EmailSummary>> {
+            Ok(Vec::new())
+        }
+        fn send_email(&self, _: &OutgoingMessage) -> Result<()> {
+            Ok(())
+        }
+        fn delete_email(&self, email: &EmailSummary) -> Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("delete {}", email.subject));
+            Ok(())
+        }
+        fn update_email(&self, email: &EmailSummary) -> Result<()> {
+            self.calls.borrow_mut().push(format!(
+                "update {} read={} flagged={} tags={}",
+                email.subject,
+                email.read,
+                email.flagged,
+                email.tags.join(",")
+            ));
+            Ok(())
+        }
+        fn move_email(&self, email: &EmailSummary, folder: &str) -> Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("move {} to {}", email.subject, folder));
+            Ok(())
+        }
+        fn open_email(&self, _: &mut EmailSummary) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1500").unwrap(), 1500);
+        assert_eq!(parse_size(" 100k ").unwrap(), 100 << 10);
+        assert_eq!(parse_size("2M").unwrap(), 2 << 20);
+        assert_eq!(parse_size("1G").unwrap(), 1 << 30);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("2T").is_err());
+    }
+
+    #[test]
+    fn oversized_sizes_are_errors() {
+        assert_eq!(parse_size("17179869183G").unwrap(), 17179869183 << 30);
+        let error = parse_size("17179869184G").unwrap_err();
+        assert_eq!(error.to_string(), "size \"17179869184G\" is too large");
+        let error = compile(
+            "[[rule]]\ndelete = true\n[[rule.condition]]\nlarger_than = \"99999999999999G\"\n",
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("is too large"));
+    }
+
+    #[test]
+    fn header_tests() {
+        let email = newsletter();
+        let header = |test| Condition::Header {
+            name: "list-id".to_string(),
+            test,
+        };
+        assert!(header(Test::Contains("NEWS.example".to_string())).matches(&email));
+        assert!(header(Test::Is(" <News.Example.com> ".to_string())).matches(&email));
+        assert!(!header(Test::Is("news.example.com".to_string())).matches(&email));
+        assert!(header(Test::Regex(Regex::new(r"^<\w+\.").unwrap())).matches(&email));
+        assert!(!header(Test::Regex(Regex::new("NEWS").unwrap())).matches(&email));
+        // A missing header matches nothing
+        let missing = Condition::Header {
+            name: "X-Spam".to_string(),
+            test: Test::Contains(String::new()),
+        };
+        assert!(!missing.matches(&email));
+        assert!(Condition::Not(Box::new(missing)).matches(&email));
+    }
+
+    #[test]
+    fn body_and_size_tests() {
+        let email = newsletter();
+        assert!(Condition::Body(Test::Contains("unsubscribe".to_string())).matches(&email));
+        let size = email.raw.len() as u64;
+        assert!(Condition::LargerThan(size - 1).matches(&email));
+        assert!(!Condition::LargerThan(size).matches(&email));
+        assert!(Condition::SmallerThan(size + 1).matches(&email));
+        assert!(!Condition::SmallerThan(size).matches(&email));
+    }
+
+    #[test]
+    fn compiles_config_rules() {
+        let rules = compile(
+            r#"
+[[rule]]
+name = "lists"
+any = true
+tag = ["list"]
+[[rule.condition]]
+header = "List-Id"
+contains = "example"
+[[rule.condition]]
+header = "Subject"
+regex = "(?i)^\\[list\\]"
+not = true
+
+[[rule]]
+flag = true
+[[rule.condition]]
+smaller_than = "1K"
+larger_than = "10"
+"#,
+        )
+        .unwrap();
+        assert_eq!(rules.rules[0].name, "lists");
+        assert_eq!(rules.rules[1].name, "rule 2");
+        assert_eq!(
+            rules.rules[0].condition.to_string(),
+            r#"(List-Id contains "example" or not Subject matches /(?i)^\[list\]/)"#
+        );
+        assert_eq!(
+            rules.rules[1].condition.to_string(),
+            "((size over 10 bytes and size under 1024 bytes))"
+        );
+    }
+
+    #[test]
+    fn rejects_unclear_rules() {
+        let error = |text: &str| format!("{:#}", compile(text).unwrap_err());
+        assert_eq!(
+            error("[[rule]]\nname = \"x\"\nflag = true\n"),
+            "in filtering rule \"x\": no conditions; add a [[rule.condition]] table"
+        );
+        assert!(
+            error("[[rule]]\n[[rule.condition]]\nbody = true\ncontains = \"a\"\n")
+                .ends_with("no actions")
+        );
+        assert!(
+            error("[[rule]]\nflag = true\n[[rule.condition]]\nbody = true\ncontains = \"a\"\nis = \"b\"\n")
+                .ends_with("use only one of contains, is and regex")
+        );
+        assert!(
+            error("[[rule]]\nflag = true\n[[rule.condition]]\nheader = \"From\"\nlarger_than = \"1\"\n")
+                .ends_with("sizes apply to the whole message, not a header or the body")
+        );
+        assert!(
+            error("[[rule]]\nflag = true\n[[rule.condition]]\nbody = true\nregex = \"(\"\n")
+                .contains("condition 1")
+        );
+        for tag in ["a,b", "a\\tb", "a\\nb", ""] {
+            assert!(
+                error(&format!(
+                    "[[rule]]\ntag = [\"{}\"]\n[[rule.condition]]\nbody = true\ncontains = \"a\"\n",
+                    tag
+                ))
+                .ends_with("must not be empty or contain commas, tabs or line breaks")
+            );
+        }
+    }
+
+    #[test]
+    fn stop_skips_later_rules_and_actions_merge() {
+        let rules = compile(
+            r#"
+[[rule]]
+tag = ["news"]
+move = "News"
+[[rule.condition]]
+header = "List-Id"
+contains = "news"
+
+[[rule]]
+tag = ["digest", "news"]
+move = "Digests"
+mark_read = true
+stop = true
+[[rule.condition]]
+header = "Subject"
+contains = "digest"
+
+[[rule]]
+delete = true
+[[rule.condition]]
+body = true
+contains = "unsubscribe"
+"#,
+        )
+        .unwrap();
+        let email = newsletter();
+        assert_eq!(rules.matching(&email).len(), 2);
+        assert_eq!(
+            rules.actions(&email),
+            Actions {
+                move_to: Some("News".to_string()),
+                tags: vec!["news".to_string(), "digest".to_string()],
+                mark_read: true,
+                flag: false,
+                delete: false,
+            }
+        );
+        let explained = rules.explain(&email);
+        assert!(explained.contains("rule 3: not reached"));
+        assert!(explained.ends_with("Result: tag news, digest, mark read, move to News\n"));
+    }
+
+    #[test]
+    fn run_applies_actions_to_recent_mail_only() {
+        let rules = compile(
+            r#"
+[[rule]]
+tag = ["news"]
+flag = true
+move = "News"
+[[rule.condition]]
+header = "List-Id"
+contains = "news"
+
+[[rule]]
+delete = true
+[[rule.condition]]
+header = "Subject"
+is = "spam"
+"#,
+        )
+        .unwrap();
+        let mut seen = newsletter();
+        seen.recent = false;
+        seen.subject = "Seen".to_string();
+        let spam = email("From: x@example.net\r\nSubject: Spam\r\n\r\nBuy\r\n");
+        let other = email("From: bob@example.org\r\nSubject: Lunch\r\n\r\nNoon?\r\n");
+        let mut remote = newsletter();
+        remote.subject = "Remote".to_string();
+        remote.remote = Some(RemoteMessage {
+            folder: "INBOX".to_string(),
+            uid: 7,
+        });
+        let backend = Recorder::default();
+        let applied = rules.run(&backend, vec![newsletter(), seen, spam, other, remote]);
+
+        assert_eq!(applied.moved, 1);
+        assert_eq!(applied.deleted, 1);
+        let kept: Vec<&str> = applied.inbox.iter().map(|e| e.subject.as_str()).collect();
+        assert_eq!(kept, vec!["Seen", "Lunch", "Remote"]);
+        assert_eq!(
+            backend.calls.into_inner(),
+            vec![
+                "update Weekly digest read=false flagged=true tags=news",
+                "move Weekly digest to News",
+                "delete Spam",
+            ]
+        );
+        assert_eq!(applied.summary().unwrap(), "Rules: 1 moved, 1 deleted");
+    }
+}
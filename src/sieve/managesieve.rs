@@ -0,0 +1,540 @@
+//! A ManageSieve client (RFC 5804), for uploading and activating scripts
+//! on the mail server.
+
+use std::io::{Read, Write};
+
+use anyhow::{Context, Result, bail};
+
+use crate::backend::mime::encode_base64;
+use crate::config;
+use crate::net::{Server, Stream, Tls};
+
+/// One item of a response line: an atom such as `OK`, or a quoted or
+/// literal string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Atom(String),
+    String(String),
+}
+
+impl Item {
+    fn text(&self) -> &str {
+        match self {
+            Item::Atom(text) | Item::String(text) => text,
+        }
+    }
+}
+
+/// The line that ends a response.
+struct Reply {
+    ok: bool,
+    /// `OK`, `NO` or `BYE`, then the human-readable text.
+    status: String,
+    message: String,
+}
+
+impl Reply {
+    fn describe(&self) -> String {
+        if self.message.is_empty() {
+            self.status.clone()
+        } else {
+            format!("{} {}", self.status, self.message.trim_end())
+        }
+    }
+}
+
+/// A logged-in session.
+pub struct ManageSieve {
+    stream: Stream,
+    buffer: Vec<u8>,
+    /// Capability names (upper case) and their values.
+    capabilities: Vec<(String, String)>,
+}
+
+impl ManageSieve {
+    /// Connects, negotiates TLS as configured and logs in.
+    pub fn connect(server: &Server) -> Result<ManageSieve> {
+        let stream = match server.tls {
+            Tls::Implicit => {
+                Stream::connect_tls(&server.host, server.port, server.ca_file.as_deref())?
+            }
+            Tls::Starttls | Tls::None => Stream::connect(&server.host, server.port)?,
+        };
+        let mut session = ManageSieve {
+            stream,
+            buffer: Vec::new(),
+            capabilities: Vec::new(),
+        };
+        session.read_capabilities()?;
+
+        if server.tls == Tls::Starttls {
+            if session.capability("STARTTLS").is_none() {
+                bail!("{} does not offer STARTTLS", server.host);
+            }
+            session.command(b"STARTTLS\r\n")?;
+            let ManageSieve { stream, .. } = session;
+            session = ManageSieve {
+                stream: stream.start_tls(&server.host, server.ca_file.as_deref())?,
+                buffer: Vec::new(),
+                capabilities: Vec::new(),
+            };
+            // The server repeats its capabilities over TLS
+            session.read_capabilities()?;
+        }
+
+        let mechanisms = session.capability("SASL").unwrap_or_default();
+        if !mechanisms
+            .split_whitespace()
+            .any(|m| m.eq_ignore_ascii_case("PLAIN"))
+        {
+            bail!(
+                "{} does not accept PLAIN logins (it offers: {})",
+                server.host,
+                mechanisms
+            );
+        }
+        let password = match &server.password_command {
+            Some(command) => config::password(command)?,
+            None => String::new(),
+        };
+        let credentials = encode_base64(format!("\0{}\0{}", server.user, password).as_bytes());
+        session
+            .command(format!("AUTHENTICATE \"PLAIN\" \"{}\"\r\n", credentials).as_bytes())
+            .with_context(|| format!("logging in to {} as {}", server.host, server.user))?;
+        Ok(session)
+    }
+
+    /// The value of a capability the server announced, such as the
+    /// extensions listed under `SIEVE`.
+    pub fn capability(&self, name: &str) -> Option<String> {
+        self.capabilities
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Has the server check a script without storing it. Its complaint is
+    /// returned as the error.
+    pub fn check_script(&mut self, script: &str) -> Result<()> {
+        let mut request = b"CHECKSCRIPT ".to_vec();
+        literal(&mut request, script);
+        self.command(&request).map(|_| ())
+    }
+
+    /// Stores a script, replacing any script of the same name.
+    pub fn put_script(&mut self, name: &str, script: &str) -> Result<()> {
+        let mut request = format!("PUTSCRIPT {} ", quote(name)).into_bytes();
+        literal(&mut request, script);
+        self.command(&request).map(|_| ())
+    }
+
+    /// Makes a stored script the one the server runs.
+    pub fn set_active(&mut self, name: &str) -> Result<()> {
+        self.command(format!("SETACTIVE {}\r\n", quote(name)).as_bytes())
+            .map(|_| ())
+    }
+
+    /// The scripts on the server, and whether each is active.
+    pub fn list_scripts(&mut self) -> Result<Vec<(String, bool)>> {
+        let lines = self.command(b"LISTSCRIPTS\r\n")?;
+        Ok(lines
+            .into_iter()
+            .filter_map(|line| {
+                let mut items = line.into_iter();
+                let name = items.next()?.text().to_string();
+                let active = items.any(|i| i.text().eq_ignore_ascii_case("ACTIVE"));
+                Some((name, active))
+            })
+            .collect())
+    }
+
+    pub fn logout(mut self) -> Result<()> {
+        self.command(b"LOGOUT\r\n").map(|_| ())
+    }
+
+    /// Sends a request and returns the lines before its `OK`.
+    fn command(&mut self, request: &[u8]) -> Result<Vec<Vec<Item>>> {
+        self.stream.write_all(request)?;
+        self.stream.flush()?;
+        let (lines, reply) = self.response()?;
+        if !reply.ok {
+            bail!("server replied {}", reply.describe());
+        }
+        Ok(lines)
+    }
+
+    fn read_capabilities(&mut self) -> Result<()> {
+        let (lines, reply) = self.response()?;
+        if !reply.ok {
+            bail!("server replied {}", reply.describe());
+        }
+        self.capabilities = lines
+            .into_iter()
+            .filter_map(|line| {
+                let mut items = line.into_iter();
+                let name = items.next()?.text().to_ascii_uppercase();
+                let value = items
+                    .next()
+                    .map(|i| i.text().to_string())
+                    .unwrap_or_default();
+                Some((name, value))
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Reads lines up to the one starting with `OK`, `NO` or `BYE`.
+    fn response(&mut self) -> Result<(Vec<Vec<Item>>, Reply)> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_items()?;
+            if let Some(Item::Atom(status)) = line.first() {
+                let status = status.to_ascii_uppercase();
+                if matches!(status.as_str(), "OK" | "NO" | "BYE") {
+                    let message = line[1..]
+                        .iter()
+                        .map(Item::text)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    return Ok((
+                        lines,
+                        Reply {
+                            ok: status == "OK",
+                            status,
+                            message,
+                        },
+                    ));
+                }
+            }
+            lines.push(line);
+        }
+    }
+
+    /// Reads one response line, following any literals it contains.
+    fn read_items(&mut self) -> Result<Vec<Item>> {
+        let mut items = Vec::new();
+        let mut line = self.read_line()?;
+        let mut pos = 0;
+        loop {
+            while line.get(pos) == Some(&b' ') {
+                pos += 1;
+            }
+            let Some(&c) = line.get(pos) else {
+                return Ok(items);
+            };
+            match c {
+                b'"' => {
+                    let mut text = Vec::new();
+                    pos += 1;
+                    while let Some(&c) = line.get(pos) {
+                        pos += 1;
+                        match c {
+                            b'"' => break,
+                            b'\\' => {
+                                text.extend(line.get(pos));
+                                pos += 1;
+                            }
+                            _ => text.push(c),
+                        }
+                    }
+                    items.push(Item::String(String::from_utf8_lossy(&text).into_owned()));
+                }
+                b'{' => {
+                    let end = line[pos..]
+                        .iter()
+                        .position(|&c| c == b'}')
+                        .map(|e| pos + e)
+                        .context("malformed literal in the server response")?;
+                    let length: usize = String::from_utf8_lossy(&line[pos + 1..end])
+                        .trim_end_matches('+')
+                        .parse()
+                        .context("malformed literal length in the server response")?;
+                    let text = self.read_exact(length)?;
+                    items.push(Item::String(String::from_utf8_lossy(&text).into_owned()));
+                    // The line goes on after the literal
+                    line = self.read_line()?;
+                    pos = 0;
+                }
+                b'(' => {
+                    // A response code such as `(WARNINGS)`, kept as one atom
+                    let mut depth = 0;
+                    let mut quoted = false;
+                    let start = pos;
+                    while let Some(&c) = line.get(pos) {
+                        pos += 1;
+                        match c {
+                            b'"' => quoted = !quoted,
+                            b'(' if !quoted => depth += 1,
+                            b')' if !quoted => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    items.push(Item::Atom(
+                        String::from_utf8_lossy(&line[start..pos]).into_owned(),
+                    ));
+                }
+                _ => {
+                    let start = pos;
+                    while line.get(pos).is_some_and(|&c| c != b' ') {
+                        pos += 1;
+                    }
+                    items.push(Item::Atom(
+                        String::from_utf8_lossy(&line[start..pos]).into_owned(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// The next line, without its CRLF.
+    fn read_line(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&c| c == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(line);
+            }
+            self.fill()?;
+        }
+    }
+
+    fn read_exact(&mut self, length: usize) -> Result<Vec<u8>> {
+        while self.buffer.len() < length {
+            self.fill()?;
+        }
+        Ok(self.buffer.drain(..length).collect())
+    }
+
+    fn fill(&mut self) -> Result<()> {
+        let mut chunk = [0; 4096];
+        let n = self
+            .stream
+            .read(&mut chunk)
+            .context("reading from the ManageSieve server")?;
+        if n == 0 {
+            bail!("the ManageSieve server closed the connection");
+        }
+        self.buffer.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+}
+
+/// A quoted string.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Appends a non-synchronizing literal, with CRLF line endings, and ends
+/// the request.
+fn literal(request: &mut Vec<u8>, text: &str) {
+    let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+    request.extend_from_slice(format!("{{{}+}}\r\n", text.len()).as_bytes());
+    request.extend_from_slice(text.as_bytes());
+    request.extend_from_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::Server;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    const CAPABILITIES: &str = "\"IMPLEMENTATION\" \"Scripted\"\n\
+\"SASL\" \"LOGIN PLAIN\"\n\
+\"SIEVE\" \"fileinto envelope\"\n\
+\"VERSION\" \"1.0\"";
+
+    /// One client connection to the scripted server.
+    struct Client {
+        reader: BufReader<TcpStream>,
+        stream: TcpStream,
+        /// Every command received, with the contents of its literal.
+        log: Vec<String>,
+    }
+
+    impl Client {
+        /// Accepts a connection and greets it with `capabilities`.
+        fn accept(listener: &TcpListener, capabilities: &str) -> Client {
+            let (stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut client = Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                stream,
+                log: Vec::new(),
+            };
+            client.send(capabilities);
+            client.send("OK \"ready\"");
+            client
+        }
+
+        fn send(&mut self, text: &str) {
+            for line in text.lines() {
+                self.stream.write_all(line.as_bytes()).unwrap();
+                self.stream.write_all(b"\r\n").unwrap();
+            }
+        }
+
+        /// Reads a command, and the literal it ends with, checking it
+        /// starts as expected.
+        fn expect(&mut self, command: &str) {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            let mut line = line.trim_end().to_string();
+            assert!(
+                line.starts_with(command),
+                "expected {}, got {:?}",
+                command,
+                line
+            );
+            if let Some(open) = line.rfind('{')
+                && line.ends_with("+}")
+            {
+                let length: usize = line[open + 1..line.len() - 2].parse().unwrap();
+                let mut literal = vec![0; length + 2];
+                self.reader.read_exact(&mut literal).unwrap();
+                assert!(literal.ends_with(b"\r\n"));
+                literal.truncate(length);
+                line.push('\n');
+                line.push_str(&String::from_utf8(literal).unwrap());
+            }
+            self.log.push(line);
+        }
+
+        fn reply(&mut self, command: &str, response: &str) {
+            self.expect(command);
+            self.send(response);
+        }
+    }
+
+    /// Runs `serve` as the server side of a connection to `server`.
+    fn scripted(
+        tls: Tls,
+        serve: impl FnOnce(&TcpListener) -> Vec<String> + Send + 'static,
+    ) -> (Server, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Server {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            user: "alice".to_string(),
+            password_command: Some("echo secret".to_string()),
+            tls,
+            ca_file: None,
+        };
+        (server, thread::spawn(move || serve(&listener)))
+    }
+
+    #[test]
+    fn uploads_and_activates_scripts() {
+        let (server, script) = scripted(Tls::None, |listener| {
+            let mut client = Client::accept(listener, CAPABILITIES);
+            client.reply("AUTHENTICATE \"PLAIN\"", "OK \"logged in\"");
+            client.reply("PUTSCRIPT \"hermes \\\"mail\\\"\"", "OK");
+            client.reply("CHECKSCRIPT", "NO \"line 2: unknown command vacation\"");
+            client.reply(
+                "CHECKSCRIPT",
+                "NO {22}\nline 1: missing ;\nend (QUOTA) \"more\"",
+            );
+            client.reply("SETACTIVE", "OK");
+            client.reply(
+                "LISTSCRIPTS",
+                "\"hermes \\\"mail\\\"\" ACTIVE\n{3}\nold\nOK \"listed\"",
+            );
+            client.reply("LOGOUT", "OK \"bye\"");
+            client.log
+        });
+
+        let mut session = ManageSieve::connect(&server).unwrap();
+        assert_eq!(
+            session.capability("sieve").as_deref(),
+            Some("fileinto envelope")
+        );
+        assert_eq!(session.capability("STARTTLS"), None);
+        let name = "hermes \"mail\"";
+        session
+            .put_script(name, "require \"fileinto\";\nkeep;\n")
+            .unwrap();
+        assert_eq!(
+            format!("{:#}", session.check_script("vacation;").unwrap_err()),
+            "server replied NO line 2: unknown command vacation"
+        );
+        assert_eq!(
+            format!("{:#}", session.check_script("keep").unwrap_err()),
+            "server replied NO line 1: missing ;\r\nend (QUOTA) more"
+        );
+        session.set_active(name).unwrap();
+        assert_eq!(
+            session.list_scripts().unwrap(),
+            vec![(name.to_string(), true), ("old".to_string(), false)]
+        );
+        session.logout().unwrap();
+
+        let credentials = encode_base64(b"\0alice\0secret");
+        assert_eq!(
+            script.join().unwrap(),
+            vec![
+                format!("AUTHENTICATE \"PLAIN\" \"{}\"", credentials),
+                "PUTSCRIPT \"hermes \\\"mail\\\"\" {28+}\nrequire \"fileinto\";\r\nkeep;\r\n"
+                    .to_string(),
+                "CHECKSCRIPT {9+}\nvacation;".to_string(),
+                "CHECKSCRIPT {4+}\nkeep".to_string(),
+                "SETACTIVE \"hermes \\\"mail\\\"\"".to_string(),
+                "LISTSCRIPTS".to_string(),
+                "LOGOUT".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_servers_without_starttls() {
+        let (server, script) = scripted(Tls::Starttls, |listener| {
+            Client::accept(listener, CAPABILITIES).log
+        });
+        let error = ManageSieve::connect(&server).err().unwrap();
+        assert_eq!(format!("{:#}", error), "127.0.0.1 does not offer STARTTLS");
+        // Nothing, least of all the password, was sent
+        assert!(script.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_refused_logins() {
+        let (server, script) = scripted(Tls::None, |listener| {
+            let mut client = Client::accept(listener, CAPABILITIES);
+            client.reply(
+                "AUTHENTICATE",
+                "NO (AUTH-TOO-WEAK) \"Authentication failed\"",
+            );
+            client.log
+        });
+        let error = ManageSieve::connect(&server).err().unwrap();
+        assert_eq!(
+            format!("{:#}", error),
+            "logging in to 127.0.0.1 as alice: server replied NO (AUTH-TOO-WEAK) \
+Authentication failed"
+        );
+        assert_eq!(script.join().unwrap().len(), 1);
+
+        // Without PLAIN there is no way to log in
+        let (server, script) = scripted(Tls::None, |listener| {
+            Client::accept(listener, "\"SASL\" \"GSSAPI\"").log
+        });
+        let error = ManageSieve::connect(&server).err().unwrap();
+        assert_eq!(
+            format!("{:#}", error),
+            "127.0.0.1 does not accept PLAIN logins (it offers: GSSAPI)"
+        );
+        assert!(script.join().unwrap().is_empty());
+    }
+}
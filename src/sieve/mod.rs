@@ -0,0 +1,952 @@
+//! A subset of the Sieve mail filtering language (RFC 5228), applied to
+//! new mail locally and uploaded to the server with ManageSieve, so both
+//! filter with the same script.
+//!
+//! Supported: `require`, `if`/`elsif`/`else`, `keep`, `discard`,
+//! `fileinto`, `redirect` and `stop`; the `header`, `address`, `envelope`,
+//! `exists`, `size`, `allof`, `anyof`, `not`, `true` and `false` tests;
+//! `:is`, `:contains` and `:matches` with the `i;ascii-casemap` and
+//! `i;octet` comparators; and the `:all`, `:localpart` and `:domain`
+//! address parts.
+
+pub mod managesieve;
+mod parse;
+
+use std::{fmt, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+
+use crate::backend::{EmailSummary, address};
+use crate::rules::Actions;
+use parse::Argument;
+
+/// Extensions a script may `require`.
+const CAPABILITIES: &[&str] = &[
+    "fileinto",
+    "envelope",
+    "comparator-i;octet",
+    "comparator-i;ascii-casemap",
+];
+
+/// A checked script, ready to run.
+#[derive(Debug, Clone)]
+pub struct Script {
+    commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone)]
+enum Command {
+    /// `if`, its `elsif` branches, and `else`.
+    If {
+        branches: Vec<(Test, Vec<Command>)>,
+        otherwise: Option<Vec<Command>>,
+    },
+    Keep,
+    Discard,
+    FileInto(String),
+    Redirect(String),
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchType {
+    Is,
+    Contains,
+    Matches,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparator {
+    /// Case-insensitive for ASCII letters, the default.
+    AsciiCaseMap,
+    /// Exact.
+    Octet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddressPart {
+    All,
+    LocalPart,
+    Domain,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Matcher {
+    match_type: MatchType,
+    comparator: Comparator,
+}
+
+impl Matcher {
+    fn matches(&self, value: &str, key: &str) -> bool {
+        let (value, key) = match self.comparator {
+            Comparator::AsciiCaseMap => (value.to_ascii_lowercase(), key.to_ascii_lowercase()),
+            Comparator::Octet => (value.to_string(), key.to_string()),
+        };
+        match self.match_type {
+            MatchType::Is => value == key,
+            MatchType::Contains => value.contains(&key),
+            MatchType::Matches => {
+                let value: Vec<char> = value.chars().collect();
+                let pattern: Vec<char> = key.chars().collect();
+                wildcard(&value, &pattern)
+            }
+        }
+    }
+}
+
+/// One element of a `:matches` pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Glob {
+    /// `*`, any run of characters.
+    Any,
+    /// `?`, any one character.
+    One,
+    Char(char),
+}
+
+/// `:matches` patterns: `*` is any run of characters, `?` any one, and a
+/// backslash makes the next character literal. Each `*` is tried against
+/// a growing run of the value, going back only to the last `*` seen, so
+/// matching takes at most `value.len() * pattern.len()` steps.
+fn wildcard(value: &[char], pattern: &[char]) -> bool {
+    let mut globs = Vec::with_capacity(pattern.len());
+    let mut chars = pattern.iter();
+    while let Some(&c) = chars.next() {
+        globs.push(match c {
+            '*' => Glob::Any,
+            '?' => Glob::One,
+            '\\' => Glob::Char(chars.next().copied().unwrap_or('\\')),
+            c => Glob::Char(c),
+        });
+    }
+
+    let (mut v, mut g) = (0, 0);
+    // The last `*`, and where in the value its run ends
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match globs.get(g) {
+            Some(Glob::Any) => {
+                star = Some((g, v));
+                g += 1;
+                continue;
+            }
+            Some(Glob::One) => {
+                v += 1;
+                g += 1;
+                continue;
+            }
+            Some(Glob::Char(c)) if *c == value[v] => {
+                v += 1;
+                g += 1;
+                continue;
+            }
+            _ => {}
+        }
+        // Mismatch: let the last `*` take one more character
+        let Some((star_g, star_v)) = star else {
+            return false;
+        };
+        star = Some((star_g, star_v + 1));
+        g = star_g + 1;
+        v = star_v + 1;
+    }
+    globs[g..].iter().all(|glob| *glob == Glob::Any)
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Header {
+        matcher: Matcher,
+        names: Vec<String>,
+        keys: Vec<String>,
+    },
+    Address {
+        matcher: Matcher,
+        part: AddressPart,
+        headers: Vec<String>,
+        keys: Vec<String>,
+    },
+    Envelope {
+        matcher: Matcher,
+        part: AddressPart,
+        parts: Vec<String>,
+        keys: Vec<String>,
+    },
+    Exists(Vec<String>),
+    Size {
+        over: bool,
+        limit: u64,
+    },
+    AllOf(Vec<Test>),
+    AnyOf(Vec<Test>),
+    Not(Box<Test>),
+    True,
+    False,
+}
+
+impl Test {
+    fn matches(&self, email: &EmailSummary) -> bool {
+        let any_key = |matcher: &Matcher, values: &[String], keys: &[String]| {
+            values
+                .iter()
+                .any(|value| keys.iter().any(|key| matcher.matches(value, key)))
+        };
+        match self {
+            Test::Header {
+                matcher,
+                names,
+                keys,
+            } => {
+                let values: Vec<String> = names
+                    .iter()
+                    .flat_map(|name| header_values(email, name))
+                    .map(str::to_string)
+                    .collect();
+                any_key(matcher, &values, keys)
+            }
+            Test::Address {
+                matcher,
+                part,
+                headers,
+                keys,
+            } => {
+                let values: Vec<String> = headers
+                    .iter()
+                    .flat_map(|name| header_values(email, name))
+                    .flat_map(addresses)
+                    .map(|a| address_part(&a, *part))
+                    .collect();
+                any_key(matcher, &values, keys)
+            }
+            Test::Envelope {
+                matcher,
+                part,
+                parts,
+                keys,
+            } => {
+                let values: Vec<String> = parts
+                    .iter()
+                    .flat_map(|name| envelope(email, name))
+                    .map(|a| address_part(&a, *part))
+                    .collect();
+                any_key(matcher, &values, keys)
+            }
+            Test::Exists(names) => names
+                .iter()
+                .all(|name| header_values(email, name).next().is_some()),
+            Test::Size { over, limit } => {
+                let size = email.raw.len() as u64;
+                if *over { size > *limit } else { size < *limit }
+            }
+            Test::AllOf(tests) => tests.iter().all(|t| t.matches(email)),
+            Test::AnyOf(tests) => tests.iter().any(|t| t.matches(email)),
+            Test::Not(test) => !test.matches(email),
+            Test::True => true,
+            Test::False => false,
+        }
+    }
+}
+
+fn header_values<'a>(email: &'a EmailSummary, name: &'a str) -> impl Iterator<Item = &'a str> {
+    email
+        .headers
+        .iter()
+        .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// The addresses in a header; the whole value when it does not parse.
+fn addresses(value: &str) -> Vec<String> {
+    match address::parse_address_list(value) {
+        Ok(list) if !list.is_empty() => list.into_iter().map(|a| a.email).collect(),
+        _ => vec![value.trim().trim_matches(['<', '>']).to_string()],
+    }
+}
+
+fn address_part(address: &str, part: AddressPart) -> String {
+    let (local, domain) = address.rsplit_once('@').unwrap_or((address, ""));
+    match part {
+        AddressPart::All => address.to_string(),
+        AddressPart::LocalPart => local.to_string(),
+        AddressPart::Domain => domain.to_string(),
+    }
+}
+
+/// The SMTP envelope as far as the stored message records it: the sender
+/// from `Return-Path`, the recipient from `Delivered-To` or
+/// `X-Original-To`.
+fn envelope(email: &EmailSummary, part: &str) -> Vec<String> {
+    let headers: &[&str] = match part.to_ascii_lowercase().as_str() {
+        "from" => &["Return-Path"],
+        "to" => &["Delivered-To", "X-Original-To"],
+        _ => &[],
+    };
+    headers
+        .iter()
+        .flat_map(|name| header_values(email, name))
+        .flat_map(addresses)
+        .collect()
+}
+
+/// What a script decided for a message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// Stays in the inbox, explicitly or because nothing else was done.
+    pub keep: bool,
+    pub fileinto: Vec<String>,
+    pub discard: bool,
+    pub redirect: Vec<String>,
+}
+
+impl Outcome {
+    /// The actions carried out locally. Redirecting is left to the server,
+    /// so a redirected message is kept rather than lost; a message both
+    /// kept and filed stays in the inbox.
+    pub fn actions(&self) -> Actions {
+        let mut actions = Actions::default();
+        if !self.keep {
+            if let Some(folder) = self.fileinto.first() {
+                actions.move_to = Some(folder.clone());
+            } else if self.discard && self.redirect.is_empty() {
+                actions.delete = true;
+            }
+        }
+        actions
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.keep {
+            parts.push("keep".to_string());
+        }
+        for folder in &self.fileinto {
+            parts.push(format!("fileinto {:?}", folder));
+        }
+        for address in &self.redirect {
+            parts.push(format!("redirect {:?} (on the server only)", address));
+        }
+        if self.discard && !self.keep {
+            parts.push("discard".to_string());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Execution state: implicit keep is cancelled by any action that files,
+/// redirects or discards the message (RFC 5228, section 2.10.2).
+struct Run {
+    outcome: Outcome,
+    explicit_keep: bool,
+    cancelled: bool,
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Script> {
+        let commands = parse::parse(text)?;
+        let mut required = Vec::new();
+        let mut seen_other = false;
+        for command in &commands {
+            if command.name == "require" {
+                if seen_other {
+                    bail!("line {}: require must come first", command.line);
+                }
+                for capability in strings(&command.arguments, command.line, "require")? {
+                    if !CAPABILITIES.contains(&capability.as_str()) {
+                        bail!(
+                            "line {}: unsupported extension \"{}\"",
+                            command.line,
+                            capability
+                        );
+                    }
+                    required.push(capability);
+                }
+            } else {
+                seen_other = true;
+            }
+        }
+        let commands = block(commands.iter().filter(|c| c.name != "require"), &required)?;
+        Ok(Script { commands })
+    }
+
+    pub fn load(path: &Path) -> Result<Script> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Script::parse(&text).with_context(|| format!("in Sieve script {}", path.display()))
+    }
+
+    pub fn run(&self, email: &EmailSummary) -> Outcome {
+        let mut run = Run {
+            outcome: Outcome::default(),
+            explicit_keep: false,
+            cancelled: false,
+        };
+        execute(&self.commands, email, &mut run);
+        let mut outcome = run.outcome;
+        outcome.keep = run.explicit_keep || !run.cancelled;
+        outcome
+    }
+}
+
+/// Runs commands; false once `stop` is reached.
+fn execute(commands: &[Command], email: &EmailSummary, run: &mut Run) -> bool {
+    for command in commands {
+        match command {
+            Command::If {
+                branches,
+                otherwise,
+            } => {
+                let chosen = branches
+                    .iter()
+                    .find(|(test, _)| test.matches(email))
+                    .map(|(_, block)| block)
+                    .or(otherwise.as_ref());
+                if let Some(block) = chosen
+                    && !execute(block, email, run)
+                {
+                    return false;
+                }
+            }
+            Command::Keep => run.explicit_keep = true,
+            Command::Discard => {
+                run.cancelled = true;
+                run.outcome.discard = true;
+            }
+            Command::FileInto(folder) => {
+                run.cancelled = true;
+                if !run.outcome.fileinto.contains(folder) {
+                    run.outcome.fileinto.push(folder.clone());
+                }
+            }
+            Command::Redirect(address) => {
+                run.cancelled = true;
+                run.outcome.redirect.push(address.clone());
+            }
+            Command::Stop => return false,
+        }
+    }
+    true
+}
+
+/// Checks a block of commands, pairing `if` with its `elsif` and `else`.
+fn block<'a>(
+    commands: impl Iterator<Item = &'a parse::Command>,
+    required: &[String],
+) -> Result<Vec<Command>> {
+    let mut out: Vec<Command> = Vec::new();
+    for command in commands {
+        let line = command.line;
+        let no_arguments = |name: &str| -> Result<()> {
+            if !command.arguments.is_empty() || !command.tests.is_empty() {
+                bail!("line {}: {} takes no arguments", line, name);
+            }
+            Ok(())
+        };
+        let inner = || -> Result<Vec<Command>> {
+            match &command.block {
+                Some(commands) => block(commands.iter(), required),
+                None => bail!("line {}: {} needs a {{ ... }} block", line, command.name),
+            }
+        };
+        if command.block.is_some() && !matches!(command.name.as_str(), "if" | "elsif" | "else") {
+            bail!("line {}: {} does not take a block", line, command.name);
+        }
+        let compiled = match command.name.as_str() {
+            "if" => Command::If {
+                branches: vec![(single_test(command, required)?, inner()?)],
+                otherwise: None,
+            },
+            "elsif" | "else" => {
+                let Some(Command::If {
+                    branches,
+                    otherwise,
+                }) = out.last_mut()
+                else {
+                    bail!("line {}: {} without if", line, command.name);
+                };
+                if otherwise.is_some() {
+                    bail!("line {}: {} after else", line, command.name);
+                }
+                if command.name == "elsif" {
+                    branches.push((single_test(command, required)?, inner()?));
+                } else {
+                    no_arguments("else")?;
+                    *otherwise = Some(inner()?);
+                }
+                continue;
+            }
+            "keep" => {
+                no_arguments("keep")?;
+                Command::Keep
+            }
+            "discard" => {
+                no_arguments("discard")?;
+                Command::Discard
+            }
+            "stop" => {
+                no_arguments("stop")?;
+                Command::Stop
+            }
+            "fileinto" => {
+                if !required.iter().any(|r| r == "fileinto") {
+                    bail!("line {}: fileinto needs require \"fileinto\"", line);
+                }
+                Command::FileInto(one_string(command, "a folder")?)
+            }
+            "redirect" => Command::Redirect(one_string(command, "an address")?),
+            "require" => bail!("line {}: require must come first", line),
+            other => bail!("line {}: unknown command {}", line, other),
+        };
+        out.push(compiled);
+    }
+    Ok(out)
+}
+
+fn single_test(command: &parse::Command, required: &[String]) -> Result<Test> {
+    if !command.arguments.is_empty() || command.tests.len() != 1 {
+        bail!("line {}: {} needs one test", command.line, command.name);
+    }
+    test(&command.tests[0], required)
+}
+
+fn one_string(command: &parse::Command, what: &str) -> Result<String> {
+    match (command.arguments.as_slice(), command.tests.is_empty()) {
+        ([Argument::Strings(list)], true) if list.len() == 1 => Ok(list[0].clone()),
+        _ => bail!("line {}: {} needs {}", command.line, command.name, what),
+    }
+}
+
+fn strings(arguments: &[Argument], line: usize, name: &str) -> Result<Vec<String>> {
+    match arguments {
+        [Argument::Strings(list)] => Ok(list.clone()),
+        _ => bail!("line {}: {} needs a string list", line, name),
+    }
+}
+
+fn test(test_node: &parse::Test, required: &[String]) -> Result<Test> {
+    let line = test_node.line;
+    let name = test_node.name.as_str();
+    let mut match_type = None;
+    let mut comparator = Comparator::AsciiCaseMap;
+    let mut part = None;
+    let mut size = None;
+    let mut positional = Vec::new();
+    let mut arguments = test_node.arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument {
+            Argument::Tag(tag) => match tag.as_str() {
+                "is" | "contains" | "matches" => {
+                    if match_type.is_some() {
+                        bail!("line {}: more than one match type", line);
+                    }
+                    match_type = Some(match tag.as_str() {
+                        "is" => MatchType::Is,
+                        "contains" => MatchType::Contains,
+                        _ => MatchType::Matches,
+                    });
+                }
+                "comparator" => {
+                    comparator = match arguments.next() {
+                        Some(Argument::Strings(list)) if list.len() == 1 => {
+                            match list[0].as_str() {
+                                "i;ascii-casemap" => Comparator::AsciiCaseMap,
+                                "i;octet" => Comparator::Octet,
+                                other => bail!("line {}: unsupported comparator {}", line, other),
+                            }
+                        }
+                        _ => bail!("line {}: :comparator needs a name", line),
+                    }
+                }
+                "all" | "localpart" | "domain" if matches!(name, "address" | "envelope") => {
+                    part = Some(match tag.as_str() {
+                        "all" => AddressPart::All,
+                        "localpart" => AddressPart::LocalPart,
+                        _ => AddressPart::Domain,
+                    });
+                }
+                "over" | "under" if name == "size" => {
+                    let limit = match arguments.next() {
+                        Some(Argument::Number(n)) => *n,
+                        _ => bail!("line {}: :{} needs a number", line, tag),
+                    };
+                    size = Some((tag == "over", limit));
+                }
+                other => bail!("line {}: unexpected :{} in {}", line, other, name),
+            },
+            Argument::Strings(list) => positional.push(list.clone()),
+            Argument::Number(_) => bail!("line {}: unexpected number in {}", line, name),
+        }
+    }
+    let matcher = Matcher {
+        match_type: match_type.unwrap_or(MatchType::Is),
+        comparator,
+    };
+    let two_lists = |what: &str| -> Result<(Vec<String>, Vec<String>)> {
+        match <[Vec<String>; 2]>::try_from(positional.clone()) {
+            Ok([first, second]) if test_node.tests.is_empty() => Ok((first, second)),
+            _ => bail!("line {}: {} needs {} and keys", line, name, what),
+        }
+    };
+    let tests = || -> Result<Vec<Test>> {
+        if !positional.is_empty() || test_node.tests.is_empty() {
+            bail!("line {}: {} needs a list of tests", line, name);
+        }
+        test_node
+            .tests
+            .iter()
+            .map(|inner| test(inner, required))
+            .collect()
+    };
+    let bare = |value: Test| -> Result<Test> {
+        if !positional.is_empty() || !test_node.tests.is_empty() {
+            bail!("line {}: {} takes no arguments", line, name);
+        }
+        Ok(value)
+    };
+
+    Ok(match name {
+        "header" => {
+            let (names, keys) = two_lists("header names")?;
+            Test::Header {
+                matcher,
+                names,
+                keys,
+            }
+        }
+        "address" => {
+            let (headers, keys) = two_lists("header names")?;
+            Test::Address {
+                matcher,
+                part: part.unwrap_or(AddressPart::All),
+                headers,
+                keys,
+            }
+        }
+        "envelope" => {
+            if !required.iter().any(|r| r == "envelope") {
+                bail!("line {}: envelope needs require \"envelope\"", line);
+            }
+            let (parts, keys) = two_lists("envelope parts")?;
+            Test::Envelope {
+                matcher,
+                part: part.unwrap_or(AddressPart::All),
+                parts,
+                keys,
+            }
+        }
+        "exists" => match positional.as_slice() {
+            [names] if test_node.tests.is_empty() => Test::Exists(names.clone()),
+            _ => bail!("line {}: exists needs header names", line),
+        },
+        "size" => match size {
+            Some((over, limit)) => bare(Test::Size { over, limit })?,
+            None => bail!("line {}: size needs :over or :under", line),
+        },
+        "allof" => Test::AllOf(tests()?),
+        "anyof" => Test::AnyOf(tests()?),
+        "not" => match test_node.tests.as_slice() {
+            [inner] if positional.is_empty() => Test::Not(Box::new(test(inner, required)?)),
+            _ => bail!("line {}: not needs one test", line),
+        },
+        "true" => bare(Test::True)?,
+        "false" => bare(Test::False)?,
+        other => bail!("line {}: unknown test {}", line, other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FileBackend;
+
+    fn email(headers: &str) -> EmailSummary {
+        let raw = format!("{}\r\n\r\nHello\r\n", headers.replace('\n', "\r\n"));
+        FileBackend::new("unused", "me@example.com").parse_message(raw.as_bytes())
+    }
+
+    fn list_mail() -> EmailSummary {
+        email(
+            "From: \"Dev List\" <dev-bounces@Lists.Example.org>\n\
+To: me@example.com, other@example.net\n\
+Subject: [dev] Release *candidate* 2?\n\
+List-Id: <dev.lists.example.org>\n\
+Return-Path: <bounce+123@lists.example.org>\n\
+Delivered-To: me+dev@example.com",
+        )
+    }
+
+    fn run(script: &str, email: &EmailSummary) -> Outcome {
+        Script::parse(script).unwrap().run(email)
+    }
+
+    fn error(script: &str) -> String {
+        format!("{:#}", Script::parse(script).unwrap_err())
+    }
+
+    fn matches(value: &str, pattern: &str) -> bool {
+        let value: Vec<char> = value.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        wildcard(&value, &pattern)
+    }
+
+    fn filed(folder: &str) -> Outcome {
+        Outcome {
+            fileinto: vec![folder.to_string()],
+            ..Outcome::default()
+        }
+    }
+
+    fn kept() -> Outcome {
+        Outcome {
+            keep: true,
+            ..Outcome::default()
+        }
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("", ""));
+        assert!(matches("", "*"));
+        assert!(matches("", "***"));
+        assert!(!matches("", "?"));
+        assert!(matches("abc", "a?c"));
+        assert!(!matches("abc", "a?"));
+        assert!(matches("abc", "*c"));
+        assert!(matches("abcbc", "a*bc"));
+        assert!(matches("abcbd", "a*b?"));
+        assert!(!matches("abcbc", "a*bd"));
+        assert!(matches("mississippi", "m*iss*ppi"));
+        assert!(matches("mississippi", "*s*s*s*s*"));
+        assert!(!matches("mississippi", "*s*s*s*s*s*"));
+        assert!(matches("ab", "a**b*"));
+    }
+
+    #[test]
+    fn backslashes_make_wildcards_literal() {
+        assert!(matches("a*c", r"a\*c"));
+        assert!(!matches("abc", r"a\*c"));
+        assert!(matches("why?", r"*\?"));
+        assert!(!matches("why!", r"*\?"));
+        assert!(matches(r"a\b", r"a\\b"));
+        assert!(matches("xy", r"\x\y"));
+        // A backslash at the end stands for itself
+        assert!(matches(r"end\", r"end\"));
+    }
+
+    #[test]
+    fn failing_patterns_stay_fast() {
+        // Backtracking over every `*` would take about 50^10 steps
+        let value: Vec<char> = "a".repeat(50).chars().collect();
+        let pattern: Vec<char> = format!("{}b", "*a".repeat(10)).chars().collect();
+        assert!(!wildcard(&value, &pattern));
+        let pattern: Vec<char> = "*a".repeat(50).chars().collect();
+        assert!(wildcard(&value, &pattern));
+    }
+
+    #[test]
+    fn match_types_and_comparators() {
+        let is = |comparator, value, key| {
+            Matcher {
+                match_type: MatchType::Is,
+                comparator,
+            }
+            .matches(value, key)
+        };
+        let contains = |comparator, value, key| {
+            Matcher {
+                match_type: MatchType::Contains,
+                comparator,
+            }
+            .matches(value, key)
+        };
+        assert!(is(Comparator::AsciiCaseMap, "Hello", "hELLO"));
+        assert!(!is(Comparator::Octet, "Hello", "hello"));
+        assert!(contains(Comparator::AsciiCaseMap, "Hello World", "o w"));
+        assert!(!contains(Comparator::Octet, "Hello World", "o w"));
+        // Only :matches treats these characters specially
+        assert!(contains(Comparator::Octet, "a*b?c", "*b?"));
+        assert!(!contains(Comparator::Octet, "axbyc", "*b?"));
+        assert!(contains(Comparator::Octet, r"C:\dir", r"\d"));
+    }
+
+    #[test]
+    fn escapes_in_scripts() {
+        let mail = list_mail();
+        // "\\*" in the script is a literal star for :matches
+        let script = |test: &str| {
+            format!(
+                "require \"fileinto\";\nif {} {{ fileinto \"Hit\"; }}\n",
+                test
+            )
+        };
+        assert_eq!(
+            run(
+                &script(r#"header :matches "subject" "*\\*candidate\\* 2\\?""#),
+                &mail
+            ),
+            filed("Hit")
+        );
+        assert_eq!(
+            run(
+                &script(r#"header :matches "subject" "*\\*candidate\\* 2\\*""#),
+                &mail
+            ),
+            kept()
+        );
+        // :contains compares the unescaped key literally
+        assert_eq!(
+            run(&script(r#"header :contains "subject" "\*cand""#), &mail),
+            filed("Hit")
+        );
+        assert_eq!(
+            run(&script(r#"header :contains "subject" "\"dev\"""#), &mail),
+            kept()
+        );
+        assert_eq!(
+            run(&script(r#"header :contains "from" "\"dev list\"""#), &mail),
+            filed("Hit")
+        );
+    }
+
+    #[test]
+    fn header_address_and_envelope_tests() {
+        let mail = list_mail();
+        let fires = |test: &str| {
+            let script = format!("require \"envelope\";\nif {} {{ discard; }}", test);
+            run(&script, &mail).discard
+        };
+        assert!(fires(
+            r#"header :is ["X-None", "List-Id"] "<DEV.lists.example.org>""#
+        ));
+        assert!(!fires(
+            r#"header :comparator "i;octet" :is "list-id" "<DEV.lists.example.org>""#
+        ));
+        assert!(fires(r#"address :domain "from" "lists.example.org""#));
+        assert!(fires(r#"address :localpart :matches "to" "oth*""#));
+        assert!(fires(r#"address "to" "other@example.net""#));
+        assert!(!fires(r#"address :all "from" "dev list""#));
+        assert!(fires(r#"envelope :localpart :matches "to" "me+*""#));
+        assert!(fires(r#"envelope :domain "from" "lists.example.org""#));
+        assert!(fires(r#"exists ["from", "list-id"]"#));
+        assert!(!fires(r#"exists ["from", "x-spam"]"#));
+        let size = mail.raw.len();
+        assert!(fires(&format!("size :over {}", size - 1)));
+        assert!(!fires(&format!("size :over {}", size)));
+        assert!(fires(&format!("size :under {}", size + 1)));
+        assert!(fires("size :under 1K"));
+        assert!(fires(
+            r#"allof (true, not false, anyof (false, exists "to"))"#
+        ));
+        assert!(!fires(r#"allof (true, false)"#));
+    }
+
+    #[test]
+    fn implicit_keep_and_stop() {
+        let mail = list_mail();
+        let script = r#"
+require ["fileinto"];
+# Lists first
+if header :contains "list-id" "dev." {
+    fileinto "Lists/dev";
+    fileinto "Lists/dev";
+} elsif true {
+    fileinto "Never";
+}
+if address :domain "from" "lists.example.org" {
+    keep;
+    stop;
+}
+discard;
+"#;
+        let outcome = run(script, &mail);
+        assert_eq!(
+            outcome,
+            Outcome {
+                keep: true,
+                fileinto: vec!["Lists/dev".to_string()],
+                ..Outcome::default()
+            }
+        );
+        // Kept as well as filed, so it stays in the inbox
+        assert_eq!(outcome.actions(), Actions::default());
+        assert_eq!(outcome.to_string(), "keep, fileinto \"Lists/dev\"");
+
+        let other = email("From: a@example.com\nSubject: Hi");
+        // The elsif branch files it, and discarding does not undo that
+        assert_eq!(
+            run(script, &other).actions().move_to.as_deref(),
+            Some("Never")
+        );
+        assert_eq!(run("", &other), kept());
+    }
+
+    #[test]
+    fn outcomes_become_actions() {
+        let outcome = run(
+            "require \"fileinto\"; fileinto \"A\"; fileinto \"B\"; discard;",
+            &list_mail(),
+        );
+        assert_eq!(outcome.actions().move_to.as_deref(), Some("A"));
+        assert!(!outcome.actions().delete);
+        // Redirecting is left to the server, so the message is kept here
+        let outcome = run("redirect \"a@example.com\"; discard;", &list_mail());
+        assert_eq!(outcome.actions(), Actions::default());
+        assert_eq!(
+            outcome.to_string(),
+            "redirect \"a@example.com\" (on the server only), discard"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_scripts() {
+        assert_eq!(
+            error("fileinto \"x\";"),
+            "line 1: fileinto needs require \"fileinto\""
+        );
+        assert_eq!(
+            error("if envelope \"from\" \"a@example.com\" { keep; }"),
+            "line 1: envelope needs require \"envelope\""
+        );
+        assert_eq!(
+            error("require \"fileinto\";\nif not envelope \"to\" \"me\" { keep; }"),
+            "line 2: envelope needs require \"envelope\""
+        );
+        assert_eq!(
+            error("keep;\nrequire \"fileinto\";"),
+            "line 2: require must come first"
+        );
+        assert_eq!(
+            error("require \"vacation\";"),
+            "line 1: unsupported extension \"vacation\""
+        );
+        assert_eq!(error("else { keep; }"), "line 1: else without if");
+        assert_eq!(
+            error("if true { keep; } else { keep; } elsif true { keep; }"),
+            "line 1: elsif after else"
+        );
+        assert_eq!(
+            error("if header :is :contains \"a\" \"b\" { keep; }"),
+            "line 1: more than one match type"
+        );
+        assert_eq!(
+            error("if header \"a\" { keep; }"),
+            "line 1: header needs header names and keys"
+        );
+        assert_eq!(
+            error("if header :comparator \"i;unicode\" \"a\" \"b\" { keep; }"),
+            "line 1: unsupported comparator i;unicode"
+        );
+        assert_eq!(
+            error("if size 10 { keep; }"),
+            "line 1: unexpected number in size"
+        );
+        assert_eq!(
+            error("keep { discard; }"),
+            "line 1: keep does not take a block"
+        );
+        assert_eq!(error("if true;"), "line 1: if needs a { ... } block");
+        assert_eq!(error("vacation \"x\";"), "line 1: unknown command vacation");
+        assert_eq!(error("if spam { keep; }"), "line 1: unknown test spam");
+    }
+}
@@ -0,0 +1,464 @@
+//! Sieve lexical structure and grammar (RFC 5228, section 2 and 8),
+//! parsed into generic commands and tests that `sieve` then checks.
+
+use anyhow::{Result, bail};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Tag(String),
+    Number(u64),
+    String(String),
+    /// One of `[ ] ( ) { } , ;`.
+    Punct(char),
+}
+
+/// A command or test argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Tag(String),
+    Number(u64),
+    /// A single string or a `[...]` list.
+    Strings(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub name: String,
+    pub line: usize,
+    pub arguments: Vec<Argument>,
+    pub tests: Vec<Test>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub name: String,
+    pub line: usize,
+    pub arguments: Vec<Argument>,
+    pub tests: Vec<Test>,
+    /// The `{ ... }` block, for control commands.
+    pub block: Option<Vec<Command>>,
+}
+
+/// Parses a script into its commands.
+pub fn parse(script: &str) -> Result<Vec<Command>> {
+    let tokens = lex(script)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let commands = parser.commands()?;
+    if let Some((token, line)) = parser.tokens.get(parser.pos) {
+        bail!("line {}: unexpected {}", line, describe(token));
+    }
+    Ok(commands)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Identifier(name) => format!("identifier {}", name),
+        Token::Tag(tag) => format!("tag :{}", tag),
+        Token::Number(n) => format!("number {}", n),
+        Token::String(_) => "string".to_string(),
+        Token::Punct(c) => format!("'{}'", c),
+    }
+}
+
+fn lex(script: &str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start_line = line;
+        match c {
+            '\n' => {
+                line += 1;
+                i += 1;
+            }
+            ' ' | '\t' | '\r' => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                loop {
+                    match chars.get(i) {
+                        None => bail!("line {}: unterminated comment", start_line),
+                        Some('*') if chars.get(i + 1) == Some(&'/') => {
+                            i += 2;
+                            break;
+                        }
+                        Some('\n') => line += 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            '"' => {
+                i += 1;
+                let mut text = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => bail!("line {}: unterminated string", start_line),
+                        Some('"') => break,
+                        Some('\\') => {
+                            // `\\` and `\"` are escapes; other pairs keep the character
+                            i += 1;
+                            if let Some(&next) = chars.get(i) {
+                                text.push(next);
+                            }
+                        }
+                        Some(&c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            text.push(c);
+                        }
+                    }
+                    i += 1;
+                }
+                i += 1;
+                // Strings keep CRLF line endings
+                tokens.push((Token::String(text.replace("\r\n", "\n")), start_line));
+            }
+            '[' | ']' | '(' | ')' | '{' | '}' | ',' | ';' => {
+                tokens.push((Token::Punct(c), line));
+                i += 1;
+            }
+            ':' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                if i == start {
+                    bail!("line {}: ':' without a tag name", line);
+                }
+                let tag: String = chars[start..i].iter().collect();
+                tokens.push((Token::Tag(tag.to_ascii_lowercase()), line));
+            }
+            '0'..='9' => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                let mut number: u64 = digits.parse()?;
+                let multiplier = match chars.get(i).map(|c| c.to_ascii_uppercase()) {
+                    Some('K') => 1 << 10,
+                    Some('M') => 1 << 20,
+                    Some('G') => 1 << 30,
+                    _ => 1,
+                };
+                if multiplier > 1 {
+                    i += 1;
+                }
+                number = number.saturating_mul(multiplier);
+                tokens.push((Token::Number(number), line));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if word.eq_ignore_ascii_case("text") && chars.get(i) == Some(&':') {
+                    // Multi-line string: up to a line holding only "."
+                    i += 1;
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                    i += 1;
+                    line += 1;
+                    let mut text = String::new();
+                    loop {
+                        if i >= chars.len() {
+                            bail!("line {}: unterminated text: block", start_line);
+                        }
+                        let end = (i..chars.len()).find(|&j| chars[j] == '\n');
+                        let next = end.map(|e| e + 1).unwrap_or(chars.len());
+                        let raw: String = chars[i..end.unwrap_or(chars.len())].iter().collect();
+                        let content = raw.trim_end_matches('\r');
+                        i = next;
+                        line += 1;
+                        if content == "." {
+                            break;
+                        }
+                        // Dot-stuffed lines lose their first dot
+                        let content = if content.starts_with("..") {
+                            &content[1..]
+                        } else {
+                            content
+                        };
+                        text.push_str(content);
+                        text.push('\n');
+                    }
+                    tokens.push((Token::String(text), start_line));
+                } else {
+                    tokens.push((Token::Identifier(word.to_ascii_lowercase()), line));
+                }
+            }
+            _ => bail!("line {}: unexpected character '{}'", line, c),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map(|(_, line)| *line)
+            .unwrap_or(1)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, punct: char) -> Result<()> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Punct(c)) if c == punct => Ok(()),
+            Some(token) => bail!(
+                "line {}: expected '{}', found {}",
+                line,
+                punct,
+                describe(&token)
+            ),
+            None => bail!(
+                "line {}: expected '{}' at the end of the script",
+                line,
+                punct
+            ),
+        }
+    }
+
+    fn commands(&mut self) -> Result<Vec<Command>> {
+        let mut commands = Vec::new();
+        while let Some(Token::Identifier(_)) = self.peek() {
+            commands.push(self.command()?);
+        }
+        Ok(commands)
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        let line = self.line();
+        let Some(Token::Identifier(name)) = self.next() else {
+            bail!("line {}: expected a command", line);
+        };
+        let (arguments, tests) = self.arguments()?;
+        let block = match self.peek() {
+            Some(Token::Punct(';')) => {
+                self.pos += 1;
+                None
+            }
+            Some(Token::Punct('{')) => {
+                self.pos += 1;
+                let block = self.commands()?;
+                self.expect('}')?;
+                Some(block)
+            }
+            Some(token) => bail!(
+                "line {}: expected ';' after {}, found {}",
+                self.line(),
+                name,
+                describe(token)
+            ),
+            None => bail!("line {}: missing ';' after {}", line, name),
+        };
+        Ok(Command {
+            name,
+            line,
+            arguments,
+            tests,
+            block,
+        })
+    }
+
+    /// Arguments, then an optional test or parenthesized test list.
+    fn arguments(&mut self) -> Result<(Vec<Argument>, Vec<Test>)> {
+        let mut arguments = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Tag(_)) | Some(Token::Number(_)) | Some(Token::String(_)) => {
+                    arguments.push(match self.next() {
+                        Some(Token::Tag(tag)) => Argument::Tag(tag),
+                        Some(Token::Number(n)) => Argument::Number(n),
+                        Some(Token::String(s)) => Argument::Strings(vec![s]),
+                        _ => unreachable!(),
+                    });
+                }
+                Some(Token::Punct('[')) => {
+                    self.pos += 1;
+                    arguments.push(Argument::Strings(self.string_list()?));
+                }
+                _ => break,
+            }
+        }
+        let tests = match self.peek() {
+            Some(Token::Identifier(_)) => vec![self.test()?],
+            Some(Token::Punct('(')) => {
+                self.pos += 1;
+                let mut tests = vec![self.test()?];
+                while self.peek() == Some(&Token::Punct(',')) {
+                    self.pos += 1;
+                    tests.push(self.test()?);
+                }
+                self.expect(')')?;
+                tests
+            }
+            _ => Vec::new(),
+        };
+        Ok((arguments, tests))
+    }
+
+    fn string_list(&mut self) -> Result<Vec<String>> {
+        let mut strings = Vec::new();
+        loop {
+            let line = self.line();
+            match self.next() {
+                Some(Token::String(s)) => strings.push(s),
+                _ => bail!("line {}: expected a string in the list", line),
+            }
+            match self.next() {
+                Some(Token::Punct(',')) => {}
+                Some(Token::Punct(']')) => return Ok(strings),
+                _ => bail!("line {}: expected ',' or ']' in the string list", line),
+            }
+        }
+    }
+
+    fn test(&mut self) -> Result<Test> {
+        let line = self.line();
+        let Some(Token::Identifier(name)) = self.next() else {
+            bail!("line {}: expected a test", line);
+        };
+        let (arguments, tests) = self.arguments()?;
+        Ok(Test {
+            name,
+            line,
+            arguments,
+            tests,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(script: &str) -> Vec<Token> {
+        lex(script)
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    fn string(text: &str) -> Token {
+        Token::String(text.to_string())
+    }
+
+    #[test]
+    fn quoted_string_escapes() {
+        assert_eq!(
+            tokens(r#""a\"b" "c\\d" "\*e\x""#),
+            vec![string("a\"b"), string(r"c\d"), string("*ex")]
+        );
+        assert_eq!(tokens(r#""\\*""#), vec![string(r"\*")]);
+    }
+
+    #[test]
+    fn multi_line_strings_are_dot_unstuffed() {
+        assert_eq!(
+            tokens("text: # comment\r\nline one\r\n..dot\r\n.\r\n;"),
+            vec![string("line one\n.dot\n"), Token::Punct(';')]
+        );
+    }
+
+    #[test]
+    fn numbers_tags_and_comments() {
+        assert_eq!(
+            tokens("size :OVER 10k # 10K\n/* a\n comment */ 2M 3"),
+            vec![
+                Token::Identifier("size".to_string()),
+                Token::Tag("over".to_string()),
+                Token::Number(10 << 10),
+                Token::Number(2 << 20),
+                Token::Number(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_lines() {
+        let lines: Vec<usize> = lex("keep;\n\"two\nlines\" /* x\ny */\nstop;")
+            .unwrap()
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect();
+        assert_eq!(lines, vec![1, 1, 2, 5, 5]);
+    }
+
+    #[test]
+    fn parses_commands_tests_and_blocks() {
+        let commands = parse(
+            r#"if anyof (header :contains ["to", "cc"] "me", not exists "x") {
+    fileinto "a";
+} else { stop; }"#,
+        )
+        .unwrap();
+        assert_eq!(commands.len(), 2);
+        let if_command = &commands[0];
+        assert_eq!(if_command.name, "if");
+        let anyof = &if_command.tests[0];
+        assert_eq!(anyof.name, "anyof");
+        assert_eq!(anyof.tests.len(), 2);
+        assert_eq!(
+            anyof.tests[0].arguments,
+            vec![
+                Argument::Tag("contains".to_string()),
+                Argument::Strings(vec!["to".to_string(), "cc".to_string()]),
+                Argument::Strings(vec!["me".to_string()]),
+            ]
+        );
+        assert_eq!(anyof.tests[1].tests[0].name, "exists");
+        let block = if_command.block.as_ref().unwrap();
+        assert_eq!(block[0].name, "fileinto");
+        assert_eq!(commands[1].name, "else");
+        assert_eq!(commands[1].line, 3);
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_lines() {
+        let error = |script: &str| parse(script).unwrap_err().to_string();
+        assert_eq!(error("keep"), "line 1: missing ';' after keep");
+        assert_eq!(error("keep;\n\"x"), "line 2: unterminated string");
+        assert_eq!(error("/* open"), "line 1: unterminated comment");
+        assert_eq!(
+            error("if true {\nkeep;"),
+            "line 2: expected '}' at the end of the script"
+        );
+        assert_eq!(error("keep;\n}"), "line 2: unexpected '}'");
+        assert_eq!(
+            error("x [\"a\" \"b\"];"),
+            "line 1: expected ',' or ']' in the string list"
+        );
+        assert_eq!(error("keep @;"), "line 1: unexpected character '@'");
+        assert_eq!(error("keep : ;"), "line 1: ':' without a tag name");
+    }
+}
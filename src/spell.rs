@@ -0,0 +1,690 @@
+//! Offline spell checking with Hunspell-format dictionaries (`.aff` and
+//! `.dic` files).
+//!
+//! Only the parts of the format needed to check ordinary prose are
+//! supported: single-level prefixes and suffixes (with cross products),
+//! `FLAG` types, `TRY` and `REP` for suggestions, and the `FORBIDDENWORD`
+//! and `NEEDAFFIX` flags. Compounding and morphology are ignored.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config;
+
+/// Most suggestions offered for one word.
+const MAX_SUGGESTIONS: usize = 10;
+
+/// Characters tried in suggestions when the affix file has no `TRY`.
+const DEFAULT_TRY: &str = "esianrtolcdugmphbyfvkwzxjq'";
+
+type Flag = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagType {
+    /// One character per flag (the default).
+    Char,
+    /// Two characters per flag.
+    Long,
+    /// Comma-separated decimal numbers.
+    Num,
+}
+
+impl FlagType {
+    fn parse(self, flags: &str) -> Vec<Flag> {
+        match self {
+            FlagType::Char => flags.chars().map(|c| c as Flag).collect(),
+            FlagType::Long => flags
+                .chars()
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|pair| pair.iter().fold(0, |acc, &c| (acc << 16) | c as Flag))
+                .collect(),
+            FlagType::Num => flags
+                .split(',')
+                .filter_map(|n| n.trim().parse().ok())
+                .collect(),
+        }
+    }
+}
+
+/// One element of an affix condition: `.`, a character, or a `[...]` or
+/// `[^...]` class.
+#[derive(Debug, Clone)]
+enum CharMatch {
+    Any,
+    Set { chars: Vec<char>, negated: bool },
+}
+
+impl CharMatch {
+    fn matches(&self, ch: char) -> bool {
+        match self {
+            CharMatch::Any => true,
+            CharMatch::Set { chars, negated } => chars.contains(&ch) != *negated,
+        }
+    }
+}
+
+fn parse_condition(condition: &str) -> Vec<CharMatch> {
+    let mut out = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '.' => out.push(CharMatch::Any),
+            '[' => {
+                let mut set = Vec::new();
+                let mut negated = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => break,
+                        '^' if set.is_empty() && !negated => negated = true,
+                        c => set.push(c),
+                    }
+                }
+                out.push(CharMatch::Set {
+                    chars: set,
+                    negated,
+                });
+            }
+            c => out.push(CharMatch::Set {
+                chars: vec![c],
+                negated: false,
+            }),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone)]
+struct Affix {
+    flag: Flag,
+    /// Whether the affix combines with affixes of the other kind.
+    cross: bool,
+    /// Removed from the root before `add` is attached.
+    strip: String,
+    add: String,
+    /// Checked against the start (prefixes) or end (suffixes) of the root.
+    condition: Vec<CharMatch>,
+}
+
+impl Affix {
+    fn prefix_root(&self, word: &str) -> Option<String> {
+        let rest = word.strip_prefix(self.add.as_str())?;
+        if rest.is_empty() {
+            return None;
+        }
+        let root = format!("{}{}", self.strip, rest);
+        let chars: Vec<char> = root.chars().collect();
+        let fits = chars.len() >= self.condition.len()
+            && self
+                .condition
+                .iter()
+                .zip(&chars)
+                .all(|(m, &c)| m.matches(c));
+        fits.then_some(root)
+    }
+
+    fn suffix_root(&self, word: &str) -> Option<String> {
+        let rest = word.strip_suffix(self.add.as_str())?;
+        if rest.is_empty() {
+            return None;
+        }
+        let root = format!("{}{}", rest, self.strip);
+        let chars: Vec<char> = root.chars().collect();
+        let fits = chars.len() >= self.condition.len()
+            && self
+                .condition
+                .iter()
+                .rev()
+                .zip(chars.iter().rev())
+                .all(|(m, &c)| m.matches(c));
+        fits.then_some(root)
+    }
+}
+
+/// A loaded Hunspell dictionary.
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    /// Root words and the union of their flags.
+    words: HashMap<String, HashSet<Flag>>,
+    prefixes: Vec<Affix>,
+    suffixes: Vec<Affix>,
+    try_chars: Vec<char>,
+    /// `REP` pairs: common misspellings and their corrections.
+    replacements: Vec<(String, String)>,
+    forbidden: Option<Flag>,
+    need_affix: Option<Flag>,
+}
+
+impl Dictionary {
+    /// Loads a dictionary by name (`en_US`, looked up in the usual Hunspell
+    /// directories) or by path, with or without the `.dic` extension.
+    pub fn find(name: &str) -> Result<Self> {
+        let (aff, dic) = locate(name)
+            .with_context(|| format!("no Hunspell dictionary named {:?} found", name))?;
+        Self::load(&aff, &dic)
+    }
+
+    pub fn load(aff: &Path, dic: &Path) -> Result<Self> {
+        let aff_text = read_text(aff, None)?;
+        let mut dictionary = Dictionary::default();
+        let encoding = dictionary.parse_aff(&aff_text);
+        let flag_type = flag_type(&aff_text);
+        let dic_text = read_text(dic, Some(&encoding))?;
+        dictionary.parse_dic(&dic_text, flag_type);
+        Ok(dictionary)
+    }
+
+    /// Reads the affix file, returning its `SET` encoding.
+    fn parse_aff(&mut self, text: &str) -> String {
+        let flag_type = flag_type(text);
+        let mut encoding = "UTF-8".to_string();
+        // Cross-product setting of each affix group, from its header line
+        let mut cross_product: HashMap<(String, Flag), bool> = HashMap::new();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let Some(&keyword) = fields.first() else {
+                continue;
+            };
+            match keyword {
+                "SET" if fields.len() > 1 => encoding = fields[1].to_string(),
+                "TRY" if fields.len() > 1 => self.try_chars = fields[1].chars().collect(),
+                "REP" if fields.len() > 2 => self
+                    .replacements
+                    .push((fields[1].replace('_', " "), fields[2].replace('_', " "))),
+                "FORBIDDENWORD" if fields.len() > 1 => {
+                    self.forbidden = flag_type.parse(fields[1]).first().copied()
+                }
+                "NEEDAFFIX" if fields.len() > 1 => {
+                    self.need_affix = flag_type.parse(fields[1]).first().copied()
+                }
+                "PFX" | "SFX" if fields.len() >= 4 => {
+                    let Some(&flag) = flag_type.parse(fields[1]).first() else {
+                        continue;
+                    };
+                    // Group header: `SFX flag Y|N count`
+                    if fields.len() == 4
+                        && matches!(fields[2], "Y" | "N")
+                        && fields[3].parse::<usize>().is_ok()
+                    {
+                        cross_product.insert((keyword.to_string(), flag), fields[2] == "Y");
+                        continue;
+                    }
+                    let cross = cross_product
+                        .get(&(keyword.to_string(), flag))
+                        .copied()
+                        .unwrap_or(false);
+                    let zero = |s: &str| {
+                        if s == "0" {
+                            String::new()
+                        } else {
+                            s.to_string()
+                        }
+                    };
+                    // Continuation flags after `/` are not supported
+                    let add = fields[3].split('/').next().unwrap_or_default();
+                    let affix = Affix {
+                        flag,
+                        cross,
+                        strip: zero(fields[2]),
+                        add: zero(add),
+                        condition: parse_condition(fields.get(4).copied().unwrap_or(".")),
+                    };
+                    if keyword == "PFX" {
+                        self.prefixes.push(affix);
+                    } else {
+                        self.suffixes.push(affix);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.try_chars.is_empty() {
+            self.try_chars = DEFAULT_TRY.chars().collect();
+        }
+        encoding
+    }
+
+    fn parse_dic(&mut self, text: &str, flag_type: FlagType) {
+        // The first line is the approximate word count
+        for line in text.lines().skip(1) {
+            // Morphological fields follow a tab or space
+            let entry = line.split(['\t', ' ']).next().unwrap_or_default();
+            if entry.is_empty() {
+                continue;
+            }
+            let (word, flags) = match entry.split_once('/') {
+                Some((word, flags)) => (word, flag_type.parse(flags)),
+                None => (entry, Vec::new()),
+            };
+            self.words
+                .entry(word.to_string())
+                .or_default()
+                .extend(flags);
+        }
+    }
+
+    /// Whether a word is spelled correctly. Capitalised and all-caps forms
+    /// of lowercase dictionary words are accepted.
+    pub fn check(&self, word: &str) -> bool {
+        let word = word.replace('’', "'");
+        if self.check_exact(&word) {
+            return true;
+        }
+        let mut chars = word.chars();
+        let Some(first) = chars.next() else {
+            return true;
+        };
+        let rest: String = chars.collect();
+        if first.is_uppercase() && rest.chars().all(|c| !c.is_uppercase()) {
+            // "Hello" may be "hello"
+            return self.check_exact(&format!("{}{}", first.to_lowercase(), rest));
+        }
+        if word.chars().all(|c| !c.is_lowercase()) {
+            // "HELLO" may be "hello" or "Hello"
+            let lower = word.to_lowercase();
+            let mut lower_chars = lower.chars();
+            let capitalised = lower_chars
+                .next()
+                .map(|c| format!("{}{}", c.to_uppercase(), lower_chars.as_str()))
+                .unwrap_or_default();
+            return self.check_exact(&lower) || self.check_exact(&capitalised);
+        }
+        false
+    }
+
+    fn check_exact(&self, word: &str) -> bool {
+        if let Some(flags) = self.words.get(word) {
+            if self.forbidden.is_some_and(|f| flags.contains(&f)) {
+                return false;
+            }
+            if !self.need_affix.is_some_and(|f| flags.contains(&f)) {
+                return true;
+            }
+        }
+        self.check_suffixed(word, None) || self.check_prefixed(word)
+    }
+
+    /// Strips one suffix and looks the root up. With `prefix` set, the root
+    /// must also carry that prefix's flag (a cross product).
+    fn check_suffixed(&self, word: &str, prefix: Option<Flag>) -> bool {
+        self.suffixes.iter().any(|suffix| {
+            if prefix.is_some() && !suffix.cross {
+                return false;
+            }
+            suffix
+                .suffix_root(word)
+                .is_some_and(|root| self.has_flags(&root, suffix.flag, prefix))
+        })
+    }
+
+    fn check_prefixed(&self, word: &str) -> bool {
+        self.prefixes.iter().any(|prefix| {
+            prefix.prefix_root(word).is_some_and(|root| {
+                self.has_flags(&root, prefix.flag, None)
+                    || (prefix.cross && self.check_suffixed(&root, Some(prefix.flag)))
+            })
+        })
+    }
+
+    fn has_flags(&self, root: &str, flag: Flag, other: Option<Flag>) -> bool {
+        self.words.get(root).is_some_and(|flags| {
+            flags.contains(&flag)
+                && other.is_none_or(|f| flags.contains(&f))
+                && !self.forbidden.is_some_and(|f| flags.contains(&f))
+        })
+    }
+
+    /// Correctly spelled words close to a misspelled one: `REP`
+    /// replacements first, then single edits, then splitting in two.
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        let push = |candidate: String, out: &mut Vec<String>| {
+            if out.len() < MAX_SUGGESTIONS
+                && candidate != word
+                && !out.contains(&candidate)
+                && candidate.split(' ').all(|part| self.check(part))
+            {
+                out.push(candidate);
+            }
+        };
+
+        for (from, to) in &self.replacements {
+            for (i, _) in word.match_indices(from.as_str()) {
+                push(
+                    format!("{}{}{}", &word[..i], to, &word[i + from.len()..]),
+                    &mut out,
+                );
+            }
+        }
+
+        let chars: Vec<char> = word.chars().collect();
+        let join = |chars: &[char]| chars.iter().collect::<String>();
+        // Swapped neighbours
+        for i in 0..chars.len().saturating_sub(1) {
+            let mut edit = chars.clone();
+            edit.swap(i, i + 1);
+            push(join(&edit), &mut out);
+        }
+        // One character replaced, in `TRY` order
+        for &c in &self.try_chars {
+            for i in 0..chars.len() {
+                if chars[i] != c {
+                    let mut edit = chars.clone();
+                    edit[i] = c;
+                    push(join(&edit), &mut out);
+                }
+            }
+        }
+        // One character dropped
+        for i in 0..chars.len() {
+            let mut edit = chars.clone();
+            edit.remove(i);
+            push(join(&edit), &mut out);
+        }
+        // One character added
+        for &c in &self.try_chars {
+            for i in 0..=chars.len() {
+                let mut edit = chars.clone();
+                edit.insert(i, c);
+                push(join(&edit), &mut out);
+            }
+        }
+        // Two words run together
+        for i in 1..chars.len() {
+            push(
+                format!("{} {}", join(&chars[..i]), join(&chars[i..])),
+                &mut out,
+            );
+        }
+        out
+    }
+
+    /// Byte ranges of the misspelled words in some text.
+    pub fn misspellings(&self, text: &str) -> Vec<Range<usize>> {
+        words(text)
+            .into_iter()
+            .filter(|range| !self.check(&text[range.clone()]))
+            .collect()
+    }
+}
+
+fn flag_type(aff: &str) -> FlagType {
+    aff.lines()
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next() == Some("FLAG")).then(|| match fields.next() {
+                Some("long") => FlagType::Long,
+                Some("num") => FlagType::Num,
+                _ => FlagType::Char,
+            })
+        })
+        .unwrap_or(FlagType::Char)
+}
+
+/// Reads a dictionary file in its declared encoding. UTF-8 and ISO 8859-1
+/// are supported; the affix file is read before its `SET` line is known,
+/// which is fine since keywords are ASCII.
+fn read_text(path: &Path, encoding: Option<&str>) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    match encoding.map(|e| e.to_ascii_uppercase()) {
+        None => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Some(e) if e == "UTF-8" || e == "UTF8" => String::from_utf8(bytes)
+            .with_context(|| format!("{} is not valid UTF-8", path.display())),
+        Some(e) if e == "ISO8859-1" || e == "ISO-8859-1" => {
+            Ok(bytes.iter().map(|&b| b as char).collect())
+        }
+        Some(e) => bail!("{}: unsupported encoding {}", path.display(), e),
+    }
+}
+
+/// Finds the `.aff` and `.dic` files for a dictionary name or path.
+fn locate(name: &str) -> Option<(PathBuf, PathBuf)> {
+    let name = name.strip_suffix(".dic").unwrap_or(name);
+    let candidates: Vec<PathBuf> = if name.contains('/') {
+        vec![config::expand_path(name)]
+    } else {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        if let Some(data) = config::data_dir() {
+            dirs.push(data.join("dictionaries"));
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            dirs.push(PathBuf::from(&home).join(".local/share/hunspell"));
+            dirs.push(PathBuf::from(&home).join("Library/Spelling"));
+        }
+        for dir in [
+            "/usr/share/hunspell",
+            "/usr/share/myspell",
+            "/usr/share/myspell/dicts",
+            "/usr/local/share/hunspell",
+            "/opt/homebrew/share/hunspell",
+        ] {
+            dirs.push(PathBuf::from(dir));
+        }
+        dirs.into_iter().map(|dir| dir.join(name)).collect()
+    };
+    candidates.into_iter().find_map(|base| {
+        let aff = base.with_extension("aff");
+        let dic = base.with_extension("dic");
+        (aff.is_file() && dic.is_file()).then_some((aff, dic))
+    })
+}
+
+/// Byte ranges of the words worth checking in a message body. Quoted lines,
+/// the signature, and anything that looks like an address, URL or
+/// identifier (containing `@`, `://`, digits or underscores) are skipped.
+pub fn words(text: &str) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let start = offset;
+        offset += line.len() + 1;
+        if line == "-- " {
+            break;
+        }
+        if line.starts_with('>') {
+            continue;
+        }
+        let mut chunk_start = None;
+        for (i, ch) in line.char_indices().chain([(line.len(), ' ')]) {
+            match (ch.is_whitespace(), chunk_start) {
+                (false, None) => chunk_start = Some(i),
+                (true, Some(from)) => {
+                    chunk_words(&line[from..i], start + from, &mut out);
+                    chunk_start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    out
+}
+
+/// Splits a whitespace-delimited chunk into words: letters with apostrophes
+/// allowed between them.
+fn chunk_words(chunk: &str, offset: usize, out: &mut Vec<Range<usize>>) {
+    if chunk.contains('@') || chunk.contains("://") || chunk.starts_with("www.") {
+        return;
+    }
+    let chars: Vec<(usize, char)> = chunk.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].1.is_alphabetic() {
+            i += 1;
+            continue;
+        }
+        let begin = i;
+        while i < chars.len()
+            && (chars[i].1.is_alphabetic()
+                || (matches!(chars[i].1, '\'' | '’')
+                    && chars.get(i + 1).is_some_and(|(_, c)| c.is_alphabetic())))
+        {
+            i += 1;
+        }
+        let touches = |index: Option<usize>| {
+            index
+                .and_then(|index| chars.get(index))
+                .is_some_and(|(_, c)| c.is_ascii_digit() || *c == '_')
+        };
+        if !touches(begin.checked_sub(1)) && !touches(Some(i)) {
+            let end = chars.get(i).map_or(chunk.len(), |(index, _)| *index);
+            out.push(offset + chars[begin].0..offset + end);
+        }
+    }
+}
+
+/// The word around a position, for `z=`.
+pub fn word_at(text: &str, pos: usize) -> Option<Range<usize>> {
+    words(text)
+        .into_iter()
+        .find(|range| range.start <= pos && pos < range.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFF: &str = "SET UTF-8
+TRY esianrtolcdugmphbyfvkwzxjq
+REP 2
+REP f ph
+REP alot a_lot
+FORBIDDENWORD !
+NEEDAFFIX X
+PFX U Y 1
+PFX U 0 un .
+SFX S Y 2
+SFX S 0 s [^sxy]
+SFX S y ies [^aeiou]y
+SFX D N 1
+SFX D 0 ed .
+";
+
+    const DIC: &str = "11
+hello
+the
+phone
+a
+lot
+don't
+do/U
+kind/US
+fly/S
+walk/D
+foo/X
+foo/S
+bad/!
+";
+
+    fn dictionary(aff: &str, dic: &str) -> Dictionary {
+        let mut dictionary = Dictionary::default();
+        dictionary.parse_aff(aff);
+        dictionary.parse_dic(dic, flag_type(aff));
+        dictionary
+    }
+
+    #[test]
+    fn accepts_capitalised_forms_of_lowercase_words() {
+        let dictionary = dictionary(AFF, DIC);
+        assert!(dictionary.check("hello"));
+        assert!(dictionary.check("Hello"));
+        assert!(dictionary.check("HELLO"));
+        assert!(!dictionary.check("hELLO"));
+        assert!(!dictionary.check("helo"));
+        // Typographic apostrophes match plain ones
+        assert!(dictionary.check("don’t"));
+    }
+
+    #[test]
+    fn applies_suffixes_with_conditions_and_strip() {
+        let dictionary = dictionary(AFF, DIC);
+        assert!(dictionary.check("kinds"));
+        assert!(dictionary.check("flies"));
+        // `s` does not apply after a `y`, and `ies` needs a consonant first
+        assert!(!dictionary.check("flys"));
+        assert!(dictionary.check("walked"));
+        assert!(!dictionary.check("kinded"));
+    }
+
+    #[test]
+    fn applies_prefixes_and_cross_products() {
+        let dictionary = dictionary(AFF, DIC);
+        assert!(dictionary.check("undo"));
+        assert!(dictionary.check("unkind"));
+        assert!(dictionary.check("unkinds"));
+        assert!(!dictionary.check("unwalk"));
+        assert!(!dictionary.check("unwalked"));
+        // The affix alone is not a word
+        assert!(!dictionary.check("un"));
+    }
+
+    #[test]
+    fn honours_needaffix_and_forbidden_flags() {
+        let dictionary = dictionary(AFF, DIC);
+        assert!(!dictionary.check("foo"));
+        assert!(dictionary.check("foos"));
+        assert!(!dictionary.check("bad"));
+    }
+
+    #[test]
+    fn reads_long_and_numeric_flags() {
+        let long = dictionary("FLAG long\nSFX Aa Y 1\nSFX Aa 0 s .\n", "1\ncat/AaBb\n");
+        assert!(long.check("cats"));
+        let numeric = dictionary("FLAG num\nSFX 101 Y 1\nSFX 101 0 s .\n", "1\ndog/7,101\n");
+        assert!(numeric.check("dogs"));
+        assert!(!numeric.check("dogss"));
+    }
+
+    #[test]
+    fn suggests_replacements_edits_and_splits() {
+        let dictionary = dictionary(AFF, DIC);
+        assert_eq!(
+            dictionary.suggest("teh").first().map(String::as_str),
+            Some("the")
+        );
+        assert!(dictionary.suggest("helo").contains(&"hello".to_string()));
+        // `REP` pairs come first
+        assert_eq!(
+            dictionary.suggest("fone").first().map(String::as_str),
+            Some("phone")
+        );
+        assert_eq!(
+            dictionary.suggest("alot").first().map(String::as_str),
+            Some("a lot")
+        );
+        assert!(dictionary.suggest("zzzzzz").is_empty());
+    }
+
+    #[test]
+    fn finds_words_worth_checking() {
+        let text = "Hi thére, it's\n> quoted txt\nmail me@example.com or https://x.io v2 foo_bar\n-- \nsig";
+        let found: Vec<&str> = words(text).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(found, vec!["Hi", "thére", "it's", "mail", "or"]);
+        assert_eq!(word_at(text, 5).map(|r| &text[r]), Some("thére"));
+        assert_eq!(word_at(text, 2), None);
+    }
+
+    #[test]
+    fn reports_misspelled_ranges() {
+        let dictionary = dictionary(AFF, DIC);
+        let text = "hello wrld, the flys";
+        let ranges = dictionary.misspellings(text);
+        assert_eq!(ranges, vec![6..10, 16..20]);
+    }
+
+    #[test]
+    fn loads_latin1_dictionaries() {
+        let dir = std::env::temp_dir().join(format!("hermes-spell-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let aff = dir.join("fr.aff");
+        let dic = dir.join("fr.dic");
+        fs::write(&aff, "SET ISO8859-1\n").unwrap();
+        fs::write(&dic, b"1\ncaf\xe9\n").unwrap();
+        let dictionary = Dictionary::load(&aff, &dic);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(dictionary.unwrap().check("café"));
+    }
+}
@@ -0,0 +1,291 @@
+//! Background sync: mail is fetched on a timer on a tokio runtime, and
+//! watched IMAP folders are kept open with IDLE, so the interface keeps
+//! responding while messages are read, checked and filtered. The results
+//! are handed to the TUI loop as they come in.
+
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use tokio::{
+    runtime::Runtime,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time::MissedTickBehavior,
+};
+
+use crate::backend::EmailSummary;
+use crate::config::{self, SyncConfig};
+use crate::imap::{self, watch::Update};
+use crate::net::Server;
+use crate::rules::Applied;
+
+/// What a background sync reports.
+pub enum SyncEvent {
+    Synced {
+        /// The fetched inbox, after the filtering rules ran.
+        applied: Applied,
+        /// Messages fetched for the first time that stayed in the inbox.
+        arrived: usize,
+    },
+    Failed(String),
+    /// A change pushed by the server to a watched IMAP folder.
+    Mailbox(Update),
+}
+
+/// A running background sync; stopped when dropped.
+pub struct BackgroundSync {
+    runtime: Option<Runtime>,
+    sender: UnboundedSender<SyncEvent>,
+    events: UnboundedReceiver<SyncEvent>,
+}
+
+impl BackgroundSync {
+    pub fn new() -> Result<BackgroundSync> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("hermes-sync")
+            .enable_all()
+            .build()?;
+        let (sender, events) = mpsc::unbounded_channel();
+        Ok(BackgroundSync {
+            runtime: Some(runtime),
+            sender,
+            events,
+        })
+    }
+
+    /// Calls `fetch` every `config.interval` seconds, starting one interval
+    /// from now, as the inbox was just fetched. `fetch` runs on a thread of
+    /// its own; it should use the interface's backend through a `Mutex`, so
+    /// the two never change the same messages at once.
+    pub fn poll(
+        &self,
+        config: &SyncConfig,
+        fetch: impl Fn() -> Result<Applied> + Send + Sync + 'static,
+    ) {
+        let interval = Duration::from_secs(config.interval.max(1));
+        self.poll_every(interval, config.notify_command.clone(), fetch);
+    }
+
+    fn poll_every(
+        &self,
+        interval: Duration,
+        notify_command: Option<String>,
+        fetch: impl Fn() -> Result<Applied> + Send + Sync + 'static,
+    ) {
+        let sender = self.sender.clone();
+        if let Some(runtime) = &self.runtime {
+            runtime.spawn(run(interval, notify_command, Arc::new(fetch), sender));
+        }
+    }
+
+    /// Keeps an IMAP folder open with IDLE on a thread of its own,
+    /// reporting its messages and every change to them.
+    pub fn watch(
+        &self,
+        server: Server,
+        folder: String,
+        idle_timeout: Duration,
+        parse: impl Fn(&[u8]) -> EmailSummary + Send + 'static,
+    ) {
+        self.spawn_watch(move |send| {
+            imap::watch::watch(&server, &folder, idle_timeout, parse, send)
+        });
+    }
+
+    /// Runs `watch` on a thread of its own, passing it a function that
+    /// reports an update and returns false once the interface has gone.
+    fn spawn_watch(&self, watch: impl FnOnce(&mut dyn FnMut(Update) -> bool) + Send + 'static) {
+        let sender = self.sender.clone();
+        if let Some(runtime) = &self.runtime {
+            runtime.spawn_blocking(move || {
+                watch(&mut |update| sender.send(SyncEvent::Mailbox(update)).is_ok())
+            });
+        }
+    }
+
+    /// The next event, if one is waiting.
+    pub fn try_recv(&mut self) -> Option<SyncEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for BackgroundSync {
+    fn drop(&mut self) {
+        // Quit without waiting for a sync in progress
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+async fn run(
+    interval: Duration,
+    notify_command: Option<String>,
+    fetch: Arc<dyn Fn() -> Result<Applied> + Send + Sync>,
+    sender: UnboundedSender<SyncEvent>,
+) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    // A slow sync delays the next one rather than starting a burst
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let fetch = fetch.clone();
+        let mut events = Vec::new();
+        match tokio::task::spawn_blocking(move || fetch()).await {
+            Ok(Ok(applied)) => {
+                let new: Vec<&EmailSummary> =
+                    applied.inbox.iter().filter(|email| email.recent).collect();
+                let notified = match &notify_command {
+                    Some(command) if !new.is_empty() => notify(command, &new).await,
+                    _ => Ok(()),
+                };
+                let arrived = new.len();
+                events.push(SyncEvent::Synced { applied, arrived });
+                if let Err(e) = notified {
+                    events.push(SyncEvent::Failed(format!("notify command: {:#}", e)));
+                }
+            }
+            Ok(Err(e)) => events.push(SyncEvent::Failed(format!("{:#}", e))),
+            Err(e) => events.push(SyncEvent::Failed(e.to_string())),
+        }
+        for event in events {
+            // The interface has gone away
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Runs the notify command with a summary of the new messages.
+async fn notify(command: &str, new: &[&EmailSummary]) -> Result<()> {
+    let summary = match new {
+        [email] => format!("{}: {}", email.from, email.subject),
+        _ => format!("{} new messages", new.len()),
+    };
+    let Some((program, args)) = config::command_line(command, &summary) else {
+        return Ok(());
+    };
+    let status = tokio::process::Command::new(&program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc as std_mpsc,
+    };
+
+    use super::*;
+    use crate::backend::FileBackend;
+
+    /// Waits up to a second for the next event.
+    fn next(sync: &mut BackgroundSync) -> SyncEvent {
+        for _ in 0..200 {
+            if let Some(event) = sync.try_recv() {
+                return event;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("no sync event arrived");
+    }
+
+    fn email(subject: &str) -> EmailSummary {
+        let raw = format!(
+            "From: bob@example.org\r\nSubject: {}\r\n\r\nHi\r\n",
+            subject
+        );
+        FileBackend::new("unused", "me@example.com").parse_message(raw.as_bytes())
+    }
+
+    fn expunged(uid: u32) -> Update {
+        Update::Expunged {
+            folder: "INBOX".to_string(),
+            uid,
+        }
+    }
+
+    #[test]
+    fn reports_fetched_mail_and_failures() {
+        let mut sync = BackgroundSync::new().unwrap();
+        let calls = AtomicUsize::new(0);
+        sync.poll_every(Duration::from_millis(10), None, move || {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                let mut new = email("New");
+                new.recent = true;
+                Ok(Applied {
+                    inbox: vec![new, email("Seen")],
+                    ..Applied::default()
+                })
+            } else {
+                anyhow::bail!("connection refused")
+            }
+        });
+        match next(&mut sync) {
+            SyncEvent::Synced { applied, arrived } => {
+                assert_eq!(applied.inbox.len(), 2);
+                assert_eq!(arrived, 1);
+            }
+            _ => panic!("expected the fetched inbox first"),
+        }
+        match next(&mut sync) {
+            SyncEvent::Failed(error) => assert_eq!(error, "connection refused"),
+            _ => panic!("expected the failed fetch"),
+        }
+    }
+
+    #[test]
+    fn reports_mailbox_updates() {
+        let mut sync = BackgroundSync::new().unwrap();
+        sync.spawn_watch(|send| {
+            send(expunged(1));
+            send(expunged(2));
+        });
+        for expected in [1, 2] {
+            match next(&mut sync) {
+                SyncEvent::Mailbox(Update::Expunged { uid, .. }) => assert_eq!(uid, expected),
+                _ => panic!("expected expunge {}", expected),
+            }
+        }
+    }
+
+    #[test]
+    fn dropping_stops_the_workers() {
+        let mut sync = BackgroundSync::new().unwrap();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counted = fetches.clone();
+        sync.poll_every(Duration::from_millis(5), None, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(Applied::default())
+        });
+        let (stopped, watcher_stopped) = std_mpsc::channel();
+        sync.spawn_watch(move |send| {
+            while send(expunged(1)) {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            stopped.send(()).unwrap();
+        });
+        next(&mut sync);
+        drop(sync);
+
+        // The watcher notices the interface went away and returns
+        watcher_stopped
+            .recv_timeout(Duration::from_secs(1))
+            .expect("the watcher kept running");
+        // No fetch starts after a fetch in progress has finished
+        std::thread::sleep(Duration::from_millis(50));
+        let after_shutdown = fetches.load(Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(fetches.load(Ordering::SeqCst), after_shutdown);
+    }
+}
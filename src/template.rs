@@ -0,0 +1,90 @@
+//! Signatures and `{placeholder}` expansion for message templates.
+
+/// Line separating the body from the signature (RFC 3676, section 4.3).
+pub const SIGNATURE_SEPARATOR: &str = "-- ";
+
+/// Builds the text appended to new messages: a blank line, the `-- `
+/// separator and the signature. Empty for an empty signature.
+pub fn signature_block(signature: &str) -> String {
+    let signature = signature.trim_end();
+    if signature.is_empty() {
+        String::new()
+    } else {
+        format!("\n\n{}\n{}", SIGNATURE_SEPARATOR, signature)
+    }
+}
+
+/// Replaces `{name}` placeholders with their values. The variables offered
+/// when composing are `date`, `sender_name`, `sender_email`,
+/// `recipient_name` and `recipient_email`. Unknown placeholders (and any
+/// other braces) are left as they are.
+pub fn expand(text: &str, vars: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| (value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        vec![
+            ("date", "2024-05-06".to_string()),
+            ("sender_name", "Jane {date}".to_string()),
+        ]
+    }
+
+    #[test]
+    fn expands_known_placeholders() {
+        assert_eq!(
+            expand("Report {date} from {sender_name}", &vars()),
+            "Report 2024-05-06 from Jane {date}"
+        );
+        assert_eq!(expand("{date}{date}", &vars()), "2024-05-062024-05-06");
+        assert_eq!(expand("no placeholders", &vars()), "no placeholders");
+        assert_eq!(expand("{date}", &[]), "{date}");
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        assert_eq!(
+            expand("{unknown} { date } {{date}} {date", &vars()),
+            "{unknown} { date } {2024-05-06} {date"
+        );
+        assert_eq!(expand("fn f() {}", &vars()), "fn f() {}");
+        assert_eq!(expand("}{", &vars()), "}{");
+    }
+
+    #[test]
+    fn builds_signature_blocks() {
+        assert_eq!(signature_block("Jane\n"), "\n\n-- \nJane");
+        assert_eq!(
+            signature_block("Jane Doe\nExample Corp\n\n"),
+            "\n\n-- \nJane Doe\nExample Corp"
+        );
+        assert_eq!(signature_block(""), "");
+        assert_eq!(signature_block(" \n\n"), "");
+    }
+}
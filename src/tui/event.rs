@@ -13,6 +13,9 @@ pub fn poll_event() -> Result<Option<Event>> {
 pub fn handle_key(key: crossterm::event::KeyEvent, app: &mut crate::app::App) {
     use crate::app::Mode;
 
+    // Status messages only last until the next key press
+    app.status = None;
+
     match app.mode {
         Mode::Normal => match key.code {
             // Quit
@@ -21,10 +24,8 @@ pub fn handle_key(key: crossterm::event::KeyEvent, app: &mut crate::app::App) {
             KeyCode::Up | KeyCode::Char('k') => app.on_up(),
             KeyCode::Down | KeyCode::Char('j') => app.on_down(),
             KeyCode::Char('g') => app.selected = 0,
-            KeyCode::Char('G') => {
-                if !app.inbox.is_empty() {
-                    app.selected = app.inbox.len() - 1;
-                }
+            KeyCode::Char('G') if !app.inbox.is_empty() => {
+                app.selected = app.inbox.len() - 1;
             }
             // Enter opens selected email
             KeyCode::Enter => app.open_selected(),
@@ -34,17 +35,19 @@ pub fn handle_key(key: crossterm::event::KeyEvent, app: &mut crate::app::App) {
             KeyCode::Char('d') => app.show_delete_confirm(),
             // New email with 'n'
             KeyCode::Char('n') => app.start_compose(),
+            // Address book with 'c'
+            KeyCode::Char('c') => app.open_contacts(),
             _ => {}
         },
         Mode::Command => match key.code {
             KeyCode::Esc => app.close_overlay(),
             KeyCode::Enter => app.submit_command(),
             KeyCode::Backspace => app.pop_command_char(),
-            KeyCode::Char(ch) => {
-                // ignore Ctrl chars, accept regular input
-                if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT {
-                    app.push_command_char(ch);
-                }
+            // ignore Ctrl chars, accept regular input
+            KeyCode::Char(ch)
+                if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT =>
+            {
+                app.push_command_char(ch);
             }
             _ => {}
         },
@@ -55,97 +58,171 @@ pub fn handle_key(key: crossterm::event::KeyEvent, app: &mut crate::app::App) {
         Mode::Viewing => match key.code {
             // Close viewing with q or Esc or Enter
             KeyCode::Esc | KeyCode::Char('q') | KeyCode::Enter => app.close_overlay(),
+            // Half-page scrolling like less/vim
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.view_half_page_down()
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.view_half_page_up()
+            }
+            // Full-page scrolling
+            KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.view_page_down()
+            }
+            KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.view_page_up()
+            }
+            KeyCode::PageDown | KeyCode::Char(' ') => app.view_page_down(),
+            KeyCode::PageUp => app.view_page_up(),
             // j/k scroll within the email content
             KeyCode::Up | KeyCode::Char('k') => app.view_scroll_up(),
-            KeyCode::Down | KeyCode::Char('j') => {
-                let total_lines = if let Some(email) = app.inbox.get(app.selected) {
-                    email.body.lines().count()
-                } else {
-                    0
-                };
-                app.view_scroll_down(total_lines, app.view_height);
+            KeyCode::Down | KeyCode::Char('j') => app.view_scroll_down(),
+            KeyCode::Home | KeyCode::Char('g') => app.view_top(),
+            KeyCode::End | KeyCode::Char('G') => app.view_bottom(),
+            // Expand/collapse long quoted blocks
+            KeyCode::Char('z') => app.view_toggle_quotes(),
+            // Show all headers / the raw message source
+            KeyCode::Char('h') => app.view_toggle_headers(),
+            KeyCode::Char('r') => app.view_toggle_raw(),
+            // Pick a link to open or copy
+            KeyCode::Char('o') => {
+                let links = app
+                    .inbox
+                    .get(app.selected)
+                    .map(super::message::email_links)
+                    .unwrap_or_default();
+                app.open_link_picker(links);
             }
             _ => {}
         },
+        Mode::LinkPicker => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.close_link_picker(),
+            KeyCode::Up | KeyCode::Char('k') => app.link_up(),
+            KeyCode::Down | KeyCode::Char('j') => app.link_down(),
+            KeyCode::Char(ch @ '1'..='9') => {
+                app.link_select_number(ch.to_digit(10).unwrap_or(0) as usize)
+            }
+            KeyCode::Enter | KeyCode::Char('o') => app.link_open(),
+            KeyCode::Char('y') => app.link_copy(),
+            _ => {}
+        },
         Mode::Compose => {
-            use crate::app::{ComposeField, ComposeMode};
+            use crate::app::ComposeMode;
             match app.compose_mode {
+                // The `/` or `?` prompt takes every key until Enter or Esc
+                _ if app.compose_search_input.is_some() => match key.code {
+                    KeyCode::Enter => app.compose_search_submit(),
+                    KeyCode::Esc => app.compose_search_cancel(),
+                    KeyCode::Backspace => app.compose_search_pop_char(),
+                    KeyCode::Char(ch)
+                        if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT =>
+                    {
+                        app.compose_search_push_char(ch)
+                    }
+                    _ => {}
+                },
+                // `"x` picks the register for the next visual-mode command
+                ComposeMode::Visual | ComposeMode::VisualLine if app.compose_pending == "\"" => {
+                    app.compose_pending.clear();
+                    if let KeyCode::Char(ch) = key.code {
+                        app.compose_select_register(ch);
+                    }
+                }
+                ComposeMode::Preview => match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('K') => {
+                        app.compose_close_preview()
+                    }
+                    KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.view_half_page_down()
+                    }
+                    KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.view_half_page_up()
+                    }
+                    KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.view_page_down()
+                    }
+                    KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.view_page_up()
+                    }
+                    KeyCode::PageDown | KeyCode::Char(' ') => app.view_page_down(),
+                    KeyCode::PageUp => app.view_page_up(),
+                    KeyCode::Up | KeyCode::Char('k') => app.view_scroll_up(),
+                    KeyCode::Down | KeyCode::Char('j') => app.view_scroll_down(),
+                    KeyCode::Home | KeyCode::Char('g') => app.view_top(),
+                    KeyCode::End | KeyCode::Char('G') => app.view_bottom(),
+                    _ => {}
+                },
+                // `z=` suggestion list
+                ComposeMode::Normal if app.compose_suggestions.is_some() => match key.code {
+                    KeyCode::Char('j') | KeyCode::Down | KeyCode::Tab => {
+                        app.compose_suggestion_next()
+                    }
+                    KeyCode::Char('k') | KeyCode::Up | KeyCode::BackTab => {
+                        app.compose_suggestion_prev()
+                    }
+                    KeyCode::Char(ch @ '1'..='9') => {
+                        app.compose_accept_suggestion(Some(ch as usize - '1' as usize))
+                    }
+                    KeyCode::Enter => app.compose_accept_suggestion(None),
+                    _ => app.compose_cancel_suggestions(),
+                },
                 ComposeMode::Normal => match key.code {
-                    // Navigation with j/k - between fields or within body
-                    KeyCode::Char('j') | KeyCode::Down => {
-                        if matches!(app.compose_field, ComposeField::Body) {
-                            app.compose_move_down();
-                        } else {
-                            app.compose_next_field();
-                        }
-                    }
-                    KeyCode::Char('k') | KeyCode::Up => {
-                        if matches!(app.compose_field, ComposeField::Body) {
-                            app.compose_move_up();
-                        } else {
-                            app.compose_prev_field();
-                        }
-                    }
-                    // Horizontal movement
-                    KeyCode::Char('h') | KeyCode::Left => app.compose_move_left(),
-                    KeyCode::Char('l') | KeyCode::Right => app.compose_move_right(),
-                    KeyCode::Char('w') => app.compose_move_word_forward(),
-                    KeyCode::Char('b') => app.compose_move_word_backward(),
-                    KeyCode::Char('0') => app.compose_move_line_start(),
-                    KeyCode::Char('$') => app.compose_move_line_end(),
-                    // Enter insert mode variations
-                    KeyCode::Char('i') => app.compose_enter_insert(),
-                    KeyCode::Char('a') => app.compose_append(),
-                    KeyCode::Char('A') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.compose_append_end()
-                    }
-                    KeyCode::Char('I') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.compose_insert_start()
-                    }
-                    KeyCode::Char('o') => app.compose_open_below(),
-                    KeyCode::Char('O') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.compose_open_above()
-                    }
-                    // Deletion
-                    KeyCode::Char('x') => app.compose_delete_char(),
-                    KeyCode::Char('d') => {
-                        // Simple dd for delete line
-                        app.compose_delete_line();
-                    }
-                    // Indentation
-                    KeyCode::Char('>') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.compose_indent_right();
-                    }
-                    KeyCode::Char('<') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.compose_indent_left();
-                    }
-                    // Visual mode
-                    KeyCode::Char('v') => app.compose_enter_visual(),
-                    // Send with :wq or ZZ
-                    KeyCode::Char(':') => {
-                        app.needs_refresh = true;
-                        app.close_overlay();
-                    }
-                    KeyCode::Char('Z') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.needs_refresh = true;
-                        app.close_overlay();
-                    }
-                    // Quit without sending
-                    KeyCode::Esc | KeyCode::Char('q') => app.close_overlay(),
+                    // Esc cancels a half-typed command, otherwise quits
+                    KeyCode::Esc if !app.compose_pending.is_empty() => app.compose_pending.clear(),
+                    KeyCode::Esc => app.close_overlay(),
+                    KeyCode::Down => app.compose_normal_key('j'),
+                    KeyCode::Up => app.compose_normal_key('k'),
+                    KeyCode::Left => app.compose_normal_key('h'),
+                    KeyCode::Right => app.compose_normal_key('l'),
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.compose_pending.clear();
+                        app.compose_redo()
+                    }
+                    // Counts, operators, motions and the other commands
+                    KeyCode::Char(ch)
+                        if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT =>
+                    {
+                        app.compose_normal_key(ch)
+                    }
                     _ => {}
                 },
+                // Address completion popup
+                ComposeMode::Insert if !app.compose_completions.is_empty() => match key.code {
+                    KeyCode::Tab | KeyCode::Down => app.compose_completion_next(),
+                    KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => {
+                        app.compose_completion_next()
+                    }
+                    KeyCode::BackTab | KeyCode::Up => app.compose_completion_prev(),
+                    KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => {
+                        app.compose_completion_prev()
+                    }
+                    KeyCode::Enter => app.compose_accept_completion(),
+                    KeyCode::Esc => app.compose_cancel_completion(),
+                    KeyCode::Backspace => {
+                        app.compose_pop_char();
+                        app.compose_refresh_completion();
+                    }
+                    KeyCode::Char(ch)
+                        if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT =>
+                    {
+                        app.compose_push_char(ch);
+                        app.compose_refresh_completion();
+                    }
+                    _ => app.compose_cancel_completion(),
+                },
                 ComposeMode::Insert => match key.code {
                     KeyCode::Esc => app.compose_exit_insert(),
+                    KeyCode::Tab => app.compose_complete(),
                     KeyCode::Backspace => app.compose_pop_char(),
                     KeyCode::Enter => app.compose_insert_newline(),
                     KeyCode::Left => app.compose_move_left(),
                     KeyCode::Right => app.compose_move_right(),
                     KeyCode::Up => app.compose_move_up(),
                     KeyCode::Down => app.compose_move_down(),
-                    KeyCode::Char(ch) => {
-                        if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT {
-                            app.compose_push_char(ch);
-                        }
+                    KeyCode::Char(ch)
+                        if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT =>
+                    {
+                        app.compose_push_char(ch);
                     }
                     _ => {}
                 },
@@ -161,12 +238,66 @@ pub fn handle_key(key: crossterm::event::KeyEvent, app: &mut crate::app::App) {
                     KeyCode::Char('$') => app.compose_move_line_end(),
                     // Delete selection
                     KeyCode::Char('d') | KeyCode::Char('x') => app.compose_delete_visual(),
+                    KeyCode::Char('y') => app.compose_yank_visual(),
+                    KeyCode::Char('"') => app.compose_pending.push('"'),
+                    KeyCode::Char('V') => app.compose_toggle_visual_line(),
                     // Exit visual mode
-                    KeyCode::Esc => app.compose_exit_visual(),
+                    KeyCode::Esc | KeyCode::Char('v') => app.compose_exit_visual(),
                     _ => {}
                 },
+                ComposeMode::VisualLine => match key.code {
+                    KeyCode::Char('j') | KeyCode::Down => app.compose_visual_line_move(1),
+                    KeyCode::Char('k') | KeyCode::Up => app.compose_visual_line_move(-1),
+                    KeyCode::Char('g') => app.compose_visual_line_move(isize::MIN),
+                    KeyCode::Char('G') => app.compose_visual_line_move(isize::MAX),
+                    KeyCode::Char('>') => app.compose_visual_line_indent(true),
+                    KeyCode::Char('<') => app.compose_visual_line_indent(false),
+                    KeyCode::Char('d') | KeyCode::Char('x') => app.compose_visual_line_delete(),
+                    KeyCode::Char('y') => app.compose_visual_line_yank(),
+                    KeyCode::Char('"') => app.compose_pending.push('"'),
+                    KeyCode::Char('v') => app.compose_toggle_visual_line(),
+                    KeyCode::Esc | KeyCode::Char('V') => app.compose_exit_visual(),
+                    _ => {}
+                },
+            }
+            if app.mode == Mode::Compose {
+                app.compose_checkpoint();
             }
         }
+        Mode::Contacts if app.contact_form.is_some() => match key.code {
+            KeyCode::Esc => app.contact_form_cancel(),
+            KeyCode::Enter => app.contact_form_submit(),
+            KeyCode::Tab | KeyCode::Down => app.contact_form_next_field(),
+            KeyCode::BackTab | KeyCode::Up => app.contact_form_prev_field(),
+            KeyCode::Backspace => app.contact_form_pop_char(),
+            KeyCode::Char(ch)
+                if key.modifiers.is_empty() || key.modifiers == KeyModifiers::SHIFT =>
+            {
+                app.contact_form_push_char(ch);
+            }
+            _ => {}
+        },
+        Mode::Contacts if app.contact_delete_pending => match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => app.contact_confirm_delete(true),
+            _ => app.contact_confirm_delete(false),
+        },
+        Mode::Contacts => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.close_overlay(),
+            KeyCode::Up | KeyCode::Char('k') => app.contact_up(),
+            KeyCode::Down | KeyCode::Char('j') => app.contact_down(),
+            KeyCode::Char('g') | KeyCode::Home => app.contact_top(),
+            KeyCode::Char('G') | KeyCode::End => app.contact_bottom(),
+            KeyCode::Enter | KeyCode::Char('e') => app.contact_edit(),
+            KeyCode::Char('a') => app.contact_new(),
+            KeyCode::Char('d') => app.contact_request_delete(),
+            KeyCode::Char('c') => app.contact_compose(),
+            _ => {}
+        },
+        Mode::SendConfirm => match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => app.confirm_send(),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.cancel_send(),
+            _ => {}
+        },
         Mode::DeleteConfirm => match key.code {
             KeyCode::Char('y') | KeyCode::Char('Y') => app.confirm_delete(),
             KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.close_overlay(),
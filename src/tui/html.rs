@@ -0,0 +1,896 @@
+//! Renders text/html message bodies into styled terminal text.
+
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// A logical line of rendered text: a run of styled segments plus the
+/// prefixes used for its first and continuation rows when wrapped.
+#[derive(Debug, Default)]
+struct Block {
+    first_prefix: String,
+    rest_prefix: String,
+    segments: Vec<(String, Style)>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+struct Renderer {
+    blocks: Vec<Block>,
+    current: Vec<(String, Style)>,
+    pending_marker: Option<String>,
+    bold: usize,
+    italic: usize,
+    underline: usize,
+    skip: usize,
+    pre: usize,
+    quote: usize,
+    lists: Vec<Option<usize>>,
+    hrefs: Vec<Option<String>>,
+    links: Vec<String>,
+    tables: Vec<Table>,
+    space_pending: bool,
+}
+
+/// Renders HTML into wrapped rows no wider than `width`. Links are shown
+/// as numbered footnotes listed after the body.
+pub fn render(html: &str, width: usize) -> Vec<Line<'static>> {
+    let mut renderer = Renderer::default();
+    for token in tokenize(html) {
+        renderer.handle(token);
+    }
+    renderer.finish_line();
+
+    let mut rows = Vec::new();
+    for block in &renderer.blocks {
+        rows.extend(wrap_block(block, width));
+    }
+    // Collapse runs of blank rows left by nested block elements
+    rows.dedup_by(|a, b| a.width() == 0 && b.width() == 0);
+    while rows.first().map(|r| r.width() == 0).unwrap_or(false) {
+        rows.remove(0);
+    }
+    while rows.last().map(|r| r.width() == 0).unwrap_or(false) {
+        rows.pop();
+    }
+
+    if !renderer.links.is_empty() {
+        rows.push(Line::from(""));
+        rows.push(Line::from(Span::styled(
+            "Links:",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        for (idx, url) in renderer.links.iter().enumerate() {
+            let marker = format!("[{}] ", idx + 1);
+            let indent = " ".repeat(marker.width());
+            let block = Block {
+                first_prefix: marker,
+                rest_prefix: indent,
+                segments: vec![(url.clone(), Style::default())],
+            };
+            rows.extend(wrap_block(&block, width));
+        }
+    }
+    rows
+}
+
+/// Returns the targets of all `<a href>` links in document order.
+pub fn links(html: &str) -> Vec<String> {
+    tokenize(html)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Start(name, attrs) if name == "a" => attr(&attrs, "href"),
+            _ => None,
+        })
+        .filter(|href| !href.is_empty() && !href.starts_with('#'))
+        .collect()
+}
+
+impl Renderer {
+    fn style(&self) -> Style {
+        let mut style = Style::default();
+        if self.bold > 0 {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if self.italic > 0 {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        if self.underline > 0 {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        style
+    }
+
+    fn prefixes(&self) -> (String, String) {
+        let quote = "> ".repeat(self.quote);
+        let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+        match &self.pending_marker {
+            Some(marker) => (
+                format!("{}{}{}", quote, indent, marker),
+                format!("{}{}{}", quote, indent, " ".repeat(marker.width())),
+            ),
+            None => {
+                let cont = if self.lists.is_empty() {
+                    String::new()
+                } else {
+                    "  ".repeat(self.lists.len())
+                };
+                (format!("{}{}", quote, cont), format!("{}{}", quote, cont))
+            }
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.skip > 0 {
+            return;
+        }
+        if let Some(table) = self.tables.last_mut() {
+            if let Some(cell) = table.rows.last_mut().and_then(|r| r.last_mut()) {
+                let collapsed = collapse_whitespace(text);
+                if !collapsed.trim().is_empty() {
+                    if !cell.is_empty() && !cell.ends_with(' ') && collapsed.starts_with(' ') {
+                        cell.push(' ');
+                    }
+                    cell.push_str(collapsed.trim());
+                    if collapsed.ends_with(' ') {
+                        cell.push(' ');
+                    }
+                }
+            }
+            return;
+        }
+
+        let style = self.style();
+        if self.pre > 0 {
+            let mut lines = text.split('\n');
+            if let Some(first) = lines.next() {
+                self.current.push((first.to_string(), style));
+            }
+            for line in lines {
+                self.finish_line();
+                self.current.push((line.to_string(), style));
+            }
+            return;
+        }
+
+        let collapsed = collapse_whitespace(text);
+        let mut piece = String::new();
+        for ch in collapsed.chars() {
+            if ch == ' ' {
+                self.space_pending = true;
+            } else {
+                if self.space_pending && (!self.current.is_empty() || !piece.is_empty()) {
+                    piece.push(' ');
+                }
+                self.space_pending = false;
+                piece.push(ch);
+            }
+        }
+        if !piece.is_empty() {
+            self.current.push((piece, style));
+        }
+    }
+
+    fn finish_line(&mut self) {
+        if self.current.is_empty() {
+            return;
+        }
+        let (first_prefix, rest_prefix) = self.prefixes();
+        self.blocks.push(Block {
+            first_prefix,
+            rest_prefix,
+            segments: std::mem::take(&mut self.current),
+        });
+        self.pending_marker = None;
+        self.space_pending = false;
+    }
+
+    fn blank_line(&mut self) {
+        self.finish_line();
+        if self
+            .blocks
+            .last()
+            .map(|b| !b.segments.is_empty())
+            .unwrap_or(false)
+        {
+            let (prefix, _) = self.prefixes();
+            let prefix = prefix.trim_end().to_string();
+            self.blocks.push(Block {
+                first_prefix: prefix.clone(),
+                rest_prefix: prefix,
+                segments: Vec::new(),
+            });
+        }
+    }
+
+    fn handle(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.push_text(&decode_entities(&text)),
+            Token::Start(name, attrs) => self.start_tag(&name, &attrs),
+            Token::End(name) => self.end_tag(&name),
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attrs: &[(String, String)]) {
+        match name {
+            "script" | "style" | "head" | "title" | "noscript" => self.skip += 1,
+            "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => {
+                if name.starts_with('h') {
+                    self.blank_line();
+                }
+                self.bold += 1;
+            }
+            "i" | "em" | "cite" => self.italic += 1,
+            "u" | "ins" => self.underline += 1,
+            "br" => {
+                if self.tables.is_empty() {
+                    self.finish_line();
+                } else {
+                    self.push_text(" ");
+                }
+            }
+            "p" | "div" | "section" | "article" | "header" | "footer" | "center" => {
+                if self.tables.is_empty() {
+                    if name == "p" {
+                        self.blank_line();
+                    } else {
+                        self.finish_line();
+                    }
+                } else {
+                    self.push_text(" ");
+                }
+            }
+            "hr" => {
+                self.finish_line();
+                self.current.push(("―".repeat(20), Style::default()));
+                self.finish_line();
+            }
+            "pre" => {
+                self.blank_line();
+                self.pre += 1;
+            }
+            "blockquote" => {
+                self.blank_line();
+                self.quote += 1;
+            }
+            "ul" => {
+                self.finish_line();
+                self.lists.push(None);
+            }
+            "ol" => {
+                self.finish_line();
+                let start = attr(attrs, "start")
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .unwrap_or(1);
+                self.lists.push(Some(start));
+            }
+            "li" => {
+                self.finish_line();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.pending_marker = Some(marker);
+            }
+            "a" => {
+                let href = attr(attrs, "href").filter(|h| !h.is_empty() && !h.starts_with('#'));
+                if href.is_some() {
+                    self.underline += 1;
+                }
+                self.hrefs.push(href);
+            }
+            "img" => {
+                if let Some(alt) = attr(attrs, "alt").filter(|a| !a.trim().is_empty()) {
+                    self.push_text(&format!(" [image: {}] ", alt.trim()));
+                }
+            }
+            "table" => {
+                self.finish_line();
+                self.tables.push(Table::default());
+            }
+            "tr" => {
+                if let Some(table) = self.tables.last_mut() {
+                    table.rows.push(Vec::new());
+                }
+            }
+            "td" => self.start_cell(),
+            _ => {}
+        }
+        if name == "th" {
+            self.start_cell();
+        }
+    }
+
+    fn start_cell(&mut self) {
+        if let Some(table) = self.tables.last_mut() {
+            if table.rows.is_empty() {
+                table.rows.push(Vec::new());
+            }
+            if let Some(row) = table.rows.last_mut() {
+                row.push(String::new());
+            }
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        match name {
+            "script" | "style" | "head" | "title" | "noscript" => {
+                self.skip = self.skip.saturating_sub(1)
+            }
+            "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => {
+                self.bold = self.bold.saturating_sub(1);
+                if name.starts_with('h') {
+                    self.blank_line();
+                }
+            }
+            "i" | "em" | "cite" => self.italic = self.italic.saturating_sub(1),
+            "u" | "ins" => self.underline = self.underline.saturating_sub(1),
+            "p" if self.tables.is_empty() => self.blank_line(),
+            "div" | "section" | "article" | "header" | "footer" | "center" | "li"
+                if self.tables.is_empty() =>
+            {
+                self.finish_line()
+            }
+            "pre" => {
+                self.finish_line();
+                self.pre = self.pre.saturating_sub(1);
+                self.blank_line();
+            }
+            "blockquote" => {
+                self.finish_line();
+                self.quote = self.quote.saturating_sub(1);
+                self.blank_line();
+            }
+            "ul" | "ol" => {
+                self.finish_line();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            "a" => {
+                if let Some(Some(href)) = self.hrefs.pop() {
+                    self.underline = self.underline.saturating_sub(1);
+                    let number = match self.links.iter().position(|l| *l == href) {
+                        Some(idx) => idx + 1,
+                        None => {
+                            self.links.push(href);
+                            self.links.len()
+                        }
+                    };
+                    self.push_text(&format!("[{}]", number));
+                }
+            }
+            "table" => {
+                if let Some(table) = self.tables.pop() {
+                    self.emit_table(table);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn emit_table(&mut self, table: Table) {
+        let rows: Vec<Vec<String>> = table
+            .rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|c| c.trim().to_string()).collect())
+            .filter(|row: &Vec<String>| row.iter().any(|c| !c.is_empty()))
+            .collect();
+
+        // A table nested in another one becomes part of the outer cell
+        if !self.tables.is_empty() {
+            for row in rows {
+                self.push_text(&format!(" {} ", row.join(" ")));
+            }
+            return;
+        }
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns <= 1 {
+            // Single-column layout tables read better as plain paragraphs
+            for row in rows {
+                for cell in row {
+                    self.push_text(&cell);
+                    self.blank_line();
+                }
+            }
+            return;
+        }
+
+        let mut widths = vec![0; columns];
+        for row in &rows {
+            for (idx, cell) in row.iter().enumerate() {
+                widths[idx] = widths[idx].max(cell.width());
+            }
+        }
+        self.blank_line();
+        for row in rows {
+            let mut line = String::new();
+            for (idx, cell) in row.iter().enumerate() {
+                if idx > 0 {
+                    line.push_str(" │ ");
+                }
+                line.push_str(cell);
+                if idx + 1 < row.len() {
+                    line.push_str(&" ".repeat(widths[idx] - cell.width()));
+                }
+            }
+            self.current.push((line, Style::default()));
+            self.finish_line();
+        }
+        self.blank_line();
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_space = false;
+    for ch in text.chars() {
+        if ch.is_whitespace() && ch != '\u{a0}' {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(ch);
+            in_space = false;
+        }
+    }
+    out
+}
+
+/// Word-wraps a block's styled segments into rows, applying its prefixes.
+fn wrap_block(block: &Block, width: usize) -> Vec<Line<'static>> {
+    if block.segments.is_empty() {
+        return vec![Line::from(block.first_prefix.clone())];
+    }
+
+    let mut rows = Vec::new();
+    let mut row: Vec<Span<'static>> = vec![Span::raw(block.first_prefix.clone())];
+    let mut row_width = block.first_prefix.width();
+    let mut prefix_width = row_width;
+    let rest_width = block.rest_prefix.width();
+
+    let new_row = |rows: &mut Vec<Line<'static>>, row: &mut Vec<Span<'static>>| {
+        if let Some(last) = row.last_mut() {
+            last.content = last.content.trim_end().to_string().into();
+        }
+        rows.push(Line::from(std::mem::take(row)));
+        row.push(Span::raw(block.rest_prefix.clone()));
+    };
+
+    for (text, style) in &block.segments {
+        for word in text.split_inclusive(' ') {
+            let word_width = word.trim_end_matches(' ').width();
+            if width > 0 && row_width + word_width > width && row_width > prefix_width {
+                new_row(&mut rows, &mut row);
+                row_width = rest_width;
+                prefix_width = rest_width;
+            }
+            if width > 0 && row_width + word_width > width {
+                // Hard-split words wider than a row
+                let mut piece = String::new();
+                for ch in word.chars() {
+                    let ch_width = ch.width().unwrap_or(0);
+                    if row_width + ch_width > width && row_width > prefix_width {
+                        row.push(Span::styled(std::mem::take(&mut piece), *style));
+                        new_row(&mut rows, &mut row);
+                        row_width = rest_width;
+                        prefix_width = rest_width;
+                    }
+                    piece.push(ch);
+                    row_width += ch_width;
+                }
+                row.push(Span::styled(piece, *style));
+            } else {
+                // Never start a continuation row with a space
+                let word = if row_width == prefix_width && !rows.is_empty() {
+                    word.trim_start()
+                } else {
+                    word
+                };
+                row_width += word.width();
+                row.push(Span::styled(word.to_string(), *style));
+            }
+        }
+    }
+    if let Some(last) = row.last_mut() {
+        last.content = last.content.trim_end().to_string().into();
+    }
+    rows.push(Line::from(row));
+    rows
+}
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Start(String, Vec<(String, String)>),
+    End(String),
+}
+
+fn attr(attrs: &[(String, String)], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| decode_entities(v))
+}
+
+/// Splits HTML into text, start-tag and end-tag tokens. Comments, doctypes
+/// and processing instructions are dropped; raw-text elements (`script`,
+/// `style`) have their contents skipped without being parsed as markup.
+fn tokenize(html: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            tokens.push(Token::Text(rest.to_string()));
+            break;
+        };
+        if lt > 0 {
+            tokens.push(Token::Text(rest[..lt].to_string()));
+        }
+        rest = &rest[lt..];
+
+        // A '<' that cannot start markup is just text
+        let starts_markup = rest[1..]
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?'))
+            .unwrap_or(false);
+        if !starts_markup {
+            tokens.push(Token::Text("<".to_string()));
+            rest = &rest[1..];
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map(|i| &after[i + 3..]).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map(|i| &rest[i + 1..]).unwrap_or("");
+            continue;
+        }
+
+        let Some(gt) = find_tag_end(rest) else {
+            tokens.push(Token::Text(rest.to_string()));
+            break;
+        };
+        let inner = &rest[1..gt];
+        rest = &rest[gt + 1..];
+
+        if let Some(name) = inner.strip_prefix('/') {
+            tokens.push(Token::End(name.trim().to_ascii_lowercase()));
+            continue;
+        }
+        let self_closing = inner.ends_with('/');
+        let inner = inner.trim_end_matches('/');
+        let name_end = inner
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(inner.len());
+        let name = inner[..name_end].to_ascii_lowercase();
+        let attrs = parse_attrs(&inner[name_end..]);
+        tokens.push(Token::Start(name.clone(), attrs));
+
+        if self_closing {
+            tokens.push(Token::End(name));
+        } else if name == "script" || name == "style" {
+            let close = format!("</{}", name);
+            let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+            rest = &rest[end..];
+        }
+    }
+    tokens
+}
+
+/// Finds the `>` closing a tag, skipping over quoted attribute values.
+fn find_tag_end(s: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, ch) in s.char_indices().skip(1) {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => {}
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None if ch == '>' => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+fn parse_attrs(s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(start, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut end = start;
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() || c == '=' {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        let name = s[start..end].to_ascii_lowercase();
+        while chars
+            .peek()
+            .map(|(_, c)| c.is_whitespace())
+            .unwrap_or(false)
+        {
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek().map(|(_, c)| *c == '=').unwrap_or(false) {
+            chars.next();
+            while chars
+                .peek()
+                .map(|(_, c)| c.is_whitespace())
+                .unwrap_or(false)
+            {
+                chars.next();
+            }
+            match chars.peek().map(|(_, c)| *c) {
+                Some(q @ ('"' | '\'')) => {
+                    chars.next();
+                    for (_, c) in chars.by_ref() {
+                        if c == q {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                _ => {
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        value.push(c);
+                        chars.next();
+                    }
+                }
+            }
+        }
+        if !name.is_empty() {
+            attrs.push((name, value));
+        }
+    }
+    attrs
+}
+
+/// Decodes character references: numeric ones and the common named ones.
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&semi| semi <= 10)
+            .and_then(|semi| entity(&rest[1..semi + 1]).map(|c| (c, semi + 2)));
+        match decoded {
+            Some((ch, len)) => {
+                out.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    let ch = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "middot" => '·',
+        "euro" => '€',
+        "pound" => '£',
+        "laquo" => '«',
+        "raquo" => '»',
+        "zwnj" | "zwj" | "shy" => '\u{200b}',
+        _ => return None,
+    };
+    Some(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(html: &str, width: usize) -> Vec<String> {
+        render(html, width)
+            .into_iter()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    fn styled(html: &str, word: &str) -> Style {
+        render(html, 80)
+            .into_iter()
+            .flat_map(|line| line.spans)
+            .find(|span| span.content.trim() == word)
+            .map(|span| span.style)
+            .unwrap()
+    }
+
+    #[test]
+    fn paragraphs_and_line_breaks() {
+        assert_eq!(
+            text("<p>One\n  two</p><p>Three<br>four</p>", 80),
+            vec!["One two", "", "Three", "four"]
+        );
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            text(
+                "Fish &amp; chips &lt;3 &#65;&#x42; &hellip; &bogus; & more",
+                80
+            ),
+            vec!["Fish & chips <3 AB … &bogus; & more"]
+        );
+        // Non-breaking spaces are kept
+        assert_eq!(text("a&nbsp;&nbsp;b", 80), vec!["a\u{a0}\u{a0}b"]);
+    }
+
+    #[test]
+    fn hides_scripts_styles_and_comments() {
+        assert_eq!(
+            text(
+                "<head><title>T</title><style>p { color: red }</style></head>\
+<script>if (a < b) { document.write('<p>x</p>') }</script>\
+<!-- a <b>comment</b> --><p>Shown</p><!DOCTYPE html>",
+                80
+            ),
+            vec!["Shown"]
+        );
+    }
+
+    #[test]
+    fn tolerates_broken_markup() {
+        // Unclosed tags and stray angle brackets
+        assert_eq!(text("<b>bold <i>both", 80), vec!["bold both"]);
+        assert_eq!(styled("<b>bold <i>both", "both"), {
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::ITALIC)
+        });
+        assert_eq!(text("1 < 2 and 3 > 2", 80), vec!["1 < 2 and 3 > 2"]);
+        assert_eq!(text("text <a href=\"x", 80), vec!["text <a href=\"x"]);
+        assert_eq!(text("</i>stray end", 80), vec!["stray end"]);
+        assert_eq!(text("<!-- never closed", 80), Vec::<String>::new());
+    }
+
+    #[test]
+    fn lists_and_quotes() {
+        assert_eq!(
+            text(
+                "<ul><li>one</li><li>two<ol start=\"3\"><li>three</li><li>four</li></ol></li></ul>\
+<blockquote>quoted</blockquote>",
+                80
+            ),
+            vec!["• one", "• two", "  3. three", "  4. four", "", "> quoted"]
+        );
+    }
+
+    #[test]
+    fn numbers_links_as_footnotes() {
+        let html = "<a href=\"https://a.example\">A</a>, <a href='https://b.example'>B</a>, \
+<a href=\"https://a.example\">again</a> <a href=\"#top\">top</a> <a>none</a>";
+        assert_eq!(
+            text(html, 80),
+            vec![
+                "A[1], B[2], again[1] top none",
+                "",
+                "Links:",
+                "[1] https://a.example",
+                "[2] https://b.example",
+            ]
+        );
+        assert_eq!(
+            styled(html, "A"),
+            Style::default().add_modifier(Modifier::UNDERLINED)
+        );
+    }
+
+    #[test]
+    fn lists_link_targets() {
+        assert_eq!(
+            links(
+                "<a href=\"https://a.example/?x=1&amp;y=2\">a</a> <A HREF=mailto:b@example.com>b</A> \
+<a href=\"#anchor\">c</a> <a href=\"\">d</a>"
+            ),
+            vec!["https://a.example/?x=1&y=2", "mailto:b@example.com"]
+        );
+    }
+
+    #[test]
+    fn lays_out_tables_in_columns() {
+        assert_eq!(
+            text(
+                "<table><tr><th>Name</th><th>Qty</th></tr>\
+<tr><td>Apple</td><td>3</td></tr><tr><td>Kiwi</td><td>12</td></tr></table>",
+                80
+            ),
+            vec!["Name  │ Qty", "Apple │ 3", "Kiwi  │ 12"]
+        );
+        // Layout tables with one column become paragraphs
+        assert_eq!(
+            text(
+                "<table><tr><td>First</td></tr><tr><td>Second</td></tr></table>",
+                80
+            ),
+            vec!["First", "", "Second"]
+        );
+    }
+
+    #[test]
+    fn wraps_at_the_width() {
+        assert_eq!(
+            text("<p>the quick brown fox jumps</p>", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+        // List items wrap under their text, and long words are split
+        assert_eq!(
+            text("<ul><li>alpha beta gamma</li></ul><p>abcdefghijkl</p>", 8),
+            vec!["• alpha", "  beta", "  gamma", "", "abcdefgh", "ijkl"]
+        );
+        // Wide characters count two columns
+        assert_eq!(
+            text("<p>日本語 テキスト</p>", 8),
+            vec!["日本語", "テキスト"]
+        );
+    }
+
+    #[test]
+    fn preformatted_text_keeps_its_lines() {
+        assert_eq!(
+            text("<pre>a  b\n  c</pre>after", 80),
+            vec!["a  b", "  c", "", "after"]
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use std::ops::Range;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use super::{html, wrap};
+use crate::backend::EmailSummary;
+use crate::template::SIGNATURE_SEPARATOR;
+
+/// Quoted blocks longer than this are folded unless expanded with `z`.
+const QUOTE_FOLD_THRESHOLD: usize = 6;
+
+/// Colours cycled through for increasing quote depth.
+const QUOTE_COLORS: [Color; 4] = [Color::Cyan, Color::Green, Color::Yellow, Color::Magenta];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Text,
+    Quote(usize),
+    Signature,
+    Folded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Url,
+    Email,
+}
+
+/// Renders an email body into styled, wrapped rows for the viewer.
+pub fn render_body(
+    body: &str,
+    width: usize,
+    content_type: Option<&str>,
+    expand_quotes: bool,
+) -> Vec<Line<'static>> {
+    let lines = wrap::logical_lines(body, content_type);
+    let classified = fold_quotes(classify(lines), expand_quotes);
+
+    let mut rows = Vec::new();
+    for (kind, line) in classified {
+        for row in wrap::wrap_line(&line, width) {
+            rows.push(style_row(kind, row));
+        }
+    }
+    rows
+}
+
+/// Renders header fields as `Name: value` rows, with the name in bold and
+/// long values wrapped under an indent.
+pub fn render_headers(headers: &[(String, String)], width: usize) -> Vec<Line<'static>> {
+    let name_style = Style::default().add_modifier(Modifier::BOLD);
+    let mut rows = Vec::new();
+    for (name, value) in headers {
+        let line = format!("{}: {}", name, value);
+        for (idx, row) in wrap::wrap_line(&line, width.saturating_sub(4))
+            .into_iter()
+            .enumerate()
+        {
+            let label = format!("{}:", name);
+            if idx == 0
+                && let Some(value) = row.strip_prefix(&label)
+            {
+                rows.push(Line::from(vec![
+                    Span::styled(label.clone(), name_style),
+                    Span::raw(value.to_string()),
+                ]));
+            } else if idx == 0 {
+                rows.push(Line::from(row));
+            } else {
+                rows.push(Line::from(format!("    {}", row)));
+            }
+        }
+    }
+    rows
+}
+
+/// Renders the message source verbatim, wrapped but otherwise unstyled.
+pub fn render_raw(raw: &str, width: usize) -> Vec<Line<'static>> {
+    raw.lines()
+        .flat_map(|line| wrap::wrap_line(line, width))
+        .map(Line::from)
+        .collect()
+}
+
+/// Tags each logical line as plain text, a quote (with depth) or signature.
+fn classify(lines: Vec<String>) -> Vec<(LineKind, String)> {
+    // The signature starts at the last standard "-- " separator
+    let signature_start = lines
+        .iter()
+        .rposition(|l| l == SIGNATURE_SEPARATOR)
+        .unwrap_or(lines.len());
+
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            let depth = wrap::quote_depth(&line);
+            let kind = if idx >= signature_start {
+                LineKind::Signature
+            } else if depth > 0 {
+                LineKind::Quote(depth)
+            } else {
+                LineKind::Text
+            };
+            (kind, line)
+        })
+        .collect()
+}
+
+/// Replaces runs of quoted lines longer than the threshold with a single
+/// placeholder line, unless quotes are expanded.
+fn fold_quotes(lines: Vec<(LineKind, String)>, expand: bool) -> Vec<(LineKind, String)> {
+    if expand {
+        return lines;
+    }
+
+    let mut out = Vec::with_capacity(lines.len());
+    let mut block: Vec<(LineKind, String)> = Vec::new();
+    let flush = |block: &mut Vec<(LineKind, String)>, out: &mut Vec<(LineKind, String)>| {
+        if block.len() > QUOTE_FOLD_THRESHOLD {
+            out.push((
+                LineKind::Folded,
+                format!("[… {} quoted lines hidden, press z to expand]", block.len()),
+            ));
+            block.clear();
+        } else {
+            out.append(block);
+        }
+    };
+
+    for (kind, line) in lines {
+        if matches!(kind, LineKind::Quote(_)) {
+            block.push((kind, line));
+        } else {
+            flush(&mut block, &mut out);
+            out.push((kind, line));
+        }
+    }
+    flush(&mut block, &mut out);
+    out
+}
+
+fn style_row(kind: LineKind, row: String) -> Line<'static> {
+    let base = match kind {
+        LineKind::Text => Style::default(),
+        LineKind::Quote(depth) => {
+            Style::default().fg(QUOTE_COLORS[(depth - 1) % QUOTE_COLORS.len()])
+        }
+        LineKind::Signature => Style::default()
+            .fg(Color::DarkGray)
+            .add_modifier(Modifier::DIM),
+        LineKind::Folded => Style::default()
+            .fg(Color::DarkGray)
+            .add_modifier(Modifier::ITALIC),
+    };
+    if kind == LineKind::Folded {
+        return Line::from(Span::styled(row, base));
+    }
+
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for (range, _) in find_links(&row) {
+        if range.start > last {
+            spans.push(Span::styled(row[last..range.start].to_string(), base));
+        }
+        spans.push(Span::styled(
+            row[range.clone()].to_string(),
+            base.fg(Color::Blue).add_modifier(Modifier::UNDERLINED),
+        ));
+        last = range.end;
+    }
+    if last < row.len() || spans.is_empty() {
+        spans.push(Span::styled(row[last..].to_string(), base));
+    }
+    Line::from(spans)
+}
+
+/// Collects the links of a message for the link picker: URLs and addresses
+/// in the plain-text body followed by `href` targets of the HTML part.
+/// Each link is normalised so it can be handed straight to an opener.
+pub fn email_links(email: &EmailSummary) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for line in wrap::logical_lines(&email.body, email.content_type.as_deref()) {
+        for (range, kind) in find_links(&line) {
+            let text = &line[range];
+            let link = match kind {
+                LinkKind::Url if text.to_ascii_lowercase().starts_with("www.") => {
+                    format!("https://{}", text)
+                }
+                LinkKind::Email if !text.to_ascii_lowercase().starts_with("mailto:") => {
+                    format!("mailto:{}", text)
+                }
+                _ => text.to_string(),
+            };
+            links.push(link);
+        }
+    }
+    if let Some(html) = &email.html {
+        links.extend(html::links(html));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    links.retain(|link| seen.insert(link.clone()));
+    links
+}
+
+/// Finds URLs and email addresses in a piece of text, returning their byte
+/// ranges in order.
+pub fn find_links(text: &str) -> Vec<(Range<usize>, LinkKind)> {
+    let mut links = Vec::new();
+    let mut offset = 0;
+    for chunk in text.split_inclusive(char::is_whitespace) {
+        let word = chunk.trim_end();
+        let lead = word.len() - word.trim_start_matches(['<', '(', '[', '"', '\'']).len();
+        let core =
+            word[lead..].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\'']);
+        let start = offset + lead;
+        if let Some(kind) = link_kind(core) {
+            links.push((start..start + core.len(), kind));
+        }
+        offset += chunk.len();
+    }
+    links
+}
+
+fn link_kind(word: &str) -> Option<LinkKind> {
+    let lower = word.to_ascii_lowercase();
+    if ["http://", "https://", "ftp://", "www."]
+        .iter()
+        .any(|p| lower.starts_with(p) && lower.len() > p.len())
+    {
+        return Some(LinkKind::Url);
+    }
+    let word = lower.strip_prefix("mailto:").unwrap_or(&lower);
+    let (local, domain) = word.split_once('@')?;
+    let valid = !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains('@');
+    valid.then_some(LinkKind::Email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(body: &str) -> Vec<LineKind> {
+        let lines = body.lines().map(str::to_string).collect();
+        classify(lines).into_iter().map(|(kind, _)| kind).collect()
+    }
+
+    #[test]
+    fn classifies_quotes_by_depth() {
+        assert_eq!(
+            kinds("Hi\n> one\n>> two\n> > two too\nback"),
+            vec![
+                LineKind::Text,
+                LineKind::Quote(1),
+                LineKind::Quote(2),
+                LineKind::Quote(2),
+                LineKind::Text,
+            ]
+        );
+    }
+
+    #[test]
+    fn signature_starts_at_the_last_separator() {
+        assert_eq!(
+            kinds("text\n-- \nquoted sig\n-- \nme"),
+            vec![
+                LineKind::Text,
+                LineKind::Text,
+                LineKind::Text,
+                LineKind::Signature,
+                LineKind::Signature,
+            ]
+        );
+        // Quotes inside the signature are still signature
+        assert_eq!(
+            kinds("-- \n> me"),
+            vec![LineKind::Signature, LineKind::Signature]
+        );
+    }
+
+    #[test]
+    fn dividers_are_not_signatures() {
+        assert_eq!(
+            kinds("part one\n--\npart two\n---"),
+            vec![LineKind::Text; 4]
+        );
+    }
+
+    #[test]
+    fn long_quotes_fold_unless_expanded() {
+        let body = format!("Hi\n{}\nBye", ["> q"; QUOTE_FOLD_THRESHOLD + 1].join("\n"));
+        let lines: Vec<String> = body.lines().map(str::to_string).collect();
+        let folded = fold_quotes(classify(lines.clone()), false);
+        assert_eq!(folded.len(), 3);
+        assert_eq!(folded[1].0, LineKind::Folded);
+        assert!(folded[1].1.contains("7 quoted lines hidden"));
+        assert_eq!(
+            fold_quotes(classify(lines.clone()), true).len(),
+            lines.len()
+        );
+
+        let short: Vec<String> = vec!["> q".to_string(); QUOTE_FOLD_THRESHOLD];
+        assert_eq!(
+            fold_quotes(classify(short), false).len(),
+            QUOTE_FOLD_THRESHOLD
+        );
+    }
+
+    #[test]
+    fn finds_links_without_surrounding_punctuation() {
+        let text = "See <https://example.com/a>, www.example.org. or mail bob@example.com!";
+        let found: Vec<(&str, LinkKind)> = find_links(text)
+            .into_iter()
+            .map(|(range, kind)| (&text[range], kind))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("https://example.com/a", LinkKind::Url),
+                ("www.example.org", LinkKind::Url),
+                ("bob@example.com", LinkKind::Email),
+            ]
+        );
+        assert!(find_links("a@b @ x@.com http:// www.").is_empty());
+    }
+}
@@ -1,20 +1,40 @@
 pub mod event;
+pub mod html;
+pub mod message;
+pub mod textarea;
 pub mod ui;
+pub mod wrap;
 
-use crate::app::App;
-use crate::backend::EmailBackend;
+use crate::app::{App, LinkAction};
+use crate::backend::{EmailBackend, mime};
+use crate::config::{self, Config};
+use crate::crypto::Scratch;
+use crate::sync::{BackgroundSync, SyncEvent};
+use anyhow::Context;
 use crossterm::{
+    cursor::{Hide, SetCursorStyle, Show},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
-    cursor::{SetCursorStyle, Show, Hide},
 };
 use ratatui::{Terminal, backend::CrosstermBackend};
-use std::io::stdout;
+use std::fs;
+use std::io::{Stdout, Write, stdout};
+use std::process::{Command, Stdio};
 
-pub fn run(app: &mut App, backend: &impl EmailBackend) -> anyhow::Result<()> {
+pub fn run(
+    app: &mut App,
+    backend: &impl EmailBackend,
+    config: &Config,
+    mut sync: Option<&mut BackgroundSync>,
+) -> anyhow::Result<()> {
     enable_raw_mode()?;
     let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen, Show, SetCursorStyle::SteadyBlock)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        Show,
+        SetCursorStyle::SteadyBlock
+    )?;
 
     let term_backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(term_backend)?;
@@ -22,46 +42,143 @@ pub fn run(app: &mut App, backend: &impl EmailBackend) -> anyhow::Result<()> {
     let mut prev_mode = app.mode;
 
     while !app.should_quit {
-        // Show blinking block cursor only in compose mode
+        // Show blinking block cursor only while editing text
         match app.mode {
             crate::app::Mode::Compose => {
                 execute!(terminal.backend_mut(), Show, SetCursorStyle::BlinkingBlock)?;
             }
+            crate::app::Mode::Contacts if app.contact_form.is_some() => {
+                execute!(terminal.backend_mut(), Show, SetCursorStyle::BlinkingBlock)?;
+            }
             _ => {
                 execute!(terminal.backend_mut(), Hide)?;
             }
         }
-        
+
+        // Mail fetched in the background
+        while let Some(event) = sync.as_deref_mut().and_then(BackgroundSync::try_recv) {
+            match event {
+                SyncEvent::Synced { applied, arrived } => {
+                    if let Some(summary) = applied.summary() {
+                        app.status = Some(summary);
+                    }
+                    app.refresh_inbox(applied.inbox);
+                    app.new_mail += arrived;
+                }
+                SyncEvent::Failed(error) => app.status = Some(format!("Sync failed: {}", error)),
+                SyncEvent::Mailbox(update) => app.mailbox_update(update),
+            }
+        }
+
+        // Messages are decrypted, verified and authenticated when shown
+        if matches!(
+            app.mode,
+            crate::app::Mode::Viewing | crate::app::Mode::LinkPicker
+        ) && let Some(email) = app.inbox.get_mut(app.selected)
+            && !email.opened
+            && let Err(e) = backend.open_email(email)
+        {
+            // Shown once rather than retried on every redraw
+            email.opened = true;
+            app.status = Some(format!("{:#}", e));
+        }
+
         terminal.draw(|f| ui::draw(f, app))?;
 
-        if let Some(event) = event::poll_event()? {
-            if let crossterm::event::Event::Key(key) = event {
-                event::handle_key(key, app);
+        if let Some(event) = event::poll_event()?
+            && let crossterm::event::Event::Key(key) = event
+        {
+            event::handle_key(key, app);
 
-                // Handle delete confirmation
-                if app.needs_refresh && prev_mode == crate::app::Mode::DeleteConfirm {
-                    // Delete was confirmed - use the stored email
-                    if let Some(email) = &app.email_to_delete {
-                        backend.delete_email(email)?;
+            // A key pressed in the inbox acknowledges new mail
+            if prev_mode == crate::app::Mode::Normal {
+                app.new_mail = 0;
+            }
+
+            // Handle a link chosen in the link picker
+            if let Some(action) = app.link_action.take() {
+                app.status = Some(match action {
+                    LinkAction::Open(url) => open_link(&config.viewer.open_command, &url),
+                    LinkAction::Copy(url) => {
+                        copy_to_clipboard(terminal.backend_mut(), &url)?;
+                        format!("Copied {}", url)
                     }
-                    app.email_to_delete = None;
-                    app.needs_refresh = false;
+                });
+            }
+
+            // New drafts go straight to the editor when configured to
+            if config.compose.use_editor
+                && app.mode == crate::app::Mode::Compose
+                && prev_mode != crate::app::Mode::Compose
+            {
+                app.compose_external = true;
+            }
+
+            // Hand the draft to the external editor
+            if app.compose_external {
+                app.compose_external = false;
+                match edit_draft(&mut terminal, &config.compose.editor_command(), app) {
+                    Ok(Some(status)) => app.status = Some(status),
+                    Ok(None) => {}
+                    Err(e) => app.status = Some(format!("Editor failed: {:#}", e)),
+                }
+            }
+
+            // Yanks sent to the system clipboard
+            if let Some(text) = app.clipboard_copy.take() {
+                copy_to_clipboard(terminal.backend_mut(), &text)?;
+            }
+
+            // Opening a message marks it read
+            if app.mode == crate::app::Mode::Viewing
+                && prev_mode != crate::app::Mode::Viewing
+                && let Some(email) = app.inbox.get_mut(app.selected)
+                && !email.read
+            {
+                email.read = true;
+                if let Err(e) = backend.update_email(email) {
+                    app.status = Some(format!("{:#}", e));
                 }
+            }
+
+            // Handle delete confirmation
+            if app.needs_refresh && prev_mode == crate::app::Mode::DeleteConfirm {
+                // Delete was confirmed - use the stored email
+                if let Some(email) = app.email_to_delete.take()
+                    && let Err(e) = backend.delete_email(&email)
+                {
+                    // Show the message again, as it is still there
+                    refresh(app, backend);
+                    app.status = Some(format!("Not deleted: {:#}", e));
+                }
+                app.needs_refresh = false;
+            }
 
-                // Handle compose send
-                if app.needs_refresh && prev_mode == crate::app::Mode::Compose {
-                    // Send email
-                    let (to, subject, body) = app.get_compose_data();
-                    if !to.is_empty() && !subject.is_empty() {
-                        backend.send_email(to, subject, body)?;
-                        // Refresh inbox
-                        app.inbox = backend.fetch_inbox()?;
+            // Send a message confirmed in the review overlay
+            if app.needs_refresh && prev_mode == crate::app::Mode::SendConfirm {
+                if let Some(review) = app.send_review.take() {
+                    match backend.send_email(&review.message) {
+                        Ok(()) => {
+                            app.contacts.harvest_sent(&review.message);
+                            app.status = Some("Message sent".to_string());
+                            refresh(app, backend);
+                        }
+                        Err(e) => {
+                            // Back to the draft, which is still intact
+                            app.mode = crate::app::Mode::Compose;
+                            app.status = Some(format!("Not sent: {:#}", e));
+                        }
                     }
-                    app.needs_refresh = false;
                 }
+                app.needs_refresh = false;
+            }
 
-                prev_mode = app.mode;
+            // Persist address book edits and harvested addresses
+            if let Err(e) = app.contacts.save() {
+                app.status = Some(format!("{:#}", e));
             }
+
+            prev_mode = app.mode;
         }
     }
 
@@ -71,3 +188,127 @@ pub fn run(app: &mut App, backend: &impl EmailBackend) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Fetches the inbox again, filtering what is new.
+fn refresh(app: &mut App, backend: &impl EmailBackend) {
+    match backend.fetch_inbox() {
+        Ok(inbox) => {
+            let applied = app.rules.run(backend, inbox);
+            if let Some(summary) = applied.summary() {
+                app.status = Some(summary);
+            }
+            app.refresh_inbox(applied.inbox);
+        }
+        Err(e) => app.status = Some(format!("Fetching mail failed: {:#}", e)),
+    }
+}
+
+/// Writes the draft to a temporary file, suspends the TUI while the editor
+/// runs, and loads the edited file back. Returns a status message when the
+/// draft was left unchanged.
+fn edit_draft(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    editor: &str,
+    app: &mut App,
+) -> anyhow::Result<Option<String>> {
+    // Inside a private directory, which takes the draft with it when dropped
+    let scratch = Scratch::new()?;
+    let path = scratch.file("draft.eml", app.compose_draft().as_bytes())?;
+
+    let Some((program, args)) = config::command_line(editor, &path.to_string_lossy()) else {
+        return Ok(Some("No editor configured".to_string()));
+    };
+
+    // Give the terminal back to the editor
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, Show)?;
+
+    let status = Command::new(&program).args(&args).status();
+
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    // The editor drew over everything; repaint from scratch
+    terminal.clear()?;
+
+    let result = match status {
+        Ok(status) if status.success() => {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("reading draft {}", path.display()))?;
+            app.compose_load_draft(&text.replace("\r\n", "\n"));
+            None
+        }
+        Ok(status) => Some(format!(
+            "{} exited with {}; draft unchanged",
+            program, status
+        )),
+        Err(e) => Some(format!("Failed to run {}: {}", program, e)),
+    };
+    Ok(result)
+}
+
+/// Link schemes handed to the opener; anything else in a message (file:,
+/// javascript:, custom URL handlers) is refused.
+const OPEN_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+fn openable(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        OPEN_SCHEMES
+            .iter()
+            .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+    })
+}
+
+/// Launches the configured opener in the background and returns a status
+/// message describing the outcome.
+fn open_link(command: &str, url: &str) -> String {
+    if !openable(url) {
+        return format!("Refused to open {}", url);
+    }
+    let Some((program, args)) = config::command_line(command, url) else {
+        return "No open command configured".to_string();
+    };
+    let spawned = Command::new(&program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match spawned {
+        Ok(mut child) => {
+            // Reap the opener when it exits so it does not linger as a zombie
+            std::thread::spawn(move || child.wait());
+            format!("Opened {}", url)
+        }
+        Err(e) => format!("Failed to run {}: {}", program, e),
+    }
+}
+
+/// Copies text to the system clipboard with an OSC 52 escape sequence,
+/// which most terminal emulators (and tmux) forward to the clipboard.
+fn copy_to_clipboard(out: &mut impl Write, text: &str) -> std::io::Result<()> {
+    write!(
+        out,
+        "\x1b]52;c;{}\x07",
+        mime::encode_base64(text.as_bytes())
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_only_web_and_mail_links() {
+        assert!(openable("https://example.com/"));
+        assert!(openable("HTTP://example.com/"));
+        assert!(openable("mailto:someone@example.com"));
+        assert!(!openable("file:///etc/passwd"));
+        assert!(!openable("javascript:alert(1)"));
+        assert!(!openable("example.com"));
+        assert_eq!(
+            open_link("true {}", "file:///etc/passwd"),
+            "Refused to open file:///etc/passwd"
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Soft-wrapping, scrolling text area used for the compose body.
+//!
+//! The text is split into display rows given as byte ranges, so the cursor
+//! (a byte offset) maps onto a row and a display column without copying.
+//! Columns are measured in terminal cells, so wide characters (CJK, emoji)
+//! take two.
+
+use std::ops::Range;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::Widget,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Splits text into display rows no wider than `width` cells.
+///
+/// Rows break after a space where possible and inside a word only when it
+/// is wider than a whole row. Spaces at a break stay at the end of the row
+/// they follow (and may overhang it), so the rows cover every byte except
+/// the newlines. Every line, including an empty last one, has at least one
+/// row.
+pub fn wrap_rows(text: &str, width: usize) -> Vec<Range<usize>> {
+    let mut rows = Vec::new();
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        let mut start = line_start;
+        let mut row_width = 0;
+        // Where the row could end: just after the last space seen
+        let mut last_break = None;
+        for (i, ch) in line.char_indices() {
+            let pos = line_start + i;
+            let ch_width = ch.width().unwrap_or(0);
+            if width > 0 && ch != ' ' && pos > start && row_width + ch_width > width {
+                let end = last_break.filter(|&b| b > start).unwrap_or(pos);
+                rows.push(start..end);
+                start = end;
+                row_width = text[start..pos].width();
+                last_break = None;
+            }
+            row_width += ch_width;
+            if ch == ' ' {
+                last_break = Some(pos + 1);
+            }
+        }
+        rows.push(start..line_start + line.len());
+        line_start += line.len() + 1;
+    }
+    rows
+}
+
+/// Index of the row the cursor is on. A cursor at a soft break shows at
+/// the start of the following row.
+pub fn row_of(rows: &[Range<usize>], cursor: usize) -> usize {
+    rows.iter()
+        .rposition(|row| row.start <= cursor)
+        .unwrap_or(0)
+}
+
+/// Moves the cursor `delta` display rows, keeping its display column as far
+/// as the target row allows (`gj` and `gk`).
+pub fn move_rows(text: &str, width: usize, cursor: usize, delta: isize) -> usize {
+    let rows = wrap_rows(text, width);
+    let row = row_of(&rows, cursor);
+    let column = text[rows[row].start..cursor].width();
+    let target = &rows[row.saturating_add_signed(delta).min(rows.len() - 1)];
+    let mut pos = target.start;
+    let mut used = 0;
+    for ch in text[target.clone()].chars() {
+        let ch_width = ch.width().unwrap_or(0);
+        if used + ch_width > column {
+            break;
+        }
+        used += ch_width;
+        pos += ch.len_utf8();
+    }
+    // The end of a soft-wrapped row is the start of the next one; stay on
+    // its last character instead, before any combining marks
+    if pos == target.end && pos > target.start && !ends_line(text, pos) {
+        pos = text[target.start..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, ch)| ch.width().unwrap_or(0) > 0)
+            .map_or(pos, |(i, _)| target.start + i);
+    }
+    pos
+}
+
+/// Whether a position is at a newline or the end of the text, where the
+/// cursor may rest past the last character.
+fn ends_line(text: &str, pos: usize) -> bool {
+    text[pos..].is_empty() || text[pos..].starts_with('\n')
+}
+
+/// Returns the first visible row after scrolling just enough to keep the
+/// cursor's row inside a view `height` rows tall.
+pub fn follow_cursor(scroll: usize, cursor_row: usize, height: usize) -> usize {
+    if cursor_row < scroll {
+        cursor_row
+    } else if height > 0 && cursor_row >= scroll + height {
+        cursor_row + 1 - height
+    } else {
+        scroll
+    }
+}
+
+/// Renders wrapped text from a scroll offset, highlighting a selection.
+pub struct TextArea<'a> {
+    text: &'a str,
+    rows: &'a [Range<usize>],
+    scroll: usize,
+    selection: Option<Range<usize>>,
+    underline: Vec<Range<usize>>,
+}
+
+impl<'a> TextArea<'a> {
+    /// `rows` must come from [`wrap_rows`] with the width of the area the
+    /// widget is rendered into.
+    pub fn new(text: &'a str, rows: &'a [Range<usize>]) -> Self {
+        Self {
+            text,
+            rows,
+            scroll: 0,
+            selection: None,
+            underline: Vec::new(),
+        }
+    }
+
+    pub fn scroll(mut self, scroll: usize) -> Self {
+        self.scroll = scroll;
+        self
+    }
+
+    pub fn selection(mut self, selection: Option<Range<usize>>) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Ranges to underline in red, such as misspelled words.
+    pub fn underline(mut self, ranges: Vec<Range<usize>>) -> Self {
+        self.underline = ranges;
+        self
+    }
+
+    /// Screen position of the cursor, or `None` when it is scrolled out of
+    /// view.
+    pub fn cursor_position(&self, area: Rect, cursor: usize) -> Option<(u16, u16)> {
+        let row = row_of(self.rows, cursor);
+        if row < self.scroll || row >= self.scroll + area.height as usize {
+            return None;
+        }
+        let column = self.text[self.rows[row].start..cursor].width();
+        Some((
+            area.x + (column as u16).min(area.width.saturating_sub(1)),
+            area.y + (row - self.scroll) as u16,
+        ))
+    }
+
+    fn line(&self, row: &Range<usize>) -> Line<'a> {
+        let selected = Style::default().add_modifier(Modifier::REVERSED);
+        let underlined = Style::default()
+            .add_modifier(Modifier::UNDERLINED)
+            .underline_color(Color::Red);
+        let styled = self
+            .selection
+            .iter()
+            .map(|range| (range, selected))
+            .chain(self.underline.iter().map(|range| (range, underlined)));
+
+        // Split the row wherever a styled range starts or ends
+        let mut cuts = vec![row.start, row.end];
+        for (range, _) in styled.clone() {
+            cuts.extend([range.start, range.end].map(|p| p.clamp(row.start, row.end)));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        let mut spans: Vec<Span<'a>> = cuts
+            .windows(2)
+            .map(|pair| {
+                let style = styled
+                    .clone()
+                    .filter(|(range, _)| range.start <= pair[0] && pair[1] <= range.end)
+                    .fold(Style::default(), |style, (_, s)| style.patch(s));
+                Span::styled(&self.text[pair[0]..pair[1]], style)
+            })
+            .collect();
+
+        // Show selected empty lines and line ends, as vim does
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|s| s.contains(&row.end))
+            && self.text[row.end..].starts_with('\n')
+        {
+            spans.push(Span::styled(" ", selected));
+        }
+        Line::from(spans)
+    }
+}
+
+impl Widget for TextArea<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for (y, row) in self
+            .rows
+            .iter()
+            .skip(self.scroll)
+            .take(area.height as usize)
+            .enumerate()
+        {
+            buf.set_line(area.x, area.y + y as u16, &self.line(row), area.width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(text: &str, width: usize) -> Vec<&str> {
+        wrap_rows(text, width)
+            .into_iter()
+            .map(|row| &text[row])
+            .collect()
+    }
+
+    #[test]
+    fn wraps_at_spaces_and_splits_long_words() {
+        assert_eq!(rows("one two three", 6), vec!["one ", "two ", "three"]);
+        assert_eq!(rows("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(rows("a\n\nb\n", 3), vec!["a", "", "b", ""]);
+        // Spaces overhang the row instead of starting the next one
+        assert_eq!(rows("ab    cd", 3), vec!["ab    ", "cd"]);
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        assert_eq!(
+            rows("日本語テキスト", 5),
+            vec!["日本", "語テ", "キス", "ト"]
+        );
+        // A wide character that would straddle the edge moves down
+        assert_eq!(rows("abc日本", 4), vec!["abc", "日本"]);
+        assert_eq!(rows("日本 語", 5), vec!["日本 ", "語"]);
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_base() {
+        // The mark takes no column, so it never starts a row
+        assert_eq!(rows("abce\u{301}x", 4), vec!["abce\u{301}", "x"]);
+        assert_eq!(rows("abcde\u{301}", 4), vec!["abcd", "e\u{301}"]);
+        assert_eq!(rows("cafe\u{301} ok", 4), vec!["cafe\u{301} ", "ok"]);
+    }
+
+    #[test]
+    fn moves_by_display_columns() {
+        let text = "abcd日本";
+        // Onto a wide character from either of its columns
+        assert_eq!(move_rows(text, 4, 1, 1), 4);
+        assert_eq!(move_rows(text, 4, 3, 1), 7);
+        assert_eq!(move_rows(text, 4, 7, -1), 2);
+        // Past the last row stays on it
+        assert_eq!(move_rows(text, 4, 4, 5), 4);
+        assert_eq!(move_rows(text, 4, 2, -1), 2);
+
+        // Columns after a combining mark count its base only once
+        let text = "e\u{301}bcd\nxyz";
+        assert_eq!(move_rows(text, 10, 8, -1), 3);
+        assert_eq!(move_rows(text, 10, 3, 1), 8);
+    }
+
+    #[test]
+    fn stays_before_the_soft_break() {
+        // The end of "ab" + mark is the start of the next row; the cursor
+        // lands on the base character, not inside it
+        let text = "ab\u{301}日x";
+        assert_eq!(rows(text, 3), vec!["ab\u{301}", "日x"]);
+        assert_eq!(move_rows(text, 3, 7, -1), 1);
+        // At a hard line end the cursor may rest past the last character
+        let text = "ab\u{301}\nxyz";
+        assert_eq!(move_rows(text, 3, 7, -1), 4);
+    }
+
+    #[test]
+    fn cursor_columns_count_cells() {
+        let text = "日本e\u{301}x\n語";
+        let rows = wrap_rows(text, 10);
+        let area = TextArea::new(text, &rows);
+        let rect = Rect::new(2, 1, 10, 5);
+        assert_eq!(area.cursor_position(rect, 0), Some((2, 1)));
+        assert_eq!(area.cursor_position(rect, 3), Some((4, 1)));
+        assert_eq!(area.cursor_position(rect, 9), Some((7, 1)));
+        assert_eq!(area.cursor_position(rect, 11), Some((2, 2)));
+        // A cursor at a soft break shows at the start of the next row
+        let text = "abc日本";
+        let rows = wrap_rows(text, 4);
+        let area = TextArea::new(text, &rows);
+        assert_eq!(area.cursor_position(rect, 3), Some((2, 2)));
+        // Scrolled out of view
+        assert_eq!(area.scroll(1).cursor_position(rect, 0), None);
+    }
+
+    #[test]
+    fn scrolls_only_to_keep_the_cursor_visible() {
+        assert_eq!(follow_cursor(0, 3, 5), 0);
+        assert_eq!(follow_cursor(0, 5, 5), 1);
+        assert_eq!(follow_cursor(4, 2, 5), 2);
+        assert_eq!(follow_cursor(4, 6, 5), 4);
+    }
+}
@@ -1,11 +1,21 @@
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout},
-    style::{Modifier, Style},
-    widgets::{Block, Borders, List, ListItem},
+    layout::{Alignment, Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{
+        Block, Borders, Clear, List, ListItem,
+        block::{Position, Title},
+    },
 };
 
+use unicode_width::UnicodeWidthStr;
+
+use super::{html, message, textarea, wrap};
 use crate::app::App;
+use crate::backend::address;
+use crate::crypto::{Level, Security, auth::AuthResult};
+use crate::markdown;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     use crate::app::Mode;
@@ -24,7 +34,25 @@ pub fn draw(f: &mut Frame, app: &mut App) {
             let items: Vec<ListItem> = app
                 .inbox
                 .iter()
-                .map(|email| ListItem::new(format!("{} — {}", email.from, email.subject)))
+                .map(|email| {
+                    // Flagged mail is marked, tags follow the subject and
+                    // unread mail is bold
+                    let mut text = format!(
+                        "{}{} — {}",
+                        if email.flagged { "! " } else { "" },
+                        email.from,
+                        email.subject
+                    );
+                    if !email.tags.is_empty() {
+                        text.push_str(&format!(" [{}]", email.tags.join(", ")));
+                    }
+                    let style = if email.read {
+                        Style::default()
+                    } else {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    };
+                    ListItem::new(Line::styled(text, style))
+                })
                 .collect();
 
             let list = List::new(items)
@@ -44,6 +72,36 @@ pub fn draw(f: &mut Frame, app: &mut App) {
                 let cmd_block = Block::default().borders(Borders::TOP).title("Command");
                 let cmd_list = List::new(vec![ListItem::new(cmd_text)]).block(cmd_block);
                 f.render_widget(cmd_list, cmd_chunks[1]);
+            } else if app.status.is_some() || app.new_mail > 0 {
+                // Last status message on the bottom border of the inbox,
+                // with mail that arrived in the background on the right
+                let status_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+                    .split(area);
+                let new_mail = if app.new_mail > 0 {
+                    format!(" {} new ", app.new_mail)
+                } else {
+                    String::new()
+                };
+                let bar_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(new_mail.width() as u16),
+                    ])
+                    .split(status_chunks[1]);
+                if let Some(status) = &app.status {
+                    let status_line = List::new(vec![ListItem::new(format!(" {} ", status))]);
+                    f.render_widget(status_line, bar_chunks[0]);
+                }
+                let new_line = List::new(vec![ListItem::new(Line::styled(
+                    new_mail,
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ))]);
+                f.render_widget(new_line, bar_chunks[1]);
             }
         }
         Mode::Help => {
@@ -58,7 +116,10 @@ pub fn draw(f: &mut Frame, app: &mut App) {
                 ListItem::new("  Enter      — open selected email"),
                 ListItem::new("  d          — delete selected email"),
                 ListItem::new("  n          — compose new email"),
+                ListItem::new("  c          — address book"),
                 ListItem::new("  :help      — show this help"),
+                ListItem::new("  :template <name> — compose from a template"),
+                ListItem::new("  :identity <name> — choose the identity to send from"),
                 ListItem::new("  Esc/q      — close overlay / quit help"),
                 ListItem::new("  q          — quit app"),
             ];
@@ -68,44 +129,33 @@ pub fn draw(f: &mut Frame, app: &mut App) {
                 .highlight_style(Style::default().add_modifier(Modifier::BOLD));
             f.render_widget(help, help_area);
         }
-        Mode::Viewing => {
-            // Show selected email summary in a centered box
-            let view_area = centered_rect(70, 50, area);
-
-            // Calculate visible height for body content (minus borders and header lines)
-            // Subtract 2 for borders, 3 for header lines (From, Subject, blank line)
-            let visible_body_height = view_area.height.saturating_sub(5) as usize;
-            app.view_height = visible_body_height;
+        Mode::Viewing => draw_viewer(f, app, area),
+        Mode::LinkPicker => {
+            draw_viewer(f, app, area);
 
-            let mut lines = Vec::new();
-            if let Some(email) = app.inbox.get(app.selected) {
-                // Header lines always visible
-                lines.push(ListItem::new(format!("From: {}", email.from)));
-                lines.push(ListItem::new(format!("Subject: {}", email.subject)));
-                lines.push(ListItem::new(""));
-
-                if email.body.is_empty() {
-                    lines.push(ListItem::new("(No body)"));
-                } else {
-                    // Apply view_offset only to body content
-                    for (idx, line) in email.body.lines().enumerate() {
-                        if idx >= app.view_offset {
-                            lines.push(ListItem::new(line.to_string()));
-                        }
-                    }
-                }
-            } else {
-                lines.push(ListItem::new("No email selected"));
-            }
-            let view =
-                List::new(lines).block(Block::default().title("Email").borders(Borders::ALL));
-            f.render_widget(view, view_area);
+            // Numbered list of links over the message
+            let picker_area = centered_rect(60, 40, area);
+            let items: Vec<ListItem> = app
+                .links
+                .iter()
+                .enumerate()
+                .map(|(idx, link)| ListItem::new(format!("{:>2}. {}", idx + 1, link)))
+                .collect();
+            let mut state = ratatui::widgets::ListState::default();
+            state.select(Some(app.link_selected));
+            let picker = List::new(items)
+                .block(Block::default().title("Links").borders(Borders::ALL))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_widget(Clear, picker_area);
+            f.render_stateful_widget(picker, picker_area, &mut state);
 
-            // Render the close hint just below the bordered box, outside of it
-            let hint_area = line_below(area, view_area);
+            let hint_area = line_below(area, picker_area);
             if hint_area.height > 0 {
-                let hint =
-                    List::new(vec![ListItem::new("[Esc/q/Enter] Close")]).block(Block::default());
+                let hint = List::new(vec![ListItem::new(
+                    "[j/k/1-9] Select  [Enter/o] Open  [y] Copy  [Esc/q] Back",
+                )])
+                .block(Block::default());
+                f.render_widget(Clear, hint_area);
                 f.render_widget(hint, hint_area);
             }
         }
@@ -118,45 +168,46 @@ pub fn draw(f: &mut Frame, app: &mut App) {
                 ComposeMode::Normal => "-- NORMAL --",
                 ComposeMode::Insert => "-- INSERT --",
                 ComposeMode::Visual => "-- VISUAL --",
+                ComposeMode::VisualLine => "-- VISUAL LINE --",
+                ComposeMode::Preview => "-- PREVIEW --",
             };
 
             let mut lines = Vec::new();
             lines.push(ListItem::new(format!("Compose Email {}", mode_indicator)));
             lines.push(ListItem::new(""));
 
-            // To field
-            let to_marker = if matches!(app.compose_field, ComposeField::To) {
-                ">"
-            } else {
-                " "
-            };
-            lines.push(ListItem::new(format!("{}To: {}", to_marker, &app.compose_to)));
-            
-            if matches!(app.compose_field, ComposeField::To) {
-                let char_idx = app.compose_to[..app.compose_cursor.min(app.compose_to.len())].chars().count();
-                cursor_pos = Some((
-                    compose_area.x + 1 + 1 + 4 + char_idx as u16, // border + marker + "To: " + offset
-                    compose_area.y + 1 + 2, // border + title line + blank line + this line
-                ));
+            // Sending identity, when any are configured
+            if let Some(identity) = app.identities.get(app.compose_identity) {
+                lines.push(ListItem::new(format!(" From: {}", identity.address())));
+            }
+            if app.compose_protected() {
+                lines.push(ListItem::new(format!(" {}", app.compose_security())));
             }
 
-            // Subject field
-            let subj_marker = if matches!(app.compose_field, ComposeField::Subject) {
-                ">"
-            } else {
-                " "
-            };
-            lines.push(ListItem::new(format!(
-                "{}Subject: {}",
-                subj_marker, &app.compose_subject
-            )));
-            
-            if matches!(app.compose_field, ComposeField::Subject) {
-                let char_idx = app.compose_subject[..app.compose_cursor.min(app.compose_subject.len())].chars().count();
-                cursor_pos = Some((
-                    compose_area.x + 1 + 1 + 9 + char_idx as u16, // border + marker + "Subject: " + offset
-                    compose_area.y + 1 + 3, // border + title + blank + to + this line
-                ));
+            // Header fields; Cc, Bcc and Reply-To only when extra headers are shown
+            let fields = [
+                (ComposeField::To, "To", &app.compose_to),
+                (ComposeField::Cc, "Cc", &app.compose_cc),
+                (ComposeField::Bcc, "Bcc", &app.compose_bcc),
+                (ComposeField::ReplyTo, "Reply-To", &app.compose_reply_to),
+                (ComposeField::Subject, "Subject", &app.compose_subject),
+            ];
+            for (field, label, value) in fields {
+                if field.is_extra() && !app.compose_show_extra {
+                    continue;
+                }
+                let active = app.compose_field == field;
+                let marker = if active { ">" } else { " " };
+                if active {
+                    let column = value[..app.compose_cursor.min(value.len())].width();
+                    cursor_pos = Some((
+                        // border + marker + "Label: " + offset
+                        compose_area.x + 1 + 1 + label.len() as u16 + 2 + column as u16,
+                        // border + lines above this one
+                        compose_area.y + 1 + lines.len() as u16,
+                    ));
+                }
+                lines.push(ListItem::new(format!("{}{}: {}", marker, label, value)));
             }
 
             lines.push(ListItem::new(""));
@@ -167,40 +218,156 @@ pub fn draw(f: &mut Frame, app: &mut App) {
             } else {
                 " "
             };
-            lines.push(ListItem::new(format!("{}Body:", body_marker)));
+            let body_label = if app.compose_markdown {
+                "Body (Markdown)"
+            } else {
+                "Body"
+            };
+            lines.push(ListItem::new(format!("{}{}:", body_marker, body_label)));
+            let body_top = lines.len() as u16;
 
-            let body_lines: Vec<&str> = app.compose_body.lines().collect();
-            for line in &body_lines {
-                lines.push(ListItem::new(format!("  {}", line)));
+            let compose =
+                List::new(lines).block(Block::default().title("New Email").borders(Borders::ALL));
+            f.render_widget(compose, compose_area);
+
+            // The body fills the rest of the box, wrapped and scrolled to
+            // keep the cursor in view
+            let body_area = ratatui::layout::Rect {
+                x: compose_area.x + 1 + 2,
+                y: compose_area.y + 1 + body_top,
+                width: compose_area.width.saturating_sub(2 + 2),
+                height: compose_area.height.saturating_sub(2 + body_top),
+            };
+            if app.compose_mode == ComposeMode::Preview {
+                // The HTML part as a mail reader would show it, scrolled
+                // like the message viewer
+                let rows = html::render(
+                    &markdown::to_html(&app.compose_body),
+                    body_area.width as usize,
+                );
+                let height = body_area.height as usize;
+                app.view_height = height;
+                app.view_rows = rows.len();
+                app.view_offset = app.view_offset.min(rows.len().saturating_sub(height));
+                let preview: Vec<ListItem> = rows
+                    .into_iter()
+                    .skip(app.view_offset)
+                    .take(height)
+                    .map(ListItem::new)
+                    .collect();
+                f.render_widget(List::new(preview), body_area);
             }
-            if body_lines.is_empty() {
-                lines.push(ListItem::new("  "));
+            let body_focused = matches!(app.compose_field, ComposeField::Body);
+            let rows = textarea::wrap_rows(&app.compose_body, body_area.width as usize);
+            app.compose_body_width = body_area.width as usize;
+            if body_focused {
+                let cursor_row = textarea::row_of(&rows, app.compose_cursor);
+                app.compose_scroll = textarea::follow_cursor(
+                    app.compose_scroll,
+                    cursor_row,
+                    body_area.height as usize,
+                );
             }
-            
-            if matches!(app.compose_field, ComposeField::Body) {
-                cursor_pos = Some((
-                    compose_area.x + 1 + 2 + app.compose_col as u16, // border + "  " indent + column
-                    compose_area.y + 1 + 6 + app.compose_line as u16, // border + title + blank + to + subject + blank + "Body:" + line
-                ));
+            app.compose_scroll = app.compose_scroll.min(rows.len().saturating_sub(1));
+            let body = textarea::TextArea::new(&app.compose_body, &rows)
+                .scroll(app.compose_scroll)
+                .selection(app.compose_selection().filter(|_| body_focused))
+                .underline(app.compose_misspellings());
+            if app.compose_mode == ComposeMode::Preview {
+                cursor_pos = None;
+            } else {
+                if body_focused {
+                    cursor_pos = body.cursor_position(body_area, app.compose_cursor);
+                }
+                f.render_widget(body, body_area);
             }
 
-            let compose =
-                List::new(lines).block(Block::default().title("New Email").borders(Borders::ALL));
-            f.render_widget(compose, compose_area);
+            // Address completions or spelling suggestions, just below the
+            // field being edited
+            if let Some((_, cursor_y)) = cursor_pos {
+                let popup_x = compose_area.x + 2;
+                if !app.compose_completions.is_empty() {
+                    let items = app.compose_completions.iter().map(|a| a.to_string());
+                    draw_popup(
+                        f,
+                        (popup_x, cursor_y + 1),
+                        items.collect(),
+                        app.compose_completion_selected,
+                    );
+                } else if let Some(suggestions) = &app.compose_suggestions {
+                    let items = suggestions
+                        .words
+                        .iter()
+                        .enumerate()
+                        .map(|(i, word)| format!("{} {}", i + 1, word));
+                    draw_popup(
+                        f,
+                        (popup_x, cursor_y + 1),
+                        items.collect(),
+                        suggestions.selected,
+                    );
+                }
+            }
 
             let hint_area = line_below(area, compose_area);
-            if hint_area.height > 0 {
+            if hint_area.height > 0
+                && let Some(input) = &app.compose_search_input
+            {
+                // The search prompt takes over the hint line, like vim's
+                let prompt = format!(
+                    "{}{}",
+                    if app.compose_search_forward { '/' } else { '?' },
+                    input
+                );
+                cursor_pos = Some((hint_area.x + prompt.width() as u16, hint_area.y));
+                let hint = List::new(vec![ListItem::new(prompt)]).block(Block::default());
+                f.render_widget(hint, hint_area);
+            } else if hint_area.height > 0 {
                 let hint_text = match app.compose_mode {
+                    _ if app.status.is_some() => app.status.as_deref().unwrap_or_default(),
+                    _ if app.compose_suggestions.is_some() => {
+                        "[j/k] Select  [1-9] Choose  [Enter] Replace  [Esc] Cancel"
+                    }
+                    ComposeMode::Insert if !app.compose_completions.is_empty() => {
+                        "[Tab/Down] Next  [S-Tab/Up] Previous  [Enter] Accept  [Esc] Cancel"
+                    }
                     ComposeMode::Normal => {
-                        "[j/k] Navigate  [i/a/o] Insert  [v] Visual  [x/dd] Delete  [>/<] Indent  [:] Send"
+                        "[j/k] Navigate  [i/a/o] Insert  [v/V] Visual  [d/c/y+motion] Edit  [/?/n/N] Search  []s/z=] Spelling  [p] Put  [.] Repeat  [u/^r/g-/g+] Undo/Redo  [H] Cc/Bcc  [M/K] Markdown/Preview  [zs/ze/zm] Sign/Encrypt/S/MIME  [E] Editor  [:] Send"
+                    }
+                    ComposeMode::Preview => {
+                        "[j/k] Scroll  [^d/^u] Half page  [g/G] Top/Bottom  [Esc/q/K] Back to editing"
+                    }
+                    ComposeMode::Insert => "[Esc] Normal  [Arrows] Move  [Tab] Complete address",
+                    ComposeMode::Visual => {
+                        "[h/l] Move  [d/x] Delete  [y] Yank  [V] Lines  [Esc] Exit"
+                    }
+                    ComposeMode::VisualLine => {
+                        "[j/k] Select  [>/<] Indent  [d/x] Delete  [y] Yank  [v] Characters  [Esc] Exit"
                     }
-                    ComposeMode::Insert => "[Esc] Normal  [h/j/k/l] Move",
-                    ComposeMode::Visual => "[h/l] Move  [d/x] Delete  [Esc] Exit",
                 };
                 let hint = List::new(vec![ListItem::new(hint_text)]).block(Block::default());
                 f.render_widget(hint, hint_area);
+
+                // Keys of an unfinished command, where vim shows them
+                if !app.compose_pending.is_empty() {
+                    let pending = format!(" {} ", app.compose_pending);
+                    let width = (pending.width() as u16).min(hint_area.width);
+                    let pending_area = ratatui::layout::Rect {
+                        x: hint_area.x + hint_area.width - width,
+                        width,
+                        ..hint_area
+                    };
+                    let pending = List::new(vec![ListItem::new(Line::styled(
+                        pending,
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ))]);
+                    f.render_widget(Clear, pending_area);
+                    f.render_widget(pending, pending_area);
+                }
             }
         }
+        Mode::Contacts => draw_contacts(f, app, area, &mut cursor_pos),
+        Mode::SendConfirm => draw_send_confirm(f, app, area),
         Mode::DeleteConfirm => {
             // Show delete confirmation in centered box
             let confirm_area = centered_rect(50, 30, area);
@@ -225,13 +392,319 @@ pub fn draw(f: &mut Frame, app: &mut App) {
             f.render_widget(confirm, confirm_area);
         }
     }
-    
+
     // Set hardware cursor position if in compose mode
     if let Some((x, y)) = cursor_pos {
         f.set_cursor(x, y);
     }
 }
 
+/// Summary of the message about to be sent, with the pre-send warnings.
+fn draw_send_confirm(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let confirm_area = centered_rect(70, 50, area);
+    let mut lines = Vec::new();
+    if let Some(review) = &app.send_review {
+        let message = &review.message;
+        lines.push(ListItem::new("Send this email?"));
+        lines.push(ListItem::new(""));
+        if let Some(from) = &message.from {
+            lines.push(ListItem::new(format!("From: {}", from)));
+        }
+        for (label, list) in [
+            ("To", &message.to),
+            ("Cc", &message.cc),
+            ("Bcc", &message.bcc),
+            ("Reply-To", &message.reply_to),
+        ] {
+            if !list.is_empty() {
+                lines.push(ListItem::new(format!(
+                    "{}: {}",
+                    label,
+                    address::format_list(list)
+                )));
+            }
+        }
+        let subject = if message.subject.trim().is_empty() {
+            "(no subject)"
+        } else {
+            &message.subject
+        };
+        lines.push(ListItem::new(format!("Subject: {}", subject)));
+        let format = if message.html.is_some() {
+            "plain text and HTML (Markdown)"
+        } else {
+            "plain text"
+        };
+        lines.push(ListItem::new(format!(
+            "Body: {} lines, {}",
+            message.body.lines().count(),
+            format
+        )));
+        if message.sign || message.encrypt || message.smime_sign {
+            lines.push(ListItem::new(app.compose_security()));
+        }
+
+        if !review.warnings.is_empty() {
+            lines.push(ListItem::new(""));
+            let warning = Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD);
+            for text in &review.warnings {
+                lines.push(ListItem::new(Line::styled(format!("! {}", text), warning)));
+            }
+        }
+        lines.push(ListItem::new(""));
+        lines.push(ListItem::new("Press Y to send, N or Esc to keep editing"));
+    }
+
+    let confirm =
+        List::new(lines).block(Block::default().title("Confirm Send").borders(Borders::ALL));
+    f.render_widget(Clear, confirm_area);
+    f.render_widget(confirm, confirm_area);
+}
+
+/// Signature and encryption status, coloured by how far it can be trusted.
+fn security_badge(security: &Security) -> ListItem<'static> {
+    let (text, level) = security.badge();
+    let colour = match level {
+        Level::Good => Color::Green,
+        Level::Warning => Color::Yellow,
+        Level::Bad => Color::Red,
+    };
+    ListItem::new(Line::styled(
+        text,
+        Style::default().fg(colour).add_modifier(Modifier::BOLD),
+    ))
+}
+
+/// DKIM, SPF and DMARC results on one line, each coloured by outcome.
+fn authentication_badges(results: &[AuthResult]) -> Option<ListItem<'static>> {
+    if results.is_empty() {
+        return None;
+    }
+    let mut spans = vec![Span::raw("Auth:")];
+    for result in results {
+        let colour = match result.level() {
+            Level::Good => Color::Green,
+            Level::Warning => Color::Yellow,
+            Level::Bad => Color::Red,
+        };
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(
+            format!("[{}]", result.label()),
+            Style::default().fg(colour),
+        ));
+    }
+    Some(ListItem::new(Line::from(spans)))
+}
+
+fn draw_viewer(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    // Show selected email summary in a centered box
+    let view_area = centered_rect(70, 50, area);
+    let body_width = view_area.width.saturating_sub(2) as usize;
+
+    // Fixed lines stay in place above the scrolling rows
+    let mut fixed: Vec<ListItem> = Vec::new();
+    let mut rows: Vec<Line<'static>> = Vec::new();
+    if let Some(email) = app.inbox.get(app.selected) {
+        if app.view_raw {
+            rows = message::render_raw(&email.raw, body_width);
+        } else {
+            let badge = email.security.as_ref().map(security_badge);
+            let authentication = authentication_badges(&email.authentication);
+            if app.view_all_headers {
+                // Full headers scroll along with the body
+                rows = message::render_headers(&email.headers, body_width);
+                rows.push(Line::from(""));
+                fixed.extend(badge);
+                fixed.extend(authentication);
+            } else {
+                // Header lines always visible
+                fixed.push(ListItem::new(format!("From: {}", email.from)));
+                fixed.push(ListItem::new(format!("Subject: {}", email.subject)));
+                fixed.extend(badge);
+                fixed.extend(authentication);
+                fixed.push(ListItem::new(""));
+            }
+
+            if email.body.is_empty() && email.html.is_none() {
+                rows.push(Line::from("(No body)"));
+            } else {
+                // Fall back to the HTML part when there is no text/plain one
+                rows.extend(match &email.html {
+                    Some(html) if email.body.is_empty() => html::render(html, body_width),
+                    _ => message::render_body(
+                        &email.body,
+                        body_width,
+                        email.content_type.as_deref(),
+                        app.view_expand_quotes,
+                    ),
+                });
+            }
+        }
+    } else {
+        fixed.push(ListItem::new("No email selected"));
+    }
+
+    // Visible height for the scrolling rows: minus borders and fixed lines
+    let visible_body_height = (view_area.height as usize).saturating_sub(2 + fixed.len());
+    app.view_height = visible_body_height;
+    app.view_rows = rows.len();
+    // Keep the offset valid after a resize changes the wrapping
+    app.view_offset = app
+        .view_offset
+        .min(rows.len().saturating_sub(visible_body_height));
+
+    let mut lines = fixed;
+    lines.extend(
+        rows.into_iter()
+            .skip(app.view_offset)
+            .take(visible_body_height)
+            .map(ListItem::new),
+    );
+
+    let title = if app.view_raw {
+        "Email (source)"
+    } else {
+        "Email"
+    };
+    let position = match app.view_scroll_percent() {
+        Some(percent) => format!(" {}% ", percent),
+        None => " All ".to_string(),
+    };
+    let block = Block::default()
+        .title(title)
+        .title(
+            Title::from(position)
+                .alignment(Alignment::Right)
+                .position(Position::Bottom),
+        )
+        .borders(Borders::ALL);
+    let view = List::new(lines).block(block);
+    f.render_widget(view, view_area);
+
+    // Render the close hint just below the bordered box, outside of it
+    let hint_area = line_below(area, view_area);
+    if hint_area.height > 0 {
+        let hint_text = app.status.clone().unwrap_or_else(|| {
+            "[j/k] Scroll  [^d/^u] Half page  [Space/PgUp/PgDn] Page  [g/G] Top/Bottom  [z] Quotes  [h] Headers  [r] Source  [o] Links  [Esc/q/Enter] Close".to_string()
+        });
+        let hint = List::new(vec![ListItem::new(hint_text)]).block(Block::default());
+        f.render_widget(hint, hint_area);
+    }
+}
+
+fn draw_contacts(
+    f: &mut Frame,
+    app: &App,
+    area: ratatui::layout::Rect,
+    cursor_pos: &mut Option<(u16, u16)>,
+) {
+    use crate::app::ContactField;
+
+    let contacts_area = centered_rect(80, 70, area);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(contacts_area);
+
+    let items: Vec<ListItem> = app
+        .contacts
+        .contacts
+        .iter()
+        .map(|contact| ListItem::new(contact.display_name().to_string()))
+        .collect();
+    let mut state = ratatui::widgets::ListState::default();
+    if !items.is_empty() {
+        state.select(Some(app.contact_selected));
+    }
+    let title = format!("Contacts ({})", items.len());
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, columns[0], &mut state);
+
+    // Details of the selected contact
+    let detail_width = columns[1].width.saturating_sub(2) as usize;
+    let mut details: Vec<ListItem> = Vec::new();
+    match app.contacts.contacts.get(app.contact_selected) {
+        Some(contact) => {
+            details.push(ListItem::new(Line::styled(
+                contact.display_name().to_string(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            details.push(ListItem::new(""));
+            for email in &contact.emails {
+                details.push(ListItem::new(email.clone()));
+            }
+            if !contact.notes.is_empty() {
+                details.push(ListItem::new(""));
+                for line in contact.notes.lines() {
+                    for row in wrap::wrap_line(line, detail_width) {
+                        details.push(ListItem::new(row));
+                    }
+                }
+            }
+        }
+        None => details.push(ListItem::new("No contacts yet. Press a to add one.")),
+    }
+    let detail = List::new(details).block(Block::default().borders(Borders::ALL));
+    f.render_widget(detail, columns[1]);
+
+    if let Some(form) = &app.contact_form {
+        let form_area = centered_rect(60, 30, area);
+        let fields = [
+            (ContactField::Name, "Name", &form.name),
+            (ContactField::Emails, "Addresses", &form.emails),
+            (ContactField::Notes, "Notes", &form.notes),
+        ];
+        let mut lines = vec![ListItem::new("")];
+        for (field, label, value) in fields {
+            let marker = if form.field == field { ">" } else { " " };
+            // Notes may span lines; keep them on one row while editing
+            let value = value.replace('\n', " ↵ ");
+            if form.field == field {
+                *cursor_pos = Some((
+                    // border + marker + "Label: " + text
+                    form_area.x + 1 + 1 + label.len() as u16 + 2 + value.width() as u16,
+                    form_area.y + 1 + lines.len() as u16,
+                ));
+            }
+            lines.push(ListItem::new(format!("{}{}: {}", marker, label, value)));
+        }
+        let title = if form.index.is_some() {
+            "Edit Contact"
+        } else {
+            "New Contact"
+        };
+        let form_widget =
+            List::new(lines).block(Block::default().title(title).borders(Borders::ALL));
+        f.render_widget(Clear, form_area);
+        f.render_widget(form_widget, form_area);
+    }
+
+    let hint_area = line_below(area, contacts_area);
+    if hint_area.height > 0 {
+        let hint_text = if let Some(status) = &app.status {
+            status.clone()
+        } else if app.contact_form.is_some() {
+            "[Tab/Up/Down] Field  [Enter] Save  [Esc] Cancel".to_string()
+        } else if app.contact_delete_pending {
+            let name = app
+                .contacts
+                .contacts
+                .get(app.contact_selected)
+                .map(|c| c.display_name())
+                .unwrap_or_default();
+            format!("Delete {}? [y/n]", name)
+        } else {
+            "[j/k] Move  [e/Enter] Edit  [a] Add  [d] Delete  [c] Compose  [Esc/q] Back".to_string()
+        };
+        let hint = List::new(vec![ListItem::new(hint_text)]).block(Block::default());
+        f.render_widget(hint, hint_area);
+    }
+}
+
 // Helper to keep ListState creation clean
 fn app_state(app: &App) -> ratatui::widgets::ListState {
     let mut state = ratatui::widgets::ListState::default();
@@ -239,6 +712,26 @@ fn app_state(app: &App) -> ratatui::widgets::ListState {
     state
 }
 
+/// Draws a bordered list with the selected row highlighted, anchored at
+/// `(x, y)` and clipped to the frame.
+fn draw_popup(f: &mut Frame, (x, y): (u16, u16), items: Vec<String>, selected: usize) {
+    let area = f.size();
+    let widest = items.iter().map(|item| item.width()).max().unwrap_or(0) as u16;
+    let popup_area = ratatui::layout::Rect {
+        x,
+        y,
+        width: (widest + 2).min(area.width.saturating_sub(x)),
+        height: (items.len() as u16 + 2).min(area.height.saturating_sub(y)),
+    };
+    let mut state = ratatui::widgets::ListState::default();
+    state.select(Some(selected));
+    let popup = List::new(items.into_iter().map(ListItem::new))
+        .block(Block::default().borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_widget(Clear, popup_area);
+    f.render_stateful_widget(popup, popup_area, &mut state);
+}
+
 fn centered_rect(
     percent_x: u16,
     percent_y: u16,
@@ -0,0 +1,284 @@
+use std::borrow::Cow;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Columns between tab stops, as most terminals set them.
+const TAB_WIDTH: usize = 8;
+
+/// Splits an email body into logical lines ready for wrapping.
+///
+/// When the message is `format=flowed` (RFC 3676) soft line breaks are joined
+/// back into paragraphs first, so the text reflows to the pane width instead
+/// of the width the sender's client chose.
+pub fn logical_lines(body: &str, content_type: Option<&str>) -> Vec<String> {
+    let (flowed, delsp) = flowed_params(content_type);
+    if flowed {
+        unflow(body, delsp)
+    } else {
+        body.lines().map(str::to_string).collect()
+    }
+}
+
+/// Returns whether a Content-Type value declares `format=flowed` and `delsp=yes`.
+fn flowed_params(content_type: Option<&str>) -> (bool, bool) {
+    let Some(ct) = content_type else {
+        return (false, false);
+    };
+    let mut flowed = false;
+    let mut delsp = false;
+    for param in ct.split(';').skip(1) {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        match name.trim().to_ascii_lowercase().as_str() {
+            "format" => flowed = value.eq_ignore_ascii_case("flowed"),
+            "delsp" => delsp = value.eq_ignore_ascii_case("yes"),
+            _ => {}
+        }
+    }
+    (flowed, delsp)
+}
+
+/// Joins flowed lines (those ending in a space) into logical lines.
+///
+/// Quote depth is preserved: a change in depth always ends a paragraph, and
+/// the joined text is re-prefixed with the same number of `>` markers.
+fn unflow(body: &str, delsp: bool) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for raw in body.lines() {
+        let depth = raw.chars().take_while(|c| *c == '>').count();
+        let mut content = &raw[depth..];
+        // Undo space-stuffing
+        if let Some(rest) = content.strip_prefix(' ') {
+            content = rest;
+        }
+        let is_signature = content == "-- ";
+        let soft = !is_signature && content.ends_with(' ');
+        if soft && delsp {
+            content = &content[..content.len() - 1];
+        }
+
+        match current.as_mut() {
+            Some((d, text)) if *d == depth => text.push_str(content),
+            _ => {
+                if let Some((d, text)) = current.take() {
+                    out.push(with_quote_prefix(d, &text));
+                }
+                current = Some((depth, content.to_string()));
+            }
+        }
+
+        if !soft && let Some((d, text)) = current.take() {
+            out.push(with_quote_prefix(d, &text));
+        }
+    }
+
+    if let Some((d, text)) = current {
+        out.push(with_quote_prefix(d, &text));
+    }
+    out
+}
+
+fn with_quote_prefix(depth: usize, text: &str) -> String {
+    if depth == 0 {
+        text.to_string()
+    } else {
+        format!("{} {}", ">".repeat(depth), text.trim_end())
+    }
+}
+
+/// Returns the leading quote markers of a line (e.g. `"> > "`), if any.
+fn quote_prefix(line: &str) -> &str {
+    let end = line
+        .char_indices()
+        .find(|(_, c)| *c != '>' && *c != ' ')
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+    if line[..end].contains('>') {
+        &line[..end]
+    } else {
+        ""
+    }
+}
+
+/// Number of `>` quote markers at the start of a line.
+pub fn quote_depth(line: &str) -> usize {
+    quote_prefix(line).matches('>').count()
+}
+
+/// Replaces tabs with spaces up to the next tab stop, since unicode-width
+/// counts a tab as zero columns.
+fn expand_tabs(line: &str) -> Cow<'_, str> {
+    if !line.contains('\t') {
+        return Cow::Borrowed(line);
+    }
+    let mut out = String::with_capacity(line.len() + TAB_WIDTH);
+    let mut column = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = TAB_WIDTH - column % TAB_WIDTH;
+            out.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            out.push(ch);
+            column += ch.width().unwrap_or(0);
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Greedily word-wraps a single line to `width` display columns.
+///
+/// Tabs are expanded first. Words wider than a whole row are split at
+/// character boundaries, and continuation rows of quoted lines repeat the
+/// quote markers.
+pub fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let line = expand_tabs(line);
+    let line = line.as_ref();
+    if width == 0 || line.width() <= width {
+        return vec![line.to_string()];
+    }
+
+    let mut prefix = quote_prefix(line);
+    if prefix.width() * 2 > width {
+        prefix = "";
+    }
+    let prefix_width = prefix.width();
+
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut row_width = 0;
+
+    for word in line.split_inclusive(' ') {
+        let word_width = word.trim_end_matches(' ').width();
+        if row_width + word_width > width && row_width > prefix_width {
+            rows.push(row.trim_end().to_string());
+            row = prefix.to_string();
+            row_width = prefix_width;
+        }
+
+        if row_width + word_width > width {
+            // Word is wider than a whole row: hard-split it
+            for ch in word.chars() {
+                let ch_width = ch.width().unwrap_or(0);
+                if row_width + ch_width > width && row_width > prefix_width {
+                    rows.push(row.trim_end().to_string());
+                    row = prefix.to_string();
+                    row_width = prefix_width;
+                }
+                row.push(ch);
+                row_width += ch_width;
+            }
+        } else {
+            row.push_str(word);
+            row_width += word.width();
+        }
+    }
+
+    if row_width > prefix_width || rows.is_empty() {
+        rows.push(row.trim_end().to_string());
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_lines_are_left_alone() {
+        assert_eq!(wrap_line("hello world", 20), vec!["hello world"]);
+        assert_eq!(wrap_line("", 20), vec![""]);
+        assert_eq!(wrap_line("anything at all", 0), vec!["anything at all"]);
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries() {
+        assert_eq!(
+            wrap_line("the quick brown fox jumps", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+    }
+
+    #[test]
+    fn splits_words_wider_than_a_row() {
+        assert_eq!(wrap_line("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn counts_wide_characters_as_two_columns() {
+        // Each ideograph takes two columns, so three fit in six
+        assert_eq!(wrap_line("日本語日本語", 6), vec!["日本語", "日本語"]);
+        // A wide character never straddles the edge of a row
+        assert_eq!(wrap_line("a日本", 4), vec!["a日", "本"]);
+    }
+
+    #[test]
+    fn combining_characters_take_no_columns() {
+        // "e" followed by a combining acute accent is one column wide
+        let word = "e\u{301}e\u{301}e\u{301}";
+        assert_eq!(wrap_line(word, 3), vec![word]);
+        assert_eq!(
+            wrap_line(&format!("{} {}", word, word), 3),
+            vec![word, word]
+        );
+    }
+
+    #[test]
+    fn expands_tabs_to_tab_stops() {
+        assert_eq!(expand_tabs("a\tb"), "a       b");
+        assert_eq!(expand_tabs("abcdefgh\tb"), "abcdefgh        b");
+        assert_eq!(expand_tabs("\t\tx"), format!("{}x", " ".repeat(16)));
+        assert_eq!(
+            wrap_line("\tindented text", 20),
+            vec!["        indented", "text"]
+        );
+    }
+
+    #[test]
+    fn quoted_lines_repeat_their_markers() {
+        assert_eq!(
+            wrap_line("> > one two three four", 12),
+            vec!["> > one two", "> > three", "> > four"]
+        );
+        assert_eq!(quote_depth("> > text"), 2);
+        assert_eq!(quote_depth(">>text"), 2);
+        assert_eq!(quote_depth("no quote"), 0);
+    }
+
+    #[test]
+    fn joins_flowed_paragraphs() {
+        let body = "one \ntwo\n> quoted \n> more\nplain";
+        let content_type = Some("text/plain; charset=utf-8; format=flowed");
+        assert_eq!(
+            logical_lines(body, content_type),
+            vec!["one two", "> quoted more", "plain"]
+        );
+        // Without format=flowed the lines are kept as they are
+        assert_eq!(
+            logical_lines(body, Some("text/plain")),
+            vec!["one ", "two", "> quoted ", "> more", "plain"]
+        );
+    }
+
+    #[test]
+    fn delsp_removes_the_soft_break_space() {
+        let content_type = Some("text/plain; format=flowed; delsp=yes");
+        assert_eq!(
+            logical_lines("hyphen- \nated", content_type),
+            vec!["hyphen-ated"]
+        );
+    }
+
+    #[test]
+    fn signature_separator_is_not_a_soft_break() {
+        let content_type = Some("text/plain; format=flowed");
+        assert_eq!(
+            logical_lines("text\n-- \nme", content_type),
+            vec!["text", "-- ", "me"]
+        );
+    }
+}
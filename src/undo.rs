@@ -0,0 +1,180 @@
+//! Vim-style undo tree. Every recorded state is kept: undoing and then
+//! making a new change starts a new branch instead of discarding the old
+//! one, and redo follows the branch that was visited last. Other branches
+//! are reached by stepping through states in the order they were recorded
+//! (vim's `g-` and `g+`).
+
+#[derive(Debug)]
+struct Node<T> {
+    state: T,
+    parent: Option<usize>,
+    /// Child that redo moves to: the newest one, or the one last undone from.
+    redo: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct UndoTree<T> {
+    /// In the order they were recorded.
+    nodes: Vec<Node<T>>,
+    current: usize,
+}
+
+impl<T> UndoTree<T> {
+    pub fn new(state: T) -> Self {
+        Self {
+            nodes: vec![Node {
+                state,
+                parent: None,
+                redo: None,
+            }],
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> &T {
+        &self.nodes[self.current].state
+    }
+
+    /// Lets the caller refresh bookkeeping (such as the cursor position)
+    /// stored alongside the current state.
+    pub fn current_mut(&mut self) -> &mut T {
+        &mut self.nodes[self.current].state
+    }
+
+    /// Adds a new state as a child of the current one.
+    pub fn record(&mut self, state: T) {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            state,
+            parent: Some(self.current),
+            redo: None,
+        });
+        self.nodes[self.current].redo = Some(index);
+        self.current = index;
+    }
+
+    /// Steps back to the parent state, if there is one.
+    pub fn undo(&mut self) -> Option<&T> {
+        let parent = self.nodes[self.current].parent?;
+        self.nodes[parent].redo = Some(self.current);
+        self.current = parent;
+        Some(self.current())
+    }
+
+    /// Steps forward along the most recently visited branch.
+    pub fn redo(&mut self) -> Option<&T> {
+        self.current = self.nodes[self.current].redo?;
+        Some(self.current())
+    }
+
+    /// Moves to the state recorded just before the current one.
+    pub fn earlier(&mut self) -> Option<&T> {
+        let index = self.current.checked_sub(1)?;
+        Some(self.jump(index))
+    }
+
+    /// Moves to the state recorded just after the current one.
+    pub fn later(&mut self) -> Option<&T> {
+        let index = self.current + 1;
+        if index >= self.nodes.len() {
+            return None;
+        }
+        Some(self.jump(index))
+    }
+
+    /// Makes `index` current, pointing redo along the path to it so undo
+    /// and redo then move along its branch.
+    fn jump(&mut self, index: usize) -> &T {
+        let mut child = index;
+        while let Some(parent) = self.nodes[child].parent {
+            self.nodes[parent].redo = Some(child);
+            child = parent;
+        }
+        self.current = index;
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_walk_a_line_of_changes() {
+        let mut tree = UndoTree::new("a");
+        tree.record("ab");
+        tree.record("abc");
+        assert_eq!(tree.undo(), Some(&"ab"));
+        assert_eq!(tree.undo(), Some(&"a"));
+        assert_eq!(tree.undo(), None);
+        assert_eq!(*tree.current(), "a");
+        assert_eq!(tree.redo(), Some(&"ab"));
+        assert_eq!(tree.redo(), Some(&"abc"));
+        assert_eq!(tree.redo(), None);
+        assert_eq!(*tree.current(), "abc");
+    }
+
+    #[test]
+    fn a_change_after_undo_starts_a_branch() {
+        let mut tree = UndoTree::new("a");
+        tree.record("ab");
+        tree.undo();
+        tree.record("ax");
+        // Redo has nothing to follow from the new branch's tip
+        assert_eq!(tree.redo(), None);
+        // Undo goes back to the shared parent, and redo follows the branch
+        // just left, not the older one
+        assert_eq!(tree.undo(), Some(&"a"));
+        assert_eq!(tree.redo(), Some(&"ax"));
+        // The older branch is kept, and reached by stepping back in time
+        assert_eq!(tree.earlier(), Some(&"ab"));
+    }
+
+    #[test]
+    fn earlier_and_later_step_through_every_branch() {
+        let mut tree = UndoTree::new("a");
+        tree.record("ab");
+        tree.record("abc");
+        tree.undo();
+        tree.undo();
+        tree.record("ax");
+        assert_eq!(tree.later(), None);
+        assert_eq!(tree.earlier(), Some(&"abc"));
+        assert_eq!(tree.earlier(), Some(&"ab"));
+        assert_eq!(tree.earlier(), Some(&"a"));
+        assert_eq!(tree.earlier(), None);
+        assert_eq!(tree.later(), Some(&"ab"));
+        assert_eq!(tree.later(), Some(&"abc"));
+        // Undo and redo then follow the branch stepped onto
+        assert_eq!(tree.undo(), Some(&"ab"));
+        assert_eq!(tree.undo(), Some(&"a"));
+        assert_eq!(tree.redo(), Some(&"ab"));
+        assert_eq!(tree.redo(), Some(&"abc"));
+        assert_eq!(tree.later(), Some(&"ax"));
+        assert_eq!(tree.undo(), Some(&"a"));
+        assert_eq!(tree.redo(), Some(&"ax"));
+    }
+
+    #[test]
+    fn redo_follows_the_branch_last_undone_from() {
+        let mut tree = UndoTree::new(0);
+        tree.record(1);
+        tree.undo();
+        tree.record(2);
+        tree.record(3);
+        tree.undo();
+        tree.undo();
+        assert_eq!(*tree.current(), 0);
+        assert_eq!(tree.redo(), Some(&2));
+        assert_eq!(tree.redo(), Some(&3));
+    }
+
+    #[test]
+    fn current_mut_updates_the_state_in_place() {
+        let mut tree = UndoTree::new(String::from("a"));
+        tree.record(String::from("b"));
+        tree.current_mut().push('!');
+        tree.undo();
+        assert_eq!(tree.redo(), Some(&String::from("b!")));
+    }
+}
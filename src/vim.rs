@@ -0,0 +1,827 @@
+//! Vim normal-mode command grammar and the text motions it needs.
+//!
+//! Commands follow `["x][count]operator[count](motion|text object)` or
+//! `["x][count]command`. Positions are byte offsets into the text being
+//! edited, always on character boundaries.
+
+use std::ops::Range;
+
+/// Largest count accepted, so a typo cannot start a huge loop.
+const MAX_COUNT: usize = 9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    /// `gg`, or line N with a count.
+    FirstLine,
+    /// `G`, or line N with a count.
+    LastLine,
+    /// `gk`: up one row of soft-wrapped text.
+    DisplayUp,
+    /// `gj`: down one row of soft-wrapped text.
+    DisplayDown,
+    /// `]s`: start of the next misspelled word.
+    NextMisspelled,
+    /// `[s`: start of the previous misspelled word.
+    PrevMisspelled,
+}
+
+impl Motion {
+    /// Line-wise motions make operators act on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    /// Inclusive motions make operators take the character they land on.
+    pub fn is_inclusive(self) -> bool {
+        self == Motion::WordEnd
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Word,
+    Paragraph,
+    /// Text between a pair of the given quote character on the line.
+    Quote(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextObject {
+    pub kind: ObjectKind,
+    /// `a` objects include surrounding whitespace or delimiters; `i` ones
+    /// only the inside.
+    pub around: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// The operator doubled (`dd`, `cc`, `yy`): whole lines.
+    Line,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Insert {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    DeleteChar,
+    DeleteCharBefore,
+    Put {
+        after: bool,
+    },
+    Insert(Insert),
+    Indent {
+        right: bool,
+    },
+    Undo,
+    /// `g-` (earlier) or `g+` (later): steps through every recorded state
+    /// in the order it was made, including branches undo and redo skip.
+    Chronological {
+        later: bool,
+    },
+    Repeat,
+    Visual,
+    VisualLine,
+    /// `/` (forward) or `?` (backward).
+    Search {
+        forward: bool,
+    },
+    /// `n`, or `N` to search the other way.
+    SearchNext {
+        reverse: bool,
+    },
+    ToggleExtra,
+    /// `M`: write the body in Markdown, sent with an HTML alternative.
+    ToggleMarkdown,
+    /// `K`: show the body as the HTML part will render.
+    Preview,
+    /// `zs`: sign the message with OpenPGP.
+    ToggleSign,
+    /// `ze`: encrypt the message with OpenPGP.
+    ToggleEncrypt,
+    /// `zm`: sign the message with S/MIME.
+    ToggleSmime,
+    Editor,
+    /// `z=`: spelling suggestions for the word under the cursor; with a
+    /// count, the nth suggestion replaces it directly.
+    Suggest,
+    Send,
+    Quit,
+}
+
+impl Action {
+    /// Whether `.` repeats this action.
+    pub fn is_change(self) -> bool {
+        match self {
+            Action::Operate(op, _) => op != Operator::Yank,
+            Action::DeleteChar
+            | Action::DeleteCharBefore
+            | Action::Put { .. }
+            | Action::Insert(_)
+            | Action::Indent { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub register: Option<char>,
+    pub count: Option<usize>,
+    pub action: Action,
+}
+
+impl Command {
+    pub fn count(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parsed {
+    /// A valid prefix; wait for more keys.
+    Pending,
+    Invalid,
+    Done(Command),
+}
+
+/// Parses the keys typed so far in normal mode.
+pub fn parse(keys: &str) -> Parsed {
+    let mut chars = keys.chars().peekable();
+
+    let mut register = None;
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        match chars.next() {
+            Some(name) => register = Some(name),
+            None => return Parsed::Pending,
+        }
+    }
+
+    let count = parse_count(&mut chars);
+    let Some(key) = chars.next() else {
+        return Parsed::Pending;
+    };
+    let done = |count, action| {
+        Parsed::Done(Command {
+            register,
+            count,
+            action,
+        })
+    };
+
+    let action = match key {
+        'd' | 'c' | 'y' => {
+            let operator = match key {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+            let inner = parse_count(&mut chars);
+            let count = match (count, inner) {
+                (Some(a), Some(b)) => Some((a * b).min(MAX_COUNT)),
+                (a, b) => a.or(b),
+            };
+            let target = match chars.next() {
+                None => return Parsed::Pending,
+                Some(k) if k == key => Target::Line,
+                Some(k @ ('i' | 'a')) => {
+                    let Some(object) = chars.next() else {
+                        return Parsed::Pending;
+                    };
+                    let kind = match object {
+                        'w' => ObjectKind::Word,
+                        'p' => ObjectKind::Paragraph,
+                        '"' | '\'' | '`' => ObjectKind::Quote(object),
+                        _ => return Parsed::Invalid,
+                    };
+                    Target::Object(TextObject {
+                        kind,
+                        around: k == 'a',
+                    })
+                }
+                Some(k) => match parse_motion(k, &mut chars) {
+                    Some(Ok(motion)) => Target::Motion(motion),
+                    Some(Err(())) => return Parsed::Pending,
+                    None => return Parsed::Invalid,
+                },
+            };
+            return done(count, Action::Operate(operator, target));
+        }
+        '>' | '<' => match chars.next() {
+            None => return Parsed::Pending,
+            Some(k) if k == key => Action::Indent { right: key == '>' },
+            Some(_) => return Parsed::Invalid,
+        },
+        'Z' => match chars.next() {
+            None => return Parsed::Pending,
+            Some('Z') => Action::Send,
+            Some(_) => return Parsed::Invalid,
+        },
+        'z' => match chars.next() {
+            None => return Parsed::Pending,
+            Some('=') => Action::Suggest,
+            Some('s') => Action::ToggleSign,
+            Some('e') => Action::ToggleEncrypt,
+            Some('m') => Action::ToggleSmime,
+            Some(_) => return Parsed::Invalid,
+        },
+        'g' if matches!(chars.peek(), Some('-' | '+')) => Action::Chronological {
+            later: chars.next() == Some('+'),
+        },
+        'x' => Action::DeleteChar,
+        'X' => Action::DeleteCharBefore,
+        'D' => Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        'C' => Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        'Y' => Action::Operate(Operator::Yank, Target::Line),
+        'p' => Action::Put { after: true },
+        'P' => Action::Put { after: false },
+        'i' => Action::Insert(Insert::Before),
+        'a' => Action::Insert(Insert::After),
+        'I' => Action::Insert(Insert::LineStart),
+        'A' => Action::Insert(Insert::LineEnd),
+        'o' => Action::Insert(Insert::LineBelow),
+        'O' => Action::Insert(Insert::LineAbove),
+        'u' => Action::Undo,
+        '.' => Action::Repeat,
+        'v' => Action::Visual,
+        'V' => Action::VisualLine,
+        '/' => Action::Search { forward: true },
+        '?' => Action::Search { forward: false },
+        'n' => Action::SearchNext { reverse: false },
+        'N' => Action::SearchNext { reverse: true },
+        'H' => Action::ToggleExtra,
+        'M' => Action::ToggleMarkdown,
+        'K' => Action::Preview,
+        'E' => Action::Editor,
+        ':' => Action::Send,
+        'q' => Action::Quit,
+        k => match parse_motion(k, &mut chars) {
+            Some(Ok(motion)) => Action::Move(motion),
+            Some(Err(())) => return Parsed::Pending,
+            None => return Parsed::Invalid,
+        },
+    };
+    done(count, action)
+}
+
+/// Reads a count. A leading `0` is the line-start motion, not a count.
+fn parse_count(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut count: Option<usize> = None;
+    while let Some(&ch) = chars.peek() {
+        let Some(digit) = ch.to_digit(10) else {
+            break;
+        };
+        if digit == 0 && count.is_none() {
+            break;
+        }
+        chars.next();
+        count = Some((count.unwrap_or(0) * 10 + digit as usize).min(MAX_COUNT));
+    }
+    count
+}
+
+/// Parses a motion starting with `key`: `Some(Err)` when more keys are
+/// needed, `None` when it is not a motion.
+fn parse_motion(
+    key: char,
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Option<Result<Motion, ()>> {
+    let motion = match key {
+        'h' => Motion::Left,
+        'l' => Motion::Right,
+        'k' => Motion::Up,
+        'j' => Motion::Down,
+        'w' => Motion::WordForward,
+        'b' => Motion::WordBackward,
+        'e' => Motion::WordEnd,
+        '0' => Motion::LineStart,
+        '$' => Motion::LineEnd,
+        'G' => Motion::LastLine,
+        'g' => match chars.next() {
+            None => return Some(Err(())),
+            Some('g') => Motion::FirstLine,
+            Some('k') => Motion::DisplayUp,
+            Some('j') => Motion::DisplayDown,
+            Some(_) => return None,
+        },
+        ']' | '[' => match chars.next() {
+            None => return Some(Err(())),
+            Some('s') if key == ']' => Motion::NextMisspelled,
+            Some('s') => Motion::PrevMisspelled,
+            Some(_) => return None,
+        },
+        _ => return None,
+    };
+    Some(Ok(motion))
+}
+
+#[derive(PartialEq, Eq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(ch: char) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Space
+    } else if ch.is_alphanumeric() || ch == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+fn char_at(text: &str, pos: usize) -> Option<char> {
+    text.get(pos..)?.chars().next()
+}
+
+pub fn next_char(text: &str, pos: usize) -> usize {
+    char_at(text, pos).map_or(text.len(), |c| pos + c.len_utf8())
+}
+
+pub fn prev_char(text: &str, pos: usize) -> usize {
+    text[..pos]
+        .chars()
+        .next_back()
+        .map_or(0, |c| pos - c.len_utf8())
+}
+
+pub fn line_start(text: &str, pos: usize) -> usize {
+    text[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// End of the line containing `pos`, before its newline.
+pub fn line_end(text: &str, pos: usize) -> usize {
+    text[pos..].find('\n').map_or(text.len(), |i| pos + i)
+}
+
+/// Start of the 0-based line `n`, or of the last line when there are fewer.
+pub fn nth_line_start(text: &str, n: usize) -> usize {
+    let mut start = 0;
+    for _ in 0..n {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => break,
+        }
+    }
+    start
+}
+
+pub fn line_count(text: &str) -> usize {
+    text.matches('\n').count() + 1
+}
+
+/// Moves `delta` lines up or down, keeping the character column where the
+/// target line is long enough.
+pub fn move_lines(text: &str, pos: usize, delta: isize) -> usize {
+    let line = text[..pos].matches('\n').count();
+    let column = text[line_start(text, pos)..pos].chars().count();
+    let target = line.saturating_add_signed(delta).min(line_count(text) - 1);
+    let start = nth_line_start(text, target);
+    let end = line_end(text, start);
+    text[start..end]
+        .char_indices()
+        .nth(column)
+        .map_or(end, |(i, _)| start + i)
+}
+
+/// `w`: start of the next word.
+pub fn word_forward(text: &str, pos: usize) -> usize {
+    let Some(ch) = char_at(text, pos) else {
+        return pos;
+    };
+    let start_class = class(ch);
+    let mut pos = pos;
+    if start_class != CharClass::Space {
+        while let Some(c) = char_at(text, pos)
+            && class(c) == start_class
+        {
+            pos += c.len_utf8();
+        }
+    }
+    while let Some(c) = char_at(text, pos)
+        && c.is_whitespace()
+    {
+        pos += c.len_utf8();
+    }
+    pos
+}
+
+/// `b`: start of the previous word.
+pub fn word_backward(text: &str, pos: usize) -> usize {
+    let mut pos = pos;
+    while pos > 0 && text[..pos].ends_with(char::is_whitespace) {
+        pos = prev_char(text, pos);
+    }
+    let Some(ch) = text[..pos].chars().next_back() else {
+        return 0;
+    };
+    let word_class = class(ch);
+    while let Some(c) = text[..pos].chars().next_back()
+        && class(c) == word_class
+    {
+        pos -= c.len_utf8();
+    }
+    pos
+}
+
+/// `e`: last character of the current or next word.
+pub fn word_end(text: &str, pos: usize) -> usize {
+    let mut pos = next_char(text, pos);
+    while let Some(c) = char_at(text, pos)
+        && c.is_whitespace()
+    {
+        pos += c.len_utf8();
+    }
+    let Some(ch) = char_at(text, pos) else {
+        return prev_char(text, text.len());
+    };
+    let word_class = class(ch);
+    loop {
+        let next = next_char(text, pos);
+        match char_at(text, next) {
+            Some(c) if class(c) == word_class => pos = next,
+            _ => return pos,
+        }
+    }
+}
+
+/// The range a text object covers around `pos`, and whether it is made of
+/// whole lines.
+pub fn text_object(text: &str, pos: usize, object: TextObject) -> Option<(Range<usize>, bool)> {
+    match object.kind {
+        ObjectKind::Word => word_object(text, pos, object.around).map(|r| (r, false)),
+        ObjectKind::Paragraph => Some((paragraph_object(text, pos, object.around), true)),
+        ObjectKind::Quote(quote) => {
+            quote_object(text, pos, quote, object.around).map(|r| (r, false))
+        }
+    }
+}
+
+fn word_object(text: &str, pos: usize, around: bool) -> Option<Range<usize>> {
+    let ch = char_at(text, pos)?;
+    let same_run = |class_of: &CharClass, c: char| class(c) == *class_of && c != '\n';
+    let run = |pos: usize| {
+        let run_class = class(char_at(text, pos).unwrap_or(' '));
+        let mut start = pos;
+        while let Some(c) = text[..start].chars().next_back()
+            && same_run(&run_class, c)
+        {
+            start -= c.len_utf8();
+        }
+        let mut end = pos;
+        while let Some(c) = char_at(text, end)
+            && same_run(&run_class, c)
+        {
+            end += c.len_utf8();
+        }
+        start..end
+    };
+
+    let mut range = run(pos);
+    if !around {
+        return Some(range);
+    }
+    let is_blank = |c: char| c.is_whitespace() && c != '\n';
+    if class(ch) == CharClass::Space {
+        // Whitespace plus the word after it
+        if let Some(c) = char_at(text, range.end)
+            && c != '\n'
+        {
+            range.end = run(range.end).end;
+        }
+    } else if char_at(text, range.end).is_some_and(is_blank) {
+        range.end = run(range.end).end;
+    } else if let Some(c) = text[..range.start].chars().next_back()
+        && is_blank(c)
+    {
+        range.start = run(range.start - c.len_utf8()).start;
+    }
+    Some(range)
+}
+
+fn paragraph_object(text: &str, pos: usize, around: bool) -> Range<usize> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let current = text[..pos].matches('\n').count();
+    let blank = |i: usize| lines[i].trim().is_empty();
+    let kind = blank(current);
+
+    let mut first = current;
+    while first > 0 && blank(first - 1) == kind {
+        first -= 1;
+    }
+    let mut last = current;
+    while last + 1 < lines.len() && blank(last + 1) == kind {
+        last += 1;
+    }
+    if around {
+        // Take the following run of the other kind, or the preceding one
+        if last + 1 < lines.len() {
+            last += 1;
+            while last + 1 < lines.len() && blank(last + 1) != kind {
+                last += 1;
+            }
+        } else {
+            while first > 0 && blank(first - 1) != kind {
+                first -= 1;
+            }
+        }
+    }
+    let start = nth_line_start(text, first);
+    start..line_end(text, nth_line_start(text, last))
+}
+
+fn quote_object(text: &str, pos: usize, quote: char, around: bool) -> Option<Range<usize>> {
+    let start = line_start(text, pos);
+    let line = &text[start..line_end(text, pos)];
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            quotes.push(start + i);
+        }
+    }
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| pos <= close)?;
+    Some(if around {
+        open..close + quote.len_utf8()
+    } else {
+        open + quote.len_utf8()..close
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(register: Option<char>, count: Option<usize>, action: Action) -> Parsed {
+        Parsed::Done(Command {
+            register,
+            count,
+            action,
+        })
+    }
+
+    #[test]
+    fn parses_counts_and_motions() {
+        assert_eq!(parse("j"), command(None, None, Action::Move(Motion::Down)));
+        assert_eq!(
+            parse("12w"),
+            command(None, Some(12), Action::Move(Motion::WordForward))
+        );
+        // A leading zero is the motion, a later one part of the count
+        assert_eq!(
+            parse("0"),
+            command(None, None, Action::Move(Motion::LineStart))
+        );
+        assert_eq!(
+            parse("10G"),
+            command(None, Some(10), Action::Move(Motion::LastLine))
+        );
+        assert_eq!(
+            parse("99999999j"),
+            command(None, Some(MAX_COUNT), Action::Move(Motion::Down))
+        );
+    }
+
+    #[test]
+    fn waits_for_incomplete_commands() {
+        for keys in [
+            "", "3", "\"", "\"a", "d", "2d3", "di", "g", "]", "z", "Z", ">",
+        ] {
+            assert_eq!(parse(keys), Parsed::Pending, "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for keys in ["Q", "dQ", "diQ", "gQ", "]x", "zQ", "ZQ", "><"] {
+            assert_eq!(parse(keys), Parsed::Invalid, "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn multiplies_operator_and_motion_counts() {
+        assert_eq!(
+            parse("2d3w"),
+            command(
+                None,
+                Some(6),
+                Action::Operate(Operator::Delete, Target::Motion(Motion::WordForward))
+            )
+        );
+        assert_eq!(
+            parse("d3w"),
+            command(
+                None,
+                Some(3),
+                Action::Operate(Operator::Delete, Target::Motion(Motion::WordForward))
+            )
+        );
+        assert_eq!(
+            parse("99d999w"),
+            command(
+                None,
+                Some(MAX_COUNT),
+                Action::Operate(Operator::Delete, Target::Motion(Motion::WordForward))
+            )
+        );
+    }
+
+    #[test]
+    fn parses_registers_lines_and_text_objects() {
+        assert_eq!(
+            parse("\"a2yy"),
+            command(
+                Some('a'),
+                Some(2),
+                Action::Operate(Operator::Yank, Target::Line)
+            )
+        );
+        assert_eq!(
+            parse("ci\""),
+            command(
+                None,
+                None,
+                Action::Operate(
+                    Operator::Change,
+                    Target::Object(TextObject {
+                        kind: ObjectKind::Quote('"'),
+                        around: false,
+                    })
+                )
+            )
+        );
+        assert_eq!(
+            parse("dap"),
+            command(
+                None,
+                None,
+                Action::Operate(
+                    Operator::Delete,
+                    Target::Object(TextObject {
+                        kind: ObjectKind::Paragraph,
+                        around: true,
+                    })
+                )
+            )
+        );
+        assert_eq!(
+            parse("dgg"),
+            command(
+                None,
+                None,
+                Action::Operate(Operator::Delete, Target::Motion(Motion::FirstLine))
+            )
+        );
+        assert_eq!(
+            parse(">>"),
+            command(None, None, Action::Indent { right: true })
+        );
+        assert_eq!(parse("3z="), command(None, Some(3), Action::Suggest));
+        assert_eq!(
+            parse("2g-"),
+            command(None, Some(2), Action::Chronological { later: false })
+        );
+        assert_eq!(
+            parse("g+"),
+            command(None, None, Action::Chronological { later: true })
+        );
+        assert_eq!(parse("dg-"), Parsed::Invalid);
+        assert_eq!(
+            parse("]s"),
+            command(None, None, Action::Move(Motion::NextMisspelled))
+        );
+    }
+
+    #[test]
+    fn only_changes_are_repeatable() {
+        assert!(Action::Operate(Operator::Delete, Target::Line).is_change());
+        assert!(!Action::Operate(Operator::Yank, Target::Line).is_change());
+        assert!(Action::Put { after: true }.is_change());
+        assert!(!Action::Move(Motion::Down).is_change());
+        assert!(!Action::Undo.is_change());
+        assert!(!Action::Chronological { later: true }.is_change());
+    }
+
+    #[test]
+    fn word_motions_follow_character_classes() {
+        let text = "foo.bar  baz\nqux";
+        assert_eq!(word_forward(text, 0), 3);
+        assert_eq!(word_forward(text, 3), 4);
+        assert_eq!(word_forward(text, 4), 9);
+        assert_eq!(word_forward(text, 9), 13);
+        assert_eq!(word_backward(text, 13), 9);
+        assert_eq!(word_backward(text, 9), 4);
+        assert_eq!(word_backward(text, 4), 3);
+        assert_eq!(word_end(text, 0), 2);
+        assert_eq!(word_end(text, 2), 3);
+        assert_eq!(word_end(text, 10), 11);
+        assert_eq!(word_end(text, 14), 15);
+    }
+
+    #[test]
+    fn motions_stay_on_character_boundaries() {
+        let text = "héllo wörld";
+        assert_eq!(next_char(text, 1), 3);
+        assert_eq!(prev_char(text, 3), 1);
+        assert_eq!(word_forward(text, 0), 7);
+        assert_eq!(word_end(text, 7), text.len() - 1);
+    }
+
+    #[test]
+    fn line_moves_keep_the_column() {
+        let text = "abcdef\nab\nabcdef";
+        assert_eq!(move_lines(text, 4, 1), 9);
+        assert_eq!(move_lines(text, 4, 2), 14);
+        assert_eq!(move_lines(text, 14, -5), 4);
+        assert_eq!(move_lines(text, 14, 5), 14);
+        assert_eq!(line_count(text), 3);
+        assert_eq!(nth_line_start(text, 1), 7);
+        assert_eq!(nth_line_start(text, 9), 10);
+        assert_eq!(line_start(text, 8), 7);
+        assert_eq!(line_end(text, 8), 9);
+    }
+
+    #[test]
+    fn word_objects() {
+        let object = |around| TextObject {
+            kind: ObjectKind::Word,
+            around,
+        };
+        let text = "one two  three";
+        assert_eq!(text_object(text, 5, object(false)), Some((4..7, false)));
+        // `aw` takes the whitespace after the word
+        assert_eq!(text_object(text, 5, object(true)), Some((4..9, false)));
+        // ...or before it, at the end of the line
+        assert_eq!(text_object(text, 10, object(true)), Some((7..14, false)));
+        // On whitespace, `aw` is the whitespace plus the next word
+        assert_eq!(text_object(text, 7, object(true)), Some((7..14, false)));
+    }
+
+    #[test]
+    fn quote_objects_skip_escaped_quotes() {
+        let object = |around| TextObject {
+            kind: ObjectKind::Quote('"'),
+            around,
+        };
+        let text = r#"say "a \" b" now"#;
+        assert_eq!(text_object(text, 6, object(false)), Some((5..11, false)));
+        assert_eq!(text_object(text, 6, object(true)), Some((4..12, false)));
+        // Before the first quote, the next pair on the line is used
+        assert_eq!(text_object(text, 0, object(false)), Some((5..11, false)));
+        assert_eq!(text_object(text, 14, object(false)), None);
+    }
+
+    #[test]
+    fn paragraph_objects_are_linewise() {
+        let object = |around| TextObject {
+            kind: ObjectKind::Paragraph,
+            around,
+        };
+        let text = "a\nb\n\nc\n";
+        assert_eq!(text_object(text, 2, object(false)), Some((0..3, true)));
+        assert_eq!(text_object(text, 2, object(true)), Some((0..4, true)));
+        assert_eq!(text_object(text, 5, object(true)), Some((5..7, true)));
+        // The last paragraph takes the blank lines before it instead
+        assert_eq!(text_object("a\n\nc", 3, object(true)), Some((2..4, true)));
+    }
+}